@@ -46,6 +46,7 @@ use crate::http_server_factory::Listener;
 use crate::plugins::traffic_shaping::Elapsed;
 use crate::plugins::traffic_shaping::RateLimited;
 use crate::router::ApolloRouterError;
+use crate::router_factory::create_tls_acceptor;
 use crate::router_factory::Endpoint;
 use crate::router_factory::RouterFactory;
 use crate::services::router;
@@ -193,6 +194,14 @@ impl HttpServerFactory for AxumHttpServerFactory {
                     }
                 }
             };
+            let tls_acceptor = configuration
+                .supergraph
+                .tls
+                .as_ref()
+                .map(create_tls_acceptor)
+                .transpose()
+                .map_err(|e| ApolloRouterError::ServiceCreationError(e.into()))?;
+            let main_listener = main_listener.with_tls(tls_acceptor);
             let actual_main_listen_address = main_listener
                 .local_addr()
                 .map_err(ApolloRouterError::ServerCreationError)?;
@@ -7,6 +7,7 @@ use std::time::Duration;
 use std::time::Instant;
 
 use axum::response::*;
+use axum::Extension;
 use axum::Router;
 use futures::channel::oneshot;
 use futures::prelude::*;
@@ -20,6 +21,7 @@ use tokio::sync::Notify;
 use crate::configuration::Configuration;
 use crate::configuration::ListenAddr;
 use crate::http_server_factory::Listener;
+use crate::http_server_factory::LocalAddr;
 use crate::http_server_factory::NetworkStream;
 use crate::router::ApolloRouterError;
 use crate::router_factory::Endpoint;
@@ -184,6 +186,14 @@ pub(super) async fn get_extra_listeners(
     Ok(listeners_and_routers)
 }
 
+/// Adds the local address of the connection to the extensions of its requests
+fn with_local_addr(router: Router, local_addr: Option<ListenAddr>) -> Router {
+    match local_addr {
+        Some(local_addr) => router.layer(Extension(LocalAddr(local_addr))),
+        None => router,
+    }
+}
+
 pub(super) fn serve_router_on_listen_addr(
     mut listener: Listener,
     router: axum::Router,
@@ -199,6 +209,9 @@ pub(super) fn serve_router_on_listen_addr(
 
         let connection_shutdown = Arc::new(Notify::new());
         let mut max_open_file_warning = None;
+        // the requests are matched against the configured listen address, like `0.0.0.0:4000`,
+        // rather than the address of the interface the connection was accepted on
+        let router = with_local_addr(router, listener.local_addr().ok());
 
         loop {
             tokio::select! {
@@ -244,6 +257,42 @@ pub(super) fn serve_router_on_listen_addr(
                                             }
                                         }
                                     }
+                                    NetworkStream::Tls(stream, acceptor) => {
+                                        stream
+                                            .set_nodelay(true)
+                                            .expect(
+                                                "this should not fail unless the socket is invalid",
+                                            );
+                                        let stream = match acceptor.accept(stream).await {
+                                            Ok(stream) => stream,
+                                            Err(e) => {
+                                                tracing::debug!("TLS handshake failed: {}", e);
+                                                return;
+                                            }
+                                        };
+                                        // clients that negotiated HTTP/2 through ALPN will not send an HTTP/1 request first
+                                        let is_http2 = stream.get_ref().1.alpn_protocol() == Some(&b"h2"[..]);
+                                        let connection = Http::new()
+                                            .http1_keep_alive(true)
+                                            .http2_only(is_http2)
+                                            .serve_connection(stream, app);
+
+                                        tokio::pin!(connection);
+                                        tokio::select! {
+                                            // the connection finished first
+                                            _res = &mut connection => {
+                                            }
+                                            // the shutdown receiver was triggered first,
+                                            // so we tell the connection to do a graceful shutdown
+                                            // on the next request, then we wait for it to finish
+                                            _ = connection_shutdown.notified() => {
+                                                let c = connection.as_mut();
+                                                c.graceful_shutdown();
+
+                                                let _= connection.await;
+                                            }
+                                        }
+                                    }
                                     #[cfg(unix)]
                                     NetworkStream::Unix(stream) => {
                                         let connection = Http::new()
@@ -56,6 +56,7 @@ use crate::configuration::HealthCheck;
 use crate::configuration::Homepage;
 use crate::configuration::Sandbox;
 use crate::configuration::Supergraph;
+use crate::configuration::TlsSupergraph;
 use crate::graphql;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
@@ -627,6 +628,97 @@ async fn response() -> Result<(), ApolloRouterError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn it_terminates_tls() -> Result<(), ApolloRouterError> {
+    let expected_response = graphql::Response::builder()
+        .data(json!({"response": "yay"}))
+        .build();
+    let example_response = expected_response.clone();
+    let router_service = router_service::from_supergraph_mock_callback(move |req| {
+        let example_response = example_response.clone();
+
+        Ok(SupergraphResponse::new_from_graphql_response(
+            example_response,
+            req.context,
+        ))
+    })
+    .await;
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .tls(TlsSupergraph {
+                    certificate_chain: include_str!("../testdata/tls/server.crt").to_string(),
+                    key: include_str!("../testdata/tls/server.key").to_string(),
+                    client_certificate_authorities: None,
+                })
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, _) = init_with_config(router_service, Arc::new(conf), MultiMap::new()).await?;
+    let (_, port) = server
+        .graphql_listen_address()
+        .as_ref()
+        .unwrap()
+        .ip_and_port()
+        .unwrap();
+
+    let client = reqwest::Client::builder()
+        .add_root_certificate(
+            reqwest::Certificate::from_pem(include_bytes!("../testdata/tls/ca.crt")).unwrap(),
+        )
+        .https_only(true)
+        .build()
+        .unwrap();
+    let response = client
+        .post(format!("https://localhost:{port}/"))
+        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+        .header(ACCEPT, APPLICATION_JSON.essence_str())
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        expected_response,
+    );
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_rejects_a_tls_key_not_matching_the_certificate() {
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .tls(TlsSupergraph {
+                    certificate_chain: include_str!("../testdata/tls/server.crt").to_string(),
+                    key: include_str!("../testdata/tls/other.key").to_string(),
+                    client_certificate_authorities: None,
+                })
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let error = init_with_config(
+        router_service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await
+    .map(|_| ())
+    .unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "could not create router: could not load the supergraph TLS configuration: the private key does not match the certificate"
+    );
+}
+
 #[tokio::test]
 async fn bad_response() -> Result<(), ApolloRouterError> {
     let (server, client) = init(router_service::empty().await).await;
@@ -0,0 +1,274 @@
+//! In-memory Redis server for the cache tests, implementing the commands sent by the caches
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum MockValue {
+    String(Vec<u8>),
+    Set(BTreeSet<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MockEntry {
+    pub(crate) value: MockValue,
+    /// Expiration in milliseconds, as last set by a command. The entries never expire.
+    pub(crate) expiration: Option<u64>,
+}
+
+/// A command received by the server, with the connection it was received on
+#[derive(Clone, Debug)]
+pub(crate) struct MockCommand {
+    pub(crate) connection: usize,
+    pub(crate) args: Vec<String>,
+}
+
+pub(crate) struct MockRedis {
+    pub(crate) url: String,
+    entries: Arc<Mutex<HashMap<String, MockEntry>>>,
+    commands: mpsc::UnboundedReceiver<MockCommand>,
+}
+
+impl MockRedis {
+    /// Starts a server answering the commands
+    pub(crate) async fn start() -> Self {
+        Self::start_with(false).await
+    }
+
+    /// Starts a server accepting connections and commands, but never answering them
+    pub(crate) async fn stalled() -> Self {
+        Self::start_with(true).await
+    }
+
+    async fn start_with(stalled: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let entries: Arc<Mutex<HashMap<String, MockEntry>>> = Default::default();
+        let (sender, commands) = mpsc::unbounded_channel();
+
+        let server_entries = entries.clone();
+        tokio::spawn(async move {
+            let mut connection = 0;
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let entries = server_entries.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    let mut read_buffer = [0u8; 4096];
+                    loop {
+                        match stream.read(&mut read_buffer).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(read) => buffer.extend_from_slice(&read_buffer[..read]),
+                        }
+                        while let Some((args, consumed)) = parse_command(&buffer) {
+                            buffer.drain(..consumed);
+                            let _ = sender.send(MockCommand {
+                                connection,
+                                args: args.clone(),
+                            });
+                            if stalled {
+                                continue;
+                            }
+                            let reply = execute(&mut entries.lock().unwrap(), args);
+                            if stream.write_all(&reply).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+                connection += 1;
+            }
+        });
+
+        Self {
+            url,
+            entries,
+            commands,
+        }
+    }
+
+    pub(crate) fn entry(&self, key: &str) -> Option<MockEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    /// Waits for the next command received by the server
+    pub(crate) async fn next_command(&mut self) -> MockCommand {
+        self.commands.recv().await.expect("the server is running")
+    }
+}
+
+/// Parses a command sent as an array of bulk strings, returning its arguments and the number
+/// of bytes it used, or `None` if it is not complete yet
+fn parse_command(buffer: &[u8]) -> Option<(Vec<String>, usize)> {
+    fn line(buffer: &[u8], start: usize) -> Option<(&str, usize)> {
+        let end = buffer[start..].windows(2).position(|w| w == b"\r\n")? + start;
+        Some((std::str::from_utf8(&buffer[start..end]).ok()?, end + 2))
+    }
+
+    let (header, mut position) = line(buffer, 0)?;
+    let count: usize = header.strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (header, start) = line(buffer, position)?;
+        let length: usize = header.strip_prefix('$')?.parse().ok()?;
+        if buffer.len() < start + length + 2 {
+            return None;
+        }
+        args.push(String::from_utf8_lossy(&buffer[start..start + length]).into_owned());
+        position = start + length + 2;
+    }
+    Some((args, position))
+}
+
+fn execute(entries: &mut HashMap<String, MockEntry>, args: Vec<String>) -> Vec<u8> {
+    let command = args[0].to_uppercase();
+    let args = &args[1..];
+    match command.as_str() {
+        "GET" => match entries.get(&args[0]).map(|entry| &entry.value) {
+            Some(MockValue::String(value)) => bulk(value),
+            Some(MockValue::Set(_)) => error("WRONGTYPE"),
+            None => b"$-1\r\n".to_vec(),
+        },
+        "MGET" => {
+            let mut reply = format!("*{}\r\n", args.len()).into_bytes();
+            for key in args {
+                match entries.get(key).map(|entry| &entry.value) {
+                    Some(MockValue::String(value)) => reply.extend(bulk(value)),
+                    _ => reply.extend_from_slice(b"$-1\r\n"),
+                }
+            }
+            reply
+        }
+        "SET" => {
+            let mut nx = false;
+            let mut expiration = None;
+            let mut options = args[2..].iter();
+            while let Some(option) = options.next() {
+                match option.to_uppercase().as_str() {
+                    "NX" => nx = true,
+                    "PX" => expiration = options.next().and_then(|ms| ms.parse().ok()),
+                    "EX" => {
+                        expiration = options
+                            .next()
+                            .and_then(|s| s.parse::<u64>().ok())
+                            .map(|s| s * 1000)
+                    }
+                    _ => return error("ERR syntax error"),
+                }
+            }
+            if nx && entries.contains_key(&args[0]) {
+                return b"$-1\r\n".to_vec();
+            }
+            set_string(entries, &args[0], &args[1], expiration);
+            b"+OK\r\n".to_vec()
+        }
+        "SETEX" | "PSETEX" => {
+            let expiration: u64 = args[1].parse().unwrap();
+            let expiration = if command == "SETEX" {
+                expiration * 1000
+            } else {
+                expiration
+            };
+            set_string(entries, &args[0], &args[2], Some(expiration));
+            b"+OK\r\n".to_vec()
+        }
+        "SADD" => {
+            let entry = entries.entry(args[0].clone()).or_insert_with(|| MockEntry {
+                value: MockValue::Set(BTreeSet::new()),
+                expiration: None,
+            });
+            match &mut entry.value {
+                MockValue::Set(set) => {
+                    let added = args[1..]
+                        .iter()
+                        .filter(|member| set.insert(member.to_string()))
+                        .count();
+                    integer(added as i64)
+                }
+                MockValue::String(_) => error("WRONGTYPE"),
+            }
+        }
+        "SMEMBERS" => match entries.get(&args[0]).map(|entry| &entry.value) {
+            Some(MockValue::Set(set)) => {
+                let mut reply = format!("*{}\r\n", set.len()).into_bytes();
+                for member in set {
+                    reply.extend(bulk(member.as_bytes()));
+                }
+                reply
+            }
+            Some(MockValue::String(_)) => error("WRONGTYPE"),
+            None => b"*0\r\n".to_vec(),
+        },
+        "SSCAN" => {
+            let cursor: usize = args[1].parse().unwrap_or_default();
+            let count = match args.get(2).map(|option| option.to_uppercase()).as_deref() {
+                Some("COUNT") => args[3].parse().unwrap_or(10),
+                _ => 10,
+            };
+            let members: Vec<&String> = match entries.get(&args[0]).map(|entry| &entry.value) {
+                Some(MockValue::Set(set)) => set.iter().skip(cursor).take(count).collect(),
+                Some(MockValue::String(_)) => return error("WRONGTYPE"),
+                None => Vec::new(),
+            };
+            // the cursor is the position of the next member, 0 once all of them were returned
+            let next = match entries.get(&args[0]).map(|entry| &entry.value) {
+                Some(MockValue::Set(set)) if cursor + members.len() < set.len() => {
+                    cursor + members.len()
+                }
+                _ => 0,
+            };
+            let mut reply = b"*2\r\n".to_vec();
+            reply.extend(bulk(next.to_string().as_bytes()));
+            reply.extend(format!("*{}\r\n", members.len()).into_bytes());
+            for member in members {
+                reply.extend(bulk(member.as_bytes()));
+            }
+            reply
+        }
+        "PEXPIRE" => match entries.get_mut(&args[0]) {
+            Some(entry) => {
+                entry.expiration = args[1].parse().ok();
+                integer(1)
+            }
+            None => integer(0),
+        },
+        _ => error("ERR unknown command"),
+    }
+}
+
+fn set_string(
+    entries: &mut HashMap<String, MockEntry>,
+    key: &str,
+    value: &str,
+    expiration: Option<u64>,
+) {
+    entries.insert(
+        key.to_string(),
+        MockEntry {
+            value: MockValue::String(value.as_bytes().to_vec()),
+            expiration,
+        },
+    );
+}
+
+fn bulk(value: &[u8]) -> Vec<u8> {
+    let mut reply = format!("${}\r\n", value.len()).into_bytes();
+    reply.extend_from_slice(value);
+    reply.extend_from_slice(b"\r\n");
+    reply
+}
+
+fn integer(value: i64) -> Vec<u8> {
+    format!(":{}\r\n", value).into_bytes()
+}
+
+fn error(message: &str) -> Vec<u8> {
+    format!("-{}\r\n", message).into_bytes()
+}
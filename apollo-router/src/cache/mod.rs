@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
+#[cfg(feature = "experimental_cache")]
+use std::time::Duration;
 
 use tokio::sync::broadcast;
 use tokio::sync::oneshot;
@@ -10,21 +12,37 @@ use self::storage::CacheStorage;
 use self::storage::KeyType;
 use self::storage::ValueType;
 
+#[cfg(all(test, feature = "experimental_cache"))]
+pub(crate) mod mock_redis;
+pub(crate) mod policy;
 #[cfg(feature = "experimental_cache")]
 pub(crate) mod redis;
+pub(crate) mod service;
 pub(crate) mod storage;
 
+#[cfg(feature = "experimental_cache")]
+use self::redis::RedisKey;
+
 type WaitMap<K, V> = Arc<Mutex<HashMap<K, broadcast::Sender<V>>>>;
 pub(crate) const DEFAULT_CACHE_CAPACITY: NonZeroUsize = match NonZeroUsize::new(512) {
     Some(v) => v,
     None => unreachable!(),
 };
+/// Interval between the first two checks of Redis while another router computes a value. It
+/// doubles after each check, up to [`LOCK_POLL_MAX_INTERVAL`].
+#[cfg(feature = "experimental_cache")]
+const LOCK_POLL_MIN_INTERVAL: Duration = Duration::from_millis(10);
+#[cfg(feature = "experimental_cache")]
+const LOCK_POLL_MAX_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Cache implementation with query deduplication
 #[derive(Clone)]
 pub(crate) struct DeduplicatingCache<K: KeyType, V: ValueType> {
     wait_map: WaitMap<K, V>,
     storage: CacheStorage<K, V>,
+    /// How long to wait for another router computing the same value, see [`Self::shared`]
+    #[cfg(feature = "experimental_cache")]
+    lock_timeout: Option<Duration>,
 }
 
 impl<K, V> DeduplicatingCache<K, V>
@@ -34,12 +52,20 @@ where
 {
     pub(crate) async fn with_capacity(
         capacity: NonZeroUsize,
-        redis_urls: Option<Vec<String>>,
+        #[cfg(feature = "experimental_cache")] redis: Option<crate::configuration::RedisCache>,
         caller: &str,
     ) -> Self {
         Self {
             wait_map: Arc::new(Mutex::new(HashMap::new())),
-            storage: CacheStorage::new(capacity, redis_urls, caller).await,
+            storage: CacheStorage::new(
+                capacity,
+                #[cfg(feature = "experimental_cache")]
+                redis,
+                caller,
+            )
+            .await,
+            #[cfg(feature = "experimental_cache")]
+            lock_timeout: None,
         }
     }
 
@@ -50,14 +76,22 @@ where
         Self::with_capacity(
             config.in_memory.limit,
             #[cfg(feature = "experimental_cache")]
-            config.redis.as_ref().map(|c| c.urls.clone()),
-            #[cfg(not(feature = "experimental_cache"))]
-            None,
+            config.redis.clone(),
             caller,
         )
         .await
     }
 
+    /// Shares the work with the other routers using the same Redis: on a miss, the router
+    /// locking the key computes the value, and the others wait up to `lock_timeout` for it to
+    /// appear in Redis before computing it themselves. The keys are also indexed, so that they
+    /// can be expired at once with [`Self::expire_shared`].
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn shared(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = Some(lock_timeout);
+        self
+    }
+
     pub(crate) async fn get(&self, key: &K) -> Entry<K, V> {
         // waiting on a value from the cache is a potentially long(millisecond scale) task that
         // can involve a network call to an external database. To reduce the waiting time, we
@@ -104,6 +138,15 @@ where
                     };
                 }
 
+                #[cfg(feature = "experimental_cache")]
+                if let Some(value) = self.wait_for_other_router(key).await {
+                    self.send(sender, key, value.clone()).await;
+
+                    return Entry {
+                        inner: EntryInner::Value(value),
+                    };
+                }
+
                 Entry {
                     inner: EntryInner::First {
                         sender,
@@ -117,7 +160,59 @@ where
     }
 
     pub(crate) async fn insert(&self, key: K, value: V) {
-        self.storage.insert(key, value.clone()).await;
+        #[cfg(feature = "experimental_cache")]
+        if self.lock_timeout.is_some() {
+            return self.storage.insert_indexed(key, value).await;
+        }
+
+        self.storage.insert(key, value).await;
+    }
+
+    /// Takes the lock on the key in Redis if the cache is shared. If another router holds it,
+    /// waits for its value until the lock times out.
+    #[cfg(feature = "experimental_cache")]
+    async fn wait_for_other_router(&self, key: &K) -> Option<V> {
+        let redis = self.storage.redis()?;
+        let lock_timeout = self.lock_timeout.filter(|timeout| !timeout.is_zero())?;
+
+        // if Redis is not available, the value is computed here
+        let lock_key = RedisKey(format!("lock\0{}", key));
+        if redis.lock(lock_key, lock_timeout).await.unwrap_or(true) {
+            return None;
+        }
+
+        // the lock is not released: it expires on its own, and the other routers only look
+        // at it when the value is not in Redis yet
+        let deadline = tokio::time::Instant::now() + lock_timeout;
+        let mut interval = LOCK_POLL_MIN_INTERVAL;
+        loop {
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                break;
+            }
+            tokio::time::sleep(interval.min(deadline - now)).await;
+            if let Some(value) = self.storage.get(key).await {
+                return Some(value);
+            }
+            interval = (interval * 2).min(LOCK_POLL_MAX_INTERVAL);
+        }
+
+        tracing::debug!(
+            "another router did not compute the value in {:?}, computing it",
+            lock_timeout
+        );
+        None
+    }
+
+    /// Sets the expiration of the keys stored in Redis since the cache is shared
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) async fn expire_shared(&self, ttl: Duration) {
+        if let Some(redis) = self.storage.redis() {
+            match redis.expire_index(ttl).await {
+                Ok(count) => tracing::debug!("set the expiration of {} keys to {:?}", count, ttl),
+                Err(e) => tracing::error!("could not set the expiration of the keys: {}", e),
+            }
+        }
     }
 
     async fn send(&self, sender: broadcast::Sender<V>, key: &K, value: V) {
@@ -189,7 +284,6 @@ where
     }
 
     /// sends the value without storing it into the cache
-    #[allow(unused)]
     pub(crate) async fn send(self, value: V) {
         if let EntryInner::First {
             sender, cache, key, ..
@@ -214,8 +308,13 @@ mod tests {
     #[tokio::test]
     async fn example_cache_usage() {
         let k = "key".to_string();
-        let cache =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(1).unwrap(), None, "test").await;
+        let cache = DeduplicatingCache::with_capacity(
+            NonZeroUsize::new(1).unwrap(),
+            #[cfg(feature = "experimental_cache")]
+            None,
+            "test",
+        )
+        .await;
 
         let entry = cache.get(&k).await;
 
@@ -231,8 +330,13 @@ mod tests {
 
     #[test(tokio::test)]
     async fn it_should_enforce_cache_limits() {
-        let cache: DeduplicatingCache<usize, usize> =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(13).unwrap(), None, "test").await;
+        let cache: DeduplicatingCache<usize, usize> = DeduplicatingCache::with_capacity(
+            NonZeroUsize::new(13).unwrap(),
+            #[cfg(feature = "experimental_cache")]
+            None,
+            "test",
+        )
+        .await;
 
         for i in 0..14 {
             let entry = cache.get(&i).await;
@@ -254,8 +358,13 @@ mod tests {
 
         mock.expect_retrieve().times(1).return_const(1usize);
 
-        let cache: DeduplicatingCache<usize, usize> =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(10).unwrap(), None, "test").await;
+        let cache: DeduplicatingCache<usize, usize> = DeduplicatingCache::with_capacity(
+            NonZeroUsize::new(10).unwrap(),
+            #[cfg(feature = "experimental_cache")]
+            None,
+            "test",
+        )
+        .await;
 
         // Let's trigger 100 concurrent gets of the same value and ensure only
         // one delegated retrieve is made
@@ -278,3 +387,133 @@ mod tests {
         assert_eq!(cache.storage.len().await, 1);
     }
 }
+
+#[cfg(all(test, feature = "experimental_cache"))]
+mod shared_cache_tests {
+    use std::collections::BTreeSet;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use super::mock_redis::MockRedis;
+    use super::mock_redis::MockValue;
+    use super::storage::CacheStorage;
+    use super::DeduplicatingCache;
+    use crate::configuration::RedisCache;
+
+    async fn shared_cache(
+        redis: &MockRedis,
+        lock_timeout: Duration,
+    ) -> DeduplicatingCache<String, String> {
+        let mut config = RedisCache::new(vec![redis.url.clone()]);
+        config.namespace = Some("schema".to_string());
+        DeduplicatingCache::with_capacity(NonZeroUsize::new(10).unwrap(), Some(config), "test")
+            .await
+            .shared(lock_timeout)
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_the_router_holding_the_lock() {
+        let redis = MockRedis::start().await;
+        let first = shared_cache(&redis, Duration::from_secs(5)).await;
+        let second = shared_cache(&redis, Duration::from_secs(5)).await;
+        let key = "query".to_string();
+
+        let entry = first.get(&key).await;
+        assert!(entry.is_first());
+        // the lock expires on its own after the lock timeout
+        assert_eq!(
+            redis.entry("schema:lock\0query").unwrap().expiration,
+            Some(5000)
+        );
+
+        let (waiting, _) = tokio::join!(second.get(&key), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            entry.insert("plan".to_string()).await;
+        });
+        assert!(!waiting.is_first());
+        assert_eq!(waiting.get().await.unwrap(), "plan");
+    }
+
+    #[tokio::test]
+    async fn it_computes_the_value_when_the_lock_times_out() {
+        let redis = MockRedis::start().await;
+        let first = shared_cache(&redis, Duration::from_secs(5)).await;
+        let second = shared_cache(&redis, Duration::from_millis(100)).await;
+        let key = "query".to_string();
+
+        // the first router holds the lock and never inserts the value
+        let _entry = first.get(&key).await;
+
+        let start = Instant::now();
+        let entry = second.get(&key).await;
+        assert!(entry.is_first());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn it_expires_the_indexed_keys() {
+        let redis = MockRedis::start().await;
+        let cache = shared_cache(&redis, Duration::from_secs(5)).await;
+
+        cache.insert("a".to_string(), "plan".to_string()).await;
+        cache.insert("b".to_string(), "plan".to_string()).await;
+        assert_eq!(
+            redis.entry("schema:index").unwrap().value,
+            MockValue::Set(BTreeSet::from([
+                "schema:a".to_string(),
+                "schema:b".to_string()
+            ]))
+        );
+
+        cache.expire_shared(Duration::from_secs(60)).await;
+        for key in ["schema:a", "schema:b", "schema:index"] {
+            assert_eq!(redis.entry(key).unwrap().expiration, Some(60_000));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_gets_the_keys_missing_from_memory_in_one_batch() {
+        let mut redis = MockRedis::start().await;
+        let config = RedisCache::new(vec![redis.url.clone()]);
+        let storage = || {
+            CacheStorage::<String, String>::new(
+                NonZeroUsize::new(10).unwrap(),
+                Some(config.clone()),
+                "test",
+            )
+        };
+        let first = storage().await;
+        let second = storage().await;
+
+        first.insert("a".to_string(), "1".to_string()).await;
+        first.insert("b".to_string(), "2".to_string()).await;
+        second.insert("c".to_string(), "3".to_string()).await;
+
+        let keys = ["a", "missing", "b", "c"].map(str::to_string);
+        assert_eq!(
+            second.get_multiple(&keys).await,
+            vec![
+                Some("1".to_string()),
+                None,
+                Some("2".to_string()),
+                Some("3".to_string())
+            ]
+        );
+        // only the keys missing from memory are fetched, with a single command
+        loop {
+            let command = tokio::time::timeout(Duration::from_secs(5), redis.next_command())
+                .await
+                .expect("MGET was not sent");
+            if command.args[0] == "MGET" {
+                assert_eq!(command.args[1..], ["a", "missing", "b"]);
+                break;
+            }
+            assert_ne!(command.args[0], "GET");
+        }
+        // the values found in Redis are now in memory
+        let mut in_memory = second.in_memory_keys().await;
+        in_memory.sort();
+        assert_eq!(in_memory, ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,211 @@
+//! Cache policy of responses, from their `Cache-Control` header or their cache hints
+
+use http::header::CACHE_CONTROL;
+use http::HeaderMap;
+use http::HeaderValue;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::json_ext::Object;
+use crate::json_ext::Value;
+
+/// Cache policy of a response
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct CachePolicy {
+    /// Maximum age in seconds, unbounded if `None`
+    pub(crate) max_age: Option<u64>,
+    /// The response is specific to a user
+    pub(crate) private: bool,
+    /// The response must not be stored
+    pub(crate) no_store: bool,
+}
+
+impl CachePolicy {
+    /// Cache policy of the `Cache-Control` headers, `None` if there are none. `s-maxage` takes
+    /// precedence over `max-age`, and `no-cache` counts as a `max-age` of 0.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        Self::from_values(
+            headers
+                .get_all(CACHE_CONTROL)
+                .iter()
+                .filter_map(|value| value.to_str().ok()),
+        )
+    }
+
+    /// Cache policy of `Cache-Control` values, `None` if there are none
+    pub(crate) fn from_values<'a>(values: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut values = values.into_iter().peekable();
+        values.peek()?;
+
+        let mut policy = CachePolicy::default();
+        let mut no_cache = false;
+        let mut max_age = None;
+        let mut shared_max_age = None;
+        for directive in values.flat_map(|value| value.split(',')).map(str::trim) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive, None),
+            };
+            if name.eq_ignore_ascii_case("no-store") {
+                policy.no_store = true;
+            } else if name.eq_ignore_ascii_case("no-cache") {
+                no_cache = true;
+            } else if name.eq_ignore_ascii_case("private") {
+                policy.private = true;
+            } else if name.eq_ignore_ascii_case("max-age") {
+                max_age = value.and_then(|value| value.parse().ok());
+            } else if name.eq_ignore_ascii_case("s-maxage") {
+                shared_max_age = value.and_then(|value| value.parse().ok());
+            }
+        }
+        policy.max_age = if no_cache {
+            Some(0)
+        } else {
+            shared_max_age.or(max_age)
+        };
+
+        Some(policy)
+    }
+
+    /// Cache policy of the hints in the `cacheControl` extension of a subgraph response, as
+    /// set by the `@cacheControl` directive. `None` if there are no hints.
+    pub(crate) fn from_extensions(extensions: &Object) -> Option<Self> {
+        let hints = extensions
+            .get("cacheControl")?
+            .as_object()?
+            .get("hints")?
+            .as_array()?;
+
+        let mut policy = CachePolicy::default();
+        for hint in hints.iter().filter_map(Value::as_object) {
+            if let Some(max_age) = hint.get("maxAge").and_then(Value::as_u64) {
+                policy = policy.merge(CachePolicy {
+                    max_age: Some(max_age),
+                    ..Default::default()
+                });
+            }
+            if hint.get("scope").and_then(Value::as_str) == Some("PRIVATE") {
+                policy.private = true;
+            }
+        }
+
+        Some(policy)
+    }
+
+    /// Policy of a response built from two responses: the lowest `max-age` applies
+    pub(crate) fn merge(self, other: Self) -> Self {
+        CachePolicy {
+            max_age: match (self.max_age, other.max_age) {
+                (Some(max_age), Some(other_max_age)) => Some(max_age.min(other_max_age)),
+                (max_age, other_max_age) => max_age.or(other_max_age),
+            },
+            private: self.private || other.private,
+            no_store: self.no_store || other.no_store,
+        }
+    }
+
+    pub(crate) fn is_cacheable(&self) -> bool {
+        !self.no_store && matches!(self.max_age, Some(max_age) if max_age > 0)
+    }
+
+    /// Value of the `Cache-Control` header for this policy
+    pub(crate) fn header_value(&self) -> HeaderValue {
+        match self.max_age {
+            Some(max_age) if self.is_cacheable() => {
+                let scope = if self.private { "private" } else { "public" };
+                HeaderValue::from_str(&format!("max-age={}, {}", max_age, scope))
+                    .expect("the cache policy is a valid header value; qed")
+            }
+            _ => HeaderValue::from_static("no-store"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    fn headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_policy_from_headers() {
+        assert_eq!(CachePolicy::from_headers(&HeaderMap::new()), None);
+        assert_eq!(
+            CachePolicy::from_headers(&headers("public, max-age=60")),
+            Some(CachePolicy {
+                max_age: Some(60),
+                private: false,
+                no_store: false,
+            })
+        );
+        assert_eq!(
+            CachePolicy::from_headers(&headers("max-age=60, s-maxage=120, private")),
+            Some(CachePolicy {
+                max_age: Some(120),
+                private: true,
+                no_store: false,
+            })
+        );
+        assert_eq!(
+            CachePolicy::from_headers(&headers("no-cache, max-age=60")),
+            Some(CachePolicy {
+                max_age: Some(0),
+                private: false,
+                no_store: false,
+            })
+        );
+        assert!(
+            CachePolicy::from_headers(&headers("no-store"))
+                .unwrap()
+                .no_store
+        );
+    }
+
+    #[test]
+    fn test_policy_from_extensions() {
+        let extensions = json!({
+            "cacheControl": {
+                "version": 1,
+                "hints": [
+                    { "path": ["me"], "maxAge": 120 },
+                    { "path": ["me", "name"], "maxAge": 30, "scope": "PRIVATE" },
+                ]
+            }
+        });
+        assert_eq!(
+            CachePolicy::from_extensions(extensions.as_object().unwrap()),
+            Some(CachePolicy {
+                max_age: Some(30),
+                private: true,
+                no_store: false,
+            })
+        );
+        assert_eq!(CachePolicy::from_extensions(&Object::new()), None);
+    }
+
+    #[test]
+    fn test_policy_header_value() {
+        let policy = CachePolicy {
+            max_age: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(policy.header_value(), "max-age=60, public");
+        let policy = policy.merge(CachePolicy {
+            max_age: Some(30),
+            private: true,
+            no_store: false,
+        });
+        assert_eq!(policy.header_value(), "max-age=30, private");
+        let policy = policy.merge(CachePolicy {
+            max_age: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(policy.header_value(), "no-store");
+    }
+}
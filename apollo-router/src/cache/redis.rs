@@ -1,20 +1,48 @@
 // This entire file is license key functionality
 
 use std::fmt;
+use std::io;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
+use futures::Future;
+use redis::aio::ConnectionLike;
+use redis::aio::MultiplexedConnection;
 use redis::AsyncCommands;
+use redis::ConnectionAddr;
+use redis::ConnectionInfo;
+use redis::ErrorKind;
 use redis::FromRedisValue;
+use redis::IntoConnectionInfo;
+use redis::RedisError;
+use redis::RedisFuture;
 use redis::RedisResult;
 use redis::RedisWrite;
 use redis::ToRedisArgs;
 use redis_cluster_async::Client;
 use redis_cluster_async::Connection;
+use rustls::ClientConfig;
+use rustls::ServerName;
+use tokio::net::TcpStream;
 use tokio::sync::Mutex;
+use tokio_rustls::TlsConnector;
 
 use super::KeyType;
 use super::ValueType;
+use crate::configuration::RedisCache;
+use crate::router_factory::create_certificate_store;
+
+/// Key of the set listing the keys of a namespace, see [`RedisCacheStorage::index`]
+const INDEX_KEY: &str = "index";
+/// Number of keys of the index listed by each `SSCAN`, and expired by each pipeline
+const INDEX_SCAN_COUNT: usize = 1000;
+
+/// Minimum interval between two logged command failures, the others are only logged at the
+/// debug level. All the failures are counted by the error metric.
+const ERROR_LOG_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub(crate) struct RedisKey<K>(pub(crate) K)
@@ -26,15 +54,41 @@ pub(crate) struct RedisValue<V>(pub(crate) V)
 where
     V: ValueType;
 
+enum RedisClient {
+    Single(redis::Client),
+    Cluster(Client),
+    /// Single instance with TLS using custom certificate authorities
+    Tls {
+        info: ConnectionInfo,
+        host: String,
+        port: u16,
+        connector: TlsConnector,
+    },
+}
+
+/// A multiplexed connection, shared by the commands sent through it
+#[derive(Clone)]
 enum RedisConnection {
-    Single(redis::aio::Connection),
+    Single(MultiplexedConnection),
     Cluster(Connection),
 }
 
+/// Pool of Redis connections, used in turn. A connection that was lost is reestablished by the
+/// next command using it.
+struct RedisPool {
+    client: RedisClient,
+    connections: Vec<Mutex<Option<RedisConnection>>>,
+    next: AtomicUsize,
+    last_error_log: std::sync::Mutex<Option<Instant>>,
+}
+
 #[derive(Clone)]
 pub(crate) struct RedisCacheStorage {
-    inner: Arc<Mutex<RedisConnection>>,
+    inner: Arc<RedisPool>,
+    caller: String,
+    timeout: Duration,
     ttl: Option<Duration>,
+    namespace: Option<String>,
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -111,31 +165,246 @@ where
     }
 }
 
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a redis::Cmd) -> RedisFuture<'a, redis::Value> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_command(cmd),
+            RedisConnection::Cluster(conn) => conn.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a redis::Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<redis::Value>> {
+        match self {
+            RedisConnection::Single(conn) => conn.req_packed_commands(cmd, offset, count),
+            RedisConnection::Cluster(conn) => conn.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Single(conn) => conn.get_db(),
+            RedisConnection::Cluster(conn) => conn.get_db(),
+        }
+    }
+}
+
+impl RedisClient {
+    fn new(config: &RedisCache) -> RedisResult<Self> {
+        let mut infos = config
+            .urls
+            .iter()
+            .map(|url| {
+                let mut info = url.as_str().into_connection_info()?;
+                if let Some(username) = &config.username {
+                    info.redis.username = Some(username.clone());
+                }
+                if let Some(password) = &config.password {
+                    info.redis.password = Some(password.clone());
+                }
+                Ok(info)
+            })
+            .collect::<RedisResult<Vec<_>>>()?;
+
+        match (infos.len(), &config.tls) {
+            (1, None) => Ok(RedisClient::Single(redis::Client::open(infos.remove(0))?)),
+            (1, Some(tls)) => {
+                let info = infos.remove(0);
+                let (host, port) = match &info.addr {
+                    ConnectionAddr::TcpTls { host, port, .. } => (host.clone(), *port),
+                    _ => {
+                        return Err(RedisError::from((
+                            ErrorKind::InvalidClientConfig,
+                            "TLS options require a rediss:// URL",
+                        )))
+                    }
+                };
+                let store =
+                    create_certificate_store(&tls.certificate_authorities).map_err(|err| {
+                        RedisError::from((
+                            ErrorKind::InvalidClientConfig,
+                            "invalid certificate authorities",
+                            err.to_string(),
+                        ))
+                    })?;
+                let tls_config = ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_root_certificates(store)
+                    .with_no_client_auth();
+
+                Ok(RedisClient::Tls {
+                    info,
+                    host,
+                    port,
+                    connector: TlsConnector::from(Arc::new(tls_config)),
+                })
+            }
+            (_, None) => Ok(RedisClient::Cluster(Client::open(infos)?)),
+            (_, Some(_)) => Err(RedisError::from((
+                ErrorKind::InvalidClientConfig,
+                "custom certificate authorities are only supported with a single Redis URL",
+            ))),
+        }
+    }
+
+    async fn connect(&self) -> RedisResult<RedisConnection> {
+        Ok(match self {
+            RedisClient::Single(client) => {
+                RedisConnection::Single(client.get_multiplexed_tokio_connection().await?)
+            }
+            RedisClient::Cluster(client) => {
+                RedisConnection::Cluster(client.get_connection().await?)
+            }
+            RedisClient::Tls {
+                info,
+                host,
+                port,
+                connector,
+            } => {
+                let server_name = ServerName::try_from(host.as_str()).map_err(|err| {
+                    RedisError::from((
+                        ErrorKind::InvalidClientConfig,
+                        "invalid Redis host name",
+                        err.to_string(),
+                    ))
+                })?;
+                let stream = TcpStream::connect((host.as_str(), *port)).await?;
+                let stream = connector.connect(server_name, stream).await?;
+                let (connection, driver) = MultiplexedConnection::new(&info.redis, stream).await?;
+                tokio::spawn(driver);
+                RedisConnection::Single(connection)
+            }
+        })
+    }
+}
+
+impl RedisPool {
+    /// Get the connection at `index`, reconnecting if it was lost
+    async fn connection(&self, index: usize) -> RedisResult<RedisConnection> {
+        let mut slot = self.connections[index].lock().await;
+        match &*slot {
+            Some(connection) => Ok(connection.clone()),
+            None => {
+                tracing::debug!("reconnecting to redis");
+                let connection = self.client.connect().await?;
+                *slot = Some(connection.clone());
+                Ok(connection)
+            }
+        }
+    }
+
+    /// Returns `true` if a command failure should be logged as an error, at most once per
+    /// [`ERROR_LOG_INTERVAL`]
+    fn should_log_error(&self) -> bool {
+        let mut last_error_log = self.last_error_log.lock().expect("lock poisoned");
+        let now = Instant::now();
+        match *last_error_log {
+            Some(last) if now.duration_since(last) < ERROR_LOG_INTERVAL => false,
+            _ => {
+                *last_error_log = Some(now);
+                true
+            }
+        }
+    }
+}
+
 impl RedisCacheStorage {
-    pub(crate) async fn new(
-        mut urls: Vec<String>,
-        ttl: Option<Duration>,
-    ) -> Result<Self, redis::RedisError> {
-        let connection = if urls.len() == 1 {
-            let client = redis::Client::open(urls.pop().expect("urls contains only one url; qed"))?;
-            let connection = client.get_async_connection().await?;
-            RedisConnection::Single(connection)
-        } else {
-            let client = Client::open(urls)?;
-            let connection = client.get_connection().await?;
-            RedisConnection::Cluster(connection)
-        };
+    pub(crate) async fn new(config: &RedisCache, caller: &str) -> Result<Self, redis::RedisError> {
+        // the entries are stored with a time to live in milliseconds
+        if matches!(config.ttl, Some(ttl) if ttl.as_millis() == 0) {
+            return Err(RedisError::from((
+                ErrorKind::InvalidClientConfig,
+                "the TTL of Redis entries must be at least 1ms",
+            )));
+        }
+        let client = RedisClient::new(config)?;
+
+        let mut connections = Vec::with_capacity(config.pool_size.get());
+        for _ in 0..config.pool_size.get() {
+            let connection = tokio::time::timeout(config.timeout, client.connect())
+                .await
+                .map_err(|_| timeout_error())??;
+            connections.push(Mutex::new(Some(connection)));
+        }
 
-        tracing::trace!("redis connection established");
+        tracing::trace!("redis connections established");
         Ok(Self {
-            inner: Arc::new(Mutex::new(connection)),
-            ttl,
+            inner: Arc::new(RedisPool {
+                client,
+                connections,
+                next: AtomicUsize::new(0),
+                last_error_log: Default::default(),
+            }),
+            caller: caller.to_string(),
+            timeout: config.timeout,
+            ttl: config.ttl,
+            namespace: config.namespace.clone(),
         })
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn set_ttl(&mut self, ttl: Option<Duration>) {
-        self.ttl = ttl;
+    /// The key in Redis, prefixed by the namespace
+    fn make_key<K: KeyType>(&self, key: RedisKey<K>) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}:{}", namespace, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// Run a command on the next connection of the pool, within the configured timeout
+    async fn run<T, F, Fut>(&self, command: &'static str, f: F) -> RedisResult<T>
+    where
+        F: FnOnce(RedisConnection) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let start = Instant::now();
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed) % self.inner.connections.len();
+        let result = tokio::time::timeout(self.timeout, async {
+            let connection = self.inner.connection(index).await?;
+            f(connection).await
+        })
+        .await
+        .unwrap_or_else(|_| Err(timeout_error()));
+
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            histogram.apollo_router_cache_redis_command_time = start.elapsed().as_secs_f64(),
+            kind = %self.caller,
+            command = command,
+        );
+        if let Err(err) = &result {
+            if self.inner.should_log_error() {
+                tracing::error!(
+                    "redis {} command failed for {} caching: {}",
+                    command,
+                    self.caller,
+                    err
+                );
+            } else {
+                tracing::debug!(
+                    "redis {} command failed for {} caching: {}",
+                    command,
+                    self.caller,
+                    err
+                );
+            }
+            // This is a metric and will not appear in the logs
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_redis_error_count = 1u64,
+                kind = %self.caller,
+                command = command,
+            );
+            // A command timing out is treated like a lost connection: the connection or the
+            // server is stalled, and the next command using this connection will reconnect
+            if err.is_connection_dropped() || err.is_connection_refusal() || err.is_io_error() {
+                *self.inner.connections[index].lock().await = None;
+            }
+        }
+
+        result
     }
 
     pub(crate) async fn get<K: KeyType, V: ValueType>(
@@ -143,62 +412,30 @@ impl RedisCacheStorage {
         key: RedisKey<K>,
     ) -> Option<RedisValue<V>> {
         tracing::trace!("getting from redis: {:?}", key);
-        let mut guard = self.inner.lock().await;
-
-        match &mut *guard {
-            RedisConnection::Single(conn) => conn.get(key).await.ok(),
-            RedisConnection::Cluster(conn) => conn.get(key).await.ok(),
-        }
+        let key = self.make_key(key);
+        self.run("get", |mut conn| async move { conn.get(key).await })
+            .await
+            .ok()
+            .flatten()
     }
 
-    #[allow(dead_code)]
     pub(crate) async fn get_multiple<K: KeyType, V: ValueType>(
         &self,
         keys: Vec<RedisKey<K>>,
     ) -> Option<Vec<Option<RedisValue<V>>>> {
         tracing::trace!("getting multiple values from redis: {:?}", keys);
-        let mut guard = self.inner.lock().await;
+        let redis_keys: Vec<String> = keys.iter().cloned().map(|key| self.make_key(key)).collect();
 
-        let res = if keys.len() == 1 {
-            let res = match &mut *guard {
-                RedisConnection::Single(conn) => conn
-                    .get(keys.first().unwrap())
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("mget error: {}", e);
-                        e
-                    })
-                    .ok(),
-                RedisConnection::Cluster(conn) => conn
-                    .get(keys.first().unwrap())
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("mget error: {}", e);
-                        e
-                    })
-                    .ok(),
-            };
-
-            Some(vec![res])
+        let res = if redis_keys.len() == 1 {
+            let key = redis_keys[0].clone();
+            self.run("get", |mut conn| async move { conn.get(key).await })
+                .await
+                .ok()
+                .map(|value| vec![value])
         } else {
-            match &mut *guard {
-                RedisConnection::Single(conn) => conn
-                    .get::<Vec<RedisKey<K>>, Vec<Option<RedisValue<V>>>>(keys.clone())
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("mget error: {}", e);
-                        e
-                    })
-                    .ok(),
-                RedisConnection::Cluster(conn) => conn
-                    .get::<Vec<RedisKey<K>>, Vec<Option<RedisValue<V>>>>(keys.clone())
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("mget error: {}", e);
-                        e
-                    })
-                    .ok(),
-            }
+            self.run("mget", |mut conn| async move { conn.get(redis_keys).await })
+                .await
+                .ok()
         };
         tracing::trace!("result for '{:?}': {:?}", keys, res);
 
@@ -211,15 +448,25 @@ impl RedisCacheStorage {
         value: RedisValue<V>,
     ) {
         tracing::trace!("inserting into redis: {:?}, {:?}", key, value);
-        let mut guard = self.inner.lock().await;
-        let r = match &mut *guard {
-            RedisConnection::Single(conn) => {
-                conn.set::<RedisKey<K>, RedisValue<V>, redis::Value>(key, value)
+        let key = self.make_key(key);
+        let r = match self.ttl {
+            Some(ttl) => {
+                self.run("pset_ex", |mut conn| async move {
+                    conn.pset_ex::<String, RedisValue<V>, redis::Value>(
+                        key,
+                        value,
+                        ttl.as_millis() as usize,
+                    )
                     .await
+                })
+                .await
             }
-            RedisConnection::Cluster(conn) => {
-                conn.set::<RedisKey<K>, RedisValue<V>, redis::Value>(key, value)
-                    .await
+            None => {
+                self.run("set", |mut conn| async move {
+                    conn.set::<String, RedisValue<V>, redis::Value>(key, value)
+                        .await
+                })
+                .await
             }
         };
         tracing::trace!("insert result {:?}", r);
@@ -231,44 +478,412 @@ impl RedisCacheStorage {
         data: &[(RedisKey<K>, RedisValue<V>)],
     ) {
         tracing::trace!("inserting into redis: {:#?}", data);
+        let data: Vec<(String, &RedisValue<V>)> = data
+            .iter()
+            .map(|(key, value)| (self.make_key(key.clone()), value))
+            .collect();
 
-        if let Some(ttl) = self.ttl.as_ref() {
-            let expiration: usize = ttl.as_secs().try_into().unwrap();
+        let r = if let Some(ttl) = self.ttl.as_ref() {
+            let expiration = ttl.as_millis() as usize;
             let mut pipeline = redis::pipe();
             pipeline.atomic();
 
-            for (key, value) in data {
-                pipeline.set_ex(key, value, expiration);
+            for (key, value) in &data {
+                pipeline.pset_ex(key, *value, expiration);
             }
 
-            let mut guard = self.inner.lock().await;
+            self.run("pset_ex", |mut conn| async move {
+                pipeline.query_async::<_, redis::Value>(&mut conn).await
+            })
+            .await
+        } else {
+            self.run("mset", |mut conn| async move {
+                conn.set_multiple::<String, &RedisValue<V>, redis::Value>(&data)
+                    .await
+            })
+            .await
+        };
+        tracing::trace!("insert result {:?}", r);
+    }
 
-            let r = match &mut *guard {
-                RedisConnection::Single(conn) => {
-                    pipeline
-                        .query_async::<redis::aio::Connection, redis::Value>(conn)
-                        .await
-                }
-                RedisConnection::Cluster(conn) => {
-                    pipeline.query_async::<Connection, redis::Value>(conn).await
-                }
-            };
+    /// Increments the counter stored at `key` and sets its expiration, returning the new value
+    /// of the counter
+    pub(crate) async fn incr<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        expiration: Duration,
+    ) -> RedisResult<u64> {
+        tracing::trace!("incrementing in redis: {:?}", key);
+        let key = self.make_key(key);
+        let mut pipeline = redis::pipe();
+        pipeline
+            .atomic()
+            .incr(&key, 1u64)
+            .pexpire(&key, expiration.as_millis() as usize)
+            .ignore();
 
-            tracing::trace!("insert result {:?}", r);
-        } else {
-            let mut guard = self.inner.lock().await;
+        let (count,): (u64,) = self
+            .run("incr", |mut conn| async move {
+                pipeline.query_async(&mut conn).await
+            })
+            .await?;
+        Ok(count)
+    }
 
-            let r = match &mut *guard {
-                RedisConnection::Single(conn) => {
-                    conn.set_multiple::<RedisKey<K>, RedisValue<V>, redis::Value>(data)
-                        .await
-                }
-                RedisConnection::Cluster(conn) => {
-                    conn.set_multiple::<RedisKey<K>, RedisValue<V>, redis::Value>(data)
+    /// Gets the value of the counter stored at `key`
+    pub(crate) async fn get_counter<K: KeyType>(&self, key: RedisKey<K>) -> RedisResult<u64> {
+        tracing::trace!("getting counter from redis: {:?}", key);
+        let key = self.make_key(key);
+        let count: Option<u64> = self
+            .run("get", |mut conn| async move { conn.get(key).await })
+            .await?;
+        Ok(count.unwrap_or_default())
+    }
+
+    /// Sets `key` if it does not exist yet, with an expiration. Returns `true` if it was set:
+    /// the key is then a lock held by this router until it expires
+    pub(crate) async fn lock<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        expiration: Duration,
+    ) -> RedisResult<bool> {
+        tracing::trace!("locking in redis: {:?}", key);
+        let key = self.make_key(key);
+        let mut command = redis::cmd("SET");
+        command
+            .arg(key)
+            .arg(1u8)
+            .arg("NX")
+            .arg("PX")
+            .arg(expiration.as_millis() as usize);
+
+        let result: Option<String> = self
+            .run("set_nx", |mut conn| async move {
+                command.query_async(&mut conn).await
+            })
+            .await?;
+        Ok(result.is_some())
+    }
+
+    /// Adds `key` to the set listing the keys of the namespace, used to expire them all at once
+    pub(crate) async fn index<K: KeyType>(&self, key: RedisKey<K>) {
+        tracing::trace!("indexing in redis: {:?}", key);
+        let key = self.make_key(key);
+        let mut pipeline = redis::pipe();
+        self.index_commands(&mut pipeline, &key);
+
+        let r = self
+            .run("sadd", |mut conn| async move {
+                pipeline.query_async::<_, ()>(&mut conn).await
+            })
+            .await;
+        tracing::trace!("index result {:?}", r);
+    }
+
+    /// Inserts the value and adds its key to the index, see [`Self::index`]. Both commands are
+    /// sent in a single pipeline, except on a cluster where the index can be on another node.
+    pub(crate) async fn insert_indexed<K: KeyType, V: ValueType>(
+        &self,
+        key: RedisKey<K>,
+        value: RedisValue<V>,
+    ) {
+        if matches!(self.inner.client, RedisClient::Cluster(_)) {
+            futures::join!(self.insert(key.clone(), value), self.index(key));
+            return;
+        }
+
+        tracing::trace!("inserting and indexing into redis: {:?}, {:?}", key, value);
+        let key = self.make_key(key);
+        let mut pipeline = redis::pipe();
+        match self.ttl {
+            Some(ttl) => pipeline
+                .pset_ex(&key, value, ttl.as_millis() as usize)
+                .ignore(),
+            None => pipeline.set(&key, value).ignore(),
+        };
+        self.index_commands(&mut pipeline, &key);
+
+        let r = self
+            .run("set_sadd", |mut conn| async move {
+                pipeline.query_async::<_, ()>(&mut conn).await
+            })
+            .await;
+        tracing::trace!("insert result {:?}", r);
+    }
+
+    /// Adds the commands indexing `key`, already prefixed by the namespace, to the pipeline
+    fn index_commands(&self, pipeline: &mut redis::Pipeline, key: &str) {
+        let index = self.make_key(RedisKey(INDEX_KEY.to_string()));
+        pipeline.sadd(&index, key).ignore();
+        // the index does not need to outlive the keys it lists
+        if let Some(ttl) = self.ttl {
+            pipeline.pexpire(&index, ttl.as_millis() as usize).ignore();
+        }
+    }
+
+    /// Sets the expiration of the keys listed by [`Self::index`], and of the index itself.
+    /// Returns the number of keys.
+    pub(crate) async fn expire_index(&self, expiration: Duration) -> RedisResult<usize> {
+        let index = self.make_key(RedisKey(INDEX_KEY.to_string()));
+        let expiration = expiration.as_millis() as usize;
+
+        // the index expires first, so that it does not outlive its keys if this fails midway
+        let index_key = index.clone();
+        self.run("pexpire", |mut conn| async move {
+            conn.pexpire::<_, ()>(index_key, expiration).await
+        })
+        .await?;
+
+        // the index is scanned in batches, as it can list many keys
+        let mut count = 0;
+        let mut cursor: u64 = 0;
+        loop {
+            let index = index.clone();
+            let (next, keys): (u64, Vec<String>) = self
+                .run("sscan", |mut conn| async move {
+                    redis::cmd("SSCAN")
+                        .arg(index)
+                        .arg(cursor)
+                        .arg("COUNT")
+                        .arg(INDEX_SCAN_COUNT)
+                        .query_async(&mut conn)
                         .await
+                })
+                .await?;
+            count += keys.len();
+            self.expire_keys(keys, expiration).await?;
+
+            if next == 0 {
+                return Ok(count);
+            }
+            cursor = next;
+        }
+    }
+
+    /// Sets the expiration of keys, already prefixed by the namespace, in a single pipeline. On
+    /// a cluster, where the keys can be on different nodes, the commands are sent concurrently
+    /// and each of them goes to the node of its key.
+    async fn expire_keys(&self, keys: Vec<String>, expiration: usize) -> RedisResult<()> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        if matches!(self.inner.client, RedisClient::Cluster(_)) {
+            futures::future::try_join_all(keys.into_iter().map(|key| {
+                self.run("pexpire", move |mut conn| async move {
+                    conn.pexpire::<_, ()>(key, expiration).await
+                })
+            }))
+            .await?;
+            return Ok(());
+        }
+
+        let mut pipeline = redis::pipe();
+        for key in &keys {
+            pipeline.pexpire(key, expiration).ignore();
+        }
+        self.run("pexpire", |mut conn| async move {
+            pipeline.query_async::<_, ()>(&mut conn).await
+        })
+        .await
+    }
+}
+
+fn timeout_error() -> RedisError {
+    io::Error::new(io::ErrorKind::TimedOut, "redis command timed out").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::cache::mock_redis::MockRedis;
+    use crate::configuration::RedisTls;
+
+    fn config(url: &str, pool_size: usize) -> RedisCache {
+        let mut config = RedisCache::new(vec![url.to_string()]);
+        config.pool_size = pool_size.try_into().unwrap();
+        config.timeout = Duration::from_millis(100);
+        config
+    }
+
+    #[tokio::test]
+    async fn it_sends_commands_to_the_connections_of_the_pool_in_turn() {
+        let mut redis = MockRedis::start().await;
+        let storage = RedisCacheStorage::new(&config(&redis.url, 2), "test")
+            .await
+            .unwrap();
+
+        for expected in [0, 1, 0, 1] {
+            let value: Option<RedisValue<String>> = storage.get(RedisKey("key".to_string())).await;
+            assert!(value.is_none());
+            assert_eq!(redis.next_command().await.connection, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reconnects_after_a_timeout() {
+        let mut redis = MockRedis::stalled().await;
+        let storage = RedisCacheStorage::new(&config(&redis.url, 2), "test")
+            .await
+            .unwrap();
+
+        // the commands time out, and the connections they used are replaced
+        for expected in [0, 1, 2, 3] {
+            let start = Instant::now();
+            let value: Option<RedisValue<String>> = storage.get(RedisKey("key".to_string())).await;
+            assert!(value.is_none());
+            assert!(start.elapsed() < Duration::from_secs(1));
+            assert_eq!(redis.next_command().await.connection, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fails_to_connect_to_an_unreachable_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        assert!(RedisCacheStorage::new(&config(&url, 1), "test")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn it_rate_limits_the_error_logs() {
+        let redis = MockRedis::stalled().await;
+        let storage = RedisCacheStorage::new(&config(&redis.url, 1), "test")
+            .await
+            .unwrap();
+
+        assert!(storage.inner.should_log_error());
+        assert!(!storage.inner.should_log_error());
+    }
+
+    #[tokio::test]
+    async fn it_pipelines_the_insertion_and_the_indexing() {
+        let mut redis = MockRedis::start().await;
+        let mut config = config(&redis.url, 1);
+        config.namespace = Some("namespace".to_string());
+        let storage = RedisCacheStorage::new(&config, "test").await.unwrap();
+
+        storage
+            .insert_indexed(RedisKey("key".to_string()), RedisValue("value".to_string()))
+            .await;
+
+        assert_eq!(redis.next_command().await.args[0], "SET");
+        assert_eq!(
+            redis.next_command().await.args,
+            ["SADD", "namespace:index", "namespace:key"]
+        );
+        assert!(redis.entry("namespace:key").is_some());
+    }
+
+    #[tokio::test]
+    async fn it_stores_entries_with_a_ttl_in_milliseconds() {
+        let redis = MockRedis::start().await;
+        let mut config = config(&redis.url, 1);
+        config.ttl = Some(Duration::from_millis(500));
+        let storage = RedisCacheStorage::new(&config, "test").await.unwrap();
+
+        storage
+            .insert(RedisKey("key".to_string()), RedisValue("value".to_string()))
+            .await;
+
+        assert_eq!(redis.entry("key").unwrap().expiration, Some(500));
+    }
+
+    #[tokio::test]
+    async fn it_rejects_a_ttl_under_a_millisecond() {
+        let redis = MockRedis::start().await;
+        let mut config = config(&redis.url, 1);
+        config.ttl = Some(Duration::from_micros(500));
+
+        assert!(RedisCacheStorage::new(&config, "test").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_prefixes_the_keys_with_the_namespace() {
+        let redis = MockRedis::start().await;
+        let storage = RedisCacheStorage::new(&config(&redis.url, 1), "test")
+            .await
+            .unwrap();
+        assert_eq!(storage.make_key(RedisKey("key".to_string())), "key");
+
+        let mut config = config(&redis.url, 1);
+        config.namespace = Some("namespace".to_string());
+        let storage = RedisCacheStorage::new(&config, "test").await.unwrap();
+        assert_eq!(
+            storage.make_key(RedisKey("key".to_string())),
+            "namespace:key"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_client_configurations() {
+        let tls = Some(RedisTls {
+            certificate_authorities: String::new(),
+        });
+
+        let mut config = RedisCache::new(vec!["redis://localhost:6379".to_string()]);
+        config.tls = tls.clone();
+        let err = RedisClient::new(&config).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+        assert!(err.to_string().contains("rediss://"));
+
+        let mut config = RedisCache::new(vec![
+            "rediss://localhost:6379".to_string(),
+            "rediss://localhost:6380".to_string(),
+        ]);
+        config.tls = tls;
+        let err = RedisClient::new(&config).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidClientConfig);
+        assert!(err.to_string().contains("single Redis URL"));
+
+        let config = RedisCache::new(vec!["http://localhost:6379".to_string()]);
+        assert!(RedisClient::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_expires_the_index_in_batches() {
+        let mut redis = MockRedis::start().await;
+        let storage = RedisCacheStorage::new(&RedisCache::new(vec![redis.url.clone()]), "test")
+            .await
+            .unwrap();
+
+        let keys = INDEX_SCAN_COUNT * 2 + 1;
+        for key in 0..keys {
+            storage
+                .insert_indexed(RedisKey(key.to_string()), RedisValue(key.to_string()))
+                .await;
+        }
+        assert_eq!(
+            storage.expire_index(Duration::from_secs(60)).await.unwrap(),
+            keys
+        );
+        for key in (0..keys)
+            .map(|key| key.to_string())
+            .chain(["index".to_string()])
+        {
+            assert_eq!(redis.entry(&key).unwrap().expiration, Some(60_000));
+        }
+
+        // the index is listed in batches rather than all at once
+        let mut scans = 0;
+        let mut expirations = 0;
+        while scans < 3 || expirations < keys {
+            let command = redis.next_command().await;
+            match command.args[0].as_str() {
+                "SSCAN" => {
+                    assert_eq!(command.args[3], INDEX_SCAN_COUNT.to_string());
+                    scans += 1;
                 }
-            };
-            tracing::trace!("insert result {:?}", r);
+                "PEXPIRE" if command.args[1] != "index" => expirations += 1,
+                "SMEMBERS" => panic!("the whole index was listed"),
+                _ => {}
+            }
         }
+        assert_eq!(scans, 3);
     }
 }
@@ -0,0 +1,56 @@
+//! Helpers shared by the plugins caching responses
+
+use std::future::Future;
+use std::task::Poll;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use tower::Service;
+
+/// Service handing each request to a cache, with the inner service made ready for it. The cache
+/// future owns the inner service, so that it can call it after a cache miss.
+pub(crate) struct CacheService<S, F> {
+    inner: S,
+    call: F,
+}
+
+impl<S, F> CacheService<S, F> {
+    /// `inner` is cloned for each request, so it should be buffered
+    pub(crate) fn new<Request, Fut>(inner: S, call: F) -> Self
+    where
+        F: FnMut(S, Request) -> Fut,
+    {
+        Self { inner, call }
+    }
+}
+
+impl<S, F, Fut, Request> Service<Request> for CacheService<S, F>
+where
+    S: Service<Request> + Clone,
+    F: FnMut(S, Request) -> Fut,
+    Fut: Future<Output = Result<S::Response, S::Error>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Fut;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The ready service handles this request, its clone will be made ready for the next one
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+
+        (self.call)(inner, request)
+    }
+}
+
+/// Current UNIX timestamp in seconds, for the expiration of cached entries
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time must be after EPOCH")
+        .as_secs()
+}
@@ -14,6 +14,8 @@ use tokio::time::Instant;
 
 #[cfg(feature = "experimental_cache")]
 use super::redis::*;
+#[cfg(feature = "experimental_cache")]
+use crate::configuration::RedisCache;
 
 pub(crate) trait KeyType:
     Clone + fmt::Debug + fmt::Display + Hash + Eq + Send + Sync
@@ -61,15 +63,15 @@ where
 {
     pub(crate) async fn new(
         max_capacity: NonZeroUsize,
-        _redis_urls: Option<Vec<String>>,
+        #[cfg(feature = "experimental_cache")] redis: Option<RedisCache>,
         caller: &str,
     ) -> Self {
         Self {
             caller: caller.to_string(),
             inner: Arc::new(Mutex::new(LruCache::new(max_capacity))),
             #[cfg(feature = "experimental_cache")]
-            redis: if let Some(urls) = _redis_urls {
-                match RedisCacheStorage::new(urls, None).await {
+            redis: if let Some(redis) = redis {
+                match RedisCacheStorage::new(&redis, caller).await {
                     Err(e) => {
                         tracing::error!(
                             "could not open connection to Redis for {} caching: {:?}",
@@ -175,6 +177,84 @@ where
         }
     }
 
+    /// Gets the values of several keys, in the order of the keys. The keys missing from memory
+    /// are fetched from Redis in a single batch.
+    pub(crate) async fn get_multiple(&self, keys: &[K]) -> Vec<Option<V>> {
+        #[cfg_attr(not(feature = "experimental_cache"), allow(unused_mut))]
+        let mut values: Vec<Option<V>> = {
+            let mut guard = self.inner.lock().await;
+            keys.iter().map(|key| guard.get(key).cloned()).collect()
+        };
+        let missing: Vec<usize> = values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| value.is_none().then_some(index))
+            .collect();
+        self.record_lookups(
+            CacheStorageName::Memory,
+            keys.len() - missing.len(),
+            missing.len(),
+        );
+
+        #[cfg(feature = "experimental_cache")]
+        if !missing.is_empty() {
+            self.get_multiple_from_redis(keys, &missing, &mut values)
+                .await;
+        }
+
+        values
+    }
+
+    /// Fills the values of the keys at the `missing` indexes from Redis
+    #[cfg(feature = "experimental_cache")]
+    async fn get_multiple_from_redis(
+        &self,
+        keys: &[K],
+        missing: &[usize],
+        values: &mut [Option<V>],
+    ) {
+        let redis = match self.redis.as_ref() {
+            Some(redis) => redis,
+            None => return,
+        };
+        let redis_keys = missing
+            .iter()
+            .map(|index| RedisKey(keys[*index].clone()))
+            .collect();
+        let found = redis
+            .get_multiple::<K, V>(redis_keys)
+            .await
+            .unwrap_or_default();
+
+        let mut guard = self.inner.lock().await;
+        let mut hits = 0;
+        for (index, value) in missing.iter().zip(found) {
+            if let Some(value) = value {
+                guard.put(keys[*index].clone(), value.0.clone());
+                values[*index] = Some(value.0);
+                hits += 1;
+            }
+        }
+        self.record_lookups(CacheStorageName::Redis, hits, missing.len() - hits);
+    }
+
+    fn record_lookups(&self, storage: CacheStorageName, hits: usize, misses: usize) {
+        if hits > 0 {
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_hit_count = hits as u64,
+                kind = %self.caller,
+                storage = &tracing::field::display(storage),
+            );
+        }
+        if misses > 0 {
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_miss_count = misses as u64,
+                kind = %self.caller,
+                storage = &tracing::field::display(storage),
+            );
+        }
+    }
+
     pub(crate) async fn insert(&self, key: K, value: V) {
         #[cfg(feature = "experimental_cache")]
         if let Some(redis) = self.redis.as_ref() {
@@ -186,6 +266,24 @@ where
         self.inner.lock().await.put(key, value);
     }
 
+    /// Inserts the value, and adds its key to the index of the Redis namespace, see
+    /// [`RedisCacheStorage::index`]
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) async fn insert_indexed(&self, key: K, value: V) {
+        if let Some(redis) = self.redis.as_ref() {
+            redis
+                .insert_indexed(RedisKey(key.clone()), RedisValue(value.clone()))
+                .await;
+        }
+
+        self.inner.lock().await.put(key, value);
+    }
+
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn redis(&self) -> Option<&RedisCacheStorage> {
+        self.redis.as_ref()
+    }
+
     pub(crate) async fn in_memory_keys(&self) -> Vec<K> {
         self.inner
             .lock()
@@ -201,6 +299,7 @@ where
     }
 }
 
+#[derive(Clone, Copy)]
 enum CacheStorageName {
     #[cfg(feature = "experimental_cache")]
     Redis,
@@ -14,10 +14,13 @@ use std::fmt;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use derivative::Derivative;
 use displaydoc::Display;
+use http::header::CACHE_CONTROL;
 use itertools::Itertools;
 use schemars::gen::SchemaGenerator;
 use schemars::schema::ObjectValidation;
@@ -72,6 +75,12 @@ pub enum ConfigurationError {
 
     /// could not load certificate authorities: {error}
     CertificateAuthorities { error: String },
+
+    /// could not load client certificate: {error}
+    ClientCertificate { error: String },
+
+    /// could not load the supergraph TLS configuration: {error}
+    ServerCertificate { error: String },
 }
 
 /// The configuration for the router.
@@ -113,6 +122,14 @@ pub struct Configuration {
     #[serde(default)]
     pub(crate) tls: Tls,
 
+    /// Batching configuration
+    #[serde(default)]
+    pub(crate) batching: Batching,
+
+    /// Limits on the size of operations
+    #[serde(default)]
+    pub(crate) operation_limits: OperationLimits,
+
     /// Plugin configuration
     #[serde(default)]
     plugins: UserPlugins,
@@ -152,6 +169,10 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             apollo_plugins: ApolloPlugins,
             #[serde(default)]
             tls: Tls,
+            #[serde(default)]
+            batching: Batching,
+            #[serde(default)]
+            operation_limits: OperationLimits,
         }
         let ad_hoc: AdHocConfiguration = serde::Deserialize::deserialize(deserializer)?;
 
@@ -165,6 +186,8 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             .plugins(ad_hoc.plugins.plugins.unwrap_or_default())
             .apollo_plugins(ad_hoc.apollo_plugins.plugins)
             .tls(ad_hoc.tls)
+            .batching(ad_hoc.batching)
+            .operation_limits(ad_hoc.operation_limits)
             .build()
             .map_err(|e| serde::de::Error::custom(e.to_string()))
     }
@@ -197,6 +220,8 @@ impl Configuration {
         apollo_plugins: Map<String, Value>,
         dev: Option<bool>,
         tls: Option<Tls>,
+        batching: Option<Batching>,
+        operation_limits: Option<OperationLimits>,
     ) -> Result<Self, ConfigurationError> {
         let mut conf = Self {
             validated_yaml: Default::default(),
@@ -213,6 +238,8 @@ impl Configuration {
                 plugins: apollo_plugins,
             },
             tls: tls.unwrap_or_default(),
+            batching: batching.unwrap_or_default(),
+            operation_limits: operation_limits.unwrap_or_default(),
         };
         if dev.unwrap_or_default()
             || std::env::var(APOLLO_ROUTER_DEV_ENV).ok().as_deref() == Some("true")
@@ -334,6 +361,8 @@ impl Configuration {
         apollo_plugins: Map<String, Value>,
         dev: Option<bool>,
         tls: Option<Tls>,
+        batching: Option<Batching>,
+        operation_limits: Option<OperationLimits>,
     ) -> Result<Self, ConfigurationError> {
         let mut configuration = Self {
             validated_yaml: Default::default(),
@@ -350,6 +379,8 @@ impl Configuration {
                 plugins: apollo_plugins,
             },
             tls: tls.unwrap_or_default(),
+            batching: batching.unwrap_or_default(),
+            operation_limits: operation_limits.unwrap_or_default(),
         };
         if dev.unwrap_or_default()
             || std::env::var(APOLLO_ROUTER_DEV_ENV).ok().as_deref() == Some("true")
@@ -407,6 +438,35 @@ impl Configuration {
                 },
             );
         }
+        if self.supergraph.tls.is_some()
+            && !matches!(self.supergraph.listen, ListenAddr::SocketAddr(_))
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'supergraph.tls' configuration",
+                error: "TLS termination is only supported when listening on a socket address"
+                    .to_string(),
+            });
+        }
+        if self.batching.max_size == Some(0) {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'batching.max_size' configuration",
+                error: "the maximum batch size must be greater than 0".to_string(),
+            });
+        }
+        // The `Cache-Control` header of client responses has a single owner
+        let plugins = &self.apollo_plugins.plugins;
+        if let (Some(response_cache), Some(headers)) =
+            (plugins.get("response_cache"), plugins.get("headers"))
+        {
+            if crate::plugins::response_cache::sets_cache_control(response_cache)
+                && crate::plugins::headers::sets_response_header(headers, &CACHE_CONTROL)
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'headers' configuration",
+                    error: "the Cache-Control header of client responses is set by the response_cache plugin, remove the headers rules setting it or disable 'response_cache.cache_control'".to_string(),
+                });
+            }
+        }
 
         Ok(self)
     }
@@ -515,23 +575,43 @@ pub(crate) struct Supergraph {
     #[serde(default = "default_graphql_introspection")]
     pub(crate) introspection: bool,
 
+    /// Introspection access rules and limits, when introspection is enabled
+    #[serde(default)]
+    pub(crate) introspection_options: IntrospectionOptions,
+
     /// Set to false to disable defer support
     #[serde(default = "default_defer_support")]
     pub(crate) defer_support: bool,
 
+    /// Set to false to disable subscription support
+    #[serde(default = "default_subscription_support")]
+    pub(crate) subscription_support: bool,
+
     /// Configures automatic persisted queries
     #[serde(default)]
     pub(crate) apq: Apq,
 
+    /// Configures persisted queries and the safelist of operations
+    #[serde(default)]
+    pub(crate) persisted_queries: PersistedQueries,
+
     /// Query planning options
     #[serde(default)]
     pub(crate) query_planning: QueryPlanning,
+
+    /// TLS termination on the supergraph listener
+    #[serde(default)]
+    pub(crate) tls: Option<TlsSupergraph>,
 }
 
 fn default_defer_support() -> bool {
     true
 }
 
+fn default_subscription_support() -> bool {
+    true
+}
+
 #[buildstructor::buildstructor]
 impl Supergraph {
     #[builder]
@@ -539,17 +619,25 @@ impl Supergraph {
         listen: Option<ListenAddr>,
         path: Option<String>,
         introspection: Option<bool>,
+        introspection_options: Option<IntrospectionOptions>,
         defer_support: Option<bool>,
+        subscription_support: Option<bool>,
         apq: Option<Apq>,
+        persisted_queries: Option<PersistedQueries>,
         query_planning: Option<QueryPlanning>,
+        tls: Option<TlsSupergraph>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_graphql_listen),
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
+            introspection_options: introspection_options.unwrap_or_default(),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
+            subscription_support: subscription_support.unwrap_or_else(default_subscription_support),
             apq: apq.unwrap_or_default(),
+            persisted_queries: persisted_queries.unwrap_or_default(),
             query_planning: query_planning.unwrap_or_default(),
+            tls,
         }
     }
 }
@@ -562,17 +650,25 @@ impl Supergraph {
         listen: Option<ListenAddr>,
         path: Option<String>,
         introspection: Option<bool>,
+        introspection_options: Option<IntrospectionOptions>,
         defer_support: Option<bool>,
+        subscription_support: Option<bool>,
         apq: Option<Apq>,
+        persisted_queries: Option<PersistedQueries>,
         query_planning: Option<QueryPlanning>,
+        tls: Option<TlsSupergraph>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
+            introspection_options: introspection_options.unwrap_or_default(),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
+            subscription_support: subscription_support.unwrap_or_else(default_subscription_support),
             apq: apq.unwrap_or_default(),
+            persisted_queries: persisted_queries.unwrap_or_default(),
             query_planning: query_planning.unwrap_or_default(),
+            tls,
         }
     }
 }
@@ -583,6 +679,44 @@ impl Default for Supergraph {
     }
 }
 
+/// Introspection access rules and limits
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IntrospectionOptions {
+    /// Rules allowing introspection. When there are rules, introspection is only allowed for the
+    /// requests matching one of them, otherwise it is allowed for all requests.
+    #[serde(default)]
+    pub(crate) allow: Vec<IntrospectionRule>,
+    /// Maximum depth of introspection queries, counting the `__schema` and `__type` fields
+    #[serde(default)]
+    pub(crate) max_depth: Option<u32>,
+}
+
+/// Requests allowed to use introspection
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum IntrospectionRule {
+    /// Requests with a header set to this value
+    Header {
+        /// Name of the header
+        name: String,
+        /// Value of the header
+        value: String,
+    },
+    /// Requests authenticated with a JWT whose claim is set to this value, or to an array
+    /// containing it
+    Claim {
+        /// Name of the claim
+        name: String,
+        /// Value of the claim
+        value: serde_json::Value,
+    },
+    /// Requests from this client, identified by the client name header of Apollo telemetry
+    ClientName(String),
+    /// Requests received on this local address
+    Listener(ListenAddr),
+}
+
 /// Automatic Persisted Queries (APQ) configuration
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -607,6 +741,52 @@ impl Default for Apq {
     }
 }
 
+/// Persisted queries configuration
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PersistedQueries {
+    /// Manifest of the persisted queries, a JSON object mapping operation IDs to query strings.
+    /// Persisted queries are disabled without a manifest.
+    #[serde(default)]
+    pub(crate) manifest: Option<PersistedQueriesManifest>,
+    /// Interval between two downloads of a manifest from a URL. Without it, the manifest is
+    /// only downloaded when the router starts or reloads.
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) poll_interval: Option<Duration>,
+    /// Handling of the queries that are not in the manifest
+    #[serde(default)]
+    pub(crate) safelist: Safelist,
+}
+
+/// Location of the persisted queries manifest
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum PersistedQueriesManifest {
+    /// Path to a JSON file, reloaded when it changes
+    File(PathBuf),
+    /// URL of a JSON document
+    Url(String),
+}
+
+/// Handling of the queries that are not in the persisted queries manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Safelist {
+    /// Execute them (default)
+    Allow,
+    /// Execute them and log them, to audit the operations before enforcing the safelist
+    Log,
+    /// Reject them
+    Enforce,
+}
+
+impl Default for Safelist {
+    fn default() -> Self {
+        Safelist::Allow
+    }
+}
+
 /// Query planning cache configuration
 #[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -618,6 +798,120 @@ pub(crate) struct QueryPlanning {
     /// Defaults to 0 (do not warm up the cache)
     #[serde(default)]
     pub(crate) warmed_up_queries: usize,
+    /// Persists the most used queries, to warm up the cache when the router starts
+    #[serde(default)]
+    pub(crate) warm_up: WarmUp,
+    #[cfg(feature = "experimental_cache")]
+    /// Coordination of the routers sharing the query plan cache in Redis
+    #[serde(default)]
+    pub(crate) shared_cache: SharedQueryPlanCache,
+}
+
+/// Query plan cache warm up configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WarmUp {
+    /// Number of queries planned at the same time during the warm up (default: 1)
+    #[serde(default = "default_warm_up_concurrency")]
+    pub(crate) concurrency: NonZeroUsize,
+    /// Maximum duration of the warm up. The queries that were not planned by then are planned
+    /// when they are received (default: 30s)
+    #[serde(with = "humantime_serde", default = "default_warm_up_timeout")]
+    #[schemars(with = "String")]
+    pub(crate) timeout: Duration,
+    /// File where the `warmed_up_queries` most used queries are saved, and loaded when the
+    /// router starts
+    #[serde(default)]
+    pub(crate) file: Option<PathBuf>,
+    #[cfg(feature = "experimental_cache")]
+    /// Saves the most used queries in the Redis instance of the query planning cache, and
+    /// loads them when the router starts
+    #[serde(default)]
+    pub(crate) redis: bool,
+    /// Interval between two saves of the most used queries (default: 1m)
+    #[serde(with = "humantime_serde", default = "default_warm_up_save_interval")]
+    #[schemars(with = "String")]
+    pub(crate) save_interval: Duration,
+}
+
+impl Default for WarmUp {
+    fn default() -> Self {
+        Self {
+            concurrency: default_warm_up_concurrency(),
+            timeout: default_warm_up_timeout(),
+            file: None,
+            #[cfg(feature = "experimental_cache")]
+            redis: false,
+            save_interval: default_warm_up_save_interval(),
+        }
+    }
+}
+
+/// Coordination of the routers sharing the query plan cache in Redis
+#[cfg(feature = "experimental_cache")]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SharedQueryPlanCache {
+    /// Maximum time a router waits for another router planning the same query, before planning
+    /// it itself. Set to 0s to plan queries in every router (default: 5s)
+    #[serde(
+        with = "humantime_serde",
+        default = "default_shared_cache_lock_timeout"
+    )]
+    #[schemars(with = "String")]
+    pub(crate) lock_timeout: Duration,
+    /// Time to live of the query plans of the previous schema, set when the router switches
+    /// to a new schema (default: 1h)
+    #[serde(
+        with = "humantime_serde",
+        default = "default_shared_cache_previous_schema_ttl"
+    )]
+    #[schemars(with = "String")]
+    pub(crate) previous_schema_ttl: Duration,
+    /// Time to live of the query plans in Redis when `experimental_cache.redis.ttl` is not set,
+    /// so that the plans of a schema expire even if no router switched away from it, like
+    /// after a crash (default: 24h)
+    #[serde(with = "humantime_serde", default = "default_shared_cache_ttl")]
+    #[schemars(with = "String")]
+    pub(crate) ttl: Duration,
+}
+
+#[cfg(feature = "experimental_cache")]
+impl Default for SharedQueryPlanCache {
+    fn default() -> Self {
+        Self {
+            lock_timeout: default_shared_cache_lock_timeout(),
+            previous_schema_ttl: default_shared_cache_previous_schema_ttl(),
+            ttl: default_shared_cache_ttl(),
+        }
+    }
+}
+
+#[cfg(feature = "experimental_cache")]
+fn default_shared_cache_lock_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+#[cfg(feature = "experimental_cache")]
+fn default_shared_cache_previous_schema_ttl() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+#[cfg(feature = "experimental_cache")]
+fn default_shared_cache_ttl() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+fn default_warm_up_concurrency() -> NonZeroUsize {
+    NonZeroUsize::new(1).expect("1 is not 0; qed")
+}
+
+fn default_warm_up_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_warm_up_save_interval() -> Duration {
+    Duration::from_secs(60)
 }
 
 /// Cache configuration
@@ -631,6 +925,21 @@ pub(crate) struct Cache {
     pub(crate) redis: Option<RedisCache>,
 }
 
+impl Cache {
+    /// Configuration for a cache of data that depends on the schema, so that routers using
+    /// different schemas do not share entries in Redis
+    pub(crate) fn for_schema(&self, _schema_id: Option<&str>) -> Self {
+        Self {
+            in_memory: self.in_memory.clone(),
+            #[cfg(feature = "experimental_cache")]
+            redis: self
+                .redis
+                .as_ref()
+                .map(|redis| redis.for_schema(_schema_id)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 /// In memory cache configuration
@@ -654,6 +963,80 @@ impl Default for InMemoryCache {
 pub(crate) struct RedisCache {
     /// List of URLs to the Redis cluster
     pub(crate) urls: Vec<String>,
+    /// Number of connections to Redis, each of them multiplexing commands (default: 1)
+    #[serde(default = "default_redis_pool_size")]
+    pub(crate) pool_size: NonZeroUsize,
+    /// Timeout of Redis commands, including the connection when it is reestablished (default: 500ms)
+    #[serde(with = "humantime_serde", default = "default_redis_timeout")]
+    #[schemars(with = "String")]
+    pub(crate) timeout: Duration,
+    /// Username for Redis authentication, overriding the one in the URLs
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    /// Password for Redis authentication, overriding the one in the URLs
+    #[serde(default)]
+    pub(crate) password: Option<String>,
+    /// TLS options for `rediss://` URLs
+    #[serde(default)]
+    pub(crate) tls: Option<RedisTls>,
+    /// Time to live of the cache entries, at least 1ms (default: no expiration)
+    #[serde(with = "humantime_serde", default)]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) ttl: Option<Duration>,
+    /// Prefix of the keys, to separate the entries of routers sharing a Redis. The schema hash
+    /// is always added to the namespace of the query planning and introspection caches.
+    #[serde(default)]
+    pub(crate) namespace: Option<String>,
+}
+
+#[cfg(feature = "experimental_cache")]
+impl RedisCache {
+    /// Configuration for the given URLs, with the default options
+    pub(crate) fn new(urls: Vec<String>) -> Self {
+        Self {
+            urls,
+            pool_size: default_redis_pool_size(),
+            timeout: default_redis_timeout(),
+            username: None,
+            password: None,
+            tls: None,
+            ttl: None,
+            namespace: None,
+        }
+    }
+
+    /// Configuration for a cache of data that depends on the schema: the schema hash is added
+    /// to the namespace
+    pub(crate) fn for_schema(&self, schema_id: Option<&str>) -> Self {
+        let namespace = match (self.namespace.as_deref(), schema_id) {
+            (Some(namespace), Some(schema_id)) => Some(format!("{}:{}", namespace, schema_id)),
+            (namespace, schema_id) => namespace.or(schema_id).map(str::to_string),
+        };
+        Self {
+            namespace,
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(feature = "experimental_cache")]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Redis TLS configuration
+pub(crate) struct RedisTls {
+    /// list of certificate authorities in PEM format, replacing the system ones. Only supported
+    /// with a single Redis URL.
+    pub(crate) certificate_authorities: String,
+}
+
+#[cfg(feature = "experimental_cache")]
+fn default_redis_pool_size() -> NonZeroUsize {
+    NonZeroUsize::new(1).expect("1 is not 0; qed")
+}
+
+#[cfg(feature = "experimental_cache")]
+fn default_redis_timeout() -> Duration {
+    Duration::from_millis(500)
 }
 
 /// TLS related configuration options.
@@ -697,14 +1080,21 @@ pub(crate) struct TlsSubgraph {
     /// list of certificate authorities in PEM format
     #[serde(default)]
     pub(crate) certificate_authorities: Option<String>,
+    /// client certificate authentication
+    #[serde(default)]
+    pub(crate) client_authentication: Option<TlsClientAuth>,
 }
 
 #[buildstructor::buildstructor]
 impl TlsSubgraph {
     #[builder]
-    pub(crate) fn new(certificate_authorities: Option<String>) -> Self {
+    pub(crate) fn new(
+        certificate_authorities: Option<String>,
+        client_authentication: Option<TlsClientAuth>,
+    ) -> Self {
         Self {
             certificate_authorities,
+            client_authentication,
         }
     }
 }
@@ -715,6 +1105,121 @@ impl Default for TlsSubgraph {
     }
 }
 
+/// TLS termination configuration for the supergraph listener
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsSupergraph {
+    /// list of certificates in PEM format, starting with the server certificate
+    pub(crate) certificate_chain: String,
+    /// private key of the server certificate in PEM format
+    pub(crate) key: String,
+    /// list of certificate authorities in PEM format, required to authenticate clients with certificates
+    #[serde(default)]
+    pub(crate) client_certificate_authorities: Option<String>,
+}
+
+/// TLS client authentication
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsClientAuth {
+    /// list of certificates in PEM format, starting with the client certificate
+    pub(crate) certificate_chain: String,
+    /// private key of the client certificate in PEM format
+    pub(crate) key: String,
+}
+
+/// Configuration options pertaining to client-side request batching.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Batching {
+    /// Set to true to accept requests containing an array of operations
+    #[serde(default = "default_batching")]
+    pub(crate) enabled: bool,
+
+    /// Maximum number of operations in a single batch, unlimited by default
+    #[serde(default)]
+    pub(crate) max_size: Option<usize>,
+}
+
+fn default_batching() -> bool {
+    false
+}
+
+#[buildstructor::buildstructor]
+impl Batching {
+    #[builder]
+    pub(crate) fn new(enabled: Option<bool>, max_size: Option<usize>) -> Self {
+        Self {
+            enabled: enabled.unwrap_or_else(default_batching),
+            max_size,
+        }
+    }
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Limits on the size of operations, checked before query planning. They are not limited by
+/// default.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OperationLimits {
+    /// Maximum depth of nested fields
+    #[serde(default)]
+    pub(crate) max_depth: Option<u32>,
+
+    /// Maximum number of fields, including the nested ones and the ones from fragments
+    #[serde(default)]
+    pub(crate) max_height: Option<u32>,
+
+    /// Maximum number of fields at the root of the operation
+    #[serde(default)]
+    pub(crate) max_root_fields: Option<u32>,
+
+    /// Maximum number of aliased fields
+    #[serde(default)]
+    pub(crate) max_aliases: Option<u32>,
+
+    /// Maximum number of directives, including the ones of the fragments
+    #[serde(default)]
+    pub(crate) max_directives: Option<u32>,
+
+    /// Set to true to log the operations exceeding the limits instead of rejecting them
+    #[serde(default)]
+    pub(crate) warn_only: bool,
+}
+
+#[buildstructor::buildstructor]
+impl OperationLimits {
+    #[builder]
+    pub(crate) fn new(
+        max_depth: Option<u32>,
+        max_height: Option<u32>,
+        max_root_fields: Option<u32>,
+        max_aliases: Option<u32>,
+        max_directives: Option<u32>,
+        warn_only: Option<bool>,
+    ) -> Self {
+        Self {
+            max_depth,
+            max_height,
+            max_root_fields,
+            max_aliases,
+            max_directives,
+            warn_only: warn_only.unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for OperationLimits {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
 /// Configuration options pertaining to the sandbox page.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
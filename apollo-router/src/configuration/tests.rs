@@ -683,3 +683,80 @@ fn visit_schema(path: &str, schema: &Value, errors: &mut Vec<String>) {
         _ => {}
     }
 }
+
+#[cfg(feature = "experimental_cache")]
+#[test]
+fn it_adds_the_schema_to_the_redis_namespace() {
+    let redis = RedisCache::new(vec!["redis://localhost".to_string()]);
+    assert_eq!(redis.for_schema(None).namespace, None);
+    assert_eq!(
+        redis.for_schema(Some("hash")).namespace.as_deref(),
+        Some("hash")
+    );
+
+    let redis = RedisCache {
+        namespace: Some("production".to_string()),
+        ..redis
+    };
+    assert_eq!(
+        redis.for_schema(None).namespace.as_deref(),
+        Some("production")
+    );
+    assert_eq!(
+        redis.for_schema(Some("hash")).namespace.as_deref(),
+        Some("production:hash")
+    );
+
+    let cache = Cache {
+        in_memory: Default::default(),
+        redis: Some(redis),
+    };
+    let cache = cache.for_schema(Some("hash"));
+    assert_eq!(
+        cache.redis.unwrap().namespace.as_deref(),
+        Some("production:hash")
+    );
+    assert!(Cache::default().for_schema(Some("hash")).redis.is_none());
+}
+
+#[test]
+fn it_does_not_allow_two_owners_of_the_cache_control_header() {
+    let headers = r#"
+headers:
+  all:
+    response:
+      - propagate:
+          named: cache-control
+          merge: min_max_age
+"#;
+    assert!(Configuration::from_str(headers).is_ok());
+
+    let error = Configuration::from_str(&format!("{}\nresponse_cache: {{}}", headers))
+        .expect_err("should have resulted in an error");
+    assert!(matches!(
+        error,
+        ConfigurationError::InvalidConfiguration {
+            message: "invalid 'headers' configuration",
+            ..
+        }
+    ));
+
+    // other response headers can still be propagated
+    assert!(Configuration::from_str(
+        r#"
+headers:
+  all:
+    response:
+      - propagate:
+          named: set-cookie
+          merge: append
+response_cache: {}
+"#
+    )
+    .is_ok());
+    assert!(Configuration::from_str(&format!(
+        "{}\nresponse_cache:\n  cache_control: false",
+        headers
+    ))
+    .is_ok());
+}
@@ -170,6 +170,23 @@ impl Context {
         self.entries.iter_mut()
     }
 
+    /// Create a new context holding a copy of the current entries.
+    ///
+    /// Entries inserted in the forked context are not visible from the original one,
+    /// but both contexts share the creation time and the busy timer.
+    pub(crate) fn fork(&self) -> Self {
+        Context {
+            entries: Arc::new(
+                self.entries
+                    .iter()
+                    .map(|entry| (entry.key().clone(), entry.value().clone()))
+                    .collect(),
+            ),
+            created_at: self.created_at,
+            busy_timer: self.busy_timer.clone(),
+        }
+    }
+
     /// Notify the busy timer that we're waiting on a network request
     pub(crate) async fn enter_active_request(&self) {
         self.busy_timer.lock().await.increment_active_requests()
@@ -312,4 +329,14 @@ mod test {
         assert_eq!(c.get("one").unwrap(), Some(2));
         assert_eq!(c.get("two").unwrap(), Some(3));
     }
+
+    #[test]
+    fn it_forks_context() {
+        let c = Context::new();
+        assert!(c.insert("one", 1).is_ok());
+        let forked = c.fork();
+        assert!(forked.insert("two", 2).is_ok());
+        assert_eq!(forked.get("one").unwrap(), Some(1));
+        assert_eq!(c.get::<_, usize>("two").unwrap(), None);
+    }
 }
@@ -393,7 +393,7 @@ impl std::fmt::Display for PlanErrors {
 #[non_exhaustive]
 pub(crate) enum SchemaError {
     /// URL parse error for subgraph {0}: {1}
-    UrlParse(String, http::uri::InvalidUri),
+    UrlParse(String, ConfigurationError),
     /// Could not find an URL for subgraph {0}
     MissingSubgraphUrl(String),
     /// Parsing error(s).
@@ -6,6 +6,7 @@ use futures::channel::oneshot;
 use futures::prelude::*;
 use itertools::Itertools;
 use multimap::MultiMap;
+use tokio_rustls::TlsAcceptor;
 
 use super::router::ApolloRouterError;
 use crate::configuration::Configuration;
@@ -148,18 +149,45 @@ pub(crate) enum Listener {
     Tcp(tokio::net::TcpListener),
     #[cfg(unix)]
     Unix(tokio::net::UnixListener),
+    Tls {
+        listener: tokio::net::TcpListener,
+        acceptor: TlsAcceptor,
+    },
 }
 
 pub(crate) enum NetworkStream {
     Tcp(tokio::net::TcpStream),
     #[cfg(unix)]
     Unix(tokio::net::UnixStream),
+    /// The TLS handshake is performed by the connection's task, to avoid blocking the listener
+    Tls(tokio::net::TcpStream, TlsAcceptor),
 }
 
+/// Local address of the connection a request was received on, in the request's extensions
+#[derive(Clone, Debug)]
+pub(crate) struct LocalAddr(pub(crate) ListenAddr);
+
 impl Listener {
+    /// Sets up TLS termination on a TCP listener with the provided acceptor, replacing any
+    /// previous acceptor, or goes back to plain TCP if there is none.
+    ///
+    /// Connections accepted before the change keep the TLS configuration they started with.
+    pub(crate) fn with_tls(self, acceptor: Option<TlsAcceptor>) -> Self {
+        match (self, acceptor) {
+            (Listener::Tcp(listener), Some(acceptor))
+            | (Listener::Tls { listener, .. }, Some(acceptor)) => {
+                Listener::Tls { listener, acceptor }
+            }
+            (Listener::Tls { listener, .. }, None) => Listener::Tcp(listener),
+            (listener, _) => listener,
+        }
+    }
+
     pub(crate) fn local_addr(&self) -> std::io::Result<ListenAddr> {
         match self {
-            Listener::Tcp(listener) => listener.local_addr().map(Into::into),
+            Listener::Tcp(listener) | Listener::Tls { listener, .. } => {
+                listener.local_addr().map(Into::into)
+            }
             #[cfg(unix)]
             Listener::Unix(listener) => listener.local_addr().map(|addr| {
                 ListenAddr::UnixSocket(
@@ -182,6 +210,10 @@ impl Listener {
                 .accept()
                 .await
                 .map(|(stream, _)| NetworkStream::Unix(stream)),
+            Listener::Tls { listener, acceptor } => listener
+                .accept()
+                .await
+                .map(|(stream, _)| NetworkStream::Tls(stream, acceptor.clone())),
         }
     }
 }
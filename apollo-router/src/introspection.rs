@@ -8,8 +8,14 @@ use router_bridge::planner::IncrementalDeliverySupport;
 use router_bridge::planner::QueryPlannerConfig;
 
 use crate::cache::storage::CacheStorage;
+use crate::configuration::IntrospectionRule;
+use crate::graphql;
 use crate::graphql::Response;
+use crate::http_server_factory::LocalAddr;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
 use crate::Configuration;
+use crate::Context;
 
 const DEFAULT_INTROSPECTION_CACHE_CAPACITY: NonZeroUsize =
     unsafe { NonZeroUsize::new_unchecked(5) };
@@ -17,22 +23,53 @@ const DEFAULT_INTROSPECTION_CACHE_CAPACITY: NonZeroUsize =
 /// A cache containing our well known introspection queries.
 pub(crate) struct Introspection {
     cache: CacheStorage<String, Response>,
+    schema_id: Option<String>,
     defer_support: bool,
 }
 
 impl Introspection {
     pub(crate) async fn with_capacity(
         configuration: &Configuration,
+        schema_id: Option<&str>,
         capacity: NonZeroUsize,
     ) -> Self {
         Self {
-            cache: CacheStorage::new(capacity, None, "introspection").await,
+            // Introspection responses are stored in the Redis instance of the query plans
+            cache: CacheStorage::new(
+                capacity,
+                #[cfg(feature = "experimental_cache")]
+                configuration
+                    .supergraph
+                    .query_planning
+                    .experimental_cache
+                    .for_schema(schema_id)
+                    .redis,
+                "introspection",
+            )
+            .await,
+            schema_id: schema_id.map(str::to_string),
             defer_support: configuration.supergraph.defer_support,
         }
     }
 
-    pub(crate) async fn new(configuration: &Configuration) -> Self {
-        Self::with_capacity(configuration, DEFAULT_INTROSPECTION_CACHE_CAPACITY).await
+    pub(crate) async fn new(configuration: &Configuration, schema_id: Option<&str>) -> Self {
+        Self::with_capacity(
+            configuration,
+            schema_id,
+            DEFAULT_INTROSPECTION_CACHE_CAPACITY,
+        )
+        .await
+    }
+
+    /// The responses only depend on the schema and on the defer support: they can be kept
+    /// across reloads that do not change them
+    pub(crate) fn is_reusable(
+        &self,
+        configuration: &Configuration,
+        schema_id: Option<&str>,
+    ) -> bool {
+        self.schema_id.as_deref() == schema_id
+            && self.defer_support == configuration.supergraph.defer_support
     }
 
     #[cfg(test)]
@@ -40,7 +77,7 @@ impl Introspection {
         configuration: &Configuration,
         cache: HashMap<String, Response>,
     ) -> Self {
-        let this = Self::with_capacity(configuration, cache.len().try_into().unwrap()).await;
+        let this = Self::with_capacity(configuration, None, cache.len().try_into().unwrap()).await;
 
         for (query, response) in cache.into_iter() {
             this.cache.insert(query, response).await;
@@ -96,6 +133,56 @@ impl Introspection {
     }
 }
 
+/// Checks the request against the introspection access rules. Without rules, introspection
+/// is allowed for all requests.
+pub(crate) fn is_allowed(
+    rules: &[IntrospectionRule],
+    request: &http::Request<graphql::Request>,
+    context: &Context,
+) -> bool {
+    rules.is_empty()
+        || rules
+            .iter()
+            .any(|rule| matches_rule(rule, request, context))
+}
+
+fn matches_rule(
+    rule: &IntrospectionRule,
+    request: &http::Request<graphql::Request>,
+    context: &Context,
+) -> bool {
+    match rule {
+        IntrospectionRule::Header { name, value } => request
+            .headers()
+            .get_all(name.as_str())
+            .iter()
+            .any(|header| header.as_bytes() == value.as_bytes()),
+        IntrospectionRule::Claim { name, value } => {
+            let claim = context
+                .get::<_, serde_json::Value>(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+                .ok()
+                .flatten()
+                .and_then(|mut claims| claims.get_mut(name.as_str()).map(serde_json::Value::take));
+            match claim {
+                Some(serde_json::Value::Array(values)) => values.contains(value),
+                Some(claim) => &claim == value,
+                None => false,
+            }
+        }
+        IntrospectionRule::ClientName(client_name) => context
+            .get::<_, String>(CLIENT_NAME)
+            .ok()
+            .flatten()
+            .map(|name| &name == client_name)
+            .unwrap_or_default(),
+        IntrospectionRule::Listener(listener) => request
+            .extensions()
+            .get::<LocalAddr>()
+            .map(|LocalAddr(addr)| addr == listener)
+            .unwrap_or_default(),
+    }
+}
+
 #[cfg(test)]
 mod introspection_tests {
     use super::*;
@@ -120,4 +207,37 @@ mod introspection_tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn it_checks_introspection_access_rules() {
+        let rules: Vec<IntrospectionRule> = serde_json::from_value(serde_json::json!([
+            { "header": { "name": "x-introspection", "value": "allowed" } },
+            { "claim": { "name": "roles", "value": "admin" } },
+            { "client_name": "studio" },
+        ]))
+        .unwrap();
+        let request = |header: &str| {
+            http::Request::builder()
+                .header("x-introspection", header)
+                .body(graphql::Request::default())
+                .unwrap()
+        };
+
+        assert!(is_allowed(&[], &request("denied"), &Context::new()));
+        assert!(is_allowed(&rules, &request("allowed"), &Context::new()));
+        assert!(!is_allowed(&rules, &request("denied"), &Context::new()));
+
+        let context = Context::new();
+        context
+            .insert(
+                APOLLO_AUTHENTICATION_JWT_CLAIMS,
+                serde_json::json!({ "roles": ["user", "admin"] }),
+            )
+            .unwrap();
+        assert!(is_allowed(&rules, &request("denied"), &context));
+
+        let context = Context::new();
+        context.insert(CLIENT_NAME, "studio".to_string()).unwrap();
+        assert!(is_allowed(&rules, &request("denied"), &context));
+    }
 }
@@ -0,0 +1,237 @@
+//! JWKS retrieval and refresh
+// With regards to ELv2 licensing, this entire file is license key functionality
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use futures::future::join_all;
+use http::header::ACCEPT;
+use http::header::CONTENT_TYPE;
+use jsonwebtoken::jwk::Jwk;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::Algorithm;
+use mime::APPLICATION_JSON;
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use tokio::fs::read_to_string;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+use url::Url;
+
+#[cfg(not(test))]
+use crate::error::LicenseError;
+#[cfg(not(test))]
+use crate::services::apollo_graph_reference;
+
+const DEFAULT_AUTHENTICATION_NETWORK_TIMEOUT: Duration = Duration::from_secs(15);
+
+static CLIENT: Lazy<Result<Client, BoxError>> = Lazy::new(|| {
+    #[cfg(not(test))]
+    apollo_graph_reference().ok_or(LicenseError::MissingGraphReference)?;
+    Ok(Client::new())
+});
+
+/// A JWKS source, along with the validation rules of the tokens it verifies
+#[derive(Clone, Debug)]
+pub(super) struct JwksConfig {
+    pub(super) url: Url,
+    pub(super) issuer: Option<String>,
+    pub(super) audiences: Option<Vec<String>>,
+    pub(super) algorithms: Option<Vec<Algorithm>>,
+    pub(super) poll_interval: Duration,
+}
+
+/// Keeps the JWK sets of all the configured sources, refreshed in the background
+#[derive(Clone)]
+pub(super) struct JwksManager {
+    list: Vec<JwksConfig>,
+    jwks_map: Arc<ArcSwap<HashMap<Url, JwkSet>>>,
+    _refresh_tasks: Arc<RefreshTasks>,
+}
+
+/// Aborts the refresh tasks once the last JwksManager referencing them is dropped
+struct RefreshTasks(Vec<JoinHandle<()>>);
+
+impl Drop for RefreshTasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+impl JwksManager {
+    /// Download all the JWK sets and start refreshing them periodically.
+    ///
+    /// A source which cannot be downloaded does not prevent the router from starting: its
+    /// keys will be available once a refresh succeeds.
+    pub(super) async fn new(list: Vec<JwksConfig>) -> Self {
+        // Sources may share a URL with different validation rules, we only download it once,
+        // at the shortest of their polling intervals
+        let mut poll_intervals: HashMap<Url, Duration> = HashMap::new();
+        for config in &list {
+            poll_intervals
+                .entry(config.url.clone())
+                .and_modify(|interval| *interval = (*interval).min(config.poll_interval))
+                .or_insert(config.poll_interval);
+        }
+
+        let jwks_map: HashMap<Url, JwkSet> =
+            join_all(poll_intervals.keys().map(|url| async move {
+                fetch_jwks(url.clone())
+                    .await
+                    .map(|jwks| (url.clone(), jwks))
+            }))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        let jwks_map = Arc::new(ArcSwap::from_pointee(jwks_map));
+
+        let tasks = poll_intervals
+            .into_iter()
+            .map(|(url, poll_interval)| {
+                let jwks_map = jwks_map.clone();
+                tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(poll_interval).await;
+                        if let Some(jwks) = fetch_jwks(url.clone()).await {
+                            jwks_map.rcu(|map| {
+                                let mut map = HashMap::clone(map);
+                                map.insert(url.clone(), jwks.clone());
+                                map
+                            });
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        JwksManager {
+            list,
+            jwks_map,
+            _refresh_tasks: Arc::new(RefreshTasks(tasks)),
+        }
+    }
+
+    /// Find the keys which may have signed a token, along with the configuration of their source.
+    ///
+    /// If the token header has a `kid`, only the keys with that id are returned, otherwise all
+    /// the keys compatible with the token algorithm are.
+    pub(super) fn candidates(
+        &self,
+        kid: Option<&str>,
+        algorithm: Algorithm,
+    ) -> Vec<(JwksConfig, Jwk)> {
+        let jwks_map = self.jwks_map.load();
+        let mut candidates = Vec::new();
+
+        for config in &self.list {
+            if let Some(algorithms) = &config.algorithms {
+                if !algorithms.contains(&algorithm) {
+                    continue;
+                }
+            }
+
+            if let Some(jwks) = jwks_map.get(&config.url) {
+                candidates.extend(
+                    jwks.keys
+                        .iter()
+                        .filter(|jwk| match kid {
+                            Some(kid) => jwk.common.key_id.as_deref() == Some(kid),
+                            None => true,
+                        })
+                        .filter(|jwk| {
+                            jwk.common
+                                .algorithm
+                                .map(|key_algorithm| key_algorithm == algorithm)
+                                .unwrap_or(true)
+                        })
+                        .map(|jwk| (config.clone(), jwk.clone())),
+                );
+            }
+        }
+
+        candidates
+    }
+}
+
+async fn fetch_jwks(url: Url) -> Option<JwkSet> {
+    let jwks = get_jwks(url.clone()).await;
+    if jwks.is_none() {
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            monotonic_counter.apollo_authentication_jwks_refresh_failure_count = 1u64,
+            url = %url
+        );
+    }
+    jwks
+}
+
+// This function is expected to return an Optional value, but we'd like to let
+// users know the various failure conditions. Hence the various clumsy map_err()
+// scattered through the processing.
+async fn get_jwks(url: Url) -> Option<JwkSet> {
+    let data = if url.scheme() == "file" {
+        #[cfg(not(test))]
+        apollo_graph_reference()
+            .ok_or(LicenseError::MissingGraphReference)
+            .map_err(|e| {
+                tracing::error!(%e, "could not activate commercial features");
+                e
+            })
+            .ok()?;
+        let path = url
+            .to_file_path()
+            .map_err(|e| {
+                tracing::error!("could not process url: {:?}", url);
+                e
+            })
+            .ok()?;
+        read_to_string(path)
+            .await
+            .map_err(|e| {
+                tracing::error!(%e, "could not read JWKS path");
+                e
+            })
+            .ok()?
+    } else {
+        let my_client = CLIENT
+            .as_ref()
+            .map_err(|e| {
+                tracing::error!(%e, "could not activate commercial features");
+                e
+            })
+            .ok()?
+            .clone();
+
+        my_client
+            .get(url)
+            .header(ACCEPT, APPLICATION_JSON.essence_str())
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .timeout(DEFAULT_AUTHENTICATION_NETWORK_TIMEOUT)
+            .send()
+            .await
+            .map_err(|e| {
+                tracing::error!(%e, "could not get url");
+                e
+            })
+            .ok()?
+            .text()
+            .await
+            .map_err(|e| {
+                tracing::error!(%e, "could not process url content");
+                e
+            })
+            .ok()?
+    };
+    let jwks: JwkSet = serde_json::from_str(&data)
+        .map_err(|e| {
+            tracing::error!(%e, "could not create JWKS from url content");
+            e
+        })
+        .ok()?;
+    Some(jwks)
+}
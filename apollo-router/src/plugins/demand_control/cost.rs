@@ -0,0 +1,854 @@
+//! Cost of operations, estimated from their query plan or computed from the subgraph responses
+//!
+//! Each occurrence of a field costs its weight: the `weight` configured for the field, or the
+//! one of its `@cost` directive, or 1 for fields returning objects (10 for mutation root fields)
+//! and 0 for fields returning scalars or enums. The size of lists is estimated from their
+//! slicing arguments like `first` or `limit`, or their assumed size, configured or from their
+//! `@listSize` directive. The `@skip` and `@include` directives are not evaluated, so the
+//! estimated cost is an upper bound.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use apollo_parser::ast;
+use lru::LruCache;
+use tower::BoxError;
+
+use super::Config;
+use crate::json_ext::Object;
+use crate::json_ext::Value;
+use crate::query_planner::fetch::OperationKind;
+use crate::query_planner::PlanNode;
+use crate::spec::query::parse_value;
+use crate::spec::query::TYPENAME;
+use crate::spec::FieldType;
+
+const ENTITIES: &str = "_entities";
+const OBJECT_WEIGHT: f64 = 1.0;
+const LEAF_WEIGHT: f64 = 0.0;
+const MUTATION_WEIGHT: f64 = 10.0;
+/// Number of parsed fetch operations kept in cache
+const OPERATION_CACHE_SIZE: usize = 512;
+
+type Fragments = HashMap<String, ast::FragmentDefinition>;
+
+/// Cost information of a field definition
+#[derive(Debug)]
+struct FieldDefinition {
+    /// Name of the returned type, `None` for built-in scalars
+    type_name: Option<String>,
+    is_list: bool,
+    weight: Option<f64>,
+    assumed_size: Option<f64>,
+    slicing_arguments: Option<Vec<String>>,
+}
+
+impl FieldDefinition {
+    fn from_ast(field: &ast::FieldDefinition) -> Option<Self> {
+        let ty = FieldType::try_from(field.ty()?).ok()?;
+        let is_list = match &ty {
+            FieldType::NonNull(ty) => matches!(**ty, FieldType::List(_)),
+            ty => matches!(ty, FieldType::List(_)),
+        };
+
+        let mut definition = FieldDefinition {
+            type_name: ty.inner_type_name().map(str::to_string),
+            is_list,
+            weight: None,
+            assumed_size: None,
+            slicing_arguments: None,
+        };
+        for directive in field.directives().iter().flat_map(|d| d.directives()) {
+            match text(directive.name()).as_deref() {
+                Some("cost") => {
+                    definition.weight = argument(&directive, "weight").as_ref().and_then(number);
+                }
+                Some("listSize") => {
+                    definition.assumed_size = argument(&directive, "assumedSize")
+                        .as_ref()
+                        .and_then(number);
+                    definition.slicing_arguments = argument(&directive, "slicingArguments")
+                        .as_ref()
+                        .and_then(Value::as_array)
+                        .map(|arguments| {
+                            arguments
+                                .iter()
+                                .filter_map(|argument| argument.as_str().map(str::to_string))
+                                .collect()
+                        });
+                }
+                _ => {}
+            }
+        }
+
+        Some(definition)
+    }
+}
+
+/// Fetch operation, parsed once and kept in cache, as the same fetches are sent for all the
+/// requests using a query plan
+#[derive(Debug)]
+struct Operation {
+    /// Root selection sets
+    selection_sets: Vec<Vec<Selection>>,
+}
+
+#[derive(Debug)]
+enum Selection {
+    Field(Field),
+    /// Inline fragment or fragment spread. Inline fragments may have no type condition
+    Fragment {
+        type_condition: Option<String>,
+        selection_set: Vec<Selection>,
+    },
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    response_key: String,
+    arguments: Vec<(String, Argument)>,
+    selection_set: Option<Vec<Selection>>,
+}
+
+#[derive(Debug)]
+enum Argument {
+    Value(Value),
+    Variable(String),
+}
+
+impl Operation {
+    fn parse(operation: &str) -> Self {
+        let document = apollo_parser::Parser::new(operation).parse().document();
+        let mut selection_sets = Vec::new();
+        let mut fragments = HashMap::new();
+        for definition in document.definitions() {
+            match definition {
+                ast::Definition::OperationDefinition(operation) => {
+                    selection_sets.extend(operation.selection_set());
+                }
+                ast::Definition::FragmentDefinition(fragment) => {
+                    if let Some(name) = fragment.fragment_name().and_then(|name| text(name.name()))
+                    {
+                        fragments.insert(name, fragment);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Operation {
+            selection_sets: selection_sets
+                .iter()
+                .map(|selection_set| Selection::from_ast(selection_set, &fragments))
+                .collect(),
+        }
+    }
+}
+
+impl Selection {
+    fn from_ast(selection_set: &ast::SelectionSet, fragments: &Fragments) -> Vec<Selection> {
+        selection_set
+            .selections()
+            .filter_map(|selection| match selection {
+                ast::Selection::Field(field) => {
+                    let name = text(field.name())?;
+                    Some(Selection::Field(Field {
+                        response_key: field
+                            .alias()
+                            .and_then(|alias| text(alias.name()))
+                            .unwrap_or_else(|| name.clone()),
+                        arguments: field
+                            .arguments()
+                            .iter()
+                            .flat_map(|arguments| arguments.arguments())
+                            .filter_map(|argument| {
+                                let value = match argument.value()? {
+                                    ast::Value::Variable(variable) => {
+                                        Argument::Variable(text(variable.name())?)
+                                    }
+                                    value => Argument::Value(parse_value(&value)?),
+                                };
+                                Some((text(argument.name())?, value))
+                            })
+                            .collect(),
+                        selection_set: field
+                            .selection_set()
+                            .map(|selection_set| Selection::from_ast(&selection_set, fragments)),
+                        name,
+                    }))
+                }
+                ast::Selection::InlineFragment(fragment) => Some(Selection::Fragment {
+                    type_condition: fragment
+                        .type_condition()
+                        .and_then(|condition| condition.named_type())
+                        .and_then(|named| text(named.name())),
+                    selection_set: Selection::from_ast(&fragment.selection_set()?, fragments),
+                }),
+                ast::Selection::FragmentSpread(spread) => {
+                    let name = text(spread.fragment_name()?.name())?;
+                    let fragment = fragments.get(&name)?;
+                    Some(Selection::Fragment {
+                        type_condition: Some(text(
+                            fragment.type_condition()?.named_type()?.name(),
+                        )?),
+                        selection_set: Selection::from_ast(&fragment.selection_set()?, fragments),
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/// Computes the cost of operations against a supergraph schema
+#[derive(Debug)]
+pub(crate) struct CostCalculator {
+    /// Field definitions by type name and field name
+    fields: HashMap<String, HashMap<String, FieldDefinition>>,
+    /// Scalars and enums
+    leaf_types: HashSet<String>,
+    /// Interfaces and unions
+    abstract_types: HashSet<String>,
+    root_types: HashMap<OperationKind, String>,
+    list_size: f64,
+    slicing_arguments: Vec<String>,
+    /// Parsed fetch operations, by operation string
+    operations: Mutex<LruCache<String, Arc<Operation>>>,
+}
+
+impl CostCalculator {
+    pub(crate) fn new(schema: &str, config: &Config) -> Result<Self, BoxError> {
+        let tree = apollo_parser::Parser::new(schema).parse();
+        if let Some(error) = tree.errors().next() {
+            return Err(format!("could not parse the supergraph schema: {:?}", error).into());
+        }
+
+        let mut calculator = CostCalculator {
+            fields: HashMap::new(),
+            leaf_types: HashSet::new(),
+            abstract_types: HashSet::new(),
+            root_types: HashMap::new(),
+            list_size: config.list_size as f64,
+            slicing_arguments: config.slicing_arguments.clone(),
+            operations: Mutex::new(LruCache::new(
+                NonZeroUsize::new(OPERATION_CACHE_SIZE).expect("cache size is not zero"),
+            )),
+        };
+        for definition in tree.document().definitions() {
+            match definition {
+                ast::Definition::ObjectTypeDefinition(object) => {
+                    calculator.add_fields(object.name(), object.fields_definition())
+                }
+                ast::Definition::ObjectTypeExtension(object) => {
+                    calculator.add_fields(object.name(), object.fields_definition())
+                }
+                ast::Definition::InterfaceTypeDefinition(interface) => {
+                    calculator.abstract_types.extend(text(interface.name()));
+                    calculator.add_fields(interface.name(), interface.fields_definition())
+                }
+                ast::Definition::InterfaceTypeExtension(interface) => {
+                    calculator.add_fields(interface.name(), interface.fields_definition())
+                }
+                ast::Definition::UnionTypeDefinition(union) => {
+                    calculator.abstract_types.extend(text(union.name()));
+                }
+                ast::Definition::ScalarTypeDefinition(scalar) => {
+                    calculator.leaf_types.extend(text(scalar.name()));
+                }
+                ast::Definition::EnumTypeDefinition(enum_type) => {
+                    calculator.leaf_types.extend(text(enum_type.name()));
+                }
+                ast::Definition::SchemaDefinition(schema) => {
+                    for operation in schema.root_operation_type_definitions() {
+                        if let (Some(kind), Some(name)) = (
+                            operation.operation_type(),
+                            operation.named_type().and_then(|named| text(named.name())),
+                        ) {
+                            calculator.root_types.insert(kind.into(), name);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (coordinate, field_config) in &config.fields {
+            let definition = coordinate
+                .split_once('.')
+                .and_then(|(type_name, field_name)| {
+                    calculator.fields.get_mut(type_name)?.get_mut(field_name)
+                })
+                .ok_or_else(|| {
+                    format!("demand control: field '{coordinate}' is not in the supergraph schema")
+                })?;
+            if let Some(weight) = field_config.weight {
+                definition.weight = Some(weight);
+            }
+            if let Some(assumed_size) = field_config.assumed_size {
+                definition.assumed_size = Some(assumed_size as f64);
+            }
+            if let Some(slicing_arguments) = &field_config.slicing_arguments {
+                definition.slicing_arguments = Some(slicing_arguments.clone());
+            }
+        }
+
+        Ok(calculator)
+    }
+
+    fn add_fields(&mut self, type_name: Option<ast::Name>, fields: Option<ast::FieldsDefinition>) {
+        let type_name = match text(type_name) {
+            Some(type_name) => type_name,
+            None => return,
+        };
+        let definitions = self.fields.entry(type_name).or_default();
+        for field in fields.iter().flat_map(|fields| fields.field_definitions()) {
+            if let (Some(name), Some(definition)) =
+                (text(field.name()), FieldDefinition::from_ast(&field))
+            {
+                definitions.insert(name, definition);
+            }
+        }
+    }
+
+    fn root_type(&self, kind: OperationKind) -> &str {
+        self.root_types
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or_else(|| kind.as_str())
+    }
+
+    /// Parsed fetch operation, from the cache
+    fn operation(&self, operation: &str) -> Arc<Operation> {
+        if let Some(parsed) = self
+            .operations
+            .lock()
+            .expect("lock poisoned")
+            .get(operation)
+        {
+            return parsed.clone();
+        }
+
+        let parsed = Arc::new(Operation::parse(operation));
+        self.operations
+            .lock()
+            .expect("lock poisoned")
+            .put(operation.to_string(), parsed.clone());
+        parsed
+    }
+
+    fn field(&self, type_name: &str, field_name: &str) -> Option<&FieldDefinition> {
+        self.fields.get(type_name)?.get(field_name)
+    }
+
+    fn weight(&self, type_name: &str, definition: &FieldDefinition) -> f64 {
+        definition.weight.unwrap_or_else(|| {
+            if type_name == self.root_type(OperationKind::Mutation) {
+                MUTATION_WEIGHT
+            } else if definition
+                .type_name
+                .as_ref()
+                .map_or(true, |type_name| self.leaf_types.contains(type_name))
+            {
+                LEAF_WEIGHT
+            } else {
+                OBJECT_WEIGHT
+            }
+        })
+    }
+
+    /// Estimated size of a list: the highest value of its slicing arguments, or its assumed size.
+    /// Slicing arguments that are not positive are ignored, as negative sizes would cancel the
+    /// cost of the other fields.
+    fn list_size(&self, definition: &FieldDefinition, field: &Field, variables: &Object) -> f64 {
+        let slicing_arguments = definition
+            .slicing_arguments
+            .as_ref()
+            .unwrap_or(&self.slicing_arguments);
+
+        field
+            .arguments
+            .iter()
+            .filter(|(name, _)| slicing_arguments.contains(name))
+            .filter_map(|(_, argument)| match argument {
+                Argument::Value(value) => value.as_f64(),
+                Argument::Variable(variable) => {
+                    variables.get(variable.as_str()).and_then(Value::as_f64)
+                }
+            })
+            .filter(|size| *size > 0.0)
+            .reduce(f64::max)
+            .or(definition.assumed_size)
+            .unwrap_or(self.list_size)
+            .max(0.0)
+    }
+
+    /// Estimated cost of a query plan, with the variables of the request
+    pub(crate) fn estimated(&self, plan: &PlanNode, variables: &Object) -> f64 {
+        Estimator {
+            calculator: self,
+            variables,
+            multipliers: HashMap::new(),
+        }
+        .plan_node(plan, "")
+    }
+
+    /// Actual cost of a subgraph response, counting the objects and values it returned
+    pub(crate) fn actual(&self, operation: &str, kind: OperationKind, data: &Value) -> f64 {
+        let data = match data.as_object() {
+            Some(data) => data,
+            None => return 0.0,
+        };
+
+        self.operation(operation)
+            .selection_sets
+            .iter()
+            .map(|selection_set| {
+                self.actual_selection_set(selection_set, self.root_type(kind), data)
+            })
+            .sum()
+    }
+
+    fn actual_selection_set(
+        &self,
+        selection_set: &[Selection],
+        type_name: &str,
+        data: &Object,
+    ) -> f64 {
+        selection_set
+            .iter()
+            .map(|selection| match selection {
+                Selection::Field(field) => self.actual_field(field, type_name, data),
+                Selection::Fragment {
+                    type_condition,
+                    selection_set,
+                } => {
+                    let type_condition = type_condition.as_deref().unwrap_or(type_name);
+                    if self.applies(type_condition, data) {
+                        self.actual_selection_set(selection_set, type_condition, data)
+                    } else {
+                        0.0
+                    }
+                }
+            })
+            .sum()
+    }
+
+    fn actual_field(&self, field: &Field, type_name: &str, data: &Object) -> f64 {
+        let value = match data.get(field.response_key.as_str()) {
+            Some(value) => value,
+            None => return 0.0,
+        };
+
+        // the entities were counted by the fetch that returned their keys
+        if field.name == ENTITIES {
+            let selection_set = match &field.selection_set {
+                Some(selection_set) => selection_set,
+                None => return 0.0,
+            };
+            return value
+                .as_array()
+                .iter()
+                .flat_map(|entities| entities.iter())
+                .filter_map(Value::as_object)
+                .map(|entity| {
+                    let type_name = entity
+                        .get(TYPENAME)
+                        .and_then(Value::as_str)
+                        .unwrap_or(type_name);
+                    self.actual_selection_set(selection_set, type_name, entity)
+                })
+                .sum();
+        }
+
+        match self.field(type_name, &field.name) {
+            Some(definition) => self.actual_value(
+                field.selection_set.as_deref(),
+                definition,
+                self.weight(type_name, definition),
+                value,
+            ),
+            None => 0.0,
+        }
+    }
+
+    fn actual_value(
+        &self,
+        selection_set: Option<&[Selection]>,
+        definition: &FieldDefinition,
+        weight: f64,
+        value: &Value,
+    ) -> f64 {
+        match value {
+            Value::Null => 0.0,
+            Value::Array(values) => values
+                .iter()
+                .map(|value| self.actual_value(selection_set, definition, weight, value))
+                .sum(),
+            Value::Object(object) => {
+                weight
+                    + match (selection_set, &definition.type_name) {
+                        (Some(selection_set), Some(type_name)) => {
+                            self.actual_selection_set(selection_set, type_name, object)
+                        }
+                        _ => 0.0,
+                    }
+            }
+            _ => weight,
+        }
+    }
+
+    /// Whether a fragment applies to an object, from its `__typename` if it was requested
+    fn applies(&self, type_condition: &str, data: &Object) -> bool {
+        match data.get(TYPENAME).and_then(Value::as_str) {
+            Some(type_name) => {
+                type_name == type_condition || self.abstract_types.contains(type_condition)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Estimates the cost of the fetches of a query plan
+struct Estimator<'a> {
+    calculator: &'a CostCalculator,
+    variables: &'a Object,
+    /// Estimated number of occurrences of the objects at each response path, to estimate the
+    /// number of entities fetched by the flatten nodes
+    multipliers: HashMap<String, f64>,
+}
+
+impl<'a> Estimator<'a> {
+    fn plan_node(&mut self, node: &PlanNode, path: &str) -> f64 {
+        match node {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().map(|node| self.plan_node(node, path)).sum()
+            }
+            PlanNode::Fetch(fetch) => self.operation(&fetch.operation, fetch.operation_kind, path),
+            PlanNode::Flatten(flatten) => self.plan_node(&flatten.node, &flatten.path.to_string()),
+            PlanNode::Defer { primary, deferred } => {
+                primary
+                    .node
+                    .iter()
+                    .map(|node| self.plan_node(node, path))
+                    .sum::<f64>()
+                    + deferred
+                        .iter()
+                        .filter_map(|deferred| deferred.node.as_ref())
+                        .map(|node| self.plan_node(node, path))
+                        .sum::<f64>()
+            }
+            PlanNode::Condition {
+                condition,
+                if_clause,
+                else_clause,
+            } => {
+                let mut clause_cost = |clause: &Option<Box<PlanNode>>| {
+                    clause
+                        .as_ref()
+                        .map(|node| self.plan_node(node, path))
+                        .unwrap_or_default()
+                };
+                match self
+                    .variables
+                    .get(condition.as_str())
+                    .and_then(Value::as_bool)
+                {
+                    Some(true) => clause_cost(if_clause),
+                    Some(false) => clause_cost(else_clause),
+                    None => clause_cost(if_clause).max(clause_cost(else_clause)),
+                }
+            }
+            // the cost of a single event
+            PlanNode::Subscription { primary, rest } => {
+                self.operation(&primary.operation, primary.operation_kind, path)
+                    + rest
+                        .as_ref()
+                        .map(|node| self.plan_node(node, path))
+                        .unwrap_or_default()
+            }
+        }
+    }
+
+    fn operation(&mut self, operation: &str, kind: OperationKind, path: &str) -> f64 {
+        let calculator = self.calculator;
+        let root_type = calculator.root_type(kind);
+
+        calculator
+            .operation(operation)
+            .selection_sets
+            .iter()
+            .map(|selection_set| self.selection_set(selection_set, root_type, 1.0, path))
+            .sum()
+    }
+
+    fn selection_set(
+        &mut self,
+        selection_set: &[Selection],
+        type_name: &str,
+        multiplier: f64,
+        path: &str,
+    ) -> f64 {
+        selection_set
+            .iter()
+            .map(|selection| match selection {
+                Selection::Field(field) => self.field(field, type_name, multiplier, path),
+                Selection::Fragment {
+                    type_condition,
+                    selection_set,
+                } => self.selection_set(
+                    selection_set,
+                    type_condition.as_deref().unwrap_or(type_name),
+                    multiplier,
+                    path,
+                ),
+            })
+            .sum()
+    }
+
+    fn field(&mut self, field: &Field, type_name: &str, multiplier: f64, path: &str) -> f64 {
+        // the entities were counted by the fetch that returned their keys, there are as many
+        // as the objects at the path of the flatten node
+        if field.name == ENTITIES {
+            let multiplier = multiplier * self.multipliers.get(path).copied().unwrap_or(1.0);
+            return match &field.selection_set {
+                Some(selection_set) => {
+                    self.selection_set(selection_set, type_name, multiplier, path)
+                }
+                None => 0.0,
+            };
+        }
+
+        let calculator = self.calculator;
+        let definition = match calculator.field(type_name, &field.name) {
+            Some(definition) => definition,
+            None => return 0.0,
+        };
+        let occurrences = if definition.is_list {
+            multiplier * calculator.list_size(definition, field, self.variables)
+        } else {
+            multiplier
+        };
+        let mut cost = occurrences * calculator.weight(type_name, definition);
+
+        if let (Some(selection_set), Some(field_type)) =
+            (&field.selection_set, &definition.type_name)
+        {
+            let path = if definition.is_list {
+                format!("{path}/{}/@", field.response_key)
+            } else {
+                format!("{path}/{}", field.response_key)
+            };
+            self.multipliers.insert(path.clone(), occurrences);
+            cost += self.selection_set(selection_set, field_type, occurrences, &path);
+        }
+
+        cost
+    }
+}
+
+fn argument(directive: &ast::Directive, name: &str) -> Option<Value> {
+    directive
+        .arguments()?
+        .arguments()
+        .find(|argument| text(argument.name()).as_deref() == Some(name))?
+        .value()
+        .as_ref()
+        .and_then(parse_value)
+}
+
+/// Number from an `Int`, `Float` or `String` argument
+fn number(value: &Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|value| value.parse().ok()))
+}
+
+fn text(name: Option<ast::Name>) -> Option<String> {
+    name.map(|name| name.text().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        schema { query: Query mutation: Mutation }
+        directive @cost(weight: Int!) on FIELD_DEFINITION
+        directive @listSize(assumedSize: Int, slicingArguments: [String!]) on FIELD_DEFINITION
+        type Query {
+            products(first: Int, offset: Int): [Product!]! @listSize(slicingArguments: ["first"])
+            me: User
+        }
+        type Mutation {
+            review(body: String): Review
+        }
+        type Product {
+            upc: String!
+            name: String
+            reviews: [Review] @cost(weight: 2)
+        }
+        type Review {
+            body: String
+            author: User
+        }
+        type User {
+            id: ID!
+            favorites: [Product] @listSize(assumedSize: 3)
+        }
+    "#;
+
+    fn calculator(config: serde_json::Value) -> CostCalculator {
+        CostCalculator::new(SCHEMA, &serde_json::from_value(config).unwrap()).unwrap()
+    }
+
+    fn plan(plan: serde_json::Value) -> PlanNode {
+        serde_json::from_value(plan).unwrap()
+    }
+
+    fn variables(variables: serde_json_bytes::Value) -> Object {
+        variables.as_object().unwrap().clone()
+    }
+
+    fn fetch(operation: &str) -> serde_json::Value {
+        serde_json::json!({
+            "kind": "Fetch",
+            "serviceName": "products",
+            "variableUsages": [],
+            "operation": operation,
+            "operationKind": "query",
+        })
+    }
+
+    #[test]
+    fn it_estimates_the_cost_of_a_fetch() {
+        let calculator = calculator(serde_json::json!({ "max": 100.0 }));
+        let plan = plan(fetch(
+            "query($first:Int){products(first:$first){name reviews{body author{id}}}}",
+        ));
+
+        // 5 products, with 10 reviews costing 2 each, and their authors
+        assert_eq!(
+            calculator.estimated(&plan, &variables(json!({ "first": 5 }))),
+            5.0 + 5.0 * 10.0 * 2.0 + 5.0 * 10.0
+        );
+        // without slicing argument, the list has the default size
+        assert_eq!(
+            calculator.estimated(&plan, &Object::new()),
+            10.0 + 10.0 * 10.0 * 2.0 + 10.0 * 10.0
+        );
+
+        let plan = self::plan(fetch("{me{favorites{name}}}"));
+        assert_eq!(calculator.estimated(&plan, &Object::new()), 1.0 + 3.0);
+
+        let mut mutation = fetch("mutation{review(body:\"great\"){body}}");
+        mutation["operationKind"] = "mutation".into();
+        assert_eq!(
+            calculator.estimated(&self::plan(mutation), &Object::new()),
+            10.0
+        );
+    }
+
+    #[test]
+    fn it_estimates_the_entities_from_the_flatten_path() {
+        let calculator = calculator(serde_json::json!({ "max": 100.0 }));
+        let plan = plan(serde_json::json!({
+            "kind": "Sequence",
+            "nodes": [
+                fetch("{products(first:4){__typename upc}}"),
+                {
+                    "kind": "Flatten",
+                    "path": ["products", "@"],
+                    "node": fetch("query($representations:[_Any!]!){_entities(representations:$representations){...on Product{reviews{body}}}}"),
+                },
+            ],
+        }));
+
+        assert_eq!(
+            calculator.estimated(&plan, &Object::new()),
+            4.0 + 4.0 * 10.0 * 2.0
+        );
+    }
+
+    #[test]
+    fn it_ignores_slicing_arguments_that_are_not_positive() {
+        let calculator = calculator(serde_json::json!({ "max": 100.0 }));
+        let plan = plan(fetch(
+            "query($first:Int){a:products(first:-1000){name reviews{body}} b:products(first:$first){name reviews{body}}}",
+        ));
+
+        // both lists have the default size, instead of the negative one cancelling the other
+        assert_eq!(
+            calculator.estimated(&plan, &variables(json!({ "first": 0 }))),
+            2.0 * (10.0 + 10.0 * 10.0 * 2.0)
+        );
+        assert_eq!(
+            calculator.estimated(&plan, &variables(json!({ "first": 5 }))),
+            10.0 + 10.0 * 10.0 * 2.0 + 5.0 + 5.0 * 10.0 * 2.0
+        );
+    }
+
+    #[test]
+    fn it_uses_the_configured_fields() {
+        let calculator = calculator(serde_json::json!({
+            "max": 100.0,
+            "list_size": 2,
+            "fields": {
+                "Product.reviews": { "weight": 5.0, "slicing_arguments": ["limit"] },
+                "Query.products": { "assumed_size": 3 },
+            },
+        }));
+        let plan = plan(fetch("{products{reviews{body}}}"));
+
+        assert_eq!(
+            calculator.estimated(&plan, &Object::new()),
+            3.0 + 3.0 * 2.0 * 5.0
+        );
+
+        let config = serde_json::from_value(serde_json::json!({
+            "max": 100.0,
+            "fields": { "Product.unknown": { "weight": 5.0 } },
+        }))
+        .unwrap();
+        assert!(CostCalculator::new(SCHEMA, &config).is_err());
+    }
+
+    #[test]
+    fn it_computes_the_actual_cost_of_responses() {
+        let calculator = calculator(serde_json::json!({ "max": 100.0 }));
+
+        let data = json!({
+            "products": [
+                { "name": "a", "reviews": [{ "body": "x", "author": { "id": "1" } }, { "body": "y", "author": null }] },
+                { "name": "b", "reviews": [] },
+            ]
+        });
+        assert_eq!(
+            calculator.actual(
+                "{products(first:5){name reviews{body author{id}}}}",
+                OperationKind::Query,
+                &data
+            ),
+            2.0 + 2.0 * 2.0 + 1.0
+        );
+
+        let data = json!({
+            "_entities": [
+                { "__typename": "Product", "reviews": [{ "body": "x" }] },
+                { "__typename": "Product", "reviews": null },
+            ]
+        });
+        assert_eq!(
+            calculator.actual(
+                "query($representations:[_Any!]!){_entities(representations:$representations){...on Product{reviews{body}}}}",
+                OperationKind::Query,
+                &data
+            ),
+            2.0
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Demand control: static cost analysis of operations, and rejection of the expensive ones
+//!
+//! The cost of an operation is estimated from its query plan before it is executed, and
+//! operations whose estimated cost is above the configured maximum are rejected. The actual
+//! cost can also be computed from the subgraph responses, to compare it to the estimation.
+
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json_bytes::json;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use self::cost::CostCalculator;
+use crate::error::Error;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::OperationKind;
+use crate::register_plugin;
+use crate::services::execution;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+mod cost;
+
+register_plugin!("apollo", "demand_control", DemandControl);
+
+pub(crate) const ESTIMATED_COST_CONTEXT_KEY: &str = "apollo_router::demand_control::estimated_cost";
+pub(crate) const ACTUAL_COST_CONTEXT_KEY: &str = "apollo_router::demand_control::actual_cost";
+
+/// Configuration for demand control
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Maximum estimated cost of an operation
+    max: f64,
+    /// What to do with the operations whose estimated cost is higher than `max`
+    #[serde(default)]
+    mode: Mode,
+    /// Size of the lists that have no slicing argument or assumed size
+    #[serde(default = "default_list_size")]
+    list_size: u32,
+    /// Arguments setting the size of lists, for the lists with no `@listSize` directive
+    #[serde(default = "default_slicing_arguments")]
+    slicing_arguments: Vec<String>,
+    /// Cost of fields by coordinate like `Query.products`, replacing their directives
+    #[serde(default)]
+    fields: HashMap<String, FieldConfig>,
+    /// Compute the actual cost of operations from the subgraph responses, and compare it to
+    /// the estimated cost
+    #[serde(default)]
+    actual_cost: bool,
+}
+
+fn default_list_size() -> u32 {
+    10
+}
+
+fn default_slicing_arguments() -> Vec<String> {
+    vec!["first".to_string(), "last".to_string(), "limit".to_string()]
+}
+
+/// Cost of a field
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct FieldConfig {
+    /// Cost of each occurrence of the field, replacing its `@cost` directive
+    weight: Option<f64>,
+    /// Size of the list returned by the field if it has no slicing argument
+    assumed_size: Option<u32>,
+    /// Arguments setting the size of the list returned by the field
+    slicing_arguments: Option<Vec<String>>,
+}
+
+/// Handling of the operations over the maximum cost
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Mode {
+    /// Only log the operations over the maximum cost
+    Measure,
+    /// Reject the operations over the maximum cost (default)
+    Enforce,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Enforce
+    }
+}
+
+struct DemandControl {
+    calculator: Arc<CostCalculator>,
+    max: f64,
+    mode: Mode,
+    actual_cost: bool,
+}
+
+#[async_trait::async_trait]
+impl Plugin for DemandControl {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Self {
+            calculator: Arc::new(CostCalculator::new(&init.supergraph_sdl, &init.config)?),
+            max: init.config.max,
+            mode: init.config.mode,
+            actual_cost: init.config.actual_cost,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.actual_cost {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_response(|response: supergraph::Response| {
+                let context = response.context.clone();
+                response.map_stream(move |response| {
+                    // the deferred responses are counted with the last one
+                    if !response.has_next.unwrap_or_default() {
+                        record_actual_cost(&context);
+                    }
+                    response
+                })
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let calculator = self.calculator.clone();
+        let max = self.max;
+        let mode = self.mode;
+
+        ServiceBuilder::new()
+            .checkpoint(move |request: execution::Request| {
+                let cost = calculator.estimated(
+                    &request.query_plan.root,
+                    &request.supergraph_request.body().variables,
+                );
+                request.context.insert(ESTIMATED_COST_CONTEXT_KEY, cost)?;
+                // This is a metric and will not appear in the logs
+                tracing::info!(histogram.apollo_router_operation_cost_estimated = cost);
+
+                if cost <= max {
+                    return Ok(ControlFlow::Continue(request));
+                }
+                match mode {
+                    Mode::Measure => {
+                        tracing::warn!(
+                            "operation estimated cost {} is over the maximum cost {}",
+                            cost,
+                            max
+                        );
+                        Ok(ControlFlow::Continue(request))
+                    }
+                    Mode::Enforce => {
+                        let error = Error::builder()
+                            .message(format!(
+                                "operation estimated cost {cost} is over the maximum cost {max}"
+                            ))
+                            .extension_code("COST_ESTIMATED_TOO_EXPENSIVE")
+                            .extension("cost", json!({ "estimated": cost, "max": max }))
+                            .build();
+                        let response = execution::Response::builder()
+                            .error(error)
+                            .status_code(StatusCode::BAD_REQUEST)
+                            .context(request.context)
+                            .build()?;
+                        Ok(ControlFlow::Break(response))
+                    }
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.actual_cost {
+            return service;
+        }
+
+        let calculator = self.calculator.clone();
+        let name = name.to_string();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |request: &subgraph::Request| {
+                    (
+                        request.subgraph_request.body().query.clone(),
+                        request.operation_kind,
+                    )
+                },
+                move |(query, operation_kind): (Option<String>, OperationKind), future| {
+                    let calculator = calculator.clone();
+                    let name = name.clone();
+                    let future: BoxFuture<'static, Result<subgraph::Response, BoxError>> =
+                        Box::pin(async move {
+                            let response: subgraph::Response = future.await?;
+                            if let (Some(query), Some(data)) =
+                                (query, &response.response.body().data)
+                            {
+                                let cost = calculator.actual(&query, operation_kind, data);
+                                if let Err(err) = response
+                                    .context
+                                    .upsert(ACTUAL_COST_CONTEXT_KEY, |actual: f64| actual + cost)
+                                {
+                                    tracing::error!(
+                                        "cannot store the actual cost of subgraph '{}': {:?}",
+                                        name,
+                                        err
+                                    );
+                                }
+                            }
+                            Ok(response)
+                        });
+                    future
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+/// Records the actual cost of an operation, and its difference with the estimated cost
+fn record_actual_cost(context: &Context) {
+    let actual: f64 = context
+        .get(ACTUAL_COST_CONTEXT_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    // This is a metric and will not appear in the logs
+    tracing::info!(histogram.apollo_router_operation_cost_actual = actual);
+
+    if let Some(estimated) = context
+        .get::<_, f64>(ESTIMATED_COST_CONTEXT_KEY)
+        .ok()
+        .flatten()
+    {
+        // This is a metric and will not appear in the logs
+        tracing::info!(histogram.apollo_router_operation_cost_delta = actual - estimated);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+    use tower::Service;
+
+    use super::*;
+    use crate::plugin::test::MockExecutionService;
+    use crate::query_planner::QueryPlan;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            products(first: Int): [Product]
+        }
+        type Product {
+            name: String
+        }
+    "#;
+
+    async fn execution_service(mode: &str) -> execution::BoxService {
+        let mut mock_service = MockExecutionService::new();
+        mock_service.expect_call().returning(|request| {
+            Ok(execution::Response::fake_builder()
+                .context(request.context)
+                .build()
+                .unwrap())
+        });
+
+        let config =
+            serde_json::from_value(serde_json::json!({ "max": 20.0, "mode": mode })).unwrap();
+        DemandControl::new(PluginInit::new(config, Arc::new(SCHEMA.to_string())))
+            .await
+            .unwrap()
+            .execution_service(mock_service.boxed())
+    }
+
+    fn request(first: usize) -> execution::Request {
+        let root = serde_json::from_value(serde_json::json!({
+            "kind": "Fetch",
+            "serviceName": "products",
+            "variableUsages": ["first"],
+            "operation": "query($first:Int){products(first:$first){name}}",
+            "operationKind": "query",
+        }))
+        .unwrap();
+
+        execution::Request::fake_builder()
+            .supergraph_request(
+                http::Request::builder()
+                    .body(
+                        crate::graphql::Request::fake_builder()
+                            .variable("first", first)
+                            .build(),
+                    )
+                    .unwrap(),
+            )
+            .query_plan(QueryPlan::fake_builder().root(root).build())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn it_rejects_operations_over_the_maximum_cost() {
+        let mut service = execution_service("enforce").await;
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(20))
+            .await
+            .unwrap();
+        let estimated: Option<f64> = response.context.get(ESTIMATED_COST_CONTEXT_KEY).unwrap();
+        assert_eq!(estimated, Some(20.0));
+
+        let mut response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(21))
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let error = response.next_response().await.unwrap().errors.remove(0);
+        assert_eq!(
+            error.extensions.get("code"),
+            Some(&json!("COST_ESTIMATED_TOO_EXPENSIVE"))
+        );
+        assert_eq!(
+            error.extensions.get("cost"),
+            Some(&json!({ "estimated": 21.0, "max": 20.0 }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_executes_operations_over_the_maximum_cost_in_measure_mode() {
+        let mut service = execution_service("measure").await;
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(21))
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        let estimated: Option<f64> = response.context.get(ESTIMATED_COST_CONTEXT_KEY).unwrap();
+        assert_eq!(estimated, Some(21.0));
+    }
+}
@@ -0,0 +1,520 @@
+//! Entity cache for subgraph `_entities` fetches
+//!
+//! The entities returned by a subgraph are cached per representation. When a fetch asks for
+//! representations that were already resolved, only the missing ones are sent to the subgraph,
+//! and the cached entities are merged back into the subgraph response.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::header::CACHE_CONTROL;
+use http::HeaderMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::Service;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::cache::policy::CachePolicy;
+use crate::cache::service::now;
+use crate::cache::service::CacheService;
+use crate::cache::storage::CacheStorage;
+use crate::configuration::Cache;
+use crate::json_ext::Object;
+use crate::json_ext::PathElement;
+use crate::json_ext::Value;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::fetch::OperationKind;
+use crate::register_plugin;
+use crate::services::subgraph;
+
+register_plugin!("apollo", "entity_cache", EntityCache);
+
+const REPRESENTATIONS: &str = "representations";
+const ENTITIES: &str = "_entities";
+
+/// Configuration for the entity cache
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Storage of the cached entities
+    #[serde(default)]
+    cache: Cache,
+    /// Applied on all subgraphs
+    #[serde(default)]
+    all: Option<SubgraphConfig>,
+    /// Applied on specific subgraphs
+    #[serde(default)]
+    subgraphs: HashMap<String, SubgraphConfig>,
+}
+
+/// Per subgraph configuration for the entity cache
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct SubgraphConfig {
+    /// Cache the entities returned by the subgraph (disabled by default)
+    enabled: Option<bool>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// Time to live of the entities, when the subgraph response has no `Cache-Control` max-age
+    ttl: Option<Duration>,
+    /// Client request headers that are part of the cache key, for instance `authorization`
+    headers: Option<Vec<String>>,
+}
+
+impl SubgraphConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => SubgraphConfig {
+                enabled: self.enabled.or(fallback.enabled),
+                ttl: self.ttl.or(fallback.ttl),
+                headers: self.headers.clone().or_else(|| fallback.headers.clone()),
+            },
+        }
+    }
+}
+
+/// A cached entity, with its expiration as a UNIX timestamp in seconds
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CachedEntity {
+    data: Value,
+    expires_at: u64,
+}
+
+struct EntityCache {
+    storage: CacheStorage<String, CachedEntity>,
+    config: Config,
+}
+
+#[async_trait::async_trait]
+impl Plugin for EntityCache {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let storage = CacheStorage::new(
+            init.config.cache.in_memory.limit,
+            #[cfg(feature = "experimental_cache")]
+            init.config.cache.redis.clone(),
+            "entity",
+        )
+        .await;
+
+        Ok(Self {
+            storage,
+            config: init.config,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let all_config = self.config.all.as_ref();
+        let config = match self.config.subgraphs.get(name) {
+            Some(subgraph_config) => subgraph_config.merge(all_config),
+            None => all_config.cloned().unwrap_or_default(),
+        };
+        if !config.enabled.unwrap_or_default() {
+            return service;
+        }
+
+        let cache = Arc::new(SubgraphEntityCache {
+            storage: self.storage.clone(),
+            subgraph_name: name.to_string(),
+            ttl: config.ttl,
+            headers: config.headers.unwrap_or_default(),
+        });
+        CacheService::new(
+            ServiceBuilder::new().buffered().service(service),
+            move |inner, request: subgraph::Request| cache.clone().call(inner, request),
+        )
+        .boxed()
+    }
+}
+
+struct SubgraphEntityCache {
+    storage: CacheStorage<String, CachedEntity>,
+    subgraph_name: String,
+    ttl: Option<Duration>,
+    headers: Vec<String>,
+}
+
+impl SubgraphEntityCache {
+    async fn call<S>(
+        self: Arc<Self>,
+        mut inner: S,
+        mut request: subgraph::Request,
+    ) -> Result<subgraph::Response, BoxError>
+    where
+        S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>,
+    {
+        let representations = match request
+            .subgraph_request
+            .body()
+            .variables
+            .get(REPRESENTATIONS)
+        {
+            Some(Value::Array(representations))
+                if request.operation_kind == OperationKind::Query =>
+            {
+                Some(representations.clone())
+            }
+            _ => None,
+        };
+        let representations = match representations {
+            Some(representations) => representations,
+            None => return inner.call(request).await,
+        };
+
+        let base_hasher = self.base_hasher(&request)?;
+        let keys = representations
+            .iter()
+            .map(|representation| self.entity_key(&base_hasher, representation))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let now = now();
+        let mut entities: Vec<Option<Value>> = Vec::with_capacity(keys.len());
+        let mut expires_at = u64::MAX;
+        for entity in self.storage.get_multiple(&keys).await {
+            let entity = entity.filter(|entity| entity.expires_at > now);
+            if let Some(entity) = &entity {
+                expires_at = expires_at.min(entity.expires_at);
+            }
+            entities.push(entity.map(|entity| entity.data));
+        }
+        let missing: Vec<usize> = entities
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entity)| entity.is_none().then_some(index))
+            .collect();
+
+        if missing.is_empty() {
+            tracing::trace!("all the entities were found in cache");
+            let mut data = Object::new();
+            data.insert(
+                ENTITIES,
+                Value::Array(entities.into_iter().flatten().collect()),
+            );
+            let mut response = subgraph::Response::builder()
+                .data(Value::Object(data))
+                .extensions(Object::new())
+                .context(request.context)
+                .build();
+            // The response can be reused until the first of its entities expires
+            let policy = CachePolicy {
+                max_age: Some(expires_at - now),
+                ..Default::default()
+            };
+            response
+                .response
+                .headers_mut()
+                .insert(CACHE_CONTROL, policy.header_value());
+            return Ok(response);
+        }
+
+        // Only the representations missing from the cache are sent to the subgraph
+        if let Some(Value::Array(sent)) = request
+            .subgraph_request
+            .body_mut()
+            .variables
+            .get_mut(REPRESENTATIONS)
+        {
+            *sent = missing
+                .iter()
+                .map(|index| representations[*index].clone())
+                .collect();
+        }
+
+        let mut response = inner.call(request).await?;
+        let ttl = response_ttl(response.response.headers(), self.ttl);
+        let body = response.response.body_mut();
+
+        // Errors point at the index of the representations that were sent, so they are moved to
+        // the index of the representation in the original request. An error that does not point
+        // to an entity could apply to any of them, so nothing gets cached.
+        let mut cacheable = true;
+        let mut failed: HashSet<usize> = HashSet::new();
+        for error in body.errors.iter_mut() {
+            match error.path.as_mut().map(|path| path.0.as_mut_slice()) {
+                Some([PathElement::Key(key), PathElement::Index(index), ..])
+                    if key.as_str() == ENTITIES =>
+                {
+                    failed.insert(*index);
+                    if let Some(original_index) = missing.get(*index) {
+                        *index = *original_index;
+                    }
+                }
+                _ => cacheable = false,
+            }
+        }
+
+        let received = match body
+            .data
+            .as_mut()
+            .and_then(|data| data.as_object_mut())
+            .and_then(|data| data.get_mut(ENTITIES))
+        {
+            Some(Value::Array(received)) => received,
+            // The fetch will report the missing entities
+            _ => return Ok(response),
+        };
+
+        for (sent_index, original_index) in missing.into_iter().enumerate() {
+            let entity = received.get(sent_index).cloned().unwrap_or_default();
+            if let Some(ttl) = ttl {
+                if cacheable && !failed.contains(&sent_index) && !entity.is_null() {
+                    self.storage
+                        .insert(
+                            keys[original_index].clone(),
+                            CachedEntity {
+                                data: entity.clone(),
+                                expires_at: now + ttl.as_secs(),
+                            },
+                        )
+                        .await;
+                }
+            }
+            entities[original_index] = Some(entity);
+        }
+        *received = entities
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect();
+
+        Ok(response)
+    }
+
+    /// Hash of the parts of the key shared by all the entities of a request: the subgraph
+    /// query, its other variables and the configured client headers
+    fn base_hasher(&self, request: &subgraph::Request) -> Result<Sha256, BoxError> {
+        let body = request.subgraph_request.body();
+        let mut hasher = Sha256::new();
+        hasher.update(body.query.as_deref().unwrap_or_default());
+        for (name, value) in body
+            .variables
+            .iter()
+            .filter(|(name, _)| name.as_str() != REPRESENTATIONS)
+        {
+            hasher.update(name.as_str());
+            hasher.update(serde_json::to_vec(value)?);
+        }
+        for header in &self.headers {
+            for value in request.supergraph_request.headers().get_all(header) {
+                hasher.update(header);
+                hasher.update(value.as_bytes());
+            }
+        }
+        Ok(hasher)
+    }
+
+    fn entity_key(&self, base_hasher: &Sha256, representation: &Value) -> Result<String, BoxError> {
+        let typename = representation
+            .as_object()
+            .and_then(|representation| representation.get("__typename"))
+            .and_then(|typename| typename.as_str())
+            .unwrap_or_default();
+        let mut hasher = base_hasher.clone();
+        hasher.update(serde_json::to_vec(representation)?);
+
+        Ok(format!(
+            "entity:{}:{}:{}",
+            self.subgraph_name,
+            typename,
+            hex::encode(hasher.finalize())
+        ))
+    }
+}
+
+/// Time to live of the entities of a subgraph response, from its `Cache-Control` header or
+/// the configured default. Returns `None` if they must not be cached.
+fn response_ttl(headers: &HeaderMap, default: Option<Duration>) -> Option<Duration> {
+    match CachePolicy::from_headers(headers) {
+        Some(policy) if policy.no_store || policy.private => None,
+        Some(CachePolicy {
+            max_age: Some(max_age),
+            ..
+        }) => Some(Duration::from_secs(max_age)),
+        _ => default,
+    }
+    .filter(|ttl| !ttl.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql;
+    use crate::plugin::test::MockSubgraphService;
+
+    fn entity_request(ids: &[&str]) -> subgraph::Request {
+        let representations: Vec<Value> = ids
+            .iter()
+            .map(|id| json!({"__typename": "User", "id": id}))
+            .collect();
+        subgraph::Request::fake_builder()
+            .subgraph_request(
+                http::Request::builder()
+                    .body(
+                        graphql::Request::fake_builder()
+                            .query("query($representations:[_Any!]!){_entities(representations:$representations){...on User{name}}}")
+                            .variables(
+                                json!({ "representations": representations })
+                                    .as_object()
+                                    .unwrap()
+                                    .clone(),
+                            )
+                            .build(),
+                    )
+                    .unwrap(),
+            )
+            .build()
+    }
+
+    fn entity_names(response: &subgraph::Response) -> Vec<String> {
+        response
+            .response
+            .body()
+            .data
+            .as_ref()
+            .and_then(|data| data.as_object())
+            .and_then(|data| data.get(ENTITIES))
+            .and_then(|entities| entities.as_array())
+            .expect("the response must have entities")
+            .iter()
+            .map(|entity| entity.as_object().unwrap().get("name").unwrap())
+            .map(|name| name.as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_only_fetches_the_missing_entities() -> Result<(), BoxError> {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sent_by_mock = sent.clone();
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call().times(2).returning(move |request| {
+            let ids: Vec<String> = request
+                .subgraph_request
+                .body()
+                .variables
+                .get(REPRESENTATIONS)
+                .and_then(|representations| representations.as_array())
+                .unwrap()
+                .iter()
+                .filter_map(|representation| representation.as_object()?.get("id")?.as_str())
+                .map(str::to_string)
+                .collect();
+            let entities: Vec<Value> = ids
+                .iter()
+                .map(|id| json!({ "name": format!("name-{}", id) }))
+                .collect();
+            sent_by_mock.lock().unwrap().push(ids);
+            Ok(subgraph::Response::fake_builder()
+                .data(json!({ "_entities": entities }))
+                .build())
+        });
+
+        let plugin = EntityCache::new(PluginInit::new(
+            serde_json::from_value(serde_json::json!({
+                "subgraphs": {
+                    "accounts": {
+                        "enabled": true,
+                        "ttl": "60s"
+                    }
+                }
+            }))?,
+            Default::default(),
+        ))
+        .await?;
+        let mut service = plugin.subgraph_service("accounts", mock.boxed());
+
+        let response = service
+            .ready()
+            .await?
+            .call(entity_request(&["1", "2"]))
+            .await?;
+        assert_eq!(entity_names(&response), vec!["name-1", "name-2"]);
+
+        let response = service
+            .ready()
+            .await?
+            .call(entity_request(&["2", "3"]))
+            .await?;
+        assert_eq!(entity_names(&response), vec!["name-2", "name-3"]);
+
+        // Every entity is cached, the subgraph is not called
+        let response = service
+            .ready()
+            .await?
+            .call(entity_request(&["3", "1"]))
+            .await?;
+        assert_eq!(entity_names(&response), vec!["name-3", "name-1"]);
+
+        assert_eq!(*sent.lock().unwrap(), vec![vec!["1", "2"], vec!["3"]]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_does_not_cache_disabled_subgraphs() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call().times(2).returning(|_| {
+            Ok(subgraph::Response::fake_builder()
+                .data(json!({ "_entities": [{ "name": "name-1" }] }))
+                .build())
+        });
+
+        let plugin = EntityCache::new(PluginInit::new(
+            serde_json::from_value(serde_json::json!({
+                "all": {
+                    "enabled": true,
+                    "ttl": "60s"
+                },
+                "subgraphs": {
+                    "accounts": {
+                        "enabled": false
+                    }
+                }
+            }))?,
+            Default::default(),
+        ))
+        .await?;
+        let mut service = plugin.subgraph_service("accounts", mock.boxed());
+
+        for _ in 0..2 {
+            service.ready().await?.call(entity_request(&["1"])).await?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_ttl() {
+        let default = Some(Duration::from_secs(30));
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CACHE_CONTROL, value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(response_ttl(&HeaderMap::new(), default), default);
+        assert_eq!(response_ttl(&HeaderMap::new(), None), None);
+        assert_eq!(
+            response_ttl(&headers("public, max-age=60"), default),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            response_ttl(&headers("max-age=60, s-maxage=120"), None),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(response_ttl(&headers("max-age=0"), default), None);
+        assert_eq!(response_ttl(&headers("private, max-age=60"), default), None);
+        assert_eq!(response_ttl(&headers("no-store"), default), None);
+    }
+}
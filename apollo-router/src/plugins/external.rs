@@ -8,10 +8,14 @@ use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
-use bytes::Bytes;
+use futures::future::ready;
+use futures::future::BoxFuture;
+use futures::stream::once;
+use futures::StreamExt;
 use http::header::HeaderName;
 use http::HeaderMap;
 use http::HeaderValue;
+use http::StatusCode;
 use hyper::body;
 use hyper::Body;
 use schemars::JsonSchema;
@@ -24,15 +28,20 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 
 use crate::error::Error;
+use crate::graphql;
+use crate::json_ext::Object;
 use crate::layers::async_checkpoint::AsyncCheckpointLayer;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::register_plugin;
+use crate::services::execution;
 use crate::services::external::Control;
 use crate::services::external::Externalizable;
 use crate::services::external::PipelineStep;
 use crate::services::router;
+use crate::services::subgraph;
+use crate::services::supergraph;
 use crate::Context;
 
 pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
@@ -41,6 +50,36 @@ pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
 struct ExternalPlugin {
     configuration: Conf,
     sdl: Arc<String>,
+    coprocessor: Coprocessor,
+}
+
+type CoprocessorMessage = Externalizable<serde_json::Value>;
+type CoprocessorFuture = BoxFuture<'static, Result<CoprocessorMessage, BoxError>>;
+
+/// Sends the externalized data to the co-processor and returns its reply
+#[derive(Clone)]
+struct Coprocessor(Arc<dyn Fn(CoprocessorMessage) -> CoprocessorFuture + Send + Sync>);
+
+impl Coprocessor {
+    fn new(url: String, timeout: Option<Duration>) -> Self {
+        Self(Arc::new(
+            move |output: CoprocessorMessage| -> CoprocessorFuture {
+                let url = url.clone();
+                Box::pin(async move { output.call(&url, timeout).await })
+            },
+        ))
+    }
+
+    async fn call(&self, output: CoprocessorMessage) -> Result<CoprocessorMessage, BoxError> {
+        tracing::debug!(?output, "externalized output");
+        (self.0)(output).await
+    }
+}
+
+impl fmt::Debug for Coprocessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coprocessor").finish()
+    }
 }
 
 /// What information is passed to a request/response stage
@@ -60,8 +99,9 @@ struct BaseConf {
     sdl: bool,
 }
 
+/// The request/response configuration of a stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
-struct RouterStage {
+struct Stage {
     /// The request configuration
     #[serde(default)]
     request: Option<BaseConf>,
@@ -75,7 +115,16 @@ struct RouterStage {
 struct Stages {
     /// The router stage
     #[serde(default)]
-    router: Option<RouterStage>,
+    router: Option<Stage>,
+    /// The supergraph stage
+    #[serde(default)]
+    supergraph: Option<Stage>,
+    /// The execution stage
+    #[serde(default)]
+    execution: Option<Stage>,
+    /// The subgraph stage, invoked for every subgraph request
+    #[serde(default)]
+    subgraph: Option<Stage>,
 }
 
 /// Configures the externalization plugin
@@ -98,6 +147,7 @@ impl Plugin for ExternalPlugin {
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
         Ok(ExternalPlugin {
+            coprocessor: Coprocessor::new(init.config.url.clone(), init.config.timeout),
             configuration: init.config,
             sdl: init.supergraph_sdl,
         })
@@ -110,6 +160,9 @@ impl Plugin for ExternalPlugin {
         let request_full_config = self.configuration.clone();
         let response_full_config = self.configuration.clone();
 
+        let request_coprocessor = self.coprocessor.clone();
+        let response_coprocessor = self.coprocessor.clone();
+
         let request_layer = if self
             .configuration
             .stages
@@ -129,8 +182,7 @@ impl Plugin for ExternalPlugin {
             Some(AsyncCheckpointLayer::new(
                 move |mut request: router::Request| {
                     let my_sdl = request_sdl.to_string();
-                    let proto_url = request_full_config.url.clone();
-                    let timeout = request_full_config.timeout;
+                    let coprocessor = request_coprocessor.clone();
                     let request_config = request_config.clone();
                     async move {
                         // Call into our out of process processor with a body of our body
@@ -143,7 +195,7 @@ impl Plugin for ExternalPlugin {
                         let (headers, payload, context, sdl) = prepare_external_params(
                             &request_config,
                             &parts.headers,
-                            &b_bytes,
+                            || Ok(serde_json::from_slice(&b_bytes)?),
                             &request.context,
                             my_sdl,
                         )?;
@@ -152,8 +204,7 @@ impl Plugin for ExternalPlugin {
 
                         // Second, call our co-processor and get a reply.
                         let res = call_external(
-                            proto_url,
-                            timeout,
+                            &coprocessor,
                             PipelineStep::RouterRequest,
                             headers,
                             payload,
@@ -248,8 +299,7 @@ impl Plugin for ExternalPlugin {
                 .unwrap();
             Some(MapFutureLayer::new(move |fut| {
                 let my_sdl = response_sdl.to_string();
-                let proto_url = response_full_config.url.clone();
-                let timeout = response_full_config.timeout;
+                let coprocessor = response_coprocessor.clone();
                 let response_config = response_config.clone();
                 async move {
                     let mut response: router::Response = fut.await?;
@@ -264,15 +314,14 @@ impl Plugin for ExternalPlugin {
                     let (headers, payload, context, sdl) = prepare_external_params(
                         &response_config,
                         &parts.headers,
-                        &b_bytes,
+                        || Ok(serde_json::from_slice(&b_bytes)?),
                         &response.context,
                         my_sdl,
                     )?;
 
                     // Second, call our co-processor and get a reply.
                     let co_processor_output = call_external(
-                        proto_url,
-                        timeout,
+                        &coprocessor,
                         PipelineStep::RouterResponse,
                         headers,
                         payload,
@@ -328,6 +377,443 @@ impl Plugin for ExternalPlugin {
             .service(service)
             .boxed()
     }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let stage = self
+            .configuration
+            .stages
+            .as_ref()
+            .and_then(|x| x.supergraph.as_ref());
+
+        let request_layer = stage.and_then(|x| x.request.clone()).map(|request_config| {
+            let sdl = self.sdl.clone();
+            let coprocessor = self.coprocessor.clone();
+            AsyncCheckpointLayer::new(move |mut request: supergraph::Request| {
+                let my_sdl = sdl.to_string();
+                let coprocessor = coprocessor.clone();
+                let request_config = request_config.clone();
+                async move {
+                    let flow = process_graphql_request(
+                        &coprocessor,
+                        &request_config,
+                        PipelineStep::SupergraphRequest,
+                        None,
+                        &mut request.supergraph_request,
+                        &mut request.context,
+                        my_sdl,
+                    )
+                    .await?;
+
+                    Ok(match flow {
+                        ControlFlow::Break((code, body)) => ControlFlow::Break(
+                            supergraph_break_response(code, body, request.context)?,
+                        ),
+                        ControlFlow::Continue(()) => ControlFlow::Continue(request),
+                    })
+                }
+            })
+        });
+
+        let response_layer = stage
+            .and_then(|x| x.response.clone())
+            .map(|response_config| {
+                let sdl = self.sdl.clone();
+                let coprocessor = self.coprocessor.clone();
+                MapFutureLayer::new(move |fut| {
+                    let my_sdl = sdl.to_string();
+                    let coprocessor = coprocessor.clone();
+                    let response_config = response_config.clone();
+                    async move {
+                        let response: supergraph::Response = fut.await?;
+
+                        process_graphql_response(
+                            &coprocessor,
+                            &response_config,
+                            PipelineStep::SupergraphResponse,
+                            response,
+                            my_sdl,
+                        )
+                        .await
+                    }
+                })
+            });
+
+        if request_layer.is_none() && response_layer.is_none() {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .instrument(|_request: &supergraph::Request| {
+                tracing::info_span!(
+                    EXTERNAL_SPAN_NAME,
+                    "external service" = stringify!(supergraph::Request),
+                    "otel.kind" = "INTERNAL"
+                )
+            })
+            .option_layer(request_layer)
+            .option_layer(response_layer)
+            .buffered()
+            .service(service)
+            .boxed()
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let stage = self
+            .configuration
+            .stages
+            .as_ref()
+            .and_then(|x| x.execution.as_ref());
+
+        let request_layer = stage.and_then(|x| x.request.clone()).map(|request_config| {
+            let sdl = self.sdl.clone();
+            let coprocessor = self.coprocessor.clone();
+            AsyncCheckpointLayer::new(move |mut request: execution::Request| {
+                let my_sdl = sdl.to_string();
+                let coprocessor = coprocessor.clone();
+                let request_config = request_config.clone();
+                async move {
+                    let flow = process_graphql_request(
+                        &coprocessor,
+                        &request_config,
+                        PipelineStep::ExecutionRequest,
+                        None,
+                        &mut request.supergraph_request,
+                        &mut request.context,
+                        my_sdl,
+                    )
+                    .await?;
+
+                    Ok(match flow {
+                        ControlFlow::Break((code, body)) => ControlFlow::Break(
+                            supergraph_break_response(code, body, request.context)?,
+                        ),
+                        ControlFlow::Continue(()) => ControlFlow::Continue(request),
+                    })
+                }
+            })
+        });
+
+        let response_layer = stage
+            .and_then(|x| x.response.clone())
+            .map(|response_config| {
+                let sdl = self.sdl.clone();
+                let coprocessor = self.coprocessor.clone();
+                MapFutureLayer::new(move |fut| {
+                    let my_sdl = sdl.to_string();
+                    let coprocessor = coprocessor.clone();
+                    let response_config = response_config.clone();
+                    async move {
+                        let response: execution::Response = fut.await?;
+
+                        process_graphql_response(
+                            &coprocessor,
+                            &response_config,
+                            PipelineStep::ExecutionResponse,
+                            response,
+                            my_sdl,
+                        )
+                        .await
+                    }
+                })
+            });
+
+        if request_layer.is_none() && response_layer.is_none() {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .instrument(|_request: &execution::Request| {
+                tracing::info_span!(
+                    EXTERNAL_SPAN_NAME,
+                    "external service" = stringify!(execution::Request),
+                    "otel.kind" = "INTERNAL"
+                )
+            })
+            .option_layer(request_layer)
+            .option_layer(response_layer)
+            .buffered()
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let stage = self
+            .configuration
+            .stages
+            .as_ref()
+            .and_then(|x| x.subgraph.as_ref());
+
+        let request_layer = stage.and_then(|x| x.request.clone()).map(|request_config| {
+            let sdl = self.sdl.clone();
+            let coprocessor = self.coprocessor.clone();
+            let service_name = name.to_string();
+            AsyncCheckpointLayer::new(move |mut request: subgraph::Request| {
+                let my_sdl = sdl.to_string();
+                let coprocessor = coprocessor.clone();
+                let request_config = request_config.clone();
+                let service_name = service_name.clone();
+                async move {
+                    let flow = process_graphql_request(
+                        &coprocessor,
+                        &request_config,
+                        PipelineStep::SubgraphRequest,
+                        Some(service_name),
+                        &mut request.subgraph_request,
+                        &mut request.context,
+                        my_sdl,
+                    )
+                    .await?;
+
+                    // A break replaces the subgraph response: the subgraph is not called and
+                    // query plan execution carries on with what the co-processor returned.
+                    Ok(match flow {
+                        ControlFlow::Break((code, body)) => ControlFlow::Break(
+                            subgraph_break_response(code, body, request.context)?,
+                        ),
+                        ControlFlow::Continue(()) => ControlFlow::Continue(request),
+                    })
+                }
+            })
+        });
+
+        let response_layer = stage
+            .and_then(|x| x.response.clone())
+            .map(|response_config| {
+                let sdl = self.sdl.clone();
+                let coprocessor = self.coprocessor.clone();
+                let service_name = name.to_string();
+                MapFutureLayer::new(move |fut| {
+                    let my_sdl = sdl.to_string();
+                    let coprocessor = coprocessor.clone();
+                    let response_config = response_config.clone();
+                    let service_name = service_name.clone();
+                    async move {
+                        let mut response: subgraph::Response = fut.await?;
+
+                        let (headers, payload, context, sdl) = prepare_external_params(
+                            &response_config,
+                            response.response.headers(),
+                            || Ok(serde_json::to_value(response.response.body())?),
+                            &response.context,
+                            my_sdl,
+                        )?;
+
+                        let output = externalize(
+                            PipelineStep::SubgraphResponse,
+                            headers,
+                            payload,
+                            context,
+                            sdl,
+                        )?
+                        .with_subgraph(service_name, None);
+
+                        let co_processor_output = coprocessor.call(output).await?;
+
+                        tracing::debug!(?co_processor_output, "co-processor returned");
+
+                        if matches!(co_processor_output.control, Control::Break(_)) {
+                            *response.response.status_mut() =
+                                co_processor_output.control.get_http_status()?;
+                        }
+
+                        if let Some(body) = co_processor_output.body {
+                            *response.response.body_mut() = serde_json::from_value(body)?;
+                        }
+
+                        if let Some(context) = co_processor_output.context {
+                            response.context = context;
+                        }
+
+                        if let Some(headers) = co_processor_output.headers {
+                            *response.response.headers_mut() = internalize_header_map(headers)?;
+                        }
+
+                        Ok::<subgraph::Response, BoxError>(response)
+                    }
+                })
+            });
+
+        if request_layer.is_none() && response_layer.is_none() {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .instrument(|_request: &subgraph::Request| {
+                tracing::info_span!(
+                    EXTERNAL_SPAN_NAME,
+                    "external service" = stringify!(subgraph::Request),
+                    "otel.kind" = "INTERNAL"
+                )
+            })
+            .option_layer(request_layer)
+            .option_layer(response_layer)
+            .buffered()
+            .service(service)
+            .boxed()
+    }
+}
+
+/// Externalize the GraphQL request of the supergraph, execution or subgraph stage.
+///
+/// At the subgraph stage, the co-processor also receives the name of the subgraph and the URI
+/// the request is sent to, and can rewrite this URI. If the co-processor breaks the flow, the
+/// status code and body of the response to send back are returned.
+async fn process_graphql_request(
+    coprocessor: &Coprocessor,
+    config: &BaseConf,
+    stage: PipelineStep,
+    service_name: Option<String>,
+    http_request: &mut http::Request<graphql::Request>,
+    context: &mut Context,
+    sdl: String,
+) -> Result<ControlFlow<(StatusCode, serde_json::Value)>, BoxError> {
+    let (headers, payload, external_context, sdl) = prepare_external_params(
+        config,
+        http_request.headers(),
+        || Ok(serde_json::to_value(http_request.body())?),
+        context,
+        sdl,
+    )?;
+
+    let mut output = externalize(stage, headers, payload, external_context, sdl)?;
+    if let Some(service_name) = service_name {
+        output = output.with_subgraph(service_name, Some(http_request.uri().to_string()));
+    }
+
+    context.enter_active_request().await;
+    let res = coprocessor.call(output).await;
+    context.leave_active_request().await;
+
+    let co_processor_output = res?;
+
+    tracing::debug!(?co_processor_output, "co-processor returned");
+
+    if matches!(co_processor_output.control, Control::Break(_)) {
+        let code = co_processor_output.control.get_http_status()?;
+        let body = co_processor_output.body.unwrap_or(serde_json::Value::Null);
+        return Ok(ControlFlow::Break((code, body)));
+    }
+
+    if let Some(body) = co_processor_output.body {
+        *http_request.body_mut() = serde_json::from_value(body)?;
+    }
+
+    // the URI is only sent to the co-processor at the subgraph stage
+    if let Some(uri) = co_processor_output.uri {
+        *http_request.uri_mut() = uri.parse()?;
+    }
+
+    if let Some(new_context) = co_processor_output.context {
+        *context = new_context;
+    }
+
+    if let Some(headers) = co_processor_output.headers {
+        *http_request.headers_mut() = internalize_header_map(headers)?;
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
+
+/// The response to a break of the supergraph or execution stage: an error for a failure status
+/// code, otherwise the body returned by the co-processor as data.
+fn supergraph_break_response(
+    code: StatusCode,
+    body: serde_json::Value,
+    context: Context,
+) -> Result<supergraph::Response, BoxError> {
+    if !code.is_success() {
+        supergraph::Response::error_builder()
+            .errors(vec![Error {
+                message: body.to_string(),
+                ..Default::default()
+            }])
+            .status_code(code)
+            .context(context)
+            .build()
+    } else {
+        supergraph::Response::builder()
+            .data(serde_json_bytes::Value::from(body))
+            .status_code(code)
+            .context(context)
+            .build()
+    }
+}
+
+/// The response to a break of the subgraph request stage, used instead of the subgraph response
+fn subgraph_break_response(
+    code: StatusCode,
+    body: serde_json::Value,
+    context: Context,
+) -> Result<subgraph::Response, BoxError> {
+    Ok(if !code.is_success() {
+        subgraph::Response::error_builder()
+            .errors(vec![Error {
+                message: body.to_string(),
+                ..Default::default()
+            }])
+            .status_code(code)
+            .context(context)
+            .build()?
+    } else {
+        subgraph::Response::builder()
+            .data(serde_json_bytes::Value::from(body))
+            .extensions(Object::new())
+            .status_code(code)
+            .context(context)
+            .build()
+    })
+}
+
+/// Externalize the response of the supergraph or execution stage.
+///
+/// Only the first response of the stream is sent to the co-processor: it is the one carrying
+/// the data of non deferred queries, the following ones are passed through unchanged.
+async fn process_graphql_response(
+    coprocessor: &Coprocessor,
+    config: &BaseConf,
+    stage: PipelineStep,
+    response: supergraph::Response,
+    sdl: String,
+) -> Result<supergraph::Response, BoxError> {
+    let mut context = response.context;
+    let (mut parts, stream) = response.response.into_parts();
+    let (first, rest) = stream.into_future().await;
+    let first = first.unwrap_or_default();
+
+    let (headers, payload, external_context, sdl) = prepare_external_params(
+        config,
+        &parts.headers,
+        || Ok(serde_json::to_value(&first)?),
+        &context,
+        sdl,
+    )?;
+
+    let co_processor_output =
+        call_external(coprocessor, stage, headers, payload, external_context, sdl).await?;
+
+    tracing::debug!(?co_processor_output, "co-processor returned");
+
+    if matches!(co_processor_output.control, Control::Break(_)) {
+        parts.status = co_processor_output.control.get_http_status()?;
+    }
+
+    let first = match co_processor_output.body {
+        Some(body) => serde_json::from_value(body)?,
+        None => first,
+    };
+
+    if let Some(new_context) = co_processor_output.context {
+        context = new_context;
+    }
+
+    if let Some(headers) = co_processor_output.headers {
+        parts.headers = internalize_header_map(headers)?;
+    }
+
+    Ok(supergraph::Response::new_from_response(
+        http::Response::from_parts(parts, once(ready(first)).chain(rest).boxed()),
+        context,
+    ))
 }
 
 type ExternalParams<'a> = (
@@ -340,7 +826,7 @@ type ExternalParams<'a> = (
 fn prepare_external_params<'a>(
     config: &'a BaseConf,
     headers: &'a HeaderMap<HeaderValue>,
-    bytes: &'a Bytes,
+    body: impl FnOnce() -> Result<serde_json::Value, BoxError>,
     context: &'a Context,
     sdl: String,
 ) -> Result<ExternalParams<'a>, BoxError> {
@@ -353,7 +839,7 @@ fn prepare_external_params<'a>(
 
     if config.body || config.headers {
         if config.body {
-            payload_opt = Some(serde_json::from_slice(bytes)?);
+            payload_opt = Some(body()?);
         }
         if config.headers {
             headers_opt = Some(headers);
@@ -368,9 +854,19 @@ fn prepare_external_params<'a>(
     Ok((headers_opt, payload_opt, context_opt, sdl_opt))
 }
 
-async fn call_external<T>(
-    url: String,
-    timeout: Option<Duration>,
+async fn call_external(
+    coprocessor: &Coprocessor,
+    stage: PipelineStep,
+    headers: Option<&HeaderMap<HeaderValue>>,
+    payload: Option<serde_json::Value>,
+    context: Option<Context>,
+    sdl: Option<String>,
+) -> Result<Externalizable<serde_json::Value>, BoxError> {
+    let output = externalize(stage, headers, payload, context, sdl)?;
+    coprocessor.call(output).await
+}
+
+fn externalize<T>(
     stage: PipelineStep,
     headers: Option<&HeaderMap<HeaderValue>>,
     payload: Option<T>,
@@ -384,9 +880,13 @@ where
     if let Some(hdrs) = headers {
         converted_headers = Some(externalize_header_map(hdrs)?);
     };
-    let output = Externalizable::new(stage, converted_headers, payload, context, sdl);
-    tracing::debug!(?output, "externalized output");
-    output.call(&url, timeout).await
+    Ok(Externalizable::new(
+        stage,
+        converted_headers,
+        payload,
+        context,
+        sdl,
+    ))
 }
 
 /// Convert a HeaderMap into a HashMap
@@ -434,6 +934,9 @@ mod tests {
     use mime::TEXT_HTML;
 
     use super::*;
+    use crate::plugin::test::MockExecutionService;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
 
     #[tokio::test]
     async fn load_plugin() {
@@ -455,6 +958,41 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn load_plugin_with_all_stages() {
+        let config = serde_json::json!({
+            "plugins": {
+                "experimental.external": {
+                    "url": "http://127.0.0.1:8081",
+                    "stages": {
+                        "router": {
+                            "request": { "headers": true },
+                            "response": { "headers": true }
+                        },
+                        "supergraph": {
+                            "request": { "body": true },
+                            "response": { "body": true }
+                        },
+                        "execution": {
+                            "request": { "context": true },
+                            "response": { "context": true }
+                        },
+                        "subgraph": {
+                            "request": { "headers": true, "body": true },
+                            "response": { "body": true }
+                        }
+                    }
+                }
+            }
+        });
+        let _test_harness = crate::TestHarness::builder()
+            .configuration_json(config)
+            .unwrap()
+            .build_router()
+            .await
+            .unwrap();
+    }
+
     #[test]
     fn it_externalizes_headers() {
         // Build our expected HashMap
@@ -518,4 +1056,252 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    fn plugin(
+        stages: serde_json::Value,
+        coprocessor: impl Fn(CoprocessorMessage) -> CoprocessorMessage + Send + Sync + 'static,
+    ) -> ExternalPlugin {
+        let coprocessor = Arc::new(coprocessor);
+        ExternalPlugin {
+            configuration: serde_json::from_value(serde_json::json!({
+                "url": "http://127.0.0.1:8081",
+                "stages": stages
+            }))
+            .unwrap(),
+            sdl: Default::default(),
+            coprocessor: Coprocessor(Arc::new(move |output| -> CoprocessorFuture {
+                let reply = coprocessor(output);
+                Box::pin(async move { Ok(reply) })
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_breaks_at_the_supergraph_request_stage() {
+        let plugin = plugin(
+            serde_json::json!({ "supergraph": { "request": { "body": true } } }),
+            |mut output| {
+                assert_eq!(output.stage, PipelineStep::SupergraphRequest.to_string());
+                output.control = Control::Break(401);
+                output.body = Some(serde_json::json!("unauthorized"));
+                output
+            },
+        );
+        let service = plugin.supergraph_service(MockSupergraphService::new().boxed());
+
+        let request = supergraph::Request::fake_builder()
+            .query("{ me }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::UNAUTHORIZED);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(response.errors[0].message, "\"unauthorized\"");
+    }
+
+    #[tokio::test]
+    async fn it_rewrites_the_supergraph_request() {
+        let plugin = plugin(
+            serde_json::json!({ "supergraph": { "request": { "body": true, "headers": true } } }),
+            |mut output| {
+                assert_eq!(output.body.as_ref().unwrap()["query"], "{ me }");
+                output.body = Some(serde_json::json!({ "query": "{ rewritten }" }));
+                output.headers = Some(HashMap::from([(
+                    "x-rewritten".to_string(),
+                    vec!["true".to_string()],
+                )]));
+                output
+            },
+        );
+        let mut mock = MockSupergraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.supergraph_request.body().query.as_deref() == Some("{ rewritten }")
+                    && request.supergraph_request.headers().get("x-rewritten")
+                        == Some(&HeaderValue::from_static("true"))
+            })
+            .returning(|request| {
+                supergraph::Response::fake_builder()
+                    .data(serde_json_bytes::json!({ "me": null }))
+                    .context(request.context)
+                    .build()
+            });
+        let service = plugin.supergraph_service(mock.boxed());
+
+        let request = supergraph::Request::fake_builder()
+            .query("{ me }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+
+        assert_eq!(
+            response.next_response().await.unwrap().data,
+            Some(serde_json_bytes::json!({ "me": null }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_breaks_at_the_execution_request_stage() {
+        let plugin = plugin(
+            serde_json::json!({ "execution": { "request": { "context": true } } }),
+            |mut output| {
+                assert_eq!(output.stage, PipelineStep::ExecutionRequest.to_string());
+                output.control = Control::Break(200);
+                output.body = Some(serde_json::json!({ "cached": true }));
+                output
+            },
+        );
+        let service = plugin.execution_service(MockExecutionService::new().boxed());
+
+        let mut response = service
+            .oneshot(execution::Request::fake_builder().build())
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::OK);
+        assert_eq!(
+            response.next_response().await.unwrap().data,
+            Some(serde_json_bytes::json!({ "cached": true }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rewrites_the_execution_response() {
+        let plugin = plugin(
+            serde_json::json!({ "execution": { "response": { "body": true } } }),
+            |mut output| {
+                assert_eq!(output.stage, PipelineStep::ExecutionResponse.to_string());
+                assert_eq!(
+                    output.body,
+                    Some(serde_json::json!({ "data": { "me": null } }))
+                );
+                output.control = Control::Break(202);
+                output.body = Some(serde_json::json!({ "data": { "rewritten": true } }));
+                output.headers = Some(HashMap::from([(
+                    "x-rewritten".to_string(),
+                    vec!["true".to_string()],
+                )]));
+                output
+            },
+        );
+        let mut mock = MockExecutionService::new();
+        mock.expect_call().times(1).returning(|request| {
+            supergraph::Response::fake_builder()
+                .data(serde_json_bytes::json!({ "me": null }))
+                .context(request.context)
+                .build()
+        });
+        let service = plugin.execution_service(mock.boxed());
+
+        let mut response = service
+            .oneshot(execution::Request::fake_builder().build())
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::ACCEPTED);
+        assert_eq!(
+            response.response.headers().get("x-rewritten").unwrap(),
+            "true"
+        );
+        assert_eq!(
+            response.next_response().await.unwrap().data,
+            Some(serde_json_bytes::json!({ "rewritten": true }))
+        );
+    }
+
+    fn subgraph_request() -> subgraph::Request {
+        subgraph::Request::fake_builder()
+            .subgraph_request(
+                http::Request::builder()
+                    .uri("http://products/graphql")
+                    .body(graphql::Request::builder().query("{ topProducts }").build())
+                    .unwrap(),
+            )
+            .build()
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_subgraph_name_and_uri_and_rewrites_the_uri() {
+        let plugin = plugin(
+            serde_json::json!({ "subgraph": { "request": { "body": true } } }),
+            |mut output| {
+                assert_eq!(output.stage, PipelineStep::SubgraphRequest.to_string());
+                assert_eq!(output.service_name.as_deref(), Some("products"));
+                assert_eq!(output.uri.as_deref(), Some("http://products/graphql"));
+                output.uri = Some("http://rewritten/graphql".to_string());
+                output.body = Some(serde_json::json!({ "query": "{ rewritten }" }));
+                output
+            },
+        );
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.subgraph_request.uri().to_string() == "http://rewritten/graphql"
+                    && request.subgraph_request.body().query.as_deref() == Some("{ rewritten }")
+            })
+            .returning(|request| {
+                Ok(subgraph::Response::fake_builder()
+                    .data(serde_json_bytes::json!({ "topProducts": [] }))
+                    .context(request.context)
+                    .build())
+            });
+        let service = plugin.subgraph_service("products", mock.boxed());
+
+        let response = service.oneshot(subgraph_request()).await.unwrap();
+
+        assert_eq!(
+            response.response.body().data,
+            Some(serde_json_bytes::json!({ "topProducts": [] }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_breaks_at_the_subgraph_request_stage() {
+        let plugin = plugin(
+            serde_json::json!({ "subgraph": { "request": { "headers": true } } }),
+            |mut output| {
+                output.control = Control::Break(403);
+                output.body = Some(serde_json::json!("forbidden"));
+                output
+            },
+        );
+        let service = plugin.subgraph_service("products", MockSubgraphService::new().boxed());
+
+        let response = service.oneshot(subgraph_request()).await.unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.response.body().errors[0].message, "\"forbidden\"");
+    }
+
+    #[tokio::test]
+    async fn it_sends_the_subgraph_name_and_rewrites_the_subgraph_response() {
+        let plugin = plugin(
+            serde_json::json!({ "subgraph": { "response": { "body": true } } }),
+            |mut output| {
+                assert_eq!(output.stage, PipelineStep::SubgraphResponse.to_string());
+                assert_eq!(output.service_name.as_deref(), Some("products"));
+                assert_eq!(output.uri, None);
+                output.body = Some(serde_json::json!({ "data": { "rewritten": true } }));
+                output
+            },
+        );
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call().times(1).returning(|request| {
+            Ok(subgraph::Response::fake_builder()
+                .data(serde_json_bytes::json!({ "topProducts": [] }))
+                .context(request.context)
+                .build())
+        });
+        let service = plugin.subgraph_service("products", mock.boxed());
+
+        let response = service.oneshot(subgraph_request()).await.unwrap();
+
+        assert_eq!(
+            response.response.body().data,
+            Some(serde_json_bytes::json!({ "rewritten": true }))
+        );
+    }
 }
@@ -14,11 +14,13 @@ use http::header::TE;
 use http::header::TRAILER;
 use http::header::TRANSFER_ENCODING;
 use http::header::UPGRADE;
+use http::HeaderMap;
 use http::HeaderValue;
 use lazy_static::lazy_static;
 use regex::Regex;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde::Serialize;
 use serde_json::Value;
 use tower::BoxError;
 use tower::Layer;
@@ -26,6 +28,7 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tower_service::Service;
 
+use crate::cache::policy::CachePolicy;
 use crate::plugin::serde::deserialize_header_name;
 use crate::plugin::serde::deserialize_header_value;
 use crate::plugin::serde::deserialize_json_query;
@@ -34,8 +37,10 @@ use crate::plugin::serde::deserialize_option_header_value;
 use crate::plugin::serde::deserialize_regex;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::register_plugin;
 use crate::services::subgraph;
+use crate::services::supergraph;
 use crate::services::SubgraphRequest;
 
 register_plugin!("apollo", "headers", Headers);
@@ -44,9 +49,11 @@ register_plugin!("apollo", "headers", Headers);
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 struct HeadersLocation {
     /// Propagate/Insert/Remove headers from request
+    #[serde(default)]
     request: Vec<Operation>,
-    // Propagate/Insert/Remove headers from response
-    // response: Option<Operation>
+    /// Propagate/Insert/Remove headers from response
+    #[serde(default)]
+    response: Vec<ResponseOperation>,
 }
 
 #[derive(Clone, JsonSchema, Deserialize)]
@@ -90,6 +97,8 @@ enum Insert {
     FromContext(InsertFromContext),
     /// Insert header with a value coming from body
     FromBody(InsertFromBody),
+    /// Insert header with a value coming from the validated JWT claims
+    FromClaim(InsertFromClaim),
 }
 
 #[derive(Clone, JsonSchema, Deserialize)]
@@ -139,6 +148,124 @@ struct InsertFromBody {
     default: Option<HeaderValue>,
 }
 
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Insert header with a value coming from the validated JWT claims
+struct InsertFromClaim {
+    /// The target header name
+    #[schemars(with = "String")]
+    #[serde(deserialize_with = "deserialize_header_name")]
+    name: HeaderName,
+
+    /// The JSON pointer to the claim, for instance `/sub`
+    #[serde(deserialize_with = "deserialize_json_pointer")]
+    from_claim: String,
+
+    /// The default if the claim is not present
+    #[schemars(with = "Option<String>", default)]
+    #[serde(deserialize_with = "deserialize_option_header_value", default)]
+    default: Option<HeaderValue>,
+}
+
+fn deserialize_json_pointer<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let pointer = String::deserialize(deserializer)?;
+    if pointer.is_empty() || pointer.starts_with('/') {
+        Ok(pointer)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "'{pointer}' is not a valid JSON pointer, it must start with '/'"
+        )))
+    }
+}
+
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum ResponseOperation {
+    Insert(ResponseInsert),
+    Remove(Remove),
+    Propagate(ResponsePropagate),
+}
+
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+#[serde(untagged)]
+/// Insert header in the client response
+enum ResponseInsert {
+    /// Insert static header
+    Static(InsertStatic),
+    /// Insert header with a value coming from context key (works only for a string in the context)
+    FromContext(InsertFromContext),
+}
+
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+#[serde(untagged)]
+/// Propagate a subgraph response header to the client response
+enum ResponsePropagate {
+    /// Propagate header given a header name
+    Named {
+        /// The source header name
+        #[schemars(with = "String")]
+        #[serde(deserialize_with = "deserialize_header_name")]
+        named: HeaderName,
+
+        /// An optional target header name
+        #[schemars(with = "Option<String>", default)]
+        #[serde(deserialize_with = "deserialize_option_header_name", default)]
+        rename: Option<HeaderName>,
+
+        /// How to merge the values returned by several subgraphs
+        #[serde(default)]
+        merge: MergeStrategy,
+    },
+    /// Propagate header given a regex to match header name
+    Matching {
+        /// The regex on header name
+        #[schemars(schema_with = "propagate_matching")]
+        #[serde(deserialize_with = "deserialize_regex")]
+        matching: Regex,
+
+        /// How to merge the values returned by several subgraphs
+        #[serde(default)]
+        merge: MergeStrategy,
+    },
+}
+
+#[derive(Clone, Copy, Debug, JsonSchema, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+/// How to merge the values of a header returned by several subgraphs
+enum MergeStrategy {
+    /// Keep the values of the first subgraph response
+    First,
+    /// Keep the values of the last subgraph response (default)
+    Last,
+    /// Keep the values of all subgraph responses
+    Append,
+    /// Merge `Cache-Control` values: the lowest `max-age` applies, the response is private if
+    /// any value is, and a subgraph response without the header makes it `no-store`
+    MinMaxAge,
+}
+
+impl Default for MergeStrategy {
+    fn default() -> Self {
+        MergeStrategy::Last
+    }
+}
+
+/// Subgraph response data collected for the client response
+#[derive(Default, Deserialize, Serialize)]
+struct ResponseHeaders {
+    /// Names of the subgraphs which responded, in order
+    subgraphs: Vec<String>,
+    /// Headers propagated from the subgraph responses
+    headers: HashMap<String, Vec<String>>,
+}
+
+const RESPONSE_HEADERS_CONTEXT_KEY: &str = "apollo_router::headers::response";
+
 schemar_fn!(
     propagate_matching,
     String,
@@ -201,6 +328,21 @@ impl Plugin for Headers {
             config: init.config,
         })
     }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.has_response_rules() {
+            return service;
+        }
+
+        let config = self.config.clone();
+        service
+            .map_response(move |mut response: supergraph::Response| {
+                apply_response_headers(&config, &mut response);
+                response
+            })
+            .boxed()
+    }
+
     fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
         let mut operations: Vec<Operation> = self
             .config
@@ -214,13 +356,278 @@ impl Plugin for Headers {
             operations.append(&mut subgraph_operations);
         }
 
-        ServiceBuilder::new()
+        let service = ServiceBuilder::new()
             .layer(HeadersLayer::new(operations))
-            .service(service)
+            .service(service);
+
+        if !self.has_response_rules() {
+            return service.boxed();
+        }
+
+        let mut response_operations: Vec<ResponseOperation> = self
+            .config
+            .all
+            .as_ref()
+            .map(|a| a.response.clone())
+            .unwrap_or_default();
+        if let Some(mut subgraph_operations) =
+            self.config.subgraphs.get(name).map(|s| s.response.clone())
+        {
+            response_operations.append(&mut subgraph_operations);
+        }
+
+        let service_name = name.to_string();
+        service
+            .map_response(move |response: subgraph::Response| {
+                collect_response_headers(&service_name, &response_operations, &response);
+                response
+            })
             .boxed()
     }
 }
 
+impl Headers {
+    fn has_response_rules(&self) -> bool {
+        self.config
+            .all
+            .iter()
+            .chain(self.config.subgraphs.values())
+            .any(|location| !location.response.is_empty())
+    }
+}
+
+/// Whether a configuration of this plugin sets the given header on client responses
+pub(crate) fn sets_response_header(config: &Value, name: &HeaderName) -> bool {
+    let config: Config = match serde_json::from_value(config.clone()) {
+        Ok(config) => config,
+        Err(_) => return false,
+    };
+    config
+        .all
+        .iter()
+        .chain(config.subgraphs.values())
+        .flat_map(|location| location.response.iter())
+        .any(|operation| match operation {
+            ResponseOperation::Insert(ResponseInsert::Static(InsertStatic {
+                name: target,
+                ..
+            }))
+            | ResponseOperation::Insert(ResponseInsert::FromContext(InsertFromContext {
+                name: target,
+                ..
+            })) => target == name,
+            ResponseOperation::Propagate(ResponsePropagate::Named { named, rename, .. }) => {
+                rename.as_ref().unwrap_or(named) == name
+            }
+            ResponseOperation::Propagate(ResponsePropagate::Matching { matching, .. }) => {
+                matching.is_match(name.as_str())
+            }
+            ResponseOperation::Remove(_) => false,
+        })
+}
+
+/// Store the headers propagated from a subgraph response in the context, merged with the ones
+/// of the previous subgraph responses
+fn collect_response_headers(
+    service_name: &str,
+    operations: &[ResponseOperation],
+    response: &subgraph::Response,
+) {
+    let response_headers = response.response.headers();
+    let mut propagated: Vec<(String, Vec<String>, MergeStrategy)> = Vec::new();
+    for operation in operations {
+        match operation {
+            ResponseOperation::Propagate(ResponsePropagate::Named {
+                named,
+                rename,
+                merge,
+            }) => {
+                let values = header_values(response_headers, named);
+                // a missing `Cache-Control` header is merged too, as it forbids caching
+                if !values.is_empty() || *merge == MergeStrategy::MinMaxAge {
+                    propagated.push((rename.as_ref().unwrap_or(named).to_string(), values, *merge));
+                }
+            }
+            ResponseOperation::Propagate(ResponsePropagate::Matching { matching, merge }) => {
+                for name in response_headers.keys().filter(|name| {
+                    !RESERVED_HEADERS.contains(name) && matching.is_match(name.as_str())
+                }) {
+                    propagated.push((
+                        name.to_string(),
+                        header_values(response_headers, name),
+                        *merge,
+                    ));
+                }
+            }
+            // Insertions and removals are applied to the client response
+            ResponseOperation::Insert(_) | ResponseOperation::Remove(_) => {}
+        }
+    }
+
+    if let Err(err) = response.context.upsert(
+        RESPONSE_HEADERS_CONTEXT_KEY,
+        |mut collected: ResponseHeaders| {
+            if !collected.subgraphs.iter().any(|name| name == service_name) {
+                collected.subgraphs.push(service_name.to_string());
+            }
+            for (name, values, merge) in &propagated {
+                let existing = collected.headers.remove(name);
+                collected
+                    .headers
+                    .insert(name.clone(), merge_values(*merge, existing, values.clone()));
+            }
+            collected
+        },
+    ) {
+        tracing::error!(
+            "cannot store the response headers of subgraph '{}': {:?}",
+            service_name,
+            err
+        );
+    }
+}
+
+fn header_values(headers: &HeaderMap, name: &HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok().map(str::to_string))
+        .collect()
+}
+
+fn merge_values(
+    merge: MergeStrategy,
+    existing: Option<Vec<String>>,
+    new: Vec<String>,
+) -> Vec<String> {
+    match merge {
+        MergeStrategy::First => existing.unwrap_or(new),
+        MergeStrategy::Last => new,
+        MergeStrategy::Append => existing
+            .unwrap_or_default()
+            .into_iter()
+            .chain(new)
+            .collect(),
+        MergeStrategy::MinMaxAge => {
+            let policy = match existing {
+                Some(existing) => cache_policy(&existing).merge(cache_policy(&new)),
+                None => cache_policy(&new),
+            };
+            policy
+                .header_value()
+                .to_str()
+                .map(|value| vec![value.to_string()])
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Cache policy of `Cache-Control` values. Without them, or without a `max-age`, the response
+/// must not be reused.
+fn cache_policy(values: &[String]) -> CachePolicy {
+    match CachePolicy::from_values(values.iter().map(String::as_str)) {
+        Some(policy) => CachePolicy {
+            max_age: Some(policy.max_age.unwrap_or_default()),
+            ..policy
+        },
+        None => CachePolicy {
+            no_store: true,
+            ..Default::default()
+        },
+    }
+}
+
+/// Apply the headers propagated from the subgraph responses and the insert/remove rules to the
+/// client response
+fn apply_response_headers(config: &Config, response: &mut supergraph::Response) {
+    let collected: ResponseHeaders = response
+        .context
+        .get(RESPONSE_HEADERS_CONTEXT_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let headers = response.response.headers_mut();
+
+    for (name, values) in collected.headers {
+        match HeaderName::from_bytes(name.as_bytes()) {
+            Ok(name) => {
+                headers.remove(&name);
+                for value in values {
+                    match HeaderValue::from_str(&value) {
+                        Ok(value) => {
+                            headers.append(&name, value);
+                        }
+                        Err(err) => {
+                            tracing::error!("cannot convert a subgraph response header value for header name '{}': {:?}", name, err);
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!(
+                    "cannot convert a subgraph response header name '{}': {:?}",
+                    name,
+                    err
+                );
+            }
+        }
+    }
+
+    // The rules of a subgraph only apply if it was called for this request
+    let operations = config
+        .all
+        .iter()
+        .chain(
+            collected
+                .subgraphs
+                .iter()
+                .filter_map(|name| config.subgraphs.get(name)),
+        )
+        .flat_map(|location| location.response.iter());
+    for operation in operations {
+        match operation {
+            ResponseOperation::Insert(ResponseInsert::Static(static_insert)) => {
+                headers.insert(&static_insert.name, static_insert.value.clone());
+            }
+            ResponseOperation::Insert(ResponseInsert::FromContext(insert_from_context)) => {
+                if let Some(val) = response
+                    .context
+                    .get::<_, String>(&insert_from_context.from_context)
+                    .ok()
+                    .flatten()
+                {
+                    match HeaderValue::from_str(&val) {
+                        Ok(header_value) => {
+                            headers.insert(&insert_from_context.name, header_value);
+                        }
+                        Err(err) => {
+                            tracing::error!("cannot convert from the context into a header value for header name '{}': {:?}", insert_from_context.name, err);
+                        }
+                    }
+                }
+            }
+            ResponseOperation::Remove(Remove::Named(name)) => {
+                headers.remove(name);
+            }
+            ResponseOperation::Remove(Remove::Matching(matching)) => {
+                let new_headers = headers
+                    .drain()
+                    .filter_map(|(name, value)| {
+                        name.and_then(|name| {
+                            (RESERVED_HEADERS.contains(&name) || !matching.is_match(name.as_str()))
+                                .then_some((name, value))
+                        })
+                    })
+                    .collect();
+
+                let _ = std::mem::replace(headers, new_headers);
+            }
+            // Propagation is done when the subgraph responds
+            ResponseOperation::Propagate(_) => {}
+        }
+    }
+}
+
 struct HeadersLayer {
     operations: Vec<Operation>,
 }
@@ -336,6 +743,36 @@ where
                                 .insert(&from_body.name, default_val.clone());
                         }
                     }
+                    Insert::FromClaim(from_claim) => {
+                        let claim = req
+                            .context
+                            .get::<_, Value>(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+                            .ok()
+                            .flatten()
+                            .and_then(|claims| claims.pointer(&from_claim.from_claim).cloned())
+                            .filter(|claim| !claim.is_null());
+                        if let Some(val) = claim {
+                            let header_value = if let Value::String(val_str) = val {
+                                val_str
+                            } else {
+                                val.to_string()
+                            };
+                            match HeaderValue::from_str(&header_value) {
+                                Ok(header_value) => {
+                                    req.subgraph_request
+                                        .headers_mut()
+                                        .insert(&from_claim.name, header_value);
+                                }
+                                Err(err) => {
+                                    tracing::error!("cannot convert from the claims into a header value for header name '{}': {:?}", from_claim.name, err);
+                                }
+                            }
+                        } else if let Some(default_val) = &from_claim.default {
+                            req.subgraph_request
+                                .headers_mut()
+                                .insert(&from_claim.name, default_val.clone());
+                        }
+                    }
                 },
                 Operation::Remove(Remove::Named(name)) => {
                     req.subgraph_request.headers_mut().remove(name);
@@ -431,6 +868,21 @@ mod test {
         .unwrap();
     }
 
+    #[test]
+    fn test_insert_from_claim_config() {
+        serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            request:
+            - insert:
+                name: "x-user-id"
+                from_claim: "/sub"
+                default: "anonymous"
+        "#,
+        )
+        .unwrap();
+    }
+
     #[test]
     fn test_remove_config() {
         serde_yaml::from_str::<Config>(
@@ -590,6 +1042,67 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_insert_from_claim() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.assert_headers(vec![
+                    ("aa", "vaa"),
+                    ("ab", "vab"),
+                    ("ac", "vac"),
+                    ("header_from_claim", "tenant1"),
+                ])
+            })
+            .returning(example_response);
+
+        let mut service = HeadersLayer::new(vec![Operation::Insert(Insert::FromClaim(
+            InsertFromClaim {
+                name: "header_from_claim".try_into()?,
+                from_claim: "/org/tenant".to_string(),
+                default: None,
+            },
+        ))])
+        .layer(mock);
+
+        let request = example_request();
+        request.context.insert(
+            APOLLO_AUTHENTICATION_JWT_CLAIMS,
+            serde_json::json!({ "sub": "user1", "org": { "tenant": "tenant1" } }),
+        )?;
+        service.ready().await?.call(request).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_from_claim_default() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.assert_headers(vec![
+                    ("aa", "vaa"),
+                    ("ab", "vab"),
+                    ("ac", "vac"),
+                    ("header_from_claim", "anonymous"),
+                ])
+            })
+            .returning(example_response);
+
+        let mut service = HeadersLayer::new(vec![Operation::Insert(Insert::FromClaim(
+            InsertFromClaim {
+                name: "header_from_claim".try_into()?,
+                from_claim: "/sub".to_string(),
+                default: Some(HeaderValue::from_static("anonymous")),
+            },
+        ))])
+        .layer(mock);
+
+        service.ready().await?.call(example_request()).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_remove_exact() -> Result<(), BoxError> {
         let mut mock = MockSubgraphService::new();
@@ -725,6 +1238,214 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_response_config() {
+        serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            response:
+                - propagate:
+                    named: "set-cookie"
+                    merge: append
+                - propagate:
+                    named: "cache-control"
+                    merge: min_max_age
+                - propagate:
+                    matching: "x-.*"
+                - insert:
+                    name: "test"
+                    value: "test"
+                - remove:
+                    named: "test"
+        subgraphs:
+          products:
+            response:
+                - propagate:
+                    named: "x-products"
+                    rename: "x-from-products"
+                    merge: first
+        "#,
+        )
+        .unwrap();
+
+        assert!(serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            response:
+                - propagate:
+                    named: "set-cookie"
+                    merge: unknown
+        "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_merge_values() {
+        let existing = Some(vec!["a".to_string()]);
+        let new = vec!["b".to_string()];
+        assert_eq!(
+            merge_values(MergeStrategy::First, existing.clone(), new.clone()),
+            vec!["a"]
+        );
+        assert_eq!(
+            merge_values(MergeStrategy::First, None, new.clone()),
+            vec!["b"]
+        );
+        assert_eq!(
+            merge_values(MergeStrategy::Last, existing.clone(), new.clone()),
+            vec!["b"]
+        );
+        assert_eq!(
+            merge_values(MergeStrategy::Append, existing, new),
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_merge_min_max_age() {
+        let merge = |existing: Option<&str>, new: &[&str]| {
+            merge_values(
+                MergeStrategy::MinMaxAge,
+                existing.map(|value| vec![value.to_string()]),
+                new.iter().map(|value| value.to_string()).collect(),
+            )
+        };
+
+        // the lowest max-age applies
+        assert_eq!(
+            merge(Some("public, max-age=60"), &["max-age=30"]),
+            vec!["max-age=30, public"]
+        );
+        assert_eq!(
+            merge(Some("max-age=30"), &["s-maxage=60"]),
+            vec!["max-age=30, public"]
+        );
+        // private if any value is private
+        assert_eq!(
+            merge(Some("private, max-age=30"), &["public, max-age=60"]),
+            vec!["max-age=30, private"]
+        );
+        assert_eq!(
+            merge(Some("max-age=60"), &["max-age=120, private"]),
+            vec!["max-age=60, private"]
+        );
+        // a missing header, or a value without max-age, forbids caching
+        assert_eq!(merge(Some("max-age=60"), &[]), vec!["no-store"]);
+        assert_eq!(merge(None, &[]), vec!["no-store"]);
+        assert_eq!(merge(Some("no-store"), &["max-age=60"]), vec!["no-store"]);
+        assert_eq!(merge(Some("max-age=60"), &["public"]), vec!["no-store"]);
+        assert_eq!(merge(None, &["max-age=60"]), vec!["max-age=60, public"]);
+    }
+
+    #[test]
+    fn test_sets_response_header() {
+        let sets_cache_control = |config: serde_json::Value| {
+            sets_response_header(&config, &HeaderName::from_static("cache-control"))
+        };
+
+        assert!(sets_cache_control(serde_json::json!({
+            "all": { "response": [{ "propagate": { "named": "cache-control" } }] }
+        })));
+        assert!(sets_cache_control(serde_json::json!({
+            "subgraphs": { "products": { "response": [
+                { "propagate": { "named": "x-cache", "rename": "cache-control" } }
+            ] } }
+        })));
+        assert!(sets_cache_control(serde_json::json!({
+            "all": { "response": [{ "propagate": { "matching": ".*" } }] }
+        })));
+        assert!(sets_cache_control(serde_json::json!({
+            "all": { "response": [
+                { "insert": { "name": "cache-control", "value": "no-store" } }
+            ] }
+        })));
+        assert!(!sets_cache_control(serde_json::json!({
+            "all": {
+                "request": [{ "propagate": { "named": "cache-control" } }],
+                "response": [
+                    { "propagate": { "named": "set-cookie" } },
+                    { "remove": { "named": "cache-control" } }
+                ]
+            }
+        })));
+    }
+
+    #[tokio::test]
+    async fn test_response_headers() -> Result<(), BoxError> {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            response:
+                - propagate:
+                    named: "set-cookie"
+                    merge: append
+                - propagate:
+                    named: "cache-control"
+                    merge: min_max_age
+                - remove:
+                    named: "x-remove"
+        subgraphs:
+          products:
+            response:
+                - propagate:
+                    matching: "x-prod.*"
+                - insert:
+                    name: "x-called"
+                    value: "products"
+          reviews:
+            response:
+                - insert:
+                    name: "x-called"
+                    value: "reviews"
+        "#,
+        )?;
+
+        let context = Context::new();
+        let products_response = SubgraphResponse::new_from_response(
+            http::Response::builder()
+                .header("set-cookie", "a=1")
+                .header("cache-control", "max-age=60")
+                .header("x-products", "p")
+                .body(crate::graphql::Response::default())?,
+            context.clone(),
+        );
+        let operations = config.all.as_ref().unwrap().response.clone();
+        let mut products_operations = operations.clone();
+        products_operations.extend(config.subgraphs["products"].response.clone());
+        collect_response_headers("products", &products_operations, &products_response);
+
+        let accounts_response = SubgraphResponse::new_from_response(
+            http::Response::builder()
+                .header("set-cookie", "b=2")
+                .header("cache-control", "max-age=30")
+                .body(crate::graphql::Response::default())?,
+            context.clone(),
+        );
+        collect_response_headers("accounts", &operations, &accounts_response);
+
+        let mut response = supergraph::Response::fake_builder()
+            .header("x-remove", "removed")
+            .context(context)
+            .build()?;
+        apply_response_headers(&config, &mut response);
+
+        let headers = response.response.headers();
+        assert_eq!(
+            headers
+                .get_all("set-cookie")
+                .iter()
+                .map(|value| value.to_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["a=1", "b=2"]
+        );
+        assert_eq!(headers.get("cache-control").unwrap(), "max-age=30, public");
+        assert_eq!(headers.get("x-products").unwrap(), "p");
+        assert_eq!(headers.get("x-called").unwrap(), "products");
+        assert!(headers.get("x-remove").is_none());
+        Ok(())
+    }
+
     fn example_response(_: SubgraphRequest) -> Result<SubgraphResponse, BoxError> {
         Ok(SubgraphResponse::new_from_response(
             http::Response::default(),
@@ -764,6 +1485,7 @@ mod test {
                 .expect("expecting valid request"),
             operation_kind: OperationKind::Query,
             context: ctx,
+            subscription_stream: None,
         }
     }
 
@@ -211,6 +211,7 @@ mod test {
             &Configuration::default(),
         )
         .await
+        .unwrap()
         .make()
         .boxed()
     }
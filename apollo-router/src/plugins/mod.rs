@@ -20,14 +20,17 @@ macro_rules! schemar_fn {
     };
 }
 
-mod authentication;
+pub(crate) mod authentication;
 pub(crate) mod csrf;
+mod demand_control;
+mod entity_cache;
 mod expose_query_plan;
 mod external;
 mod forbid_mutations;
-mod headers;
+pub(crate) mod headers;
 mod include_subgraph_errors;
 pub(crate) mod override_url;
+pub(crate) mod response_cache;
 pub(crate) mod rhai;
 pub(crate) mod telemetry;
 pub(crate) mod traffic_shaping;
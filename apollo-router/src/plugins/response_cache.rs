@@ -0,0 +1,570 @@
+//! Cache policy of client responses, and cache of whole responses
+//!
+//! The cache policy of each subgraph response, from its `Cache-Control` header or the hints of
+//! its `cacheControl` extension, is aggregated over all the fetches of a query. The client
+//! response gets the resulting `Cache-Control` header, and the responses to queries can be
+//! served from a cache until their aggregated `max-age` expires.
+//!
+//! With `cache_control` enabled, this plugin is the only one setting the `Cache-Control` header
+//! of client responses: configurations where the headers plugin sets it too are rejected.
+
+use std::sync::Arc;
+
+use futures::future::ready;
+use futures::stream::once;
+use futures::StreamExt;
+use http::header::CACHE_CONTROL;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::Service;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::cache::policy::CachePolicy;
+use crate::cache::service::now;
+use crate::cache::service::CacheService;
+use crate::cache::storage::CacheStorage;
+use crate::configuration::Cache;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::execution;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "response_cache", ResponseCache);
+
+const CACHE_POLICY_CONTEXT_KEY: &str = "apollo_router::response_cache::policy";
+const CACHEABLE_CONTEXT_KEY: &str = "apollo_router::response_cache::cacheable";
+
+/// Configuration for the cache policy of client responses and the response cache
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Set the `Cache-Control` header of client responses from the cache policy of the subgraph
+    /// responses (enabled by default)
+    #[serde(default = "default_cache_control")]
+    cache_control: bool,
+    /// Serve repeated queries from a cache of whole responses
+    #[serde(default)]
+    cache: Option<CacheConfig>,
+}
+
+fn default_cache_control() -> bool {
+    true
+}
+
+/// Configuration for the response cache
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct CacheConfig {
+    /// Storage of the cached responses
+    #[serde(default)]
+    storage: Cache,
+    /// Client request headers that are part of the cache key, for instance `accept-language`
+    #[serde(default)]
+    headers: Vec<String>,
+    /// Client request header identifying the user, for instance `authorization`. Responses with
+    /// a private cache policy are only cached if it is set, and only served to requests with the
+    /// same value of this header.
+    #[serde(default)]
+    private_id: Option<String>,
+}
+
+/// Whether a configuration of this plugin sets the `Cache-Control` header of client responses
+pub(crate) fn sets_cache_control(config: &serde_json::Value) -> bool {
+    serde_json::from_value::<Config>(config.clone())
+        .map(|config| config.cache_control)
+        .unwrap_or_default()
+}
+
+/// Cache policy of a subgraph response, `None` if it has neither a `Cache-Control` header nor
+/// hints. Without a `max-age`, it must not be reused.
+fn subgraph_policy(response: &subgraph::Response) -> Option<CachePolicy> {
+    let mut policy = CachePolicy::from_headers(response.response.headers())
+        .or_else(|| CachePolicy::from_extensions(&response.response.body().extensions))?;
+    policy.max_age = Some(policy.max_age.unwrap_or_default());
+    Some(policy)
+}
+
+/// Aggregated cache policy of the subgraph responses of a query, `None` if no subgraph was
+/// called
+fn response_policy(context: &Context) -> Option<CachePolicy> {
+    let policy: CachePolicy = context.get(CACHE_POLICY_CONTEXT_KEY).ok().flatten()?;
+    let cacheable: bool = context
+        .get(CACHEABLE_CONTEXT_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    if cacheable {
+        Some(policy)
+    } else {
+        Some(CachePolicy {
+            no_store: true,
+            ..policy
+        })
+    }
+}
+
+/// Sets the `Cache-Control` header of a client response, unless no subgraph was called
+fn set_cache_control(response: &mut supergraph::Response) {
+    if let Some(policy) = response_policy(&response.context) {
+        response
+            .response
+            .headers_mut()
+            .insert(CACHE_CONTROL, policy.header_value());
+    }
+}
+
+/// A cached response, with its expiration as a UNIX timestamp in seconds
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CachedResponse {
+    response: graphql::Response,
+    private: bool,
+    expires_at: u64,
+}
+
+struct ResponseCache {
+    cache_control: bool,
+    cache: Option<Arc<CachedResponses>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ResponseCache {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        // Responses depend on the schema, routers using another one must not get them from Redis
+        #[cfg(feature = "experimental_cache")]
+        let schema_id = format!("{:x}", Sha256::digest(init.supergraph_sdl.as_bytes()));
+        let cache = match init.config.cache {
+            Some(cache) => Some(Arc::new(CachedResponses {
+                storage: CacheStorage::new(
+                    cache.storage.in_memory.limit,
+                    #[cfg(feature = "experimental_cache")]
+                    cache.storage.for_schema(Some(&schema_id)).redis,
+                    "response",
+                )
+                .await,
+                headers: cache.headers,
+                private_id: cache.private_id,
+            })),
+            None => None,
+        };
+
+        Ok(Self {
+            cache_control: init.config.cache_control,
+            cache,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let cache_control = self.cache_control;
+        let service = ServiceBuilder::new()
+            .map_response(move |mut response: supergraph::Response| {
+                if cache_control {
+                    set_cache_control(&mut response);
+                }
+                response
+            })
+            .service(service)
+            .boxed();
+
+        match &self.cache {
+            Some(cache) => {
+                let cache = cache.clone();
+                CacheService::new(
+                    ServiceBuilder::new().buffered().service(service),
+                    move |inner, request: supergraph::Request| {
+                        cache.clone().call(inner, request, cache_control)
+                    },
+                )
+                .boxed()
+            }
+            None => service,
+        }
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        ServiceBuilder::new()
+            .map_request(|request: execution::Request| {
+                // Only the responses of queries can be reused, and the cache policy of deferred
+                // responses is not known when the client response headers are sent
+                let body = request.supergraph_request.body();
+                let cacheable = !request.query_plan.contains_mutations()
+                    && !request.query_plan.is_subscription()
+                    && !request
+                        .query_plan
+                        .is_deferred(body.operation_name.as_deref(), &body.variables);
+                if let Err(err) = request.context.insert(CACHEABLE_CONTEXT_KEY, cacheable) {
+                    tracing::error!("cannot store the cacheability of the request: {:?}", err);
+                }
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let name = name.to_string();
+        ServiceBuilder::new()
+            .map_response(move |response: subgraph::Response| {
+                let policy = subgraph_policy(&response).unwrap_or(CachePolicy {
+                    max_age: Some(0),
+                    ..Default::default()
+                });
+                if let Err(err) = response
+                    .context
+                    .upsert(CACHE_POLICY_CONTEXT_KEY, |aggregated: CachePolicy| {
+                        aggregated.merge(policy)
+                    })
+                {
+                    tracing::error!(
+                        "cannot store the cache policy of subgraph '{}': {:?}",
+                        name,
+                        err
+                    );
+                }
+                response
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+struct CachedResponses {
+    storage: CacheStorage<String, CachedResponse>,
+    headers: Vec<String>,
+    private_id: Option<String>,
+}
+
+impl CachedResponses {
+    async fn call<S>(
+        self: Arc<Self>,
+        mut inner: S,
+        request: supergraph::Request,
+        cache_control: bool,
+    ) -> Result<supergraph::Response, BoxError>
+    where
+        S: Service<supergraph::Request, Response = supergraph::Response, Error = BoxError>,
+    {
+        let key = self.key(&request)?;
+        let private_key = self.private_key(&key, &request);
+        let now = now();
+        let mut cached = self
+            .storage
+            .get(&key)
+            .await
+            .filter(|cached| cached.expires_at > now);
+        if let (None, Some(private_key)) = (&cached, &private_key) {
+            cached = self
+                .storage
+                .get(private_key)
+                .await
+                .filter(|cached| cached.expires_at > now);
+        }
+        if let Some(cached) = cached {
+            tracing::trace!("response cache hit");
+            let mut response =
+                supergraph::Response::new_from_graphql_response(cached.response, request.context);
+            if cache_control {
+                let policy = CachePolicy {
+                    max_age: Some(cached.expires_at - now),
+                    private: cached.private,
+                    no_store: false,
+                };
+                response
+                    .response
+                    .headers_mut()
+                    .insert(CACHE_CONTROL, policy.header_value());
+            }
+            return Ok(response);
+        }
+
+        let mut response = inner.call(request).await?;
+        let policy = match response_policy(&response.context) {
+            Some(policy)
+                if policy.is_cacheable() && response.response.status() == StatusCode::OK =>
+            {
+                policy
+            }
+            _ => return Ok(response),
+        };
+        // private responses are only cached for an identified user
+        let key = match (policy.private, private_key) {
+            (false, _) => key,
+            (true, Some(private_key)) => private_key,
+            (true, None) => return Ok(response),
+        };
+
+        let first = match response.next_response().await {
+            Some(first) => first,
+            None => return Ok(response),
+        };
+        if first.errors.is_empty() && !first.has_next.unwrap_or_default() {
+            tracing::trace!("response cache insert");
+            self.storage
+                .insert(
+                    key,
+                    CachedResponse {
+                        response: first.clone(),
+                        private: policy.private,
+                        expires_at: now + policy.max_age.unwrap_or_default(),
+                    },
+                )
+                .await;
+        }
+
+        Ok(response.map(move |rest| once(ready(first)).chain(rest).boxed()))
+    }
+
+    /// The cache key is made from the query, its variables and the configured client headers
+    fn key(&self, request: &supergraph::Request) -> Result<String, BoxError> {
+        let body = request.supergraph_request.body();
+        let mut hasher = Sha256::new();
+        hasher.update(body.query.as_deref().unwrap_or_default());
+        hasher.update(body.operation_name.as_deref().unwrap_or_default());
+        hasher.update(serde_json::to_vec(&body.variables)?);
+        for header in &self.headers {
+            for value in request.supergraph_request.headers().get_all(header) {
+                hasher.update(header);
+                hasher.update(value.as_bytes());
+            }
+        }
+
+        Ok(format!("response:{}", hex::encode(hasher.finalize())))
+    }
+
+    /// Key of the private responses of the user making the request, `None` if the user is not
+    /// identified
+    fn private_key(&self, key: &str, request: &supergraph::Request) -> Option<String> {
+        let header = self.private_id.as_ref()?;
+        let mut values = request
+            .supergraph_request
+            .headers()
+            .get_all(header)
+            .iter()
+            .peekable();
+        values.peek()?;
+
+        let mut hasher = Sha256::new();
+        for value in values {
+            hasher.update(value.as_bytes());
+        }
+        Some(format!("{}:{}", key, hex::encode(hasher.finalize())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use http::HeaderValue;
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::json_ext::Value;
+    use crate::plugin::test::MockSupergraphService;
+
+    fn mock_supergraph(times: usize, cacheable: bool, private: bool) -> MockSupergraphService {
+        let mut mock = MockSupergraphService::new();
+        mock.expect_call().times(times).returning(move |request| {
+            request
+                .context
+                .upsert(CACHE_POLICY_CONTEXT_KEY, |aggregated: CachePolicy| {
+                    aggregated.merge(CachePolicy {
+                        max_age: Some(60),
+                        private,
+                        no_store: false,
+                    })
+                })?;
+            request.context.insert(CACHEABLE_CONTEXT_KEY, cacheable)?;
+            supergraph::Response::fake_builder()
+                .data(json!({ "me": { "name": "Ada" } }))
+                .context(request.context)
+                .build()
+        });
+        mock
+    }
+
+    async fn response_cache(config: serde_json::Value) -> ResponseCache {
+        ResponseCache::new(PluginInit::new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap()
+    }
+
+    async fn call(service: &mut supergraph::BoxService) -> (Option<HeaderValue>, Value) {
+        call_as(service, None).await
+    }
+
+    async fn call_as(
+        service: &mut supergraph::BoxService,
+        user: Option<&str>,
+    ) -> (Option<HeaderValue>, Value) {
+        let mut request = supergraph::Request::fake_builder()
+            .query("{ me { name } }")
+            .build()
+            .unwrap();
+        if let Some(user) = user {
+            request
+                .supergraph_request
+                .headers_mut()
+                .insert("authorization", user.parse().unwrap());
+        }
+        let mut response = service.ready().await.unwrap().call(request).await.unwrap();
+        let cache_control = response.response.headers().get(CACHE_CONTROL).cloned();
+        let data = response.next_response().await.unwrap().data.unwrap();
+        (cache_control, data)
+    }
+
+    #[tokio::test]
+    async fn it_serves_queries_from_the_cache() {
+        let plugin = response_cache(serde_json::json!({ "cache": {} })).await;
+        let mut service = plugin.supergraph_service(mock_supergraph(1, true, false).boxed());
+
+        let (cache_control, data) = call(&mut service).await;
+        assert_eq!(cache_control.unwrap(), "max-age=60, public");
+        assert_eq!(data, json!({ "me": { "name": "Ada" } }));
+
+        // The remaining time to live is sent with the cached response
+        let (cache_control, data) = call(&mut service).await;
+        assert!(cache_control
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("max-age="));
+        assert_eq!(data, json!({ "me": { "name": "Ada" } }));
+    }
+
+    #[tokio::test]
+    async fn it_does_not_cache_mutations() {
+        let plugin = response_cache(serde_json::json!({ "cache": {} })).await;
+        let mut service = plugin.supergraph_service(mock_supergraph(2, false, false).boxed());
+
+        for _ in 0..2 {
+            let (cache_control, _) = call(&mut service).await;
+            assert_eq!(cache_control.unwrap(), "no-store");
+        }
+    }
+
+    #[tokio::test]
+    async fn it_does_not_cache_private_responses_without_private_id() {
+        let plugin = response_cache(serde_json::json!({
+            "cache": { "headers": ["authorization"] }
+        }))
+        .await;
+        let mut service = plugin.supergraph_service(mock_supergraph(2, true, true).boxed());
+
+        for _ in 0..2 {
+            let (cache_control, _) = call_as(&mut service, Some("ada")).await;
+            assert_eq!(cache_control.unwrap(), "max-age=60, private");
+        }
+    }
+
+    #[tokio::test]
+    async fn it_caches_private_responses_per_user() {
+        let plugin = response_cache(serde_json::json!({
+            "cache": { "private_id": "authorization" }
+        }))
+        .await;
+        // called once for each user, and for each request of an unidentified user
+        let mut service = plugin.supergraph_service(mock_supergraph(4, true, true).boxed());
+
+        call_as(&mut service, Some("ada")).await;
+        let (cache_control, _) = call_as(&mut service, Some("ada")).await;
+        assert!(cache_control
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .ends_with(", private"));
+
+        call_as(&mut service, Some("grace")).await;
+        call(&mut service).await;
+        call(&mut service).await;
+    }
+
+    #[tokio::test]
+    async fn it_does_not_set_cache_control_when_disabled() {
+        let plugin = response_cache(serde_json::json!({ "cache_control": false })).await;
+        let mut service = plugin.supergraph_service(mock_supergraph(1, true, false).boxed());
+
+        let (cache_control, _) = call(&mut service).await;
+        assert_eq!(cache_control, None);
+    }
+
+    /// Cache-Control header of a client response, when the subgraph answers with these headers
+    async fn cache_control(plugin: Arc<ResponseCache>, headers: HeaderMap) -> Option<HeaderValue> {
+        let subgraph_plugin = plugin.clone();
+        let service = plugin.supergraph_service(
+            tower::service_fn(move |request: supergraph::Request| {
+                let headers = headers.clone();
+                let subgraph = subgraph_plugin.subgraph_service(
+                    "products",
+                    tower::service_fn(move |request: subgraph::Request| {
+                        let mut response = http::Response::new(graphql::Response::default());
+                        *response.headers_mut() = headers.clone();
+                        ready(Ok::<_, BoxError>(subgraph::Response::new_from_response(
+                            response,
+                            request.context,
+                        )))
+                    })
+                    .boxed(),
+                );
+                async move {
+                    subgraph
+                        .oneshot(
+                            subgraph::Request::fake_builder()
+                                .context(request.context.clone())
+                                .build(),
+                        )
+                        .await?;
+                    request.context.insert(CACHEABLE_CONTEXT_KEY, true)?;
+                    supergraph::Response::fake_builder()
+                        .context(request.context)
+                        .build()
+                }
+            })
+            .boxed(),
+        );
+        let response = service
+            .oneshot(supergraph::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        response.response.headers().get(CACHE_CONTROL).cloned()
+    }
+
+    #[tokio::test]
+    async fn it_sets_cache_control_from_the_subgraph_responses() {
+        let plugin = Arc::new(response_cache(serde_json::json!({})).await);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, "s-maxage=60".parse().unwrap());
+        assert_eq!(
+            cache_control(plugin.clone(), headers).await.unwrap(),
+            "max-age=60, public"
+        );
+        // a subgraph response without a cache policy cannot be reused
+        assert_eq!(
+            cache_control(plugin, HeaderMap::new()).await.unwrap(),
+            "no-store"
+        );
+    }
+
+    #[test]
+    fn test_sets_cache_control() {
+        assert!(sets_cache_control(&serde_json::json!({})));
+        assert!(!sets_cache_control(
+            &serde_json::json!({ "cache_control": false })
+        ));
+    }
+}
@@ -62,6 +62,7 @@ use crate::json_ext::Value;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::register_plugin;
 use crate::services::ExecutionRequest;
 use crate::services::ExecutionResponse;
@@ -405,7 +406,7 @@ impl EngineBlock {
 
         scope.push_constant(
             "APOLLO_AUTHENTICATION_JWT_CLAIMS",
-            "apollo_authentication::JWT::claims".to_string(),
+            APOLLO_AUTHENTICATION_JWT_CLAIMS.to_string(),
         );
 
         // Run the AST with our scope to put any global variables
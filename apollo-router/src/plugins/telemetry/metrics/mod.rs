@@ -533,6 +533,8 @@ pub(crate) trait MetricsConfigurator {
 pub(crate) struct BasicMetrics {
     pub(crate) http_requests_total: Counter<u64>,
     pub(crate) http_requests_duration: Histogram<f64>,
+    pub(crate) batches_total: Counter<u64>,
+    pub(crate) batch_size: Histogram<f64>,
 }
 
 impl Default for BasicMetrics {
@@ -547,6 +549,14 @@ impl Default for BasicMetrics {
                 .f64_histogram("apollo_router_http_request_duration_seconds")
                 .with_description("Total number of HTTP requests made.")
                 .init(),
+            batches_total: meter
+                .u64_counter("apollo_router_batches_total")
+                .with_description("Total number of batched requests received.")
+                .init(),
+            batch_size: meter
+                .f64_histogram("apollo_router_batch_size")
+                .with_description("Number of operations in batched requests.")
+                .init(),
         }
     }
 }
@@ -101,6 +101,7 @@ use crate::register_plugin;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
 use crate::services::router;
+use crate::services::router_service::BATCH_SIZE_CONTEXT_KEY;
 use crate::services::subgraph;
 use crate::services::subgraph::Request;
 use crate::services::subgraph::Response;
@@ -125,7 +126,7 @@ pub(crate) const SUPERGRAPH_SPAN_NAME: &str = "supergraph";
 pub(crate) const SUBGRAPH_SPAN_NAME: &str = "subgraph";
 pub(crate) const ROUTER_SPAN_NAME: &str = "router";
 pub(crate) const EXECUTION_SPAN_NAME: &str = "execution";
-const CLIENT_NAME: &str = "apollo_telemetry::client_name";
+pub(crate) const CLIENT_NAME: &str = "apollo_telemetry::client_name";
 const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
 const ATTRIBUTES: &str = "apollo_telemetry::metrics_attributes";
 const SUBGRAPH_ATTRIBUTES: &str = "apollo_telemetry::subgraph_metrics_attributes";
@@ -228,6 +229,7 @@ impl Plugin for Telemetry {
     fn router_service(&self, service: router::BoxService) -> router::BoxService {
         let config = self.config.clone();
         let config_later = self.config.clone();
+        let metrics = self.metrics.clone();
 
         ServiceBuilder::new()
             .instrument(move |request: &router::Request| {
@@ -263,6 +265,7 @@ impl Plugin for Telemetry {
             .map_future(move |fut| {
                 let start = Instant::now();
                 let config = config_later.clone();
+                let metrics = metrics.clone();
                 async move {
                     let span = Span::current();
                     let response: Result<router::Response, BoxError> = fut.await;
@@ -292,6 +295,10 @@ impl Plugin for Telemetry {
                             span.record("otel.status_code", "Ok");
                         }
 
+                        if let Ok(Some(batch_size)) = response.context.get::<_, usize>(BATCH_SIZE_CONTEXT_KEY) {
+                            metrics.batches_total.add(&opentelemetry::Context::current(), 1, &[]);
+                            metrics.batch_size.record(&opentelemetry::Context::current(), batch_size as f64, &[]);
+                        }
                     }
                     response
                 }
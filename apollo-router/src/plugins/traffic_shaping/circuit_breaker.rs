@@ -0,0 +1,330 @@
+//! Circuit breaker per subgraph
+//!
+//! When too many requests to a subgraph fail, the circuit opens and the following requests
+//! fail right away, without being sent, for the configured duration. The circuit is then
+//! half-open: a few probe requests are sent, and the circuit closes if they succeed, or opens
+//! again if one of them fails.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::StatusCode;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use super::CircuitBreakerConfig;
+use crate::graphql;
+use crate::services::subgraph;
+
+const DEFAULT_ERROR_RATE_THRESHOLD: f32 = 0.5;
+const DEFAULT_MIN_REQUESTS: u32 = 20;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_HALF_OPEN_REQUESTS: u32 = 1;
+
+pub(crate) const CIRCUIT_OPEN_ERROR_CODE: &str = "SUBGRAPH_CIRCUIT_OPEN";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Closed {
+        window_start: Instant,
+        requests: u32,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        /// Probe requests sent, and not cancelled
+        started: u32,
+        succeeded: u32,
+    },
+}
+
+impl State {
+    fn closed() -> Self {
+        State::Closed {
+            window_start: Instant::now(),
+            requests: 0,
+            failures: 0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            State::Closed { .. } => "closed",
+            State::Open { .. } => "open",
+            State::HalfOpen { .. } => "half_open",
+        }
+    }
+}
+
+/// State of the circuit of a subgraph, shared by all the services created for it
+struct CircuitBreaker {
+    subgraph_name: String,
+    error_rate_threshold: f32,
+    min_requests: u32,
+    window: Duration,
+    open_duration: Duration,
+    half_open_requests: u32,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// Lets the request through, or returns `None` if the circuit is open
+    fn admit(self: &Arc<Self>) -> Option<Admission> {
+        let mut state = self.state.lock().expect("lock poisoned");
+        if let State::Open { until } = *state {
+            if Instant::now() < until {
+                return None;
+            }
+            self.transition(
+                &mut state,
+                State::HalfOpen {
+                    started: 0,
+                    succeeded: 0,
+                },
+            );
+        }
+
+        let probe = match &mut *state {
+            State::HalfOpen { started, .. } => {
+                if *started >= self.half_open_requests {
+                    return None;
+                }
+                *started += 1;
+                true
+            }
+            _ => false,
+        };
+
+        Some(Admission {
+            breaker: self.clone(),
+            probe,
+            done: false,
+        })
+    }
+
+    fn record(&self, probe: bool, failed: bool) {
+        let mut state = self.state.lock().expect("lock poisoned");
+        match &mut *state {
+            State::Closed {
+                window_start,
+                requests,
+                failures,
+            } if !probe => {
+                if window_start.elapsed() >= self.window {
+                    *window_start = Instant::now();
+                    *requests = 0;
+                    *failures = 0;
+                }
+                *requests += 1;
+                *failures += failed as u32;
+
+                if *requests >= self.min_requests
+                    && *failures as f32 >= *requests as f32 * self.error_rate_threshold
+                {
+                    tracing::warn!(
+                        "opening the circuit of subgraph '{}': {} of the last {} requests failed",
+                        self.subgraph_name,
+                        failures,
+                        requests
+                    );
+                    self.open(&mut state);
+                }
+            }
+            State::HalfOpen { succeeded, .. } if probe => {
+                if failed {
+                    tracing::warn!(
+                        "opening the circuit of subgraph '{}' again: a probe request failed",
+                        self.subgraph_name
+                    );
+                    self.open(&mut state);
+                } else {
+                    *succeeded += 1;
+                    if *succeeded >= self.half_open_requests {
+                        tracing::info!("closing the circuit of subgraph '{}'", self.subgraph_name);
+                        self.transition(&mut state, State::closed());
+                    }
+                }
+            }
+            // responses to requests admitted before the last transition
+            _ => {}
+        }
+    }
+
+    /// Frees the slot of a probe request that was cancelled before getting a response
+    fn cancel(&self) {
+        if let State::HalfOpen { started, .. } = &mut *self.state.lock().expect("lock poisoned") {
+            *started = started.saturating_sub(1);
+        }
+    }
+
+    fn open(&self, state: &mut State) {
+        self.transition(
+            state,
+            State::Open {
+                until: Instant::now() + self.open_duration,
+            },
+        );
+    }
+
+    fn transition(&self, state: &mut State, next: State) {
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_subgraph_circuit_breaker_state = -1i64,
+            subgraph = %self.subgraph_name,
+            state = state.name(),
+        );
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_subgraph_circuit_breaker_state = 1i64,
+            subgraph = %self.subgraph_name,
+            state = next.name(),
+        );
+        *state = next;
+    }
+}
+
+impl Drop for CircuitBreaker {
+    fn drop(&mut self) {
+        // the breaker is dropped with the plugin, on a reload
+        let state = self
+            .state
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_subgraph_circuit_breaker_state = -1i64,
+            subgraph = %self.subgraph_name,
+            state = state.name(),
+        );
+    }
+}
+
+/// A request let through by the circuit breaker, which must record its outcome
+struct Admission {
+    breaker: Arc<CircuitBreaker>,
+    probe: bool,
+    done: bool,
+}
+
+impl Admission {
+    fn record(mut self, result: &Result<subgraph::Response, BoxError>) {
+        let failed = match result {
+            Ok(response) => response.response.status().is_server_error(),
+            Err(_) => true,
+        };
+        self.breaker.record(self.probe, failed);
+        self.done = true;
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        if !self.done && self.probe {
+            self.breaker.cancel();
+        }
+    }
+}
+
+/// [`Layer`] failing the subgraph requests fast while the subgraph's circuit is open
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerLayer {
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CircuitBreakerLayer {
+    pub(crate) fn new(subgraph_name: &str, config: &CircuitBreakerConfig) -> Self {
+        let state = State::closed();
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_subgraph_circuit_breaker_state = 1i64,
+            subgraph = subgraph_name,
+            state = state.name(),
+        );
+
+        Self {
+            breaker: Arc::new(CircuitBreaker {
+                subgraph_name: subgraph_name.to_string(),
+                error_rate_threshold: config
+                    .error_rate_threshold
+                    .unwrap_or(DEFAULT_ERROR_RATE_THRESHOLD),
+                min_requests: config.min_requests.unwrap_or(DEFAULT_MIN_REQUESTS).max(1),
+                window: config.window.unwrap_or(DEFAULT_WINDOW),
+                open_duration: config.open_duration.unwrap_or(DEFAULT_OPEN_DURATION),
+                half_open_requests: config
+                    .half_open_requests
+                    .unwrap_or(DEFAULT_HALF_OPEN_REQUESTS)
+                    .max(1),
+                state: Mutex::new(state),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreakerService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        CircuitBreakerService {
+            inner: service,
+            breaker: self.breaker.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerService<S> {
+    inner: S,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<S> Service<subgraph::Request> for CircuitBreakerService<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: subgraph::Request) -> Self::Future {
+        let admission = match self.breaker.admit() {
+            Some(admission) => admission,
+            None => {
+                let error = graphql::Error::builder()
+                    .message(format!(
+                        "subgraph '{}' is unavailable: its circuit breaker is open",
+                        self.breaker.subgraph_name
+                    ))
+                    .extension_code(CIRCUIT_OPEN_ERROR_CODE)
+                    .build();
+                let response = subgraph::Response::error_builder()
+                    .error(error)
+                    .status_code(StatusCode::SERVICE_UNAVAILABLE)
+                    .context(request.context)
+                    .build();
+                return futures::future::ready(response).boxed();
+            }
+        };
+
+        let response = self.inner.call(request);
+        async move {
+            let result = response.await;
+            admission.record(&result);
+            result
+        }
+        .boxed()
+    }
+}
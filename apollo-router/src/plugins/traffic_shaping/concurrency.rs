@@ -0,0 +1,457 @@
+//! Concurrency limit, with a bounded queue of waiting requests and load shedding
+//!
+//! Requests over the limit wait in a queue for a slot. When the queue is full, or when a
+//! request waited for longer than the queue timeout, the request is shed: it gets a `503`
+//! response with a GraphQL error, without being processed. In adaptive mode, the limit is
+//! adjusted from the latency of the requests: it increases by one while the requests are fast,
+//! and is multiplied by the backoff ratio when a request is slow or fails (AIMD).
+//!
+//! A request keeps its slot until its response is complete: for the streamed responses, like
+//! the ones to `@defer` queries, until the last part was sent.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use http::StatusCode;
+use serde_json_bytes::Value;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use super::ConcurrencyLimitConf;
+use crate::graphql;
+use crate::services::subgraph;
+use crate::services::supergraph;
+
+const DEFAULT_QUEUE_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_MIN_LIMIT: usize = 1;
+const DEFAULT_MAX_LIMIT: usize = 1000;
+const DEFAULT_LATENCY_TOLERANCE: f64 = 2.0;
+const DEFAULT_BACKOFF_RATIO: f64 = 0.9;
+/// The minimum latency is measured over this window, so that it follows the changes of the
+/// service's usual latency
+const LATENCY_WINDOW: Duration = Duration::from_secs(60);
+
+pub(crate) const OVERLOADED_ERROR_CODE: &str = "OVERLOADED";
+
+/// Why a request was shed
+#[derive(Clone, Copy, Debug)]
+enum Shed {
+    QueueFull,
+    QueueTimeout,
+}
+
+impl Shed {
+    fn reason(&self) -> &'static str {
+        match self {
+            Shed::QueueFull => "queue_full",
+            Shed::QueueTimeout => "queue_timeout",
+        }
+    }
+}
+
+/// Requests to which the concurrency limit answers directly when it sheds them
+pub(crate) trait Sheddable {
+    type Response;
+
+    fn shed(self, error: graphql::Error) -> Self::Response;
+
+    /// Keeps the slot of the request until its response is complete
+    fn hold(response: Self::Response, permit: Permit) -> Self::Response;
+}
+
+impl Sheddable for supergraph::Request {
+    type Response = supergraph::Response;
+
+    fn shed(self, error: graphql::Error) -> Self::Response {
+        supergraph::Response::error_builder()
+            .error(error)
+            .status_code(StatusCode::SERVICE_UNAVAILABLE)
+            .context(self.context)
+            .build()
+            .expect("response is valid")
+    }
+
+    fn hold(response: Self::Response, permit: Permit) -> Self::Response {
+        response.map(|stream| {
+            stream
+                .map(move |response| {
+                    // the permit is dropped with the stream, once the last part was sent or
+                    // the client went away
+                    let _permit = &permit;
+                    response
+                })
+                .boxed()
+        })
+    }
+}
+
+impl Sheddable for subgraph::Request {
+    type Response = subgraph::Response;
+
+    fn shed(self, error: graphql::Error) -> Self::Response {
+        subgraph::Response::error_builder()
+            .error(error)
+            .status_code(StatusCode::SERVICE_UNAVAILABLE)
+            .context(self.context)
+            .build()
+            .expect("response is valid")
+    }
+
+    fn hold(response: Self::Response, _permit: Permit) -> Self::Response {
+        // the subgraph responses are not streamed
+        response
+    }
+}
+
+/// Adjustment of the limit from the latency of the requests
+struct Adaptive {
+    min_limit: usize,
+    max_limit: usize,
+    latency_tolerance: f64,
+    backoff_ratio: f64,
+}
+
+/// Minimum latency of the current window and of the previous one
+struct MinLatency {
+    window_start: Instant,
+    current: Option<Duration>,
+    previous: Option<Duration>,
+}
+
+impl MinLatency {
+    fn record(&mut self, latency: Duration) {
+        if self.window_start.elapsed() >= LATENCY_WINDOW {
+            self.window_start = Instant::now();
+            self.previous = self.current.take();
+        }
+        self.current = Some(self.current.map_or(latency, |min| min.min(latency)));
+    }
+
+    fn get(&self) -> Option<Duration> {
+        match (self.current, self.previous) {
+            (Some(current), Some(previous)) => Some(current.min(previous)),
+            (current, previous) => current.or(previous),
+        }
+    }
+}
+
+struct State {
+    limit: usize,
+    in_flight: usize,
+    queued: usize,
+    /// Number of slots to remove from the semaphore after the limit decreased, as they are
+    /// released by the requests holding them
+    excess: usize,
+    min_latency: MinLatency,
+}
+
+struct Limiter {
+    /// `router`, or `subgraph:` followed by the name of the subgraph
+    name: String,
+    queue_size: usize,
+    queue_timeout: Duration,
+    adaptive: Option<Adaptive>,
+    state: Mutex<State>,
+    /// Slots of the requests, the queued requests get them in order
+    semaphore: Arc<Semaphore>,
+}
+
+impl Limiter {
+    async fn acquire(self: &Arc<Self>) -> Result<Permit, Shed> {
+        // the released slots are handed to the queued requests first, so this only succeeds
+        // if nobody is waiting
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Ok(Permit::new(self.clone(), permit));
+        }
+
+        {
+            let mut state = self.state.lock().expect("lock poisoned");
+            if state.queued >= self.queue_size {
+                return Err(Shed::QueueFull);
+            }
+            state.queued += 1;
+        }
+
+        let _queued = Queued::new(self);
+        match tokio::time::timeout(self.queue_timeout, self.semaphore.clone().acquire_owned()).await
+        {
+            Ok(permit) => Ok(Permit::new(
+                self.clone(),
+                permit.expect("the semaphore is never closed"),
+            )),
+            Err(_) => Err(Shed::QueueTimeout),
+        }
+    }
+
+    /// Adjusts the limit from the outcome of a request, in adaptive mode
+    fn record(&self, latency: Duration, failed: bool) {
+        let adaptive = match &self.adaptive {
+            Some(adaptive) => adaptive,
+            None => return,
+        };
+
+        let mut state = self.state.lock().expect("lock poisoned");
+        state.min_latency.record(latency);
+        let slow = state
+            .min_latency
+            .get()
+            .map(|min| latency.as_secs_f64() > min.as_secs_f64() * adaptive.latency_tolerance)
+            .unwrap_or_default();
+
+        let limit = if failed || slow {
+            ((state.limit as f64 * adaptive.backoff_ratio) as usize).max(adaptive.min_limit)
+        } else if state.in_flight * 2 >= state.limit {
+            // only increase the limit if it is being used
+            (state.limit + 1).min(adaptive.max_limit)
+        } else {
+            state.limit
+        };
+
+        if limit != state.limit {
+            // This is a metric and will not appear in the logs
+            tracing::info!(
+                counter.apollo_router_concurrency_limit = limit as i64 - state.limit as i64,
+                limiter = %self.name,
+            );
+            if limit > state.limit {
+                // the slots not removed yet are kept instead of adding new ones
+                let added = limit - state.limit;
+                let kept = added.min(state.excess);
+                state.excess -= kept;
+                self.semaphore.add_permits(added - kept);
+            } else {
+                // the available slots are removed now, the others when they are released
+                state.excess += state.limit - limit;
+                while state.excess > 0 {
+                    match self.semaphore.try_acquire() {
+                        Ok(permit) => {
+                            permit.forget();
+                            state.excess -= 1;
+                        }
+                        Err(_) => break,
+                    }
+                }
+            }
+            state.limit = limit;
+        }
+    }
+}
+
+impl Drop for Limiter {
+    fn drop(&mut self) {
+        // the limiter is dropped with the plugin, on a reload
+        let limit = self
+            .state
+            .get_mut()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .limit;
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_limit = -(limit as i64),
+            limiter = %self.name,
+        );
+    }
+}
+
+/// Removes a request from the queue, including when it is cancelled while waiting
+struct Queued<'a>(&'a Limiter);
+
+impl<'a> Queued<'a> {
+    fn new(limiter: &'a Limiter) -> Self {
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_queued = 1i64,
+            limiter = %limiter.name,
+        );
+        Queued(limiter)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().expect("lock poisoned").queued -= 1;
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_queued = -1i64,
+            limiter = %self.0.name,
+        );
+    }
+}
+
+/// A slot taken by a request, released when dropped
+pub(crate) struct Permit {
+    limiter: Arc<Limiter>,
+    start: Instant,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Permit {
+    fn new(limiter: Arc<Limiter>, permit: OwnedSemaphorePermit) -> Self {
+        limiter.state.lock().expect("lock poisoned").in_flight += 1;
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_in_flight = 1i64,
+            limiter = %limiter.name,
+        );
+        Permit {
+            limiter,
+            start: Instant::now(),
+            permit: Some(permit),
+        }
+    }
+
+    /// Records the latency of the request, until its response or the first part of it
+    fn record(&self, failed: bool) {
+        self.limiter.record(self.start.elapsed(), failed);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.limiter.state.lock().expect("lock poisoned");
+        state.in_flight -= 1;
+        if state.excess > 0 {
+            state.excess -= 1;
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+        }
+        drop(state);
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_in_flight = -1i64,
+            limiter = %self.limiter.name,
+        );
+    }
+}
+
+/// [`Layer`] limiting the number of requests processed at the same time, and shedding the
+/// requests over the limit when its queue is full
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+impl ConcurrencyLimitLayer {
+    pub(crate) fn new(name: String, config: &ConcurrencyLimitConf) -> Self {
+        let limit = config.limit.get();
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_concurrency_limit = limit as i64,
+            limiter = %name,
+        );
+
+        let adaptive = config.adaptive.as_ref().map(|adaptive| {
+            let min_limit = adaptive.min_limit.unwrap_or(DEFAULT_MIN_LIMIT).max(1);
+            Adaptive {
+                min_limit,
+                max_limit: adaptive
+                    .max_limit
+                    .unwrap_or(DEFAULT_MAX_LIMIT)
+                    .max(min_limit),
+                latency_tolerance: adaptive
+                    .latency_tolerance
+                    .unwrap_or(DEFAULT_LATENCY_TOLERANCE),
+                backoff_ratio: adaptive.backoff_ratio.unwrap_or(DEFAULT_BACKOFF_RATIO),
+            }
+        });
+
+        Self {
+            limiter: Arc::new(Limiter {
+                name,
+                queue_size: config.queue_size.unwrap_or_default(),
+                queue_timeout: config.queue_timeout.unwrap_or(DEFAULT_QUEUE_TIMEOUT),
+                adaptive,
+                state: Mutex::new(State {
+                    limit,
+                    in_flight: 0,
+                    queued: 0,
+                    excess: 0,
+                    min_latency: MinLatency {
+                        window_start: Instant::now(),
+                        current: None,
+                        previous: None,
+                    },
+                }),
+                semaphore: Arc::new(Semaphore::new(limit)),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner: service,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimit<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S, Request> Service<Request> for ConcurrencyLimit<S>
+where
+    Request: Sheddable<Response = S::Response> + Send + 'static,
+    S: Service<Request, Error = BoxError> + Clone + Send + 'static,
+    S::Response: Send + 'static,
+    S::Future: Send,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // the readiness of the inner service is checked once the request got a slot
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let service = self.inner.clone();
+        let limiter = self.limiter.clone();
+
+        Box::pin(async move {
+            let permit = match limiter.acquire().await {
+                Ok(permit) => permit,
+                Err(shed) => {
+                    tracing::debug!(
+                        "shedding a request to '{}': {}",
+                        limiter.name,
+                        shed.reason()
+                    );
+                    // This is a metric and will not appear in the logs
+                    tracing::info!(
+                        monotonic_counter.apollo_router_concurrency_shed_count = 1u64,
+                        limiter = %limiter.name,
+                        reason = shed.reason(),
+                    );
+                    let error = graphql::Error::builder()
+                        .message("too many concurrent requests, please try again later")
+                        .extension_code(OVERLOADED_ERROR_CODE)
+                        .extension("reason", Value::from(shed.reason()))
+                        .build();
+                    return Ok(request.shed(error));
+                }
+            };
+
+            let result = service.oneshot(request).await;
+            permit.record(result.is_err());
+            result.map(|response| Request::hold(response, permit))
+        })
+    }
+}
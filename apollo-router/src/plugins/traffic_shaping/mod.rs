@@ -5,8 +5,13 @@
 //! * Timeout
 //! * Compression
 //! * Rate limiting
+//! * Retries
+//! * Circuit breaking
+//! * Concurrency limits
 //!
 
+mod circuit_breaker;
+mod concurrency;
 mod deduplication;
 mod rate;
 mod retry;
@@ -14,13 +19,19 @@ mod timeout;
 
 use std::collections::HashMap;
 use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
+use std::ops::ControlFlow;
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 
 use futures::future::BoxFuture;
 use http::header::ACCEPT_ENCODING;
 use http::header::CONTENT_ENCODING;
+use http::header::RETRY_AFTER;
 use http::HeaderValue;
+use http::StatusCode;
+use regex::Regex;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use tower::retry::Retry;
@@ -31,18 +42,34 @@ use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use self::circuit_breaker::CircuitBreakerLayer;
+use self::concurrency::ConcurrencyLimitLayer;
 use self::deduplication::QueryDeduplicationLayer;
+#[cfg(feature = "experimental_cache")]
+use self::rate::DistributedRateLimiter;
+#[cfg(feature = "experimental_cache")]
+use self::rate::FailureMode;
+use self::rate::KeyedRateLimiter;
+use self::rate::Rate;
 use self::rate::RateLimitLayer;
 pub(crate) use self::rate::RateLimited;
 use self::retry::RetryPolicy;
 pub(crate) use self::timeout::Elapsed;
 use self::timeout::TimeoutLayer;
+#[cfg(feature = "experimental_cache")]
+use crate::cache::redis::RedisCacheStorage;
+#[cfg(feature = "experimental_cache")]
+use crate::configuration::RedisCache;
 use crate::error::ConfigurationError;
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::register_plugin;
 use crate::services::subgraph;
 use crate::services::subgraph_service::Compression;
+use crate::services::subgraph_service::HttpClientConfig;
 use crate::services::supergraph;
 use crate::services::SubgraphRequest;
 use crate::Configuration;
@@ -50,7 +77,7 @@ use crate::Configuration;
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 pub(crate) const APOLLO_TRAFFIC_SHAPING: &str = "apollo.traffic_shaping";
 
-trait Merge {
+pub(crate) trait Merge {
     fn merge(&self, fallback: Option<&Self>) -> Self;
 }
 
@@ -73,6 +100,12 @@ struct Shaping {
     /// Retry configuration
     //  *experimental feature*: Enables request retry
     experimental_retry: Option<RetryConfig>,
+    /// Fail fast on the requests to a subgraph when too many of them fail
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Limit the number of requests sent to the subgraph at the same time
+    max_concurrency: Option<ConcurrencyLimitConf>,
+    /// Connection pool and HTTP/2 options of the HTTP client
+    http_client: Option<HttpClientConfig>,
 }
 
 impl Merge for Shaping {
@@ -94,6 +127,21 @@ impl Merge for Shaping {
                     .as_ref()
                     .or(fallback.experimental_retry.as_ref())
                     .cloned(),
+                circuit_breaker: self
+                    .circuit_breaker
+                    .as_ref()
+                    .or(fallback.circuit_breaker.as_ref())
+                    .cloned(),
+                max_concurrency: self
+                    .max_concurrency
+                    .as_ref()
+                    .or(fallback.max_concurrency.as_ref())
+                    .cloned(),
+                http_client: self
+                    .http_client
+                    .as_ref()
+                    .map(|http_client| http_client.merge(fallback.http_client.as_ref()))
+                    .or_else(|| fallback.http_client.clone()),
             },
         }
     }
@@ -119,6 +167,24 @@ struct RetryConfig {
     /// allows request retries on mutations. This should only be activated if mutations
     /// are idempotent. Disabled by default
     retry_mutations: Option<bool>,
+    /// HTTP status codes of the subgraph responses that should be retried, like 502 or 503.
+    /// By default, only the requests that failed without a response are retried
+    status_codes: Option<Vec<u16>>,
+    /// GraphQL error codes (`extensions.code`) of the subgraph responses that should be
+    /// retried. By default, responses are not retried on their errors
+    error_codes: Option<Vec<String>>,
+    /// maximum number of attempts of a request, including the first one. By default, only the
+    /// retry budget limits the number of attempts
+    max_attempts: Option<u32>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// delay before the first retry, doubled at each following retry. A random jitter of up to
+    /// half of the delay is removed from it. The default value is 100ms
+    min_backoff: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// maximum delay between two attempts. The default value is 5 seconds
+    max_backoff: Option<Duration>,
 }
 
 impl Merge for RetryConfig {
@@ -130,20 +196,95 @@ impl Merge for RetryConfig {
                 min_per_sec: self.min_per_sec.or(fallback.min_per_sec),
                 retry_percent: self.retry_percent.or(fallback.retry_percent),
                 retry_mutations: self.retry_mutations.or(fallback.retry_mutations),
+                status_codes: self
+                    .status_codes
+                    .as_ref()
+                    .or(fallback.status_codes.as_ref())
+                    .cloned(),
+                error_codes: self
+                    .error_codes
+                    .as_ref()
+                    .or(fallback.error_codes.as_ref())
+                    .cloned(),
+                max_attempts: self.max_attempts.or(fallback.max_attempts),
+                min_backoff: self.min_backoff.or(fallback.min_backoff),
+                max_backoff: self.max_backoff.or(fallback.max_backoff),
             },
         }
     }
 }
 
+/// Circuit breaker configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct CircuitBreakerConfig {
+    /// proportion of failed requests, between 0 and 1, above which the circuit opens. A
+    /// request fails if it returns an error, times out or gets a 5xx response. The default
+    /// value is 0.5
+    error_rate_threshold: Option<f32>,
+    /// minimum number of requests in the window before the error rate is evaluated. The
+    /// default value is 20
+    min_requests: Option<u32>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// duration over which the error rate is measured. The default value is 10 seconds
+    window: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// how long the circuit stays open, failing the requests without sending them, before
+    /// letting probe requests through. The default value is 30 seconds
+    open_duration: Option<Duration>,
+    /// number of probe requests sent when the circuit is half-open. The circuit closes if
+    /// they all succeed, and opens again if one of them fails. The default value is 1
+    half_open_requests: Option<u32>,
+}
+
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct RouterShaping {
     /// Enable global rate limiting
     global_rate_limit: Option<RateLimitConf>,
+    /// Enable rate limiting per value of a request attribute
+    keyed_rate_limit: Option<KeyedRateLimitConf>,
     #[serde(deserialize_with = "humantime_serde::deserialize", default)]
     #[schemars(with = "String", default)]
     /// Enable timeout for incoming requests
     timeout: Option<Duration>,
+    /// Limit the number of requests processed at the same time
+    max_concurrency: Option<ConcurrencyLimitConf>,
+}
+
+/// Concurrency limit configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct ConcurrencyLimitConf {
+    /// Number of requests processed at the same time. In adaptive mode, this is the initial
+    /// limit
+    limit: NonZeroUsize,
+    /// Number of requests waiting for a slot when the limit is reached. The requests beyond
+    /// it are rejected right away. The default value is 0
+    queue_size: Option<usize>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// How long a request waits for a slot before being rejected. The default value is 1 second
+    queue_timeout: Option<Duration>,
+    /// Adjust the limit from the latency of the requests
+    adaptive: Option<AdaptiveConcurrencyConf>,
+}
+
+/// Adaptive concurrency limit configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct AdaptiveConcurrencyConf {
+    /// Lowest value of the limit. The default value is 1
+    min_limit: Option<usize>,
+    /// Highest value of the limit. The default value is 1000
+    max_limit: Option<usize>,
+    /// A request is slow if its latency is over this multiple of the lowest latency observed
+    /// recently. The default value is 2.0
+    latency_tolerance: Option<f64>,
+    /// Factor applied to the limit when a request is slow or fails. The default value is 0.9
+    backoff_ratio: Option<f64>,
 }
 
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
@@ -174,6 +315,9 @@ struct RateLimitConf {
     #[schemars(with = "String")]
     /// Per interval
     interval: Duration,
+    #[cfg(feature = "experimental_cache")]
+    /// Count the requests in Redis, to share the rate limit between router instances
+    redis: Option<RateLimitRedisConf>,
 }
 
 impl Merge for RateLimitConf {
@@ -183,17 +327,86 @@ impl Merge for RateLimitConf {
             Some(fallback) => Self {
                 capacity: fallback.capacity,
                 interval: fallback.interval,
+                #[cfg(feature = "experimental_cache")]
+                redis: fallback.redis.clone(),
             },
         }
     }
 }
 
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct KeyedRateLimitConf {
+    /// The request attribute identifying who is rate limited
+    key: RateLimitKey,
+    /// Number of requests allowed per key
+    capacity: NonZeroU64,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// Per interval
+    interval: Duration,
+    #[serde(default)]
+    /// Capacities for the keys matching a regex, the first matching rule applies
+    rules: Vec<KeyedRateLimitRule>,
+    #[serde(default = "default_max_keys")]
+    /// Number of keys tracked at the same time, the least recently used keys are forgotten first
+    max_keys: NonZeroUsize,
+}
+
+fn default_max_keys() -> NonZeroUsize {
+    NonZeroUsize::new(10_000).expect("10_000 is not zero; qed")
+}
+
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum RateLimitKey {
+    /// The name of the operation
+    OperationName,
+    /// The value of a request header
+    Header(String),
+    /// A string value from the context
+    Context(String),
+    /// A claim of the validated JWT, as a JSON pointer, for instance `/sub`
+    Claim(String),
+}
+
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct KeyedRateLimitRule {
+    /// The regex on the key
+    matching: String,
+    /// Number of requests allowed per key matching the regex
+    capacity: NonZeroU64,
+}
+
+#[cfg(feature = "experimental_cache")]
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Redis rate limit configuration
+struct RateLimitRedisConf {
+    /// List of URLs to the Redis cluster
+    urls: Vec<String>,
+    #[serde(default)]
+    /// What to do with requests when Redis is unavailable
+    failure_mode: FailureMode,
+}
+
+/// Redis connections for the rate limits, by list of URLs
+#[cfg(feature = "experimental_cache")]
+type RateLimitStorages = HashMap<Vec<String>, Option<RedisCacheStorage>>;
+
 // FIXME: This struct is pub(crate) because we need its configuration in the query planner service.
 // Remove this once the configuration yml changes.
 pub(crate) struct TrafficShaping {
     config: Config,
     rate_limit_router: Option<RateLimitLayer>,
+    concurrency_limit_router: Option<ConcurrencyLimitLayer>,
+    concurrency_limit_subgraphs: Mutex<HashMap<String, ConcurrencyLimitLayer>>,
     rate_limit_subgraphs: Mutex<HashMap<String, RateLimitLayer>>,
+    circuit_breakers: Mutex<HashMap<String, CircuitBreakerLayer>>,
+    keyed_rate_limit: Option<(RateLimitKey, Arc<KeyedRateLimiter>)>,
+    #[cfg(feature = "experimental_cache")]
+    rate_limit_storages: RateLimitStorages,
 }
 
 #[async_trait::async_trait]
@@ -201,6 +414,9 @@ impl Plugin for TrafficShaping {
     type Config = Config;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        #[cfg(feature = "experimental_cache")]
+        let rate_limit_storages = connect_rate_limit_storages(&init.config).await;
+
         let rate_limit_router = init
             .config
             .router
@@ -216,20 +432,194 @@ impl Plugin for TrafficShaping {
                         ),
                     })
                 } else {
-                    Ok(RateLimitLayer::new(
-                        router_rate_limit_conf.capacity,
-                        router_rate_limit_conf.interval,
+                    Ok(rate_limit_layer(
+                        router_rate_limit_conf,
+                        "router",
+                        #[cfg(feature = "experimental_cache")]
+                        &rate_limit_storages,
                     ))
                 }
             })
             .transpose()?;
 
+        let keyed_rate_limit = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.keyed_rate_limit.as_ref())
+            .map(|conf| {
+                let rules = conf
+                    .rules
+                    .iter()
+                    .map(|rule| {
+                        Regex::new(&rule.matching)
+                            .map(|matching| (matching, Rate::new(rule.capacity, conf.interval)))
+                            .map_err(|e| ConfigurationError::InvalidConfiguration {
+                                message: "bad configuration for traffic_shaping plugin",
+                                error: format!(
+                                    "invalid regex '{}' in the keyed rate limit: {}",
+                                    rule.matching, e
+                                ),
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok::<_, ConfigurationError>((
+                    conf.key.clone(),
+                    Arc::new(KeyedRateLimiter::new(
+                        Rate::new(conf.capacity, conf.interval),
+                        rules,
+                        conf.max_keys,
+                    )),
+                ))
+            })
+            .transpose()?;
+
+        let concurrency_limit_router = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.max_concurrency.as_ref())
+            .map(|conf| ConcurrencyLimitLayer::new("router".to_string(), conf));
+
         Ok(Self {
             config: init.config,
             rate_limit_router,
+            concurrency_limit_router,
+            concurrency_limit_subgraphs: Mutex::new(HashMap::new()),
             rate_limit_subgraphs: Mutex::new(HashMap::new()),
+            circuit_breakers: Mutex::new(HashMap::new()),
+            keyed_rate_limit,
+            #[cfg(feature = "experimental_cache")]
+            rate_limit_storages,
         })
     }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        match self.keyed_rate_limit.clone() {
+            Some((key, limiter)) => ServiceBuilder::new()
+                .checkpoint(move |request: supergraph::Request| {
+                    // Requests without the key are not rate limited
+                    let key_value = match rate_limit_key(&key, &request) {
+                        Some(key_value) => key_value,
+                        None => return Ok(ControlFlow::Continue(request)),
+                    };
+                    match limiter.check(&key_value) {
+                        Ok(()) => Ok(ControlFlow::Continue(request)),
+                        Err(retry_after) => {
+                            // This is a metric and will not appear in the logs
+                            tracing::info!(
+                                monotonic_counter.apollo_router_keyed_rate_limit_count = 1u64,
+                            );
+                            let response = supergraph::Response::error_builder()
+                                .error(
+                                    graphql::Error::builder()
+                                        .message("your request has been rate limited")
+                                        .extension_code("RATE_LIMITED")
+                                        .build(),
+                                )
+                                .status_code(StatusCode::TOO_MANY_REQUESTS)
+                                .header(
+                                    RETRY_AFTER,
+                                    // Retry-After is in seconds, round up to not retry too early
+                                    (retry_after.as_secs()
+                                        + u64::from(retry_after.subsec_nanos() > 0))
+                                    .max(1)
+                                    .to_string(),
+                                )
+                                .context(request.context)
+                                .build()?;
+                            Ok(ControlFlow::Break(response))
+                        }
+                    }
+                })
+                .service(service)
+                .boxed(),
+            None => service,
+        }
+    }
+}
+
+/// Extract the value of the keyed rate limit key from the request
+fn rate_limit_key(key: &RateLimitKey, request: &supergraph::Request) -> Option<String> {
+    match key {
+        RateLimitKey::Header(name) => request
+            .supergraph_request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        RateLimitKey::Context(context_key) => {
+            request.context.get::<_, String>(context_key).ok().flatten()
+        }
+        RateLimitKey::Claim(pointer) => request
+            .context
+            .get::<_, serde_json::Value>(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+            .ok()
+            .flatten()
+            .and_then(|claims| match claims.pointer(pointer)? {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(value) => Some(value.clone()),
+                value => Some(value.to_string()),
+            }),
+        RateLimitKey::OperationName => request.supergraph_request.body().operation_name.clone(),
+    }
+}
+
+#[cfg_attr(not(feature = "experimental_cache"), allow(unused_variables))]
+fn rate_limit_layer(
+    conf: &RateLimitConf,
+    name: &str,
+    #[cfg(feature = "experimental_cache")] storages: &RateLimitStorages,
+) -> RateLimitLayer {
+    let layer = RateLimitLayer::new(conf.capacity, conf.interval);
+    #[cfg(feature = "experimental_cache")]
+    if let Some(redis) = &conf.redis {
+        return layer.with_distributed(DistributedRateLimiter::new(
+            storages.get(&redis.urls).cloned().flatten(),
+            format!("apollo_router:rate_limit:{}", name),
+            redis.failure_mode,
+        ));
+    }
+    layer
+}
+
+/// Open one Redis connection per list of URLs used by the rate limits
+#[cfg(feature = "experimental_cache")]
+async fn connect_rate_limit_storages(config: &Config) -> RateLimitStorages {
+    let mut storages = HashMap::new();
+    let rate_limits = config
+        .router
+        .iter()
+        .filter_map(|r| r.global_rate_limit.as_ref())
+        .chain(
+            config
+                .all
+                .iter()
+                .chain(config.subgraphs.values())
+                .filter_map(|s| s.global_rate_limit.as_ref()),
+        );
+    for redis in rate_limits.filter_map(|r| r.redis.as_ref()) {
+        if storages.contains_key(&redis.urls) {
+            continue;
+        }
+        let storage = match RedisCacheStorage::new(
+            &RedisCache::new(redis.urls.clone()),
+            "rate_limit",
+        )
+        .await
+        {
+            Err(e) => {
+                tracing::error!(
+                    "could not open connection to Redis for rate limiting: {:?}",
+                    e
+                );
+                None
+            }
+            Ok(storage) => Some(storage),
+        };
+        storages.insert(redis.urls.clone(), storage);
+    }
+    storages
 }
 
 impl TrafficShaping {
@@ -248,8 +638,11 @@ impl TrafficShaping {
         supergraph::Request,
         Response = supergraph::Response,
         Error = BoxError,
-        Future = timeout::future::ResponseFuture<
-            Oneshot<tower::util::Either<rate::service::RateLimit<S>, S>, supergraph::Request>,
+        Future = tower::util::Either<
+            BoxFuture<'static, Result<supergraph::Response, BoxError>>,
+            timeout::future::ResponseFuture<
+                Oneshot<tower::util::Either<rate::service::RateLimit<S>, S>, supergraph::Request>,
+            >,
         >,
     > + Clone
            + Send
@@ -264,6 +657,8 @@ impl TrafficShaping {
         <S as Service<supergraph::Request>>::Future: std::marker::Send,
     {
         ServiceBuilder::new()
+            // the time spent waiting for a slot is not part of the timeout
+            .option_layer(self.concurrency_limit_router.clone())
             .layer(TimeoutLayer::new(
                 self.config
                     .router
@@ -286,13 +681,22 @@ impl TrafficShaping {
         Future = tower::util::Either<
             tower::util::Either<
                 BoxFuture<'static, Result<subgraph::Response, BoxError>>,
-                timeout::future::ResponseFuture<
-                    Oneshot<
-                        tower::util::Either<
-                            Retry<RetryPolicy, tower::util::Either<rate::service::RateLimit<S>, S>>,
-                            tower::util::Either<rate::service::RateLimit<S>, S>,
+                tower::util::Either<
+                    BoxFuture<'static, Result<subgraph::Response, BoxError>>,
+                    tower::util::Either<
+                        BoxFuture<'static, Result<subgraph::Response, BoxError>>,
+                        timeout::future::ResponseFuture<
+                            Oneshot<
+                                tower::util::Either<
+                                    Retry<
+                                        RetryPolicy,
+                                        tower::util::Either<rate::service::RateLimit<S>, S>,
+                                    >,
+                                    tower::util::Either<rate::service::RateLimit<S>, S>,
+                                >,
+                                subgraph::Request,
+                            >,
                         >,
-                        subgraph::Request,
                     >,
                 >,
             >,
@@ -322,18 +726,40 @@ impl TrafficShaping {
                     .unwrap()
                     .entry(name.to_string())
                     .or_insert_with(|| {
-                        RateLimitLayer::new(rate_limit_conf.capacity, rate_limit_conf.interval)
+                        rate_limit_layer(
+                            rate_limit_conf,
+                            &format!("subgraph:{}", name),
+                            #[cfg(feature = "experimental_cache")]
+                            &self.rate_limit_storages,
+                        )
+                    })
+                    .clone()
+            });
+
+            // like the rate limit, the circuit state is shared by all the services created for
+            // the subgraph
+            let circuit_breaker = config.circuit_breaker.as_ref().map(|circuit_breaker_conf| {
+                self.circuit_breakers
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| CircuitBreakerLayer::new(name, circuit_breaker_conf))
+                    .clone()
+            });
+
+            let concurrency_limit = config.max_concurrency.as_ref().map(|concurrency_conf| {
+                self.concurrency_limit_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| {
+                        ConcurrencyLimitLayer::new(format!("subgraph:{}", name), concurrency_conf)
                     })
                     .clone()
             });
 
             let retry = config.experimental_retry.as_ref().map(|config| {
-                let retry_policy = RetryPolicy::new(
-                    config.ttl,
-                    config.min_per_sec,
-                    config.retry_percent,
-                    config.retry_mutations,
-                );
+                let retry_policy = RetryPolicy::new(name, config);
                 tower::retry::RetryLayer::new(retry_policy)
             });
 
@@ -341,6 +767,8 @@ impl TrafficShaping {
                 .option_layer(config.deduplicate_query.unwrap_or_default().then(
                   QueryDeduplicationLayer::default
                 ))
+                    .option_layer(concurrency_limit)
+                    .option_layer(circuit_breaker)
                     .layer(TimeoutLayer::new(
                         config
                         .timeout
@@ -366,6 +794,10 @@ impl TrafficShaping {
     pub(crate) fn get_apq(&self, name: &str) -> Option<bool> {
         self.config.subgraphs.get(name)?.apq
     }
+
+    pub(crate) fn get_http_client(&self, name: &str) -> Option<HttpClientConfig> {
+        Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))?.http_client
+    }
 }
 
 impl TrafficShaping {
@@ -513,6 +945,7 @@ mod test {
             &Configuration::default(),
         )
         .await
+        .unwrap()
         .make()
         .boxed()
     }
@@ -614,6 +1047,39 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_merge_http_client_config() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+          http_client:
+            pool_idle_timeout: 30s
+            http2_only: true
+        subgraphs:
+          products:
+            http_client:
+              connect_timeout: 1s
+              http2_only: false
+        "#,
+        )
+        .unwrap();
+
+        // the subgraph options override the ones for all subgraphs, one by one
+        let expected = serde_yaml::from_str::<HttpClientConfig>(
+            r#"
+        pool_idle_timeout: 30s
+        connect_timeout: 1s
+        http2_only: false
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            TrafficShaping::merge_config(config.all.as_ref(), config.subgraphs.get("products"))
+                .and_then(|shaping| shaping.http_client),
+            Some(expected)
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn it_rate_limit_subgraph_requests() {
         let config = serde_yaml::from_str::<serde_json::Value>(
@@ -671,6 +1137,456 @@ mod test {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn it_retries_subgraph_responses_with_retryable_status_codes() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                experimental_retry:
+                    status_codes: [503]
+                    max_attempts: 3
+                    min_backoff: 1ms
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        // the subgraph is unavailable for the first `failures` requests
+        let service = |failures: usize| {
+            let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let service_calls = calls.clone();
+            let service = tower::service_fn(move |request: SubgraphRequest| {
+                let call = service_calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let status_code = if call < failures {
+                    StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    StatusCode::OK
+                };
+                futures::future::ready(Ok::<_, BoxError>(
+                    subgraph::Response::fake_builder()
+                        .status_code(status_code)
+                        .context(request.context)
+                        .build(),
+                ))
+            });
+            (service, calls)
+        };
+
+        let (test_service, calls) = service(1);
+        let response = traffic_shaping
+            .subgraph_service_internal("test", test_service)
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let (test_service, calls) = service(usize::MAX);
+        let response = traffic_shaping
+            .subgraph_service_internal("test", test_service)
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_opens_the_circuit_of_failing_subgraphs() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                circuit_breaker:
+                    min_requests: 2
+                    open_duration: 100ms
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        let healthy = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let test_service = {
+            let healthy = healthy.clone();
+            let calls = calls.clone();
+            tower::service_fn(move |request: SubgraphRequest| {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let status_code = if healthy.load(std::sync::atomic::Ordering::SeqCst) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                };
+                futures::future::ready(Ok::<_, BoxError>(
+                    subgraph::Response::fake_builder()
+                        .status_code(status_code)
+                        .context(request.context)
+                        .build(),
+                ))
+            })
+        };
+        let call = || {
+            traffic_shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build())
+        };
+
+        for _ in 0..2 {
+            let response = call().await.unwrap();
+            assert_eq!(
+                response.response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+        }
+
+        // the circuit is open, the subgraph is not called
+        let response = call().await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response.response.body().errors[0].extensions.get("code"),
+            Some(&json!("SUBGRAPH_CIRCUIT_OPEN"))
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // once the open duration elapsed, a successful probe closes the circuit
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        healthy.store(true, std::sync::atomic::Ordering::SeqCst);
+        for _ in 0..2 {
+            let response = call().await.unwrap();
+            assert_eq!(response.response.status(), StatusCode::OK);
+        }
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn it_sheds_subgraph_requests_over_the_concurrency_limit() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                max_concurrency:
+                    limit: 1
+                    queue_size: 1
+                    queue_timeout: 50ms
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        let test_service = tower::service_fn(|request: SubgraphRequest| async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, BoxError>(
+                subgraph::Response::fake_builder()
+                    .context(request.context)
+                    .build(),
+            )
+        });
+        let call = || {
+            traffic_shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build())
+        };
+
+        // the first request is processed, the second one waits in the queue longer than the
+        // queue timeout, and the third one finds the queue full
+        let (first, second, third) = tokio::join!(call(), call(), call());
+        assert_eq!(first.unwrap().response.status(), StatusCode::OK);
+        for (response, reason) in [(second, "queue_timeout"), (third, "queue_full")] {
+            let response = response.unwrap();
+            assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let error = &response.response.body().errors[0];
+            assert_eq!(error.extensions.get("code"), Some(&json!("OVERLOADED")));
+            assert_eq!(error.extensions.get("reason"), Some(&json!(reason)));
+        }
+
+        // the slot is available again
+        let response = call().await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_holds_the_router_slot_until_the_response_stream_completes() {
+        use futures::StreamExt;
+
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            max_concurrency:
+                limit: 1
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        // the responses are streamed like deferred responses: the last part is sent once the
+        // test allows it
+        let last_part = Arc::new(tokio::sync::Notify::new());
+        let test_service = {
+            let last_part = last_part.clone();
+            tower::service_fn(move |request: SupergraphRequest| {
+                let last_part = last_part.clone();
+                let stream = futures::stream::once(async {
+                    graphql::Response::builder()
+                        .data(json!({"test": 1}))
+                        .build()
+                })
+                .chain(futures::stream::once(async move {
+                    last_part.notified().await;
+                    graphql::Response::builder().has_next(false).build()
+                }));
+                futures::future::ready(Ok::<_, BoxError>(SupergraphResponse::new_from_response(
+                    http::Response::new(stream.boxed()),
+                    request.context,
+                )))
+            })
+        };
+        let service = traffic_shaping.supergraph_service_internal(test_service);
+        let call = || {
+            service
+                .clone()
+                .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+        };
+
+        let mut first = call().await.unwrap();
+        assert_eq!(first.response.status(), StatusCode::OK);
+        first.next_response().await.unwrap();
+
+        // the first response is not complete, its request still holds the slot
+        let mut second = call().await.unwrap();
+        assert_eq!(second.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let error = &second.next_response().await.unwrap().errors[0];
+        assert_eq!(error.extensions.get("code"), Some(&json!("OVERLOADED")));
+        assert_eq!(error.extensions.get("reason"), Some(&json!("queue_full")));
+
+        last_part.notify_one();
+        first.next_response().await.unwrap();
+        assert!(first.next_response().await.is_none());
+        drop(first);
+
+        let third = call().await.unwrap();
+        assert_eq!(third.response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_adapts_the_concurrency_limit_to_the_latency() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                max_concurrency:
+                    limit: 4
+                    adaptive:
+                        min_limit: 1
+                        latency_tolerance: 5.0
+                        backoff_ratio: 0.5
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        let latency = Arc::new(std::sync::atomic::AtomicU64::new(10));
+        let test_service = {
+            let latency = latency.clone();
+            tower::service_fn(move |request: SubgraphRequest| {
+                let latency = latency.load(std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    tokio::time::sleep(Duration::from_millis(latency)).await;
+                    Ok::<_, BoxError>(
+                        subgraph::Response::fake_builder()
+                            .context(request.context)
+                            .build(),
+                    )
+                }
+            })
+        };
+        let call = || {
+            traffic_shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build())
+        };
+        let concurrent_calls = || async {
+            let (first, second) = tokio::join!(call(), call());
+            (
+                first.unwrap().response.status(),
+                second.unwrap().response.status(),
+            )
+        };
+
+        // sets the lowest latency
+        call().await.unwrap();
+        assert_eq!(concurrent_calls().await, (StatusCode::OK, StatusCode::OK));
+
+        // the slow requests divide the limit by two each time, down to the minimum
+        latency.store(200, std::sync::atomic::Ordering::SeqCst);
+        call().await.unwrap();
+        call().await.unwrap();
+        latency.store(10, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(
+            concurrent_calls().await,
+            (StatusCode::OK, StatusCode::SERVICE_UNAVAILABLE)
+        );
+
+        // a fast request using the whole limit increases it by one
+        call().await.unwrap();
+        assert_eq!(concurrent_calls().await, (StatusCode::OK, StatusCode::OK));
+    }
+
+    #[cfg(feature = "experimental_cache")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_applies_the_redis_failure_mode() {
+        let config = |failure_mode: &str| {
+            serde_json::json!({
+                "subgraphs": {
+                    "test": {
+                        "global_rate_limit": {
+                            "capacity": 1,
+                            "interval": "100ms",
+                            // nothing listens on this port
+                            "redis": { "urls": ["redis://127.0.0.1:1"], "failure_mode": failure_mode }
+                        }
+                    }
+                }
+            })
+        };
+        let test_service = MockSubgraph::new(HashMap::new());
+
+        let plugin = get_traffic_shaping_plugin(&config("open")).await;
+        let traffic_shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+        for _ in 0..3 {
+            traffic_shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build())
+                .await
+                .expect("requests are let through when failing open");
+        }
+
+        let plugin = get_traffic_shaping_plugin(&config("closed")).await;
+        let error = plugin
+            .as_any()
+            .downcast_ref::<TrafficShaping>()
+            .unwrap()
+            .subgraph_service_internal("test", test_service.clone())
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .expect_err("requests are rejected when failing closed");
+        assert!(error.is::<RateLimited>());
+    }
+
+    // Needs a Redis instance listening on localhost:6379
+    #[cfg(feature = "experimental_cache")]
+    #[ignore]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_shares_the_rate_limit_through_redis() {
+        // A unique subgraph name so that counters from previous runs do not interfere
+        let subgraph_name = uuid::Uuid::new_v4().to_string();
+        let config = serde_json::json!({
+            "subgraphs": {
+                subgraph_name.clone(): {
+                    "global_rate_limit": {
+                        "capacity": 1,
+                        "interval": "10s",
+                        "redis": { "urls": ["redis://127.0.0.1:6379"], "failure_mode": "closed" }
+                    }
+                }
+            }
+        });
+        let test_service = MockSubgraph::new(HashMap::new());
+
+        // Two plugin instances stand for two router instances
+        let first_router = get_traffic_shaping_plugin(&config).await;
+        let second_router = get_traffic_shaping_plugin(&config).await;
+
+        first_router
+            .as_any()
+            .downcast_ref::<TrafficShaping>()
+            .unwrap()
+            .subgraph_service_internal(&subgraph_name, test_service.clone())
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .unwrap();
+        let error = second_router
+            .as_any()
+            .downcast_ref::<TrafficShaping>()
+            .unwrap()
+            .subgraph_service_internal(&subgraph_name, test_service.clone())
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .expect_err("the rate limit is shared between instances");
+        assert!(error.is::<RateLimited>());
+    }
+
+    #[tokio::test]
+    async fn it_rate_limit_router_requests_per_key() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            keyed_rate_limit:
+                key:
+                    header: apollographql-client-name
+                capacity: 2
+                interval: 60s
+                rules:
+                    - matching: ^mobile-
+                      capacity: 1
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(4).returning(move |_| {
+            Ok(SupergraphResponse::fake_builder()
+                .data(json!({ "test": 1234_u32 }))
+                .build()
+                .unwrap())
+        });
+        let mut service = plugin.supergraph_service(mock_service.boxed());
+
+        let mut call = |client_name: &'static str| {
+            let request = SupergraphRequest::fake_builder()
+                .header("apollographql-client-name", client_name)
+                .build()
+                .unwrap();
+            let future = service.call(request);
+            async move { future.await.unwrap() }
+        };
+
+        call("web").await;
+        call("web").await;
+        let mut response = call("web").await;
+        assert_eq!(response.response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.response.headers()[RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((1..=60).contains(&retry_after));
+        let graphql_response = response.next_response().await.unwrap();
+        assert_eq!(
+            graphql_response.errors[0].extensions.get("code").unwrap(),
+            "RATE_LIMITED"
+        );
+
+        call("mobile-ios").await;
+        let response = call("mobile-ios").await;
+        assert_eq!(response.response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // another key has its own budget
+        let response = call("other").await;
+        assert_eq!(response.response.status(), StatusCode::OK);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn it_rate_limit_router_requests() {
         let config = serde_yaml::from_str::<serde_json::Value>(
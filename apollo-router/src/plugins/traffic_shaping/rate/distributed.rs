@@ -0,0 +1,123 @@
+//! Rate limit shared between router instances, backed by Redis
+
+use std::fmt;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use super::Rate;
+use crate::cache::redis::RedisCacheStorage;
+use crate::cache::redis::RedisKey;
+
+/// Behaviour of the rate limit when Redis is unavailable
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FailureMode {
+    /// Let the requests through
+    Open,
+    /// Reject the requests as rate limited
+    Closed,
+}
+
+impl Default for FailureMode {
+    fn default() -> Self {
+        FailureMode::Open
+    }
+}
+
+/// Counts the requests in Redis, so that the rate is enforced across all the router instances
+/// using the same Redis
+#[derive(Clone)]
+pub(crate) struct DistributedRateLimiter {
+    storage: Option<RedisCacheStorage>,
+    key_prefix: String,
+    failure_mode: FailureMode,
+}
+
+impl fmt::Debug for DistributedRateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DistributedRateLimiter")
+            .field("connected", &self.storage.is_some())
+            .field("key_prefix", &self.key_prefix)
+            .field("failure_mode", &self.failure_mode)
+            .finish()
+    }
+}
+
+impl DistributedRateLimiter {
+    /// Create a new limiter. Without a Redis connection, every request is handled according to
+    /// the failure mode.
+    pub(crate) fn new(
+        storage: Option<RedisCacheStorage>,
+        key_prefix: String,
+        failure_mode: FailureMode,
+    ) -> Self {
+        Self {
+            storage,
+            key_prefix,
+            failure_mode,
+        }
+    }
+
+    /// Count a new request, returns `true` if it is allowed by the rate
+    pub(crate) async fn acquire(self, rate: Rate) -> bool {
+        match self.try_acquire(rate).await {
+            Ok(allowed) => allowed,
+            Err(err) => {
+                tracing::error!(
+                    "cannot check the rate limit '{}' in Redis: {}",
+                    self.key_prefix,
+                    err
+                );
+                // This is a metric and will not appear in the logs
+                tracing::info!(
+                    monotonic_counter.apollo_router_rate_limit_redis_failure_count = 1u64,
+                    rate_limit = %self.key_prefix,
+                );
+                self.allowed_on_failure()
+            }
+        }
+    }
+
+    pub(crate) fn allowed_on_failure(&self) -> bool {
+        self.failure_mode == FailureMode::Open
+    }
+
+    /// Same sliding window approximation as the local rate limit: the count of the previous
+    /// window is weighted by how much it overlaps with the last interval.
+    async fn try_acquire(&self, rate: Rate) -> Result<bool, BoxError> {
+        let storage = self
+            .storage
+            .as_ref()
+            .ok_or("no connection to Redis was established")?;
+
+        let per = rate.per().as_millis() as u64;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time must be after EPOCH")
+            .as_millis() as u64;
+        let window = now / per;
+        let elapsed = now % per;
+
+        // The counter must outlive the next window, where it is used as the previous count
+        let current = storage
+            .incr(
+                RedisKey(format!("{}:{}", self.key_prefix, window)),
+                rate.per() * 2,
+            )
+            .await?;
+        let previous = storage
+            .get_counter(RedisKey(format!(
+                "{}:{}",
+                self.key_prefix,
+                window.saturating_sub(1)
+            )))
+            .await?;
+
+        let estimated = previous.saturating_mul(per - elapsed) / per + current;
+        Ok(estimated <= rate.num())
+    }
+}
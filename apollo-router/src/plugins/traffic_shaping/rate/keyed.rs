@@ -0,0 +1,151 @@
+//! Rate limit counted separately for each key
+
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use lru::LruCache;
+use regex::Regex;
+
+use super::Rate;
+
+/// Request counts of a key over the current and previous windows
+struct KeyWindow {
+    start: Instant,
+    previous_nb_requests: u64,
+    current_nb_requests: u64,
+}
+
+/// Enforces a rate for each key, with a different rate for the keys matching a rule
+pub(crate) struct KeyedRateLimiter {
+    rate: Rate,
+    rules: Vec<(Regex, Rate)>,
+    windows: Mutex<LruCache<String, KeyWindow>>,
+}
+
+impl KeyedRateLimiter {
+    /// Create a new keyed rate limiter, tracking at most `max_keys` keys. When a key is evicted,
+    /// its next request starts from an empty window.
+    pub(crate) fn new(rate: Rate, rules: Vec<(Regex, Rate)>, max_keys: NonZeroUsize) -> Self {
+        Self {
+            rate,
+            rules,
+            windows: Mutex::new(LruCache::new(max_keys)),
+        }
+    }
+
+    /// Count a request for `key`. If the key is over its rate, the request is not counted and
+    /// the time until the current window ends is returned.
+    pub(crate) fn check(&self, key: &str) -> Result<(), Duration> {
+        let rate = self
+            .rules
+            .iter()
+            .find(|(matching, _)| matching.is_match(key))
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.rate);
+        let per = rate.per();
+        let now = Instant::now();
+
+        let mut windows = self.windows.lock().expect("lock poisoned");
+        if !windows.contains(key) {
+            windows.put(
+                key.to_string(),
+                KeyWindow {
+                    start: now,
+                    previous_nb_requests: 0,
+                    current_nb_requests: 0,
+                },
+            );
+        }
+        let window = windows
+            .get_mut(key)
+            .expect("the key was inserted if it was missing; qed");
+
+        let elapsed = now.duration_since(window.start);
+        if elapsed >= per * 2 {
+            window.start = now;
+            window.previous_nb_requests = 0;
+            window.current_nb_requests = 0;
+        } else if elapsed >= per {
+            window.start += per;
+            window.previous_nb_requests = window.current_nb_requests;
+            window.current_nb_requests = 0;
+        }
+
+        // Sliding window approximation: the previous window is weighted by how much it overlaps
+        // with the last interval
+        let remaining = per.saturating_sub(now.duration_since(window.start));
+        let estimated = window.previous_nb_requests as f64 * remaining.as_secs_f64()
+            / per.as_secs_f64()
+            + window.current_nb_requests as f64;
+        if estimated + 1.0 > rate.num() as f64 {
+            return Err(remaining);
+        }
+
+        window.current_nb_requests += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU64;
+
+    use super::*;
+
+    fn rate(num: u64, per: Duration) -> Rate {
+        Rate::new(NonZeroU64::new(num).unwrap(), per)
+    }
+
+    #[test]
+    fn it_limits_each_key_separately() {
+        let limiter = KeyedRateLimiter::new(
+            rate(2, Duration::from_secs(60)),
+            vec![(
+                Regex::new("^mobile-").unwrap(),
+                rate(1, Duration::from_secs(60)),
+            )],
+            NonZeroUsize::new(10).unwrap(),
+        );
+
+        assert!(limiter.check("web").is_ok());
+        assert!(limiter.check("web").is_ok());
+        let retry_after = limiter.check("web").unwrap_err();
+        assert!(retry_after <= Duration::from_secs(60));
+
+        assert!(limiter.check("other").is_ok());
+
+        assert!(limiter.check("mobile-ios").is_ok());
+        assert!(limiter.check("mobile-ios").is_err());
+    }
+
+    #[test]
+    fn it_forgets_the_least_recently_used_keys() {
+        let limiter = KeyedRateLimiter::new(
+            rate(1, Duration::from_secs(60)),
+            Vec::new(),
+            NonZeroUsize::new(1).unwrap(),
+        );
+
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("b").is_ok());
+        // "a" was evicted by "b"
+        assert!(limiter.check("a").is_ok());
+    }
+
+    #[test]
+    fn it_allows_requests_in_a_new_window() {
+        let limiter = KeyedRateLimiter::new(
+            rate(1, Duration::from_millis(50)),
+            Vec::new(),
+            NonZeroUsize::new(10).unwrap(),
+        );
+
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        std::thread::sleep(Duration::from_millis(110));
+        assert!(limiter.check("a").is_ok());
+    }
+}
@@ -8,8 +8,13 @@ use std::time::UNIX_EPOCH;
 
 use tower::Layer;
 
+#[cfg(feature = "experimental_cache")]
+use super::service::DistributedState;
+#[cfg(feature = "experimental_cache")]
+use super::DistributedRateLimiter;
 use super::Rate;
 use super::RateLimit;
+
 /// Enforces a rate limit on the number of requests the underlying
 /// service can handle over a period of time.
 #[derive(Debug, Clone)]
@@ -18,6 +23,8 @@ pub(crate) struct RateLimitLayer {
     window_start: Arc<AtomicU64>,
     previous_nb_requests: Arc<AtomicUsize>,
     current_nb_requests: Arc<AtomicUsize>,
+    #[cfg(feature = "experimental_cache")]
+    distributed: Option<DistributedRateLimiter>,
 }
 
 impl RateLimitLayer {
@@ -34,8 +41,17 @@ impl RateLimitLayer {
             )),
             previous_nb_requests: Arc::default(),
             current_nb_requests: Arc::new(AtomicUsize::new(1)),
+            #[cfg(feature = "experimental_cache")]
+            distributed: None,
         }
     }
+
+    /// Count the requests in Redis instead of in memory
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn with_distributed(mut self, distributed: DistributedRateLimiter) -> Self {
+        self.distributed = Some(distributed);
+        self
+    }
 }
 
 impl<S> Layer<S> for RateLimitLayer {
@@ -48,6 +64,10 @@ impl<S> Layer<S> for RateLimitLayer {
             window_start: self.window_start.clone(),
             previous_nb_requests: self.previous_nb_requests.clone(),
             current_nb_requests: self.current_nb_requests.clone(),
+            #[cfg(feature = "experimental_cache")]
+            distributed: self.distributed.clone(),
+            #[cfg(feature = "experimental_cache")]
+            distributed_state: DistributedState::Idle,
         }
     }
 }
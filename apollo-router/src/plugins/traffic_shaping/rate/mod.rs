@@ -1,13 +1,21 @@
 //! Limit the rate at which requests are processed.
 
+#[cfg(feature = "experimental_cache")]
+mod distributed;
 mod error;
 pub(crate) mod future;
+mod keyed;
 mod layer;
 #[allow(clippy::module_inception)]
 mod rate;
 pub(crate) mod service;
 
+#[cfg(feature = "experimental_cache")]
+pub(crate) use self::distributed::DistributedRateLimiter;
+#[cfg(feature = "experimental_cache")]
+pub(crate) use self::distributed::FailureMode;
 pub(crate) use self::error::RateLimited;
+pub(crate) use self::keyed::KeyedRateLimiter;
 pub(crate) use self::layer::RateLimitLayer;
 pub(crate) use self::rate::Rate;
 pub(crate) use self::service::RateLimit;
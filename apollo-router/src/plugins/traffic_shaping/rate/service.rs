@@ -1,3 +1,7 @@
+#[cfg(feature = "experimental_cache")]
+use std::future::Future;
+#[cfg(feature = "experimental_cache")]
+use std::pin::Pin;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
@@ -8,9 +12,13 @@ use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use futures::ready;
+#[cfg(feature = "experimental_cache")]
+use tokio::task::JoinHandle;
 use tower::Service;
 
 use super::future::ResponseFuture;
+#[cfg(feature = "experimental_cache")]
+use super::DistributedRateLimiter;
 use super::Rate;
 use crate::plugins::traffic_shaping::rate::error::RateLimited;
 
@@ -24,6 +32,29 @@ pub(crate) struct RateLimit<T> {
     pub(crate) window_start: Arc<AtomicU64>,
     pub(crate) previous_nb_requests: Arc<AtomicUsize>,
     pub(crate) current_nb_requests: Arc<AtomicUsize>,
+    /// When set, requests are counted in Redis instead of the local counters
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) distributed: Option<DistributedRateLimiter>,
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) distributed_state: DistributedState,
+}
+
+/// Progress of the Redis check for the next call
+#[cfg(feature = "experimental_cache")]
+#[derive(Debug)]
+pub(crate) enum DistributedState {
+    Idle,
+    /// The check runs in its own task so the service stays `Sync`
+    Checking(JoinHandle<bool>),
+    Allowed,
+}
+
+#[cfg(feature = "experimental_cache")]
+impl Clone for DistributedState {
+    fn clone(&self) -> Self {
+        // A clone must acquire its own permit before being called
+        DistributedState::Idle
+    }
 }
 
 impl<S, Request> Service<Request> for RateLimit<S>
@@ -36,6 +67,33 @@ where
     type Future = ResponseFuture<S::Future>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        #[cfg(feature = "experimental_cache")]
+        if let Some(distributed) = &self.distributed {
+            loop {
+                match &mut self.distributed_state {
+                    DistributedState::Idle => {
+                        self.distributed_state = DistributedState::Checking(tokio::spawn(
+                            distributed.clone().acquire(self.rate),
+                        ));
+                    }
+                    DistributedState::Checking(handle) => {
+                        // If the task failed, Redis could not give an answer
+                        let allowed = ready!(Pin::new(handle).poll(cx))
+                            .unwrap_or_else(|_| distributed.allowed_on_failure());
+                        if !allowed {
+                            self.distributed_state = DistributedState::Idle;
+                            tracing::trace!("distributed rate limit exceeded");
+                            return Poll::Ready(Err(RateLimited::new().into()));
+                        }
+                        self.distributed_state = DistributedState::Allowed;
+                    }
+                    DistributedState::Allowed => {
+                        return Poll::Ready(ready!(self.inner.poll_ready(cx)).map_err(Into::into));
+                    }
+                }
+            }
+        }
+
         let time_unit = self.rate.per().as_millis() as u64;
 
         let updated =
@@ -78,6 +136,10 @@ where
     }
 
     fn call(&mut self, request: Request) -> Self::Future {
+        #[cfg(feature = "experimental_cache")]
+        {
+            self.distributed_state = DistributedState::Idle;
+        }
         ResponseFuture::new(self.inner.call(request))
     }
 }
@@ -1,61 +1,144 @@
-use std::future;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rand::Rng;
 use tower::retry::budget::Budget;
 use tower::retry::Policy;
 
+use super::RetryConfig;
 use crate::query_planner::OperationKind;
 use crate::services::subgraph;
 
+const DEFAULT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
 #[derive(Clone, Default)]
 pub(crate) struct RetryPolicy {
     budget: Arc<Budget>,
     retry_mutations: bool,
+    subgraph_name: Arc<String>,
+    status_codes: Arc<HashSet<u16>>,
+    error_codes: Arc<HashSet<String>>,
+    max_attempts: Option<u32>,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    /// Number of requests sent so far, including the first one
+    attempts: u32,
 }
 
 impl RetryPolicy {
-    pub(crate) fn new(
-        duration: Option<Duration>,
-        min_per_sec: Option<u32>,
-        retry_percent: Option<f32>,
-        retry_mutations: Option<bool>,
-    ) -> Self {
+    pub(crate) fn new(subgraph_name: &str, config: &RetryConfig) -> Self {
         Self {
             budget: Arc::new(Budget::new(
-                duration.unwrap_or_else(|| Duration::from_secs(10)),
-                min_per_sec.unwrap_or(10),
-                retry_percent.unwrap_or(0.2),
+                config.ttl.unwrap_or_else(|| Duration::from_secs(10)),
+                config.min_per_sec.unwrap_or(10),
+                config.retry_percent.unwrap_or(0.2),
             )),
-            retry_mutations: retry_mutations.unwrap_or(false),
+            retry_mutations: config.retry_mutations.unwrap_or(false),
+            subgraph_name: Arc::new(subgraph_name.to_string()),
+            status_codes: Arc::new(config.status_codes.iter().flatten().copied().collect()),
+            error_codes: Arc::new(config.error_codes.iter().flatten().cloned().collect()),
+            max_attempts: config.max_attempts,
+            min_backoff: config.min_backoff.unwrap_or(DEFAULT_MIN_BACKOFF),
+            max_backoff: config.max_backoff.unwrap_or(DEFAULT_MAX_BACKOFF),
+            attempts: 1,
+        }
+    }
+
+    /// Why the response should be retried, if it should
+    fn retry_reason(&self, response: &subgraph::Response) -> Option<&'static str> {
+        if self
+            .status_codes
+            .contains(&response.response.status().as_u16())
+        {
+            return Some("status_code");
         }
+
+        let has_retryable_error = response.response.body().errors.iter().any(|error| {
+            error
+                .extensions
+                .get("code")
+                .and_then(|code| code.as_str())
+                .map(|code| self.error_codes.contains(code))
+                .unwrap_or_default()
+        });
+        has_retryable_error.then_some("error_code")
+    }
+
+    /// Exponential backoff before the next attempt, capped at `max_backoff`. The delay is
+    /// picked randomly between half of it and all of it, so that the retries of concurrent
+    /// requests are spread over time.
+    fn backoff(&self) -> Duration {
+        let exponent = self.attempts.saturating_sub(1).min(31);
+        let delay = self
+            .min_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        rand::thread_rng().gen_range(delay / 2..=delay)
     }
 }
 
-impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
-    type Future = future::Ready<Self>;
+impl<E> Policy<subgraph::Request, subgraph::Response, E> for RetryPolicy {
+    type Future = BoxFuture<'static, Self>;
 
-    fn retry(&self, req: &subgraph::Request, result: Result<&Res, &E>) -> Option<Self::Future> {
-        match result {
-            Ok(_) => {
-                // Treat all `Response`s as success,
-                // so deposit budget and don't retry...
-                self.budget.deposit();
-                None
-            }
-            Err(_e) => {
-                if req.operation_kind == OperationKind::Mutation && !self.retry_mutations {
+    fn retry(
+        &self,
+        req: &subgraph::Request,
+        result: Result<&subgraph::Response, &E>,
+    ) -> Option<Self::Future> {
+        let reason = match result {
+            Ok(response) => match self.retry_reason(response) {
+                Some(reason) => reason,
+                None => {
+                    // the response is a success, or a failure that will not get better
+                    // with a retry, so deposit budget and don't retry...
+                    self.budget.deposit();
                     return None;
                 }
+            },
+            Err(_) => "error",
+        };
 
-                let withdrew = self.budget.withdraw();
-                if withdrew.is_err() {
-                    return None;
-                }
+        if req.operation_kind == OperationKind::Mutation && !self.retry_mutations {
+            return None;
+        }
 
-                Some(future::ready(self.clone()))
+        if let Some(max_attempts) = self.max_attempts {
+            if self.attempts >= max_attempts {
+                return None;
             }
         }
+
+        if self.budget.withdraw().is_err() {
+            return None;
+        }
+
+        tracing::info!(
+            subgraph = %self.subgraph_name,
+            attempt = self.attempts + 1,
+            reason,
+            "retrying subgraph request"
+        );
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            monotonic_counter.apollo_router_subgraph_retry_count = 1u64,
+            subgraph = %self.subgraph_name,
+            reason,
+        );
+
+        let backoff = self.backoff();
+        let mut policy = self.clone();
+        policy.attempts += 1;
+        Some(
+            async move {
+                tokio::time::sleep(backoff).await;
+                policy
+            }
+            .boxed(),
+        )
     }
 
     fn clone_request(&self, req: &subgraph::Request) -> Option<subgraph::Request> {
@@ -15,6 +15,7 @@ use serde_json_bytes::json;
 use tower::Service;
 use tracing::Instrument;
 
+use super::subscription_planner::SubscriptionPlanner;
 use super::PlanNode;
 use super::QueryKey;
 use super::QueryPlanOptions;
@@ -25,9 +26,11 @@ use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::services::QueryPlannerContent;
 use crate::services::QueryPlannerRequest;
 use crate::services::QueryPlannerResponse;
+use crate::spec::operation_limits::OperationMeasures;
 use crate::spec::query::TYPENAME;
 use crate::spec::Query;
 use crate::spec::Schema;
+use crate::spec::SpecError;
 use crate::Configuration;
 
 pub(crate) static USAGE_REPORTING: &str = "apollo_telemetry::usage_reporting";
@@ -38,6 +41,7 @@ pub(crate) static USAGE_REPORTING: &str = "apollo_telemetry::usage_reporting";
 /// No caching is performed. To cache, wrap in a [`CachingQueryPlanner`].
 pub(crate) struct BridgeQueryPlanner {
     planner: Arc<Planner<QueryPlanResult>>,
+    subscription_planner: Arc<SubscriptionPlanner>,
     schema: Arc<Schema>,
     introspection: Option<Arc<Introspection>>,
     configuration: Arc<Configuration>,
@@ -65,6 +69,7 @@ impl BridgeQueryPlanner {
                 )
                 .await?,
             ),
+            subscription_planner: Arc::new(SubscriptionPlanner::new(schema.as_string())),
             schema,
             introspection,
             configuration,
@@ -87,7 +92,11 @@ impl BridgeQueryPlanner {
         }
     }
 
-    async fn introspection(&self, query: String) -> Result<QueryPlannerContent, QueryPlannerError> {
+    async fn introspection(
+        &self,
+        query: String,
+        measures: Option<OperationMeasures>,
+    ) -> Result<QueryPlannerContent, QueryPlannerError> {
         match self.introspection.as_ref() {
             Some(introspection) => {
                 let response = introspection
@@ -97,6 +106,7 @@ impl BridgeQueryPlanner {
 
                 Ok(QueryPlannerContent::Introspection {
                     response: Box::new(response),
+                    measures,
                 })
             }
             None => Ok(QueryPlannerContent::IntrospectionDisabled),
@@ -108,6 +118,7 @@ impl BridgeQueryPlanner {
         query: String,
         operation: Option<String>,
         mut selections: Query,
+        measures: Option<OperationMeasures>,
     ) -> Result<QueryPlannerContent, QueryPlannerError> {
         let planner_result = self
             .planner
@@ -137,6 +148,7 @@ impl BridgeQueryPlanner {
                         options: QueryPlanOptions {
                             enable_deduplicate_variables: self.deduplicate_variables,
                         },
+                        measures,
                     }),
                 })
             }
@@ -154,6 +166,48 @@ impl BridgeQueryPlanner {
             }
         }
     }
+
+    /// Plans a subscription on the subgraph resolving all of its fields, as the nodejs query
+    /// planner does not plan subscriptions
+    fn plan_subscription(
+        &self,
+        query: String,
+        operation: Option<String>,
+        selections: Query,
+        measures: Option<OperationMeasures>,
+    ) -> Result<QueryPlannerContent, QueryPlannerError> {
+        let primary = self
+            .subscription_planner
+            .plan(&query, operation.as_deref())?;
+        let stats_report_key = format!(
+            "# {}\n{}",
+            operation.as_deref().unwrap_or("-"),
+            primary
+                .operation
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        Ok(QueryPlannerContent::Plan {
+            plan: Arc::new(super::QueryPlan {
+                usage_reporting: UsageReporting {
+                    stats_report_key,
+                    referenced_fields_by_type: HashMap::new(),
+                },
+                root: PlanNode::Subscription {
+                    primary,
+                    rest: None,
+                },
+                formatted_query_plan: None,
+                query: Arc::new(selections),
+                options: QueryPlanOptions {
+                    enable_deduplicate_variables: self.deduplicate_variables,
+                },
+                measures,
+            }),
+        })
+    }
 }
 
 impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
@@ -223,11 +277,18 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
 impl BridgeQueryPlanner {
     async fn get(&self, key: QueryKey) -> Result<QueryPlannerContent, QueryPlannerError> {
         let selections = self.parse_selections(key.0.clone()).await?;
+        self.check_subscription_support(&selections, key.1.as_deref())?;
+        // the operations exceeding the limits are rejected before planning them. The measures
+        // are cached with the content, to check the limits again when it is used from the cache
+        let measures = selections.measure(key.1.as_deref());
+        if let Some(measures) = &measures {
+            measures.enforce(&self.configuration.operation_limits, key.1.as_deref())?;
+        }
 
         if selections.contains_introspection() {
             // If we have only one operation containing a single root field `__typename`
             if selections.contains_only_typename() {
-                return Ok(QueryPlannerContent::Introspection {
+                return Ok(QueryPlannerContent::Typename {
                     response: Box::new(
                         graphql::Response::builder()
                             .data(json!({TYPENAME: selections.operations[0].kind().to_string()}))
@@ -235,11 +296,54 @@ impl BridgeQueryPlanner {
                     ),
                 });
             } else {
-                return self.introspection(key.0).await;
+                self.check_introspection_depth(&selections)?;
+                return self.introspection(key.0, measures).await;
             }
         }
 
-        self.plan(key.0, key.1, selections).await
+        if selections.is_subscription(key.1.as_deref()) {
+            return self.plan_subscription(key.0, key.1, selections, measures);
+        }
+
+        self.plan(key.0, key.1, selections, measures).await
+    }
+
+    /// Rejects subscriptions when their support is disabled
+    fn check_subscription_support(
+        &self,
+        selections: &Query,
+        operation_name: Option<&str>,
+    ) -> Result<(), SpecError> {
+        if selections.is_subscription(operation_name)
+            && !self.configuration.supergraph.subscription_support
+        {
+            return Err(SpecError::SubscriptionNotSupported);
+        }
+        Ok(())
+    }
+
+    /// Rejects the introspection queries deeper than the configured maximum, as deeply nested
+    /// introspection queries are expensive to execute
+    fn check_introspection_depth(&self, selections: &Query) -> Result<(), SpecError> {
+        let max = match self
+            .configuration
+            .supergraph
+            .introspection_options
+            .max_depth
+        {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+
+        let measured = selections.introspection_depth();
+        if measured > max {
+            return Err(SpecError::OperationLimitExceeded {
+                limit: "introspection.max_depth".to_string(),
+                measured,
+                max,
+            });
+        }
+        Ok(())
     }
 }
 
@@ -271,7 +375,7 @@ mod tests {
         let planner = BridgeQueryPlanner::new(
             Arc::new(example_schema()),
             Some(Arc::new(
-                Introspection::new(&Configuration::default()).await,
+                Introspection::new(&Configuration::default(), None).await,
             )),
             Default::default(),
         )
@@ -296,7 +400,7 @@ mod tests {
         let planner = BridgeQueryPlanner::new(
             Arc::new(example_schema()),
             Some(Arc::new(
-                Introspection::new(&Configuration::default()).await,
+                Introspection::new(&Configuration::default(), None).await,
             )),
             Default::default(),
         )
@@ -341,7 +445,7 @@ mod tests {
         let err = BridgeQueryPlanner::new(
             Arc::new(example_schema()),
             Some(Arc::new(
-                Introspection::new(&Configuration::default()).await,
+                Introspection::new(&Configuration::default(), None).await,
             )),
             Default::default(),
         )
@@ -355,6 +459,7 @@ mod tests {
             include_str!("testdata/unknown_introspection_query.graphql").into(),
             None,
             Query::default(),
+            None,
         )
         .await
         .unwrap_err();
@@ -376,7 +481,7 @@ mod tests {
         let planner = BridgeQueryPlanner::new(
             Arc::new(example_schema()),
             Some(Arc::new(
-                Introspection::new(&Configuration::default()).await,
+                Introspection::new(&Configuration::default(), None).await,
             )),
             Default::default(),
         )
@@ -2,10 +2,14 @@
 
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::task;
 
 use futures::future::BoxFuture;
+use futures::stream;
+use futures::StreamExt;
 use router_bridge::planner::UsageReporting;
 use serde::Serialize;
 use serde_json_bytes::value::Serializer;
@@ -14,11 +18,14 @@ use tracing::Instrument;
 
 use super::USAGE_REPORTING;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::OperationLimits;
+use crate::configuration::WarmUp;
 use crate::error::CacheResolverError;
 use crate::error::QueryPlannerError;
 use crate::services::QueryPlannerContent;
 use crate::services::QueryPlannerRequest;
 use crate::services::QueryPlannerResponse;
+use crate::spec::SpecError;
 use crate::Context;
 
 /// A query planner wrapper that caches results.
@@ -31,6 +38,7 @@ pub(crate) struct CachingQueryPlanner<T: Clone> {
     >,
     delegate: T,
     schema_id: Option<String>,
+    operation_limits: Arc<OperationLimits>,
 }
 
 impl<T: Clone + 'static> CachingQueryPlanner<T>
@@ -46,15 +54,23 @@ where
         delegate: T,
         schema_id: Option<String>,
         config: &crate::configuration::QueryPlanning,
+        operation_limits: Arc<OperationLimits>,
     ) -> CachingQueryPlanner<T> {
-        let cache = Arc::new(
-            DeduplicatingCache::from_configuration(&config.experimental_cache, "query planner")
-                .await,
-        );
+        #[cfg_attr(not(feature = "experimental_cache"), allow(unused_mut))]
+        let mut cache_config = config.experimental_cache.for_schema(schema_id.as_deref());
+        #[cfg(feature = "experimental_cache")]
+        if let Some(redis) = cache_config.redis.as_mut() {
+            redis.ttl.get_or_insert(config.shared_cache.ttl);
+        }
+        let cache = DeduplicatingCache::from_configuration(&cache_config, "query planner").await;
+        #[cfg(feature = "experimental_cache")]
+        let cache = cache.shared(config.shared_cache.lock_timeout);
+        let cache = Arc::new(cache);
         Self {
             cache,
             delegate,
             schema_id,
+            operation_limits,
         }
     }
 
@@ -66,48 +82,91 @@ where
             .collect()
     }
 
-    pub(crate) async fn warm_up(&mut self, cache_keys: Vec<(String, Option<String>)>) {
-        let schema_id = self.schema_id.clone();
-
-        let mut count = 0usize;
-        for (query, operation) in cache_keys {
-            let caching_key = CachingQueryKey {
-                schema_id: schema_id.clone(),
-                query: query.clone(),
-                operation: operation.to_owned(),
-            };
-            let context = Context::new();
+    /// Sets the expiration of the query plans stored in Redis for this schema, once the
+    /// router switched to `next`, using another schema. The routers still using this schema
+    /// can read them until they expire.
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn expire_for_next_schema(&self, next: &Self, ttl: std::time::Duration) {
+        if self.schema_id == next.schema_id {
+            return;
+        }
 
-            let entry = self.cache.get(&caching_key).await;
-            if entry.is_first() {
-                let request = QueryPlannerRequest {
-                    query,
-                    operation_name: operation,
-                    context: context.clone(),
-                };
+        let cache = self.cache.clone();
+        tokio::task::spawn(async move { cache.expire_shared(ttl).await });
+    }
 
-                let res = match self.delegate.ready().await {
-                    Ok(service) => service.call(request).await,
-                    Err(_) => break,
+    /// Plans the queries and stores the results in the cache, with at most `concurrency`
+    /// queries planned at the same time. The warm up stops after `timeout`, the remaining
+    /// queries are then planned when they are received.
+    pub(crate) async fn warm_up(
+        &mut self,
+        cache_keys: Vec<(String, Option<String>)>,
+        config: &WarmUp,
+    ) {
+        let count = AtomicUsize::new(0);
+        let total = cache_keys.len();
+
+        let warm_up = stream::iter(cache_keys).for_each_concurrent(
+            config.concurrency.get(),
+            |(query, operation)| {
+                let caching_key = CachingQueryKey {
+                    schema_id: self.schema_id.clone(),
+                    query: query.clone(),
+                    operation: operation.to_owned(),
                 };
+                let cache = self.cache.clone();
+                let mut delegate = self.delegate.clone();
+                let count = &count;
+
+                async move {
+                    let context = Context::new();
+
+                    let entry = cache.get(&caching_key).await;
+                    if entry.is_first() {
+                        let request = QueryPlannerRequest {
+                            query,
+                            operation_name: operation,
+                            context: context.clone(),
+                        };
+
+                        let res = match delegate.ready().await {
+                            Ok(service) => service.call(request).await,
+                            Err(_) => return,
+                        };
 
-                match res {
-                    Ok(QueryPlannerResponse { content, .. }) => {
-                        if let Some(content) = &content {
-                            count += 1;
-                            entry.insert(Ok(content.clone())).await;
+                        match res {
+                            Ok(QueryPlannerResponse { content, .. }) => {
+                                if let Some(content) = &content {
+                                    count.fetch_add(1, Ordering::Relaxed);
+                                    entry.insert(Ok(content.clone())).await;
+                                }
+                            }
+                            // the rejected operations are checked again when they are received
+                            Err(error) if is_operation_limit_error(&error) => {}
+                            Err(error) => {
+                                count.fetch_add(1, Ordering::Relaxed);
+                                let e = Arc::new(error);
+                                entry.insert(Err(e.clone())).await;
+                            }
                         }
                     }
-                    Err(error) => {
-                        count += 1;
-                        let e = Arc::new(error);
-                        entry.insert(Err(e.clone())).await;
-                    }
                 }
-            }
+            },
+        );
+
+        if tokio::time::timeout(config.timeout, warm_up).await.is_err() {
+            tracing::warn!(
+                "the query plan cache warm up did not finish in {:?}, {} queries out of {} were planned",
+                config.timeout,
+                count.load(Ordering::Relaxed),
+                total
+            );
         }
 
-        tracing::debug!("warmed up the query planner cache with {count} queries");
+        tracing::debug!(
+            "warmed up the query planner cache with {} queries",
+            count.load(Ordering::Relaxed)
+        );
     }
 }
 
@@ -180,7 +239,13 @@ where
                             }
                             Err(error) => {
                                 let e = Arc::new(error);
-                                entry.insert(Err(e.clone())).await;
+                                // the operation limits are checked before planning, their
+                                // errors are not cached so that each request is checked
+                                if is_operation_limit_error(&e) {
+                                    entry.send(Err(e.clone())).await;
+                                } else {
+                                    entry.insert(Err(e.clone())).await;
+                                }
                                 Err(CacheResolverError::RetrievalError(e))
                             }
                         }
@@ -199,6 +264,23 @@ where
                     .await
                     .map_err(|_| QueryPlannerError::UnhandledPlannerResult)?;
 
+                // the operation limits were checked before planning: the cached plans and
+                // introspection responses are checked again for each request using them
+                let res = res.and_then(|content| {
+                    let measures = match &content {
+                        QueryPlannerContent::Plan { plan } => plan.measures,
+                        QueryPlannerContent::Introspection { measures, .. } => *measures,
+                        _ => None,
+                    };
+                    match measures {
+                        Some(measures) => measures
+                            .enforce(&qp.operation_limits, request.operation_name.as_deref())
+                            .map(|()| content)
+                            .map_err(|error| Arc::new(QueryPlannerError::from(error))),
+                        None => Ok(content),
+                    }
+                });
+
                 match res {
                     Ok(content) => {
                         if let QueryPlannerContent::Plan { plan, .. } = &content {
@@ -258,6 +340,17 @@ where
     }
 }
 
+fn is_operation_limit_error(error: &QueryPlannerError) -> bool {
+    matches!(
+        error,
+        QueryPlannerError::SpecError(SpecError::OperationLimitExceeded { .. })
+    )
+}
+
+/// Version tag of the cached query plans, part of their key in Redis: the routers sharing a
+/// Redis only read the plans serialized by the same version, as their format can change
+const CACHE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub(crate) struct CachingQueryKey {
     pub(crate) schema_id: Option<String>,
@@ -269,7 +362,8 @@ impl std::fmt::Display for CachingQueryKey {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "plan\0{}\0{}\0{}",
+            "plan\0{}\0{}\0{}\0{}",
+            CACHE_VERSION,
             self.schema_id.as_deref().unwrap_or("-"),
             self.query,
             self.operation.as_deref().unwrap_or("-")
@@ -289,6 +383,7 @@ mod tests {
     use crate::error::PlanErrors;
     use crate::query_planner::QueryPlan;
     use crate::query_planner::QueryPlanOptions;
+    use crate::spec::operation_limits::OperationMeasures;
     use crate::spec::Query;
 
     mock! {
@@ -346,6 +441,7 @@ mod tests {
             delegate,
             None,
             &crate::configuration::QueryPlanning::default(),
+            Default::default(),
         )
         .await;
 
@@ -369,6 +465,111 @@ mod tests {
             .is_err());
     }
 
+    #[test(tokio::test)]
+    async fn test_warm_up() {
+        let mut delegate = MockMyQueryPlanner::new();
+        delegate.expect_clone().times(3).returning(|| {
+            let mut planner = MockMyQueryPlanner::new();
+            planner.expect_sync_call().times(1).returning(|_| {
+                Err(QueryPlannerError::from(PlanErrors {
+                    errors: Default::default(),
+                    usage_reporting: UsageReporting {
+                        stats_report_key: "this is a test key".to_string(),
+                        referenced_fields_by_type: Default::default(),
+                    },
+                }))
+            });
+            planner
+        });
+
+        let mut planner = CachingQueryPlanner::new(
+            delegate,
+            None,
+            &crate::configuration::QueryPlanning::default(),
+            Default::default(),
+        )
+        .await;
+
+        let cache_keys = vec![
+            ("query1".to_string(), None),
+            ("query2".to_string(), None),
+            ("query3".to_string(), Some("A".to_string())),
+        ];
+        let config = WarmUp {
+            concurrency: std::num::NonZeroUsize::new(2).unwrap(),
+            ..Default::default()
+        };
+        planner.warm_up(cache_keys.clone(), &config).await;
+
+        let mut warmed_up = planner.cache_keys(10).await;
+        warmed_up.sort();
+        assert_eq!(warmed_up, cache_keys);
+    }
+
+    /// Planner failing to plan the queries, after a delay for the query `slow`
+    #[derive(Clone)]
+    struct SlowQueryPlanner;
+
+    impl Service<QueryPlannerRequest> for SlowQueryPlanner {
+        type Response = QueryPlannerResponse;
+
+        type Error = QueryPlannerError;
+
+        type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(
+            &mut self,
+            _cx: &mut task::Context<'_>,
+        ) -> task::Poll<Result<(), Self::Error>> {
+            task::Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: QueryPlannerRequest) -> Self::Future {
+            Box::pin(async move {
+                if req.query == "slow" {
+                    tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                }
+                Err(QueryPlannerError::from(PlanErrors {
+                    errors: Default::default(),
+                    usage_reporting: UsageReporting {
+                        stats_report_key: "this is a test key".to_string(),
+                        referenced_fields_by_type: Default::default(),
+                    },
+                }))
+            })
+        }
+    }
+
+    #[test(tokio::test)]
+    async fn test_warm_up_stops_at_timeout() {
+        let mut planner = CachingQueryPlanner::new(
+            SlowQueryPlanner,
+            None,
+            &crate::configuration::QueryPlanning::default(),
+            Default::default(),
+        )
+        .await;
+
+        let cache_keys = vec![
+            ("query1".to_string(), None),
+            ("slow".to_string(), None),
+            ("query2".to_string(), None),
+        ];
+        let config = WarmUp {
+            timeout: std::time::Duration::from_millis(100),
+            ..Default::default()
+        };
+        let start = std::time::Instant::now();
+        planner.warm_up(cache_keys, &config).await;
+        assert!(start.elapsed() < std::time::Duration::from_secs(30));
+
+        // the queries are planned one at a time, the ones after the slow one were not planned
+        assert_eq!(
+            planner.cache_keys(10).await,
+            vec![("query1".to_string(), None)]
+        );
+    }
+
     macro_rules! test_query_plan {
         () => {
             include_str!("testdata/query_plan.json")
@@ -385,6 +586,7 @@ mod tests {
                     formatted_query_plan: Default::default(),
                     root: serde_json::from_str(test_query_plan!()).unwrap(),
                     options: QueryPlanOptions::default(),
+                    measures: None,
                     usage_reporting: UsageReporting {
                         stats_report_key: "this is a test report key".to_string(),
                         referenced_fields_by_type: Default::default(),
@@ -407,6 +609,7 @@ mod tests {
             delegate,
             None,
             &crate::configuration::QueryPlanning::default(),
+            Default::default(),
         )
         .await;
 
@@ -426,4 +629,115 @@ mod tests {
                 .is_some());
         }
     }
+
+    #[test(tokio::test)]
+    async fn checks_operation_limits_of_cached_plans() {
+        let mut delegate = MockMyQueryPlanner::new();
+        delegate.expect_clone().returning(|| {
+            let mut planner = MockMyQueryPlanner::new();
+            planner.expect_sync_call().returning(|_| {
+                let query_plan = QueryPlan {
+                    formatted_query_plan: Default::default(),
+                    root: serde_json::from_str(test_query_plan!()).unwrap(),
+                    options: QueryPlanOptions::default(),
+                    measures: Some(OperationMeasures {
+                        depth: 3,
+                        ..Default::default()
+                    }),
+                    usage_reporting: UsageReporting {
+                        stats_report_key: "this is a test report key".to_string(),
+                        referenced_fields_by_type: Default::default(),
+                    },
+                    query: Arc::new(Query::default()),
+                };
+
+                Ok(QueryPlannerResponse::builder()
+                    .content(QueryPlannerContent::Plan {
+                        plan: Arc::new(query_plan),
+                    })
+                    .context(Context::new())
+                    .build())
+            });
+            planner
+        });
+
+        let mut planner = CachingQueryPlanner::new(
+            delegate,
+            None,
+            &crate::configuration::QueryPlanning::default(),
+            Arc::new(OperationLimits::builder().max_depth(2).build()),
+        )
+        .await;
+
+        // the delegate checks the limits before planning
+        assert!(planner
+            .call(QueryPlannerRequest::new(
+                "query1".into(),
+                None,
+                Context::new()
+            ))
+            .await
+            .is_ok());
+
+        let error = planner
+            .call(QueryPlannerRequest::new(
+                "query1".into(),
+                None,
+                Context::new(),
+            ))
+            .await
+            .err()
+            .expect("the cached plan exceeds the limits");
+        assert!(matches!(
+            error,
+            CacheResolverError::RetrievalError(error)
+                if matches!(
+                    *error,
+                    QueryPlannerError::SpecError(SpecError::OperationLimitExceeded { .. })
+                )
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn does_not_cache_operation_limit_errors() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut delegate = MockMyQueryPlanner::new();
+        let delegate_calls = calls.clone();
+        delegate.expect_clone().returning(move || {
+            let mut planner = MockMyQueryPlanner::new();
+            let calls = delegate_calls.clone();
+            planner.expect_sync_call().returning(move |_| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(QueryPlannerError::SpecError(
+                    SpecError::OperationLimitExceeded {
+                        limit: "max_depth".to_string(),
+                        measured: 3,
+                        max: 2,
+                    },
+                ))
+            });
+            planner
+        });
+
+        let mut planner = CachingQueryPlanner::new(
+            delegate,
+            None,
+            &crate::configuration::QueryPlanning::default(),
+            Default::default(),
+        )
+        .await;
+
+        for _ in 0..2 {
+            assert!(planner
+                .call(QueryPlannerRequest::new(
+                    "query1".into(),
+                    None,
+                    Context::new()
+                ))
+                .await
+                .is_err());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(planner.cache_keys(10).await.is_empty());
+    }
 }
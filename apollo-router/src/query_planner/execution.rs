@@ -13,6 +13,8 @@ use super::PlanNode;
 use super::QueryPlan;
 use super::QueryPlanOptions;
 use crate::error::Error;
+use crate::error::FetchError;
+use crate::graphql;
 use crate::graphql::Request;
 use crate::graphql::Response;
 use crate::json_ext::Path;
@@ -30,6 +32,7 @@ use crate::query_planner::FETCH_SPAN_NAME;
 use crate::query_planner::FLATTEN_SPAN_NAME;
 use crate::query_planner::PARALLEL_SPAN_NAME;
 use crate::query_planner::SEQUENCE_SPAN_NAME;
+use crate::query_planner::SUBSCRIBE_SPAN_NAME;
 use crate::services::SubgraphServiceFactory;
 use crate::spec::Query;
 use crate::spec::Schema;
@@ -341,6 +344,56 @@ impl PlanNode {
                     ))
                     .await
                 }
+                PlanNode::Subscription { primary, rest } => {
+                    value = Value::default();
+                    errors = Vec::new();
+
+                    async {
+                        let (stream_sender, mut stream_receiver) =
+                            futures::channel::mpsc::channel(1);
+                        match primary.subscribe(parameters, stream_sender).await {
+                            Ok(e) => {
+                                errors = e;
+                            }
+                            Err(err) => {
+                                failfast_error!("Subscription error: {}", err);
+                                errors = vec![err.to_graphql_error(Some(current_dir.to_owned()))];
+                            }
+                        }
+
+                        // the subgraph answered with errors instead of opening the stream
+                        if !errors.is_empty() {
+                            return;
+                        }
+
+                        match stream_receiver.next().await {
+                            Some(events) => {
+                                tokio::task::spawn(
+                                    execute_subscription_events(
+                                        parameters,
+                                        rest.clone(),
+                                        events,
+                                        sender.clone(),
+                                    )
+                                    .in_current_span(),
+                                );
+                            }
+                            None => {
+                                let err = FetchError::SubrequestHttpError {
+                                    service: primary.service_name.clone(),
+                                    reason: "the subgraph did not open an event stream".to_string(),
+                                };
+                                errors = vec![err.to_graphql_error(Some(current_dir.to_owned()))];
+                            }
+                        }
+                    }
+                    .instrument(tracing::info_span!(
+                        SUBSCRIBE_SPAN_NAME,
+                        "otel.kind" = "INTERNAL",
+                        "apollo.subgraph.name" = primary.service_name.as_str()
+                    ))
+                    .await
+                }
             }
 
             (value, subselection, errors)
@@ -348,6 +401,73 @@ impl PlanNode {
     }
 }
 
+/// Runs the rest of a subscription plan for every event received from the
+/// subgraph, and forwards the results to the client.
+fn execute_subscription_events(
+    parameters: &ExecutionParameters<'_>,
+    rest: Option<Box<PlanNode>>,
+    mut events: graphql::ResponseStream,
+    mut sender: futures::channel::mpsc::Sender<Response>,
+) -> impl Future<Output = ()> {
+    let sc = parameters.schema.clone();
+    let orig = parameters.supergraph_request.clone();
+    let sf = parameters.service_factory.clone();
+    let ctx = parameters.context.clone();
+    let opt = parameters.options.clone();
+    let query = parameters.query.clone();
+
+    async move {
+        let deferred_fetches = HashMap::new();
+
+        while let Some(event) = events.next().await {
+            let mut value = event.data.unwrap_or_default();
+            let mut errors = event.errors;
+            let mut subselection = None;
+
+            if let Some(node) = &rest {
+                let (v, subselect, err) = node
+                    .execute_recursively(
+                        &ExecutionParameters {
+                            context: &ctx,
+                            service_factory: &sf,
+                            schema: &sc,
+                            supergraph_request: &orig,
+                            deferred_fetches: &deferred_fetches,
+                            query: &query,
+                            options: &opt,
+                        },
+                        &Path::default(),
+                        &value,
+                        sender.clone(),
+                    )
+                    .await;
+                value.deep_merge(v);
+                errors.extend(err.into_iter());
+                subselection = subselect;
+            }
+
+            if let Err(e) = sender
+                .send(
+                    Response::builder()
+                        .data(value)
+                        .errors(errors)
+                        .extensions(event.extensions)
+                        .and_subselection(subselection)
+                        .has_next(true)
+                        .build(),
+                )
+                .await
+            {
+                // the client went away, dropping the event stream closes the subscription
+                tracing::debug!("error sending subscription event: {:?}", e);
+                break;
+            }
+        }
+
+        sender.disconnect();
+    }
+}
+
 impl DeferredNode {
     fn execute<'a, 'b>(
         &'b self,
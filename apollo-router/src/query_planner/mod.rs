@@ -13,6 +13,9 @@ mod execution;
 pub(crate) mod fetch;
 mod plan;
 mod selection;
+mod subscription;
+mod subscription_planner;
+pub(crate) mod warm_up;
 pub use plan::*;
 
 pub(crate) const FETCH_SPAN_NAME: &str = "fetch";
@@ -25,6 +28,7 @@ pub(crate) const DEFER_DEFERRED_SPAN_NAME: &str = "defer_deferred";
 pub(crate) const CONDITION_SPAN_NAME: &str = "condition";
 pub(crate) const CONDITION_IF_SPAN_NAME: &str = "condition_if";
 pub(crate) const CONDITION_ELSE_SPAN_NAME: &str = "condition_else";
+pub(crate) const SUBSCRIBE_SPAN_NAME: &str = "subscribe";
 
 // The code resides in a separate submodule to allow writing a log filter activating it
 // separately from the query planner logs, as follows:
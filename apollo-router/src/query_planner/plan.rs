@@ -8,11 +8,13 @@ use serde::Serialize;
 
 pub(crate) use self::fetch::OperationKind;
 use super::fetch;
+use super::subscription::SubscriptionNode;
 use crate::error::QueryPlannerError;
 use crate::json_ext;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::Value;
+use crate::spec::operation_limits::OperationMeasures;
 use crate::spec::query::SubSelection;
 use crate::spec::Query;
 use crate::spec::Schema;
@@ -37,6 +39,8 @@ pub struct QueryPlan {
     pub(crate) formatted_query_plan: Option<String>,
     pub(crate) query: Arc<Query>,
     pub(crate) options: QueryPlanOptions,
+    /// Size of the operation, checked against the operation limits for each request
+    pub(crate) measures: Option<OperationMeasures>,
 }
 
 /// This default impl is useful for test users
@@ -57,6 +61,7 @@ impl QueryPlan {
             formatted_query_plan: Default::default(),
             query: Arc::new(Query::default()),
             options: QueryPlanOptions::default(),
+            measures: None,
         }
     }
 }
@@ -65,6 +70,10 @@ impl QueryPlan {
     pub(crate) fn is_deferred(&self, operation: Option<&str>, variables: &Object) -> bool {
         self.root.is_deferred(operation, variables, &self.query)
     }
+
+    pub(crate) fn is_subscription(&self) -> bool {
+        matches!(self.root, PlanNode::Subscription { .. })
+    }
 }
 
 /// Query plans are composed of a set of nodes.
@@ -100,6 +109,13 @@ pub(crate) enum PlanNode {
         if_clause: Option<Box<PlanNode>>,
         else_clause: Option<Box<PlanNode>>,
     },
+
+    /// Subscribe to an event stream from a subgraph, then execute `rest`
+    /// for every event it sends.
+    Subscription {
+        primary: SubscriptionNode,
+        rest: Option<Box<PlanNode>>,
+    },
 }
 
 impl PlanNode {
@@ -114,6 +130,7 @@ impl PlanNode {
                 .map(|n| n.contains_mutations())
                 .unwrap_or(false),
             Self::Flatten(_) => false,
+            Self::Subscription { .. } => false,
             Self::Condition {
                 if_clause,
                 else_clause,
@@ -150,6 +167,10 @@ impl PlanNode {
             Self::Flatten(node) => node.node.is_deferred(operation, variables, query),
             Self::Fetch(..) => false,
             Self::Defer { .. } => true,
+            Self::Subscription { rest, .. } => rest
+                .as_ref()
+                .map(|n| n.is_deferred(operation, variables, query))
+                .unwrap_or(false),
             Self::Condition {
                 if_clause,
                 else_clause,
@@ -188,6 +209,8 @@ impl PlanNode {
 
         let operation_kind = if self.contains_mutations() {
             OperationKind::Mutation
+        } else if matches!(self, Self::Subscription { .. }) {
+            OperationKind::Subscription
         } else {
             OperationKind::Query
         };
@@ -272,6 +295,10 @@ impl PlanNode {
                 Ok(())
             }
             Self::Fetch(..) => Ok(()),
+            Self::Subscription { rest, .. } => match rest {
+                Some(node) => node.collect_subselections(schema, initial_path, kind, subselections),
+                None => Ok(()),
+            },
             Self::Condition {
                 if_clause,
                 else_clause,
@@ -299,6 +326,11 @@ impl PlanNode {
             }
             Self::Fetch(fetch) => Box::new(Some(fetch.service_name()).into_iter()),
             Self::Flatten(flatten) => flatten.node.service_usage(),
+            Self::Subscription { primary, rest } => Box::new(
+                Some(primary.service_name.as_str())
+                    .into_iter()
+                    .chain(rest.iter().flat_map(|node| node.service_usage())),
+            ),
             Self::Defer { primary, deferred } => primary
                 .node
                 .as_ref()
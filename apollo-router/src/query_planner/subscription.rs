@@ -0,0 +1,119 @@
+use futures::channel::mpsc;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::ServiceExt;
+use tracing::Instrument;
+
+use super::execution::ExecutionParameters;
+use super::fetch::OperationKind;
+use crate::error::Error;
+use crate::error::FetchError;
+use crate::graphql;
+use crate::graphql::Request;
+use crate::http_ext;
+use crate::json_ext::Object;
+use crate::services::SubgraphRequest;
+
+/// The root of a subscription plan: opens an event stream on a subgraph.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubscriptionNode {
+    /// The name of the service or subgraph that the subscription is querying.
+    pub(crate) service_name: String,
+
+    /// The variables that are used for the subgraph subscription.
+    pub(crate) variable_usages: Vec<String>,
+
+    /// The GraphQL subquery that is used for the subscription.
+    pub(crate) operation: String,
+
+    /// The GraphQL subquery operation name.
+    pub(crate) operation_name: Option<String>,
+
+    /// The GraphQL operation kind that is used for the subscription.
+    pub(crate) operation_kind: OperationKind,
+}
+
+impl SubscriptionNode {
+    /// Opens the subscription on the subgraph.
+    ///
+    /// On success, the subgraph service sends the event stream through
+    /// `stream_sender`. The returned errors are the ones the subgraph answered
+    /// with instead of opening the stream.
+    pub(crate) async fn subscribe<'a>(
+        &'a self,
+        parameters: &'a ExecutionParameters<'a>,
+        stream_sender: mpsc::Sender<graphql::ResponseStream>,
+    ) -> Result<Vec<Error>, FetchError> {
+        let SubscriptionNode {
+            operation,
+            operation_kind,
+            operation_name,
+            service_name,
+            ..
+        } = self;
+
+        let body = parameters.supergraph_request.body();
+        let variables: Object = self
+            .variable_usages
+            .iter()
+            .filter_map(|key| {
+                body.variables
+                    .get_key_value(key.as_str())
+                    .map(|(variable_key, value)| (variable_key.clone(), value.clone()))
+            })
+            .collect();
+
+        let uri = parameters
+            .schema
+            .subgraphs()
+            .find_map(|(name, url)| (name == service_name).then_some(url))
+            .ok_or_else(|| FetchError::SubrequestHttpError {
+                service: service_name.to_string(),
+                reason: "no URL is configured for this subgraph".to_string(),
+            })?
+            .clone();
+
+        let subgraph_request = SubgraphRequest::builder()
+            .supergraph_request(parameters.supergraph_request.clone())
+            .subgraph_request(
+                http_ext::Request::builder()
+                    .method(http::Method::POST)
+                    .uri(uri)
+                    .body(
+                        Request::builder()
+                            .query(operation)
+                            .and_operation_name(operation_name.clone())
+                            .variables(variables)
+                            .build(),
+                    )
+                    .build()
+                    .expect("it won't fail because the url is correct and already checked; qed"),
+            )
+            .operation_kind(*operation_kind)
+            .context(parameters.context.clone())
+            .subscription_stream(stream_sender)
+            .build();
+
+        let service = parameters
+            .service_factory
+            .create(service_name)
+            .ok_or_else(|| FetchError::SubrequestHttpError {
+                service: service_name.to_string(),
+                reason: "the subgraph service does not exist".to_string(),
+            })?;
+
+        let (_parts, response) = service
+            .oneshot(subgraph_request)
+            .instrument(tracing::trace_span!("subscribe_stream"))
+            .await
+            .map_err(|e| FetchError::SubrequestHttpError {
+                service: service_name.to_string(),
+                reason: e.to_string(),
+            })?
+            .response
+            .into_parts();
+
+        Ok(response.errors)
+    }
+}
@@ -0,0 +1,472 @@
+//! Plans the subscriptions that a single subgraph can resolve.
+//!
+//! The query planner bundled with the router does not plan subscriptions. Those that only
+//! select fields owned by one subgraph are planned here from the join directives of the
+//! supergraph schema: the whole operation is sent to that subgraph when the subscription
+//! starts, and no entity fetch is needed for its events.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use apollo_parser::ast;
+use apollo_parser::ast::AstNode;
+
+use super::fetch::OperationKind;
+use super::subscription::SubscriptionNode;
+use crate::json_ext::Value;
+use crate::spec::query::parse_value;
+use crate::spec::FieldType;
+use crate::spec::SpecError;
+
+/// Subgraphs able to resolve the fields of the supergraph, from its join directives
+#[derive(Debug, Default)]
+pub(crate) struct SubscriptionPlanner {
+    /// Subgraph names, in the order of the `join__Graph` enum
+    subgraphs: Vec<String>,
+    /// Subgraph names by `join__Graph` enum value
+    graphs: HashMap<String, String>,
+    types: HashMap<String, JoinType>,
+    subscription_type: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct JoinType {
+    /// Subgraphs resolving the fields without a `@join__field` directive. `None` if the type
+    /// has no join directive, in which case any subgraph can resolve them.
+    subgraphs: Option<HashSet<String>>,
+    fields: HashMap<String, JoinField>,
+}
+
+#[derive(Debug)]
+struct JoinField {
+    type_name: Option<String>,
+    /// Subgraphs listed in the `@join__field` directives of the field
+    subgraphs: Option<HashSet<String>>,
+}
+
+impl SubscriptionPlanner {
+    pub(crate) fn new(schema: &str) -> Self {
+        let document = apollo_parser::Parser::new(schema).parse().document();
+        let mut planner = SubscriptionPlanner::default();
+
+        // the graph names are needed to read the other join directives
+        for definition in document.definitions() {
+            if let ast::Definition::EnumTypeDefinition(enum_type) = definition {
+                if text(enum_type.name()).as_deref() != Some("join__Graph") {
+                    continue;
+                }
+                for value in enum_type
+                    .enum_values_definition()
+                    .iter()
+                    .flat_map(|values| values.enum_value_definitions())
+                {
+                    let graph = value.enum_value().and_then(|value| text(value.name()));
+                    let name = directives(value.directives(), "join__graph")
+                        .find_map(|directive| string_argument(&directive, "name"));
+                    if let (Some(graph), Some(name)) = (graph, name) {
+                        planner.subgraphs.push(name.clone());
+                        planner.graphs.insert(graph, name);
+                    }
+                }
+            }
+        }
+
+        for definition in document.definitions() {
+            match definition {
+                ast::Definition::ObjectTypeDefinition(object) => planner.add_type(
+                    object.name(),
+                    object.directives(),
+                    object.fields_definition(),
+                ),
+                ast::Definition::ObjectTypeExtension(object) => planner.add_type(
+                    object.name(),
+                    object.directives(),
+                    object.fields_definition(),
+                ),
+                ast::Definition::InterfaceTypeDefinition(interface) => planner.add_type(
+                    interface.name(),
+                    interface.directives(),
+                    interface.fields_definition(),
+                ),
+                ast::Definition::InterfaceTypeExtension(interface) => planner.add_type(
+                    interface.name(),
+                    interface.directives(),
+                    interface.fields_definition(),
+                ),
+                ast::Definition::SchemaDefinition(schema) => {
+                    for operation in schema.root_operation_type_definitions() {
+                        if let (Some(OperationKind::Subscription), Some(name)) = (
+                            operation.operation_type().map(OperationKind::from),
+                            operation.named_type().and_then(|named| text(named.name())),
+                        ) {
+                            planner.subscription_type = Some(name);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        planner
+    }
+
+    fn add_type(
+        &mut self,
+        name: Option<ast::Name>,
+        type_directives: Option<ast::Directives>,
+        fields: Option<ast::FieldsDefinition>,
+    ) {
+        let name = match text(name) {
+            Some(name) => name,
+            None => return,
+        };
+        let owner = self.graph_arguments(directives(type_directives.clone(), "join__owner"));
+        let join_types = self.graph_arguments(directives(type_directives, "join__type"));
+
+        let fields = fields
+            .iter()
+            .flat_map(|fields| fields.field_definitions())
+            .filter_map(|field| {
+                let subgraphs = directives(field.directives(), "join__field")
+                    .filter(|directive| {
+                        !matches!(argument(directive, "external"), Some(Value::Bool(true)))
+                    })
+                    .filter_map(|directive| self.graph_argument(&directive))
+                    .collect::<HashSet<_>>();
+                let join_field = JoinField {
+                    type_name: field
+                        .ty()
+                        .and_then(|ty| FieldType::try_from(ty).ok())
+                        .and_then(|ty| ty.inner_type_name().map(str::to_string)),
+                    subgraphs: (!subgraphs.is_empty()).then_some(subgraphs),
+                };
+                Some((text(field.name())?, join_field))
+            })
+            .collect::<Vec<_>>();
+
+        let join_type = self.types.entry(name).or_default();
+        // the fields of a type with an owner are resolved by the owner, the other subgraphs
+        // only know about its keys
+        if let Some(subgraphs) = owner.or(join_types) {
+            join_type
+                .subgraphs
+                .get_or_insert_with(HashSet::new)
+                .extend(subgraphs);
+        }
+        join_type.fields.extend(fields);
+    }
+
+    /// Subgraph name from the `graph` argument of a join directive
+    fn graph_argument(&self, directive: &ast::Directive) -> Option<String> {
+        match argument(directive, "graph")? {
+            Value::String(graph) => self.graphs.get(graph.as_str()).cloned(),
+            _ => None,
+        }
+    }
+
+    fn graph_arguments(
+        &self,
+        directives: impl Iterator<Item = ast::Directive>,
+    ) -> Option<HashSet<String>> {
+        let subgraphs = directives
+            .filter_map(|directive| self.graph_argument(&directive))
+            .collect::<HashSet<_>>();
+        (!subgraphs.is_empty()).then_some(subgraphs)
+    }
+
+    /// Plans a subscription operation, which must have been validated against the schema.
+    ///
+    /// All the fields it selects must be resolvable by the same subgraph.
+    pub(crate) fn plan(
+        &self,
+        query: &str,
+        operation_name: Option<&str>,
+    ) -> Result<SubscriptionNode, SpecError> {
+        let document = apollo_parser::Parser::new(query).parse().document();
+        let mut operations = Vec::new();
+        let mut fragments = HashMap::new();
+        for definition in document.definitions() {
+            match definition {
+                ast::Definition::OperationDefinition(operation) => operations.push(operation),
+                ast::Definition::FragmentDefinition(fragment) => {
+                    if let Some(name) = fragment.fragment_name().and_then(|name| text(name.name()))
+                    {
+                        fragments.insert(name, fragment);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let operation = match operation_name {
+            Some(operation_name) => operations
+                .into_iter()
+                .find(|operation| text(operation.name()).as_deref() == Some(operation_name)),
+            None if operations.len() == 1 => operations.pop(),
+            None => None,
+        }
+        .ok_or_else(|| {
+            SpecError::UnplannableSubscription("the operation was not found".to_string())
+        })?;
+
+        let mut walk = Walk {
+            planner: self,
+            fragments: &fragments,
+            used_fragments: HashSet::new(),
+            subgraphs: None,
+        };
+        if let Some(selection_set) = operation.selection_set() {
+            walk.selection_set(&selection_set, self.subscription_type());
+        }
+
+        let service_name = match &walk.subgraphs {
+            // no join directive restricts the fields: any subgraph can resolve them
+            None => self.subgraphs.first(),
+            Some(subgraphs) => self
+                .subgraphs
+                .iter()
+                .find(|subgraph| subgraphs.contains(*subgraph)),
+        }
+        .cloned()
+        .ok_or_else(|| {
+            SpecError::UnplannableSubscription(
+                "the selected fields cannot all be resolved by the same subgraph".to_string(),
+            )
+        })?;
+
+        // the subgraph validates the operation, so it only gets the fragments it uses
+        let mut subgraph_operation = operation.syntax().to_string().trim().to_string();
+        for fragment in document
+            .definitions()
+            .filter_map(|definition| match definition {
+                ast::Definition::FragmentDefinition(fragment) => Some(fragment),
+                _ => None,
+            })
+        {
+            if fragment
+                .fragment_name()
+                .and_then(|name| text(name.name()))
+                .map(|name| walk.used_fragments.contains(&name))
+                .unwrap_or_default()
+            {
+                subgraph_operation.push('\n');
+                subgraph_operation.push_str(fragment.syntax().to_string().trim());
+            }
+        }
+
+        Ok(SubscriptionNode {
+            service_name,
+            variable_usages: operation
+                .variable_definitions()
+                .iter()
+                .flat_map(|definitions| definitions.variable_definitions())
+                .filter_map(|definition| text(definition.variable()?.name()))
+                .collect(),
+            operation: subgraph_operation,
+            operation_name: text(operation.name()),
+            operation_kind: OperationKind::Subscription,
+        })
+    }
+
+    fn subscription_type(&self) -> &str {
+        self.subscription_type
+            .as_deref()
+            .unwrap_or_else(|| OperationKind::Subscription.as_str())
+    }
+
+    /// Subgraphs able to resolve a field. `None` if any subgraph can resolve it
+    fn field_subgraphs(&self, type_name: &str, field_name: &str) -> Option<&HashSet<String>> {
+        let join_type = self.types.get(type_name)?;
+        join_type
+            .fields
+            .get(field_name)
+            .and_then(|field| field.subgraphs.as_ref())
+            .or(join_type.subgraphs.as_ref())
+    }
+
+    fn field_type(&self, type_name: &str, field_name: &str) -> Option<&str> {
+        self.types
+            .get(type_name)?
+            .fields
+            .get(field_name)?
+            .type_name
+            .as_deref()
+    }
+}
+
+/// Intersection of the subgraphs resolving the fields of an operation
+struct Walk<'a> {
+    planner: &'a SubscriptionPlanner,
+    fragments: &'a HashMap<String, ast::FragmentDefinition>,
+    used_fragments: HashSet<String>,
+    subgraphs: Option<HashSet<String>>,
+}
+
+impl<'a> Walk<'a> {
+    fn selection_set(&mut self, selection_set: &ast::SelectionSet, type_name: &str) {
+        let planner = self.planner;
+        for selection in selection_set.selections() {
+            match selection {
+                ast::Selection::Field(field) => {
+                    let name = match text(field.name()) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    // `__typename` is resolved by every subgraph
+                    if name.starts_with("__") {
+                        continue;
+                    }
+                    if let Some(field_subgraphs) = planner.field_subgraphs(type_name, &name) {
+                        self.subgraphs = Some(match self.subgraphs.take() {
+                            Some(subgraphs) => {
+                                subgraphs.intersection(field_subgraphs).cloned().collect()
+                            }
+                            None => field_subgraphs.clone(),
+                        });
+                    }
+                    if let (Some(selection_set), Some(field_type)) =
+                        (field.selection_set(), planner.field_type(type_name, &name))
+                    {
+                        self.selection_set(&selection_set, field_type);
+                    }
+                }
+                ast::Selection::InlineFragment(fragment) => {
+                    let type_condition = fragment
+                        .type_condition()
+                        .and_then(|condition| text(condition.named_type()?.name()));
+                    if let Some(selection_set) = fragment.selection_set() {
+                        self.selection_set(
+                            &selection_set,
+                            type_condition.as_deref().unwrap_or(type_name),
+                        );
+                    }
+                }
+                ast::Selection::FragmentSpread(spread) => {
+                    let name = match spread.fragment_name().and_then(|name| text(name.name())) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    // fragments are checked once, the intersection would not change anyway
+                    if !self.used_fragments.insert(name.clone()) {
+                        continue;
+                    }
+                    let fragments = self.fragments;
+                    if let Some(fragment) = fragments.get(&name) {
+                        let type_condition = fragment
+                            .type_condition()
+                            .and_then(|condition| text(condition.named_type()?.name()));
+                        if let Some(selection_set) = fragment.selection_set() {
+                            self.selection_set(
+                                &selection_set,
+                                type_condition.as_deref().unwrap_or(type_name),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn directives(
+    directives: Option<ast::Directives>,
+    name: &'static str,
+) -> impl Iterator<Item = ast::Directive> {
+    directives
+        .into_iter()
+        .flat_map(|directives| directives.directives())
+        .filter(move |directive| text(directive.name()).as_deref() == Some(name))
+}
+
+fn argument(directive: &ast::Directive, name: &str) -> Option<Value> {
+    directive
+        .arguments()?
+        .arguments()
+        .find(|argument| text(argument.name()).as_deref() == Some(name))?
+        .value()
+        .as_ref()
+        .and_then(parse_value)
+}
+
+fn string_argument(directive: &ast::Directive, name: &str) -> Option<String> {
+    match argument(directive, name)? {
+        Value::String(value) => Some(value.as_str().to_string()),
+        _ => None,
+    }
+}
+
+fn text(name: Option<ast::Name>) -> Option<String> {
+    name.map(|name| name.text().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        schema
+          @core(feature: "https://specs.apollo.dev/core/v0.1")
+          @core(feature: "https://specs.apollo.dev/join/v0.1")
+        {
+          query: Query
+          subscription: Subscription
+        }
+
+        enum join__Graph {
+          A @join__graph(name: "a" url: "http://localhost:4001")
+          B @join__graph(name: "b" url: "http://localhost:4002")
+        }
+
+        type Review @join__owner(graph: A) @join__type(graph: A, key: "id") @join__type(graph: B, key: "id") {
+          id: ID! @join__field(graph: A)
+          body: String
+          product: Product @join__field(graph: B)
+        }
+
+        type Product @join__owner(graph: B) @join__type(graph: B, key: "upc") {
+          upc: String!
+          name: String
+        }
+
+        type Subscription {
+          reviewAdded: Review @join__field(graph: A)
+        }
+
+        type Query {
+          review: Review @join__field(graph: A)
+        }
+    "#;
+
+    #[test]
+    fn plans_subscription_on_owning_subgraph() {
+        let planner = SubscriptionPlanner::new(SCHEMA);
+        let node = planner
+            .plan(
+                "subscription OnReview($id: ID) { reviewAdded { __typename ...ReviewFields } }
+                 fragment ReviewFields on Review { id body }
+                 fragment Unused on Review { product { name } }
+                 query Other { review { product { name } } }",
+                Some("OnReview"),
+            )
+            .unwrap();
+
+        assert_eq!(node.service_name, "a");
+        assert_eq!(node.operation_name.as_deref(), Some("OnReview"));
+        assert_eq!(node.variable_usages, vec!["id".to_string()]);
+        assert_eq!(
+            node.operation,
+            "subscription OnReview($id: ID) { reviewAdded { __typename ...ReviewFields } }\n\
+             fragment ReviewFields on Review { id body }"
+        );
+    }
+
+    #[test]
+    fn rejects_fields_from_several_subgraphs() {
+        let planner = SubscriptionPlanner::new(SCHEMA);
+        let error = planner
+            .plan("subscription { reviewAdded { id product { name } } }", None)
+            .unwrap_err();
+
+        assert!(matches!(error, SpecError::UnplannableSubscription(_)));
+    }
+}
@@ -75,6 +75,7 @@ async fn mock_subgraph_service_withf_panics_should_be_reported_as_service_closed
         root: serde_json::from_str(test_query_plan!()).unwrap(),
         formatted_query_plan: Default::default(),
         options: QueryPlanOptions::default(),
+        measures: None,
         query: Arc::new(Query::default()),
         usage_reporting: UsageReporting {
             stats_report_key: "this is a test report key".to_string(),
@@ -130,6 +131,7 @@ async fn fetch_includes_operation_name() {
         },
         query: Arc::new(Query::default()),
         options: QueryPlanOptions::default(),
+        measures: None,
     };
 
     let succeeded: Arc<AtomicBool> = Default::default();
@@ -185,6 +187,7 @@ async fn fetch_makes_post_requests() {
         },
         query: Arc::new(Query::default()),
         options: QueryPlanOptions::default(),
+        measures: None,
     };
 
     let succeeded: Arc<AtomicBool> = Default::default();
@@ -299,6 +302,7 @@ async fn defer() {
             },
             query: Arc::new(Query::default()),
             options: QueryPlanOptions::default(),
+            measures: None,
         };
 
     let mut mock_x_service = plugin::test::MockSubgraphService::new();
@@ -411,6 +415,7 @@ async fn defer_if_condition() {
             .unwrap(),
         ),
         options: QueryPlanOptions::default(),
+        measures: None,
         formatted_query_plan: None,
     };
 
@@ -576,6 +581,7 @@ async fn dependent_mutations() {
         },
         query: Arc::new(Query::default()),
         options: QueryPlanOptions::default(),
+        measures: None,
     };
 
     let mut mock_a_service = plugin::test::MockSubgraphService::new();
@@ -618,3 +624,176 @@ async fn dependent_mutations() {
         )
         .await;
 }
+
+#[tokio::test]
+async fn subscription() {
+    let schema = r#"schema
+        @core(feature: "https://specs.apollo.dev/core/v0.1"),
+        @core(feature: "https://specs.apollo.dev/join/v0.1")
+      {
+        query: Query
+        subscription: Subscription
+      }
+
+      directive @core(feature: String!) repeatable on SCHEMA
+      directive @join__field(graph: join__Graph, requires: join__FieldSet, provides: join__FieldSet) on FIELD_DEFINITION
+      directive @join__type(graph: join__Graph!, key: join__FieldSet) repeatable on OBJECT | INTERFACE
+      directive @join__owner(graph: join__Graph!) on OBJECT | INTERFACE
+      directive @join__graph(name: String!, url: String!) on ENUM_VALUE
+      scalar join__FieldSet
+
+      enum join__Graph {
+        A @join__graph(name: "A" url: "http://localhost:4001")
+        B @join__graph(name: "B" url: "http://localhost:4004")
+      }
+
+      type Review @join__owner(graph: A) @join__type(graph: A, key: "id") @join__type(graph: B, key: "id") {
+          id: ID! @join__field(graph: A)
+          body: String @join__field(graph: B)
+      }
+
+      type Subscription {
+          reviewAdded: Review @join__field(graph: A)
+      }
+
+      type Query {
+          query: Boolean @join__field(graph: A)
+      }"#;
+
+    let query_plan: QueryPlan = QueryPlan {
+        // generated from:
+        // subscription {
+        //   reviewAdded {
+        //     id
+        //     body
+        //   }
+        // }
+        formatted_query_plan: Default::default(),
+        root: serde_json::from_str(
+            r#"{
+                "kind": "Subscription",
+                "primary": {
+                    "serviceName": "A",
+                    "variableUsages": [],
+                    "operation": "subscription{reviewAdded{__typename id}}",
+                    "operationKind": "subscription"
+                },
+                "rest": {
+                    "kind": "Flatten",
+                    "path": [
+                        "reviewAdded"
+                    ],
+                    "node": {
+                        "kind": "Fetch",
+                        "serviceName": "B",
+                        "requires": [
+                            {
+                                "kind": "InlineFragment",
+                                "typeCondition": "Review",
+                                "selections": [
+                                    { "kind": "Field", "name": "__typename" },
+                                    { "kind": "Field", "name": "id" }
+                                ]
+                            }
+                        ],
+                        "variableUsages": [],
+                        "operation": "query($representations:[_Any!]!){_entities(representations:$representations){...on Review{body}}}",
+                        "operationKind": "query"
+                    }
+                }
+            }"#,
+        )
+        .unwrap(),
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+        measures: None,
+    };
+
+    let mut mock_a_service = plugin::test::MockSubgraphService::new();
+    mock_a_service.expect_clone().returning(|| {
+        let mut mock_a_service = plugin::test::MockSubgraphService::new();
+        mock_a_service
+            .expect_call()
+            .times(1)
+            .withf(|request| request.operation_kind == OperationKind::Subscription)
+            .returning(|request| {
+                let events = futures::stream::iter(vec![
+                    crate::graphql::Response::builder()
+                        .data(json! {{"reviewAdded": {"__typename": "Review", "id": "1"}}})
+                        .build(),
+                    crate::graphql::Response::builder()
+                        .data(json! {{"reviewAdded": {"__typename": "Review", "id": "2"}}})
+                        .build(),
+                ])
+                .boxed();
+                request
+                    .subscription_stream
+                    .expect("the subscription stream sender should be set")
+                    .try_send(events)
+                    .unwrap();
+
+                Ok(SubgraphResponse::fake_builder().build())
+            });
+
+        mock_a_service
+    });
+
+    let mut mock_b_service = plugin::test::MockSubgraphService::new();
+    mock_b_service.expect_clone().returning(|| {
+        let mut mock_b_service = plugin::test::MockSubgraphService::new();
+        mock_b_service.expect_call().times(1).returning(|request| {
+            let variables =
+                serde_json::to_value(&request.subgraph_request.body().variables).unwrap();
+            let id = variables["representations"][0]["id"].as_str().unwrap();
+            Ok(SubgraphResponse::fake_builder()
+                .data(json! {{
+                    "_entities": [{"body": format!("review {id}")}]
+                }})
+                .build())
+        });
+
+        mock_b_service
+    });
+
+    let sf = Arc::new(SubgraphServiceFactory {
+        services: Arc::new(HashMap::from([
+            (
+                "A".into(),
+                Arc::new(mock_a_service) as Arc<dyn MakeSubgraphService>,
+            ),
+            (
+                "B".into(),
+                Arc::new(mock_b_service) as Arc<dyn MakeSubgraphService>,
+            ),
+        ])),
+        plugins: Default::default(),
+    });
+
+    let (sender, receiver) = futures::channel::mpsc::channel(10);
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &Arc::new(Schema::parse(schema, &Default::default()).unwrap()),
+            sender,
+        )
+        .await;
+    assert!(response.errors.is_empty());
+
+    let events = receiver
+        .map(|response| serde_json::to_value(&response).unwrap())
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        events,
+        vec![
+            serde_json::json! {{"data":{"reviewAdded":{"__typename":"Review","id":"1","body":"review 1"}},"hasNext":true}},
+            serde_json::json! {{"data":{"reviewAdded":{"__typename":"Review","id":"2","body":"review 2"}},"hasNext":true}},
+        ]
+    );
+}
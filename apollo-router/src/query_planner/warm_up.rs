@@ -0,0 +1,269 @@
+//! Persistence of the most used queries, to warm up the query plan cache when the router starts
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+
+#[cfg(feature = "experimental_cache")]
+use crate::cache::redis::RedisCacheStorage;
+#[cfg(feature = "experimental_cache")]
+use crate::cache::redis::RedisKey;
+#[cfg(feature = "experimental_cache")]
+use crate::cache::redis::RedisValue;
+use crate::configuration::QueryPlanning;
+use crate::services::SupergraphCreator;
+
+#[cfg(feature = "experimental_cache")]
+const REDIS_KEY: &str = "query_planner:warm_up";
+
+/// A query saved for the warm up, in its serialized form
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct WarmUpQuery {
+    query: String,
+    operation: Option<String>,
+}
+
+/// Where the most used queries are saved
+#[derive(Clone)]
+pub(crate) enum WarmUpStorage {
+    File(PathBuf),
+    #[cfg(feature = "experimental_cache")]
+    Redis(RedisCacheStorage),
+}
+
+impl WarmUpStorage {
+    /// Storage of the most used queries, if one is configured. Redis takes precedence over
+    /// the file if both are configured.
+    pub(crate) async fn from_configuration(config: &QueryPlanning) -> Option<Self> {
+        #[cfg(feature = "experimental_cache")]
+        if config.warm_up.redis {
+            // the schema is not part of the namespace: the queries are kept when the schema
+            // changes, and the ones that do not apply anymore fail to plan
+            match &config.experimental_cache.redis {
+                Some(redis) => match RedisCacheStorage::new(redis, "query planner warm up").await {
+                    Ok(storage) => return Some(WarmUpStorage::Redis(storage)),
+                    Err(e) => {
+                        tracing::error!(
+                            "could not open connection to Redis for the query plan cache warm up: {:?}",
+                            e
+                        );
+                    }
+                },
+                None => {
+                    tracing::error!(
+                        "the query plan cache warm up is saved in Redis, but the query planning cache has no Redis configuration"
+                    );
+                }
+            }
+        }
+
+        config.warm_up.file.clone().map(WarmUpStorage::File)
+    }
+
+    /// Loads the saved queries. A missing file is considered empty.
+    pub(crate) async fn load(&self) -> Result<Vec<(String, Option<String>)>, BoxError> {
+        let queries: Vec<WarmUpQuery> = match self {
+            WarmUpStorage::File(path) => match tokio::fs::read(path).await {
+                Ok(content) => serde_json::from_slice(&content)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            },
+            #[cfg(feature = "experimental_cache")]
+            WarmUpStorage::Redis(storage) => storage
+                .get::<String, Vec<WarmUpQuery>>(RedisKey(REDIS_KEY.to_string()))
+                .await
+                .map(|value| value.0)
+                .unwrap_or_default(),
+        };
+
+        Ok(queries
+            .into_iter()
+            .map(|WarmUpQuery { query, operation }| (query, operation))
+            .collect())
+    }
+
+    /// Saves the queries, replacing the previous ones
+    pub(crate) async fn save(
+        &self,
+        cache_keys: Vec<(String, Option<String>)>,
+    ) -> Result<(), BoxError> {
+        let queries: Vec<WarmUpQuery> = cache_keys
+            .into_iter()
+            .map(|(query, operation)| WarmUpQuery { query, operation })
+            .collect();
+
+        match self {
+            WarmUpStorage::File(path) => {
+                // the file is written next to the destination then renamed, so that a router
+                // starting at the same time never reads a partially written file
+                let mut tmp_path = path.clone().into_os_string();
+                tmp_path.push(".tmp");
+                tokio::fs::write(&tmp_path, serde_json::to_vec(&queries)?).await?;
+                tokio::fs::rename(&tmp_path, path).await?;
+            }
+            #[cfg(feature = "experimental_cache")]
+            WarmUpStorage::Redis(storage) => {
+                storage
+                    .insert(RedisKey(REDIS_KEY.to_string()), RedisValue(queries))
+                    .await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Saves the `count` most used queries of the supergraph at each interval, until the
+/// supergraph is dropped
+pub(crate) fn save_periodically(
+    supergraph_creator: &Arc<SupergraphCreator>,
+    storage: WarmUpStorage,
+    count: usize,
+    interval: Duration,
+) {
+    let supergraph_creator = Arc::downgrade(supergraph_creator);
+    save_every(storage, interval, move || {
+        let supergraph_creator = supergraph_creator.upgrade();
+        async move {
+            match supergraph_creator {
+                Some(supergraph_creator) => Some(supergraph_creator.cache_keys(count).await),
+                None => None,
+            }
+        }
+    });
+}
+
+/// Saves the queries returned by `cache_keys` at each interval, until it returns `None`
+fn save_every<F, Fut>(
+    storage: WarmUpStorage,
+    interval: Duration,
+    mut cache_keys: F,
+) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Option<Vec<(String, Option<String>)>>> + Send,
+{
+    tokio::task::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        // the first tick completes immediately, and the cache was just created
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            let cache_keys = match cache_keys().await {
+                Some(cache_keys) => cache_keys,
+                None => break,
+            };
+
+            // do not replace the saved queries before the router received any
+            if cache_keys.is_empty() {
+                continue;
+            }
+
+            if let Err(e) = storage.save(cache_keys).await {
+                tracing::error!(
+                    "could not save the queries for the query plan cache warm up: {}",
+                    e
+                );
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    #[cfg(feature = "experimental_cache")]
+    use crate::cache::mock_redis::MockRedis;
+    #[cfg(feature = "experimental_cache")]
+    use crate::configuration::RedisCache;
+
+    fn cache_keys() -> Vec<(String, Option<String>)> {
+        vec![
+            ("query { me { id } }".to_string(), None),
+            (
+                "query A { a } query B { b }".to_string(),
+                Some("B".to_string()),
+            ),
+        ]
+    }
+
+    #[tokio::test]
+    async fn it_saves_and_loads_queries_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = WarmUpStorage::File(dir.path().join("warm_up.json"));
+
+        assert!(storage.load().await.unwrap().is_empty());
+
+        let cache_keys = cache_keys();
+        storage.save(cache_keys.clone()).await.unwrap();
+        assert_eq!(storage.load().await.unwrap(), cache_keys);
+
+        storage.save(cache_keys[..1].to_vec()).await.unwrap();
+        assert_eq!(storage.load().await.unwrap(), cache_keys[..1].to_vec());
+    }
+
+    #[cfg(feature = "experimental_cache")]
+    #[tokio::test]
+    async fn it_saves_and_loads_queries_from_redis() {
+        let redis = MockRedis::start().await;
+        let mut config = crate::configuration::QueryPlanning::default();
+        config.experimental_cache.redis = Some(RedisCache::new(vec![redis.url.clone()]));
+        config.warm_up.redis = true;
+        let storage = WarmUpStorage::from_configuration(&config).await.unwrap();
+        assert!(matches!(storage, WarmUpStorage::Redis(_)));
+
+        assert!(storage.load().await.unwrap().is_empty());
+
+        let cache_keys = cache_keys();
+        storage.save(cache_keys.clone()).await.unwrap();
+        assert!(redis.entry(REDIS_KEY).is_some());
+        assert_eq!(storage.load().await.unwrap(), cache_keys);
+
+        // another router gets the saved queries
+        let storage = WarmUpStorage::from_configuration(&config).await.unwrap();
+        assert_eq!(storage.load().await.unwrap(), cache_keys);
+    }
+
+    #[tokio::test]
+    async fn it_saves_the_queries_periodically() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = WarmUpStorage::File(dir.path().join("warm_up.json"));
+        // the most used queries, `None` once the supergraph is dropped
+        let current: Arc<Mutex<Option<Vec<(String, Option<String>)>>>> =
+            Arc::new(Mutex::new(Some(Vec::new())));
+
+        let source = current.clone();
+        let task = save_every(storage.clone(), Duration::from_millis(10), move || {
+            let cache_keys = source.lock().unwrap().clone();
+            async move { cache_keys }
+        });
+
+        // nothing is saved before the router received queries
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!dir.path().join("warm_up.json").exists());
+
+        *current.lock().unwrap() = Some(cache_keys());
+        let start = std::time::Instant::now();
+        while storage.load().await.unwrap() != cache_keys() {
+            assert!(start.elapsed() < Duration::from_secs(5), "not saved");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // the saves stop with the supergraph
+        *current.lock().unwrap() = None;
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .expect("the saves did not stop")
+            .unwrap();
+    }
+}
@@ -94,6 +94,13 @@ impl Response {
                 service: service_name.to_string(),
                 reason: error.to_string(),
             })?;
+        Response::from_value(service_name, value)
+    }
+
+    /// Create a [`Response`] from the supplied [`Value`].
+    ///
+    /// This will return an error (identifying the faulty service) if the input is invalid.
+    pub(crate) fn from_value(service_name: &str, value: Value) -> Result<Response, FetchError> {
         let mut object =
             ensure_object!(value).map_err(|error| FetchError::SubrequestMalformedResponse {
                 service: service_name.to_string(),
@@ -6,9 +6,14 @@ use axum::response::IntoResponse;
 use http::StatusCode;
 use multimap::MultiMap;
 use once_cell::sync::Lazy;
+use rustls::server::AllowAnyAuthenticatedClient;
 use rustls::RootCertStore;
+use rustls::ServerConfig;
+use rustls::SignatureScheme;
+use rustls_pemfile::Item;
 use serde_json::Map;
 use serde_json::Value;
+use tokio_rustls::TlsAcceptor;
 use tower::service_fn;
 use tower::util::Either;
 use tower::BoxError;
@@ -18,16 +23,22 @@ use tower_service::Service;
 
 use crate::configuration::Configuration;
 use crate::configuration::ConfigurationError;
+use crate::configuration::TlsClientAuth;
 use crate::configuration::TlsSubgraph;
+use crate::configuration::TlsSupergraph;
 use crate::plugin::DynPlugin;
 use crate::plugin::Handler;
 use crate::plugin::PluginFactory;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
+use crate::query_planner::warm_up::save_periodically;
+use crate::query_planner::warm_up::WarmUpStorage;
+use crate::services::generate_tls_client_config;
 use crate::services::new_service::ServiceFactory;
 use crate::services::router;
 use crate::services::router_service::RouterCreator;
 use crate::services::transport;
+use crate::services::ClientCertificate;
 use crate::services::PluggableSupergraphServiceBuilder;
 use crate::services::SubgraphService;
 use crate::services::SupergraphCreator;
@@ -139,6 +150,12 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
         previous_router: Option<&'a Self::RouterFactory>,
         extra_plugins: Option<Vec<(String, Box<dyn DynPlugin>)>>,
     ) -> Result<Self::RouterFactory, BoxError> {
+        // The HTTP server is stopped before being restarted with a new configuration,
+        // so we check the listener's TLS configuration here to keep the previous one if it is invalid
+        if let Some(tls) = &configuration.supergraph.tls {
+            create_tls_acceptor(tls)?;
+        }
+
         // Process the plugins.
         let plugins = create_plugins(&configuration, &schema, extra_plugins).await?;
 
@@ -148,20 +165,36 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
             .all
             .create_certificate_store()
             .transpose()?;
+        let shared_client_certificate = configuration
+            .tls
+            .subgraph
+            .all
+            .load_client_certificate()
+            .transpose()?;
 
         let mut builder = PluggableSupergraphServiceBuilder::new(schema.clone());
         builder = builder.with_configuration(configuration.clone());
+        if let Some(router) = previous_router {
+            builder = builder.with_previous_supergraph(router.supergraph_creator());
+        }
 
         for (name, _) in schema.subgraphs() {
-            let subgraph_root_store = configuration
-                .tls
-                .subgraph
-                .subgraphs
-                .get(name)
-                .as_ref()
+            let subgraph_tls = configuration.tls.subgraph.subgraphs.get(name);
+            let subgraph_root_store = subgraph_tls
                 .and_then(|subgraph| subgraph.create_certificate_store())
                 .transpose()?
                 .or_else(|| tls_root_store.clone());
+            let subgraph_client_certificate = subgraph_tls
+                .and_then(|subgraph| subgraph.load_client_certificate())
+                .transpose()?
+                .or_else(|| shared_client_certificate.clone());
+            let tls_config =
+                generate_tls_client_config(subgraph_root_store, subgraph_client_certificate)
+                    .map_err(|e| ConfigurationError::ClientCertificate {
+                        error: format!(
+                            "could not create the TLS configuration for subgraph {name}: {e}"
+                        ),
+                    })?;
 
             let subgraph_service = match plugins
                 .iter()
@@ -170,9 +203,14 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
             {
                 Some(shaping) => Either::A(shaping.subgraph_service_internal(
                     name,
-                    SubgraphService::new(name, shaping.get_apq(name), subgraph_root_store),
+                    SubgraphService::new(
+                        name,
+                        shaping.get_apq(name),
+                        Some(tls_config),
+                        shaping.get_http_client(name),
+                    ),
                 )),
-                None => Either::B(SubgraphService::new(name, None, subgraph_root_store)),
+                None => Either::B(SubgraphService::new(name, None, Some(tls_config), None)),
             };
             builder = builder.with_subgraph_service(name, subgraph_service);
         }
@@ -184,24 +222,62 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
         // We're good to go with the new service.
         let mut supergraph_creator = builder.build().await?;
 
-        if let Some(router) = previous_router {
-            if configuration.supergraph.query_planning.warmed_up_queries > 0 {
-                let cache_keys = router
-                    .cache_keys(configuration.supergraph.query_planning.warmed_up_queries)
-                    .await;
+        let query_planning = &configuration.supergraph.query_planning;
+        let warm_up_storage = WarmUpStorage::from_configuration(query_planning).await;
+
+        if query_planning.warmed_up_queries > 0 {
+            // on reloads, the most used queries come from the previous router, otherwise from
+            // the ones saved by a previous process
+            let cache_keys = match (previous_router, &warm_up_storage) {
+                (Some(router), _) => router.cache_keys(query_planning.warmed_up_queries).await,
+                (None, Some(storage)) => match storage.load().await {
+                    Ok(mut cache_keys) => {
+                        cache_keys.truncate(query_planning.warmed_up_queries);
+                        cache_keys
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            "could not load the queries for the query plan cache warm up: {}",
+                            e
+                        );
+                        Vec::new()
+                    }
+                },
+                (None, None) => Vec::new(),
+            };
 
-                if !cache_keys.is_empty() {
-                    tracing::info!(
-                        "warming up the query plan cache with {} queries, this might take a while",
-                        cache_keys.len()
-                    );
+            if !cache_keys.is_empty() {
+                tracing::info!(
+                    "warming up the query plan cache with {} queries, this might take a while",
+                    cache_keys.len()
+                );
 
-                    supergraph_creator.warm_up_query_planner(cache_keys).await;
-                }
+                supergraph_creator
+                    .warm_up_query_planner(cache_keys, &query_planning.warm_up)
+                    .await;
             }
         }
 
-        Ok(Self::RouterFactory::new(Arc::new(supergraph_creator), &configuration).await)
+        // the routers still using the previous schema share its query plans until they expire
+        #[cfg(feature = "experimental_cache")]
+        if let Some(router) = previous_router {
+            router.expire_query_plans(
+                &supergraph_creator,
+                query_planning.shared_cache.previous_schema_ttl,
+            );
+        }
+
+        let supergraph_creator = Arc::new(supergraph_creator);
+        if let Some(storage) = warm_up_storage.filter(|_| query_planning.warmed_up_queries > 0) {
+            save_periodically(
+                &supergraph_creator,
+                storage,
+                query_planning.warmed_up_queries,
+                query_planning.warm_up.save_interval,
+            );
+        }
+
+        Self::RouterFactory::new(supergraph_creator, &configuration).await
     }
 }
 
@@ -210,7 +286,7 @@ impl YamlRouterFactory {
         &'a mut self,
         configuration: Arc<Configuration>,
         schema: Arc<Schema>,
-        _previous_router: Option<&'a SupergraphCreator>,
+        previous_router: Option<&'a SupergraphCreator>,
         extra_plugins: Option<Vec<(String, Box<dyn DynPlugin>)>>,
     ) -> Result<SupergraphCreator, BoxError> {
         // Process the plugins.
@@ -222,20 +298,36 @@ impl YamlRouterFactory {
             .all
             .create_certificate_store()
             .transpose()?;
+        let shared_client_certificate = configuration
+            .tls
+            .subgraph
+            .all
+            .load_client_certificate()
+            .transpose()?;
 
         let mut builder = PluggableSupergraphServiceBuilder::new(schema.clone());
         builder = builder.with_configuration(configuration.clone());
+        if let Some(previous_router) = previous_router {
+            builder = builder.with_previous_supergraph(previous_router);
+        }
 
         for (name, _) in schema.subgraphs() {
-            let subgraph_root_store = configuration
-                .tls
-                .subgraph
-                .subgraphs
-                .get(name)
-                .as_ref()
+            let subgraph_tls = configuration.tls.subgraph.subgraphs.get(name);
+            let subgraph_root_store = subgraph_tls
                 .and_then(|subgraph| subgraph.create_certificate_store())
                 .transpose()?
                 .or_else(|| tls_root_store.clone());
+            let subgraph_client_certificate = subgraph_tls
+                .and_then(|subgraph| subgraph.load_client_certificate())
+                .transpose()?
+                .or_else(|| shared_client_certificate.clone());
+            let tls_config =
+                generate_tls_client_config(subgraph_root_store, subgraph_client_certificate)
+                    .map_err(|e| ConfigurationError::ClientCertificate {
+                        error: format!(
+                            "could not create the TLS configuration for subgraph {name}: {e}"
+                        ),
+                    })?;
 
             let subgraph_service = match plugins
                 .iter()
@@ -244,9 +336,14 @@ impl YamlRouterFactory {
             {
                 Some(shaping) => Either::A(shaping.subgraph_service_internal(
                     name,
-                    SubgraphService::new(name, shaping.get_apq(name), subgraph_root_store),
+                    SubgraphService::new(
+                        name,
+                        shaping.get_apq(name),
+                        Some(tls_config),
+                        shaping.get_http_client(name),
+                    ),
                 )),
-                None => Either::B(SubgraphService::new(name, None, subgraph_root_store)),
+                None => Either::B(SubgraphService::new(name, None, Some(tls_config), None)),
             };
             builder = builder.with_subgraph_service(name, subgraph_service);
         }
@@ -265,9 +362,15 @@ impl TlsSubgraph {
             .as_deref()
             .map(create_certificate_store)
     }
+
+    fn load_client_certificate(&self) -> Option<Result<ClientCertificate, ConfigurationError>> {
+        self.client_authentication
+            .as_ref()
+            .map(load_client_certificate)
+    }
 }
 
-fn create_certificate_store(
+pub(crate) fn create_certificate_store(
     certificate_authorities: &str,
 ) -> Result<RootCertStore, ConfigurationError> {
     let mut store = RootCertStore::empty();
@@ -292,6 +395,133 @@ fn create_certificate_store(
     }
 }
 
+fn load_client_certificate(
+    client_authentication: &TlsClientAuth,
+) -> Result<ClientCertificate, ConfigurationError> {
+    let certificate_chain = load_certs(&client_authentication.certificate_chain).map_err(|e| {
+        ConfigurationError::ClientCertificate {
+            error: format!("could not parse the certificate chain: {e}"),
+        }
+    })?;
+    let certificate =
+        certificate_chain
+            .first()
+            .ok_or_else(|| ConfigurationError::ClientCertificate {
+                error: "the certificate chain is empty".to_string(),
+            })?;
+    let key = load_key(&client_authentication.key).map_err(|e| {
+        ConfigurationError::ClientCertificate {
+            error: format!("could not parse the private key: {e}"),
+        }
+    })?;
+    check_key_matches_certificate(certificate, &key)
+        .map_err(|error| ConfigurationError::ClientCertificate { error })?;
+
+    Ok(ClientCertificate {
+        certificate_chain,
+        key,
+    })
+}
+
+/// Creates the TLS acceptor terminating TLS on the supergraph listener.
+///
+/// Clients can negotiate HTTP/2 or HTTP/1.1 through ALPN.
+pub(crate) fn create_tls_acceptor(tls: &TlsSupergraph) -> Result<TlsAcceptor, ConfigurationError> {
+    let certificate_chain =
+        load_certs(&tls.certificate_chain).map_err(|e| ConfigurationError::ServerCertificate {
+            error: format!("could not parse the certificate chain: {e}"),
+        })?;
+    let certificate =
+        certificate_chain
+            .first()
+            .ok_or_else(|| ConfigurationError::ServerCertificate {
+                error: "the certificate chain is empty".to_string(),
+            })?;
+    let key = load_key(&tls.key).map_err(|e| ConfigurationError::ServerCertificate {
+        error: format!("could not parse the private key: {e}"),
+    })?;
+    check_key_matches_certificate(certificate, &key)
+        .map_err(|error| ConfigurationError::ServerCertificate { error })?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &tls.client_certificate_authorities {
+        None => builder.with_no_client_auth(),
+        Some(certificate_authorities) => builder.with_client_cert_verifier(
+            AllowAnyAuthenticatedClient::new(create_certificate_store(certificate_authorities)?),
+        ),
+    };
+    let mut config = builder
+        .with_single_cert(certificate_chain, key)
+        .map_err(|e| ConfigurationError::ServerCertificate {
+            error: e.to_string(),
+        })?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_key(data: &str) -> io::Result<rustls::PrivateKey> {
+    tracing::debug!("loading private key");
+
+    let mut reader = data.as_bytes();
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                return Ok(rustls::PrivateKey(key))
+            }
+            _ => {}
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "no private key found".to_string(),
+    ))
+}
+
+// rustls only checks that the private key can be parsed, so we make sure it matches
+// the certificate by signing a message and verifying the signature with the certificate
+fn check_key_matches_certificate(
+    certificate: &rustls::Certificate,
+    key: &rustls::PrivateKey,
+) -> Result<(), String> {
+    let algorithms = [
+        (
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+            &webpki::ECDSA_P256_SHA256,
+        ),
+        (
+            SignatureScheme::ECDSA_NISTP384_SHA384,
+            &webpki::ECDSA_P384_SHA384,
+        ),
+        (SignatureScheme::ED25519, &webpki::ED25519),
+        (
+            SignatureScheme::RSA_PKCS1_SHA256,
+            &webpki::RSA_PKCS1_2048_8192_SHA256,
+        ),
+    ];
+    let schemes: Vec<SignatureScheme> = algorithms.iter().map(|(scheme, _)| *scheme).collect();
+
+    let signer = rustls::sign::any_supported_type(key)
+        .map_err(|e| format!("unsupported private key: {e}"))?
+        .choose_scheme(&schemes)
+        .ok_or_else(|| "unsupported private key algorithm".to_string())?;
+    let (_, algorithm) = algorithms
+        .iter()
+        .find(|(scheme, _)| *scheme == signer.scheme())
+        .expect("the signature scheme was chosen among the offered ones; qed");
+
+    let message = b"apollo router client certificate";
+    let signature = signer
+        .sign(message)
+        .map_err(|e| format!("could not sign with the private key: {e}"))?;
+
+    webpki::EndEntityCert::try_from(certificate.0.as_slice())
+        .map_err(|e| format!("could not parse the certificate: {e:?}"))?
+        .verify_signature(algorithm, message, &signature)
+        .map_err(|_| "the private key does not match the certificate".to_string())
+}
+
 fn load_certs(certificates: &str) -> io::Result<Vec<rustls::Certificate>> {
     tracing::debug!("loading root certificates");
 
@@ -491,10 +721,12 @@ mod test {
     use tower_http::BoxError;
 
     use crate::configuration::Configuration;
+    use crate::configuration::TlsClientAuth;
     use crate::plugin::Plugin;
     use crate::plugin::PluginInit;
     use crate::register_plugin;
     use crate::router_factory::inject_schema_id;
+    use crate::router_factory::load_client_certificate;
     use crate::router_factory::RouterSuperServiceFactory;
     use crate::router_factory::YamlRouterFactory;
     use crate::spec::Schema;
@@ -633,4 +865,29 @@ mod test {
             "ba573b479c8b3fa273f439b26b9eda700152341d897f18090d52cd073b15f909"
         );
     }
+
+    #[test]
+    fn test_load_client_certificate() {
+        let client_authentication = TlsClientAuth {
+            certificate_chain: include_str!("testdata/tls/client.crt").to_string(),
+            key: include_str!("testdata/tls/client.key").to_string(),
+        };
+        let client_certificate = load_client_certificate(&client_authentication).unwrap();
+        assert_eq!(client_certificate.certificate_chain.len(), 1);
+    }
+
+    #[test]
+    fn test_load_client_certificate_with_mismatched_key() {
+        let client_authentication = TlsClientAuth {
+            certificate_chain: include_str!("testdata/tls/client.crt").to_string(),
+            key: include_str!("testdata/tls/other.key").to_string(),
+        };
+        let error = load_client_certificate(&client_authentication)
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "could not load client certificate: the private key does not match the certificate"
+        );
+    }
 }
@@ -71,6 +71,7 @@ impl Service<ExecutionRequest> for ExecutionService {
             let is_deferred = req
                 .query_plan
                 .is_deferred(operation_name.as_deref(), &variables);
+            let is_subscription = req.query_plan.is_subscription();
 
             let first = req
                 .query_plan
@@ -86,6 +87,16 @@ impl Service<ExecutionRequest> for ExecutionService {
             let query = req.query_plan.query.clone();
             let stream = if is_deferred {
                 filter_stream(first, receiver).boxed()
+            } else if is_subscription {
+                if first.errors.is_empty() {
+                    // the first response is empty, the events come through the receiver
+                    receiver
+                        .chain(once(ready(Response::builder().has_next(false).build())))
+                        .boxed()
+                } else {
+                    // the subscription could not be opened
+                    once(ready(first)).boxed()
+                }
             } else {
                 once(ready(first)).chain(receiver).boxed()
             };
@@ -107,6 +118,11 @@ impl Service<ExecutionRequest> for ExecutionService {
                         }
                     }
 
+                    // the end of a subscription stream is sent as is
+                    if is_subscription && response.has_next == Some(false) && response.data.is_none() {
+                        return ready(Some(response));
+                    }
+
                     let has_next = response.has_next.unwrap_or(true);
                     tracing::debug_span!("format_response").in_scope(|| {
                         let paths = query.format_response(
@@ -81,6 +81,12 @@ pub(crate) struct Externalizable<T> {
     pub(crate) body: Option<T>,
     pub(crate) context: Option<Context>,
     pub(crate) sdl: Option<String>,
+    /// Name of the subgraph, only set at the subgraph stages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) service_name: Option<String>,
+    /// URI of the subgraph request, only set at the subgraph request stage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) uri: Option<String>,
 }
 
 impl<T> Externalizable<T>
@@ -103,9 +109,18 @@ where
             body,
             context,
             sdl,
+            service_name: None,
+            uri: None,
         }
     }
 
+    /// Attach the subgraph name and request URI, for the subgraph stages
+    pub(crate) fn with_subgraph(mut self, service_name: String, uri: Option<String>) -> Self {
+        self.service_name = Some(service_name);
+        self.uri = uri;
+        self
+    }
+
     pub(crate) async fn call(self, url: &str, timeout: Option<Duration>) -> Result<Self, BoxError> {
         let my_client = CLIENT.as_ref().map_err(|e| e.to_string())?.clone();
         let t = timeout.unwrap_or(DEFAULT_EXTERNALIZATION_TIMEOUT);
@@ -129,10 +144,29 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::CLIENT;
+    use super::*;
 
     #[test]
     fn it_will_not_externalize_without_environment() {
         assert!(CLIENT.as_ref().is_err());
     }
+
+    #[test]
+    fn it_only_serializes_subgraph_fields_at_subgraph_stages() {
+        let router_request: Externalizable<serde_json::Value> =
+            Externalizable::new(PipelineStep::RouterRequest, None, None, None, None);
+        let serialized = serde_json::to_value(&router_request).unwrap();
+        assert!(serialized.get("service_name").is_none());
+        assert!(serialized.get("uri").is_none());
+
+        let subgraph_request: Externalizable<serde_json::Value> =
+            Externalizable::new(PipelineStep::SubgraphRequest, None, None, None, None)
+                .with_subgraph(
+                    "products".to_string(),
+                    Some("http://products/graphql".to_string()),
+                );
+        let serialized = serde_json::to_value(&subgraph_request).unwrap();
+        assert_eq!(serialized["service_name"], "products");
+        assert_eq!(serialized["uri"], "http://products/graphql");
+    }
 }
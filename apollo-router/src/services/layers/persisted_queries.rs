@@ -0,0 +1,374 @@
+//! Persisted queries, resolved by operation ID from a manifest, and safelisting of the queries.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use futures::StreamExt;
+use http::StatusCode;
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+
+use crate::configuration::PersistedQueries;
+use crate::configuration::PersistedQueriesManifest;
+use crate::configuration::Safelist;
+use crate::services::SupergraphRequest;
+use crate::services::SupergraphResponse;
+
+const DEFAULT_MANIFEST_NETWORK_TIMEOUT: Duration = Duration::from_secs(15);
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+/// Operation IDs mapped to their query strings
+#[derive(Debug, Default)]
+struct Manifest {
+    queries: HashMap<String, String>,
+    bodies: HashSet<String>,
+}
+
+impl Manifest {
+    fn parse(content: &[u8]) -> Result<Self, BoxError> {
+        let queries: HashMap<String, String> = serde_json::from_slice(content)?;
+        let bodies = queries.values().cloned().collect();
+        Ok(Self { queries, bodies })
+    }
+
+    async fn load(source: &PersistedQueriesManifest) -> Result<Self, BoxError> {
+        match source {
+            PersistedQueriesManifest::File(path) => Self::parse(&tokio::fs::read(path).await?),
+            PersistedQueriesManifest::Url(url) => {
+                let content = CLIENT
+                    .get(url)
+                    .timeout(DEFAULT_MANIFEST_NETWORK_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?;
+                Self::parse(&content)
+            }
+        }
+    }
+}
+
+/// Aborts the manifest reload task once the last layer referencing it is dropped
+struct ReloadTask(JoinHandle<()>);
+
+impl Drop for ReloadTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// [`Layer`] resolving persisted queries and applying the safelist.
+#[derive(Clone)]
+pub(crate) struct PersistedQueryLayer {
+    manifest: Arc<ArcSwap<Manifest>>,
+    safelist: Safelist,
+    _reload_task: Option<Arc<ReloadTask>>,
+}
+
+impl PersistedQueryLayer {
+    /// Loads the manifest, and starts reloading it when the file changes, or periodically if
+    /// it comes from a URL with a polling interval. Returns `None` if no manifest is configured.
+    pub(crate) async fn new(config: &PersistedQueries) -> Result<Option<Self>, BoxError> {
+        let source = match &config.manifest {
+            Some(source) => source.clone(),
+            None => return Ok(None),
+        };
+
+        let manifest = Manifest::load(&source).await.map_err(|e| {
+            format!("could not load the persisted queries manifest from {source:?}: {e}")
+        })?;
+        tracing::info!(
+            "loaded {} persisted queries from {:?}",
+            manifest.queries.len(),
+            source
+        );
+        let manifest = Arc::new(ArcSwap::from_pointee(manifest));
+
+        let reload_task = match (&source, config.poll_interval) {
+            (PersistedQueriesManifest::File(path), _) => {
+                // the first event of the watch stream is sent right away, the file was just read
+                let mut changes = crate::files::watch(path).skip(1);
+                let source = source.clone();
+                let manifest = manifest.clone();
+                Some(tokio::spawn(async move {
+                    while changes.next().await.is_some() {
+                        reload(&source, &manifest).await;
+                    }
+                }))
+            }
+            (PersistedQueriesManifest::Url(_), Some(poll_interval)) => {
+                let source = source.clone();
+                let manifest = manifest.clone();
+                Some(tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(poll_interval).await;
+                        reload(&source, &manifest).await;
+                    }
+                }))
+            }
+            (PersistedQueriesManifest::Url(_), None) => None,
+        };
+
+        Ok(Some(Self {
+            manifest,
+            safelist: config.safelist,
+            _reload_task: reload_task.map(|task| Arc::new(ReloadTask(task))),
+        }))
+    }
+
+    /// Replaces the operation ID by its query if it is in the manifest, then checks the query
+    /// against the safelist
+    pub(crate) fn request(
+        &self,
+        mut request: SupergraphRequest,
+    ) -> Result<SupergraphRequest, SupergraphResponse> {
+        let manifest = self.manifest.load();
+        let body = request.supergraph_request.body();
+
+        let query = body
+            .query
+            .as_deref()
+            .filter(|query| !query.trim().is_empty());
+        let operation_id = body
+            .extensions
+            .get("persistedQuery")
+            .and_then(|persisted_query| persisted_query.as_object())
+            .and_then(|persisted_query| persisted_query.get("sha256Hash"))
+            .and_then(|id| id.as_str());
+
+        match (query, operation_id) {
+            (None, Some(operation_id)) => {
+                if let Some(query) = manifest.queries.get(operation_id) {
+                    tracing::trace!("persisted query: found operation {}", operation_id);
+                    let _ = request.context.insert("persisted_query_hit", true);
+                    let body = request.supergraph_request.body_mut();
+                    body.query = Some(query.clone());
+                    // the operation ID is not a hash of the query, it must not go through APQ
+                    body.extensions.remove("persistedQuery");
+                    return Ok(request);
+                }
+
+                let operation_id = operation_id.to_string();
+                self.unknown_operation(
+                    request,
+                    "PERSISTED_QUERY_NOT_IN_LIST",
+                    format!("operation '{operation_id}' is not in the persisted queries manifest"),
+                )
+            }
+            (Some(query), _) if !manifest.bodies.contains(query) => self.unknown_operation(
+                request,
+                "QUERY_NOT_IN_SAFELIST",
+                "query is not in the persisted queries safelist".to_string(),
+            ),
+            _ => Ok(request),
+        }
+    }
+
+    fn unknown_operation(
+        &self,
+        request: SupergraphRequest,
+        code: &str,
+        message: String,
+    ) -> Result<SupergraphRequest, SupergraphResponse> {
+        match self.safelist {
+            Safelist::Allow => Ok(request),
+            Safelist::Log => {
+                tracing::warn!(
+                    operation_name = request
+                        .supergraph_request
+                        .body()
+                        .operation_name
+                        .as_deref()
+                        .unwrap_or_default(),
+                    "{}",
+                    message
+                );
+                Ok(request)
+            }
+            Safelist::Enforce => {
+                let errors = vec![crate::error::Error::builder()
+                    .message(message)
+                    .extension_code(code)
+                    .build()];
+                Err(SupergraphResponse::builder()
+                    .errors(errors)
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .context(request.context)
+                    .build()
+                    .expect("response is valid"))
+            }
+        }
+    }
+}
+
+async fn reload(source: &PersistedQueriesManifest, manifest: &ArcSwap<Manifest>) {
+    match Manifest::load(source).await {
+        Ok(new_manifest) => {
+            tracing::info!(
+                "reloaded {} persisted queries from {:?}",
+                new_manifest.queries.len(),
+                source
+            );
+            manifest.store(Arc::new(new_manifest));
+        }
+        Err(e) => {
+            tracing::error!(
+                "could not reload the persisted queries manifest from {:?}, keeping the previous one: {}",
+                source,
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use futures::StreamExt;
+    use serde_json_bytes::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::graphql::Response;
+    use crate::services::router_service::from_supergraph_mock_callback_and_configuration;
+    use crate::Configuration;
+
+    fn manifest_file() -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(br#"{"op1": "query Op1 { me { id } }"}"#)
+            .unwrap();
+        file
+    }
+
+    fn configuration(file: &tempfile::NamedTempFile, safelist: Safelist) -> Arc<Configuration> {
+        Arc::new(
+            Configuration::fake_builder()
+                .supergraph(
+                    crate::configuration::Supergraph::fake_builder()
+                        .persisted_queries(PersistedQueries {
+                            manifest: Some(PersistedQueriesManifest::File(
+                                file.path().to_path_buf(),
+                            )),
+                            poll_interval: None,
+                            safelist,
+                        })
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
+    }
+
+    async fn call(configuration: Arc<Configuration>, request: SupergraphRequest) -> Response {
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            |req| {
+                let query = req.supergraph_request.body().query.clone().unwrap();
+                Ok(SupergraphResponse::fake_builder()
+                    .data(json!({ "query": query }))
+                    .build()
+                    .unwrap())
+            },
+            configuration,
+        )
+        .await;
+
+        router_service
+            .oneshot(request.try_into().unwrap())
+            .await
+            .unwrap()
+            .into_graphql_response_stream()
+            .await
+            .next()
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn error_code(response: &Response) -> Option<String> {
+        response
+            .errors
+            .first()
+            .and_then(|error| error.extensions.get("code"))
+            .and_then(|code| code.as_str())
+            .map(str::to_string)
+    }
+
+    #[tokio::test]
+    async fn it_resolves_operation_ids() {
+        let file = manifest_file();
+        let request = SupergraphRequest::fake_builder()
+            .extension(
+                "persistedQuery",
+                json!({ "version": 1, "sha256Hash": "op1" }),
+            )
+            .build()
+            .unwrap();
+
+        let response = call(configuration(&file, Safelist::Enforce), request).await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data.unwrap(),
+            json!({ "query": "query Op1 { me { id } }" })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_unknown_operations_when_enforced() {
+        let file = manifest_file();
+
+        let request = SupergraphRequest::fake_builder()
+            .extension(
+                "persistedQuery",
+                json!({ "version": 1, "sha256Hash": "unknown" }),
+            )
+            .build()
+            .unwrap();
+        let response = call(configuration(&file, Safelist::Enforce), request).await;
+        assert_eq!(
+            error_code(&response).as_deref(),
+            Some("PERSISTED_QUERY_NOT_IN_LIST")
+        );
+
+        let request = SupergraphRequest::fake_builder()
+            .query("query Other { me { name } }")
+            .build()
+            .unwrap();
+        let response = call(configuration(&file, Safelist::Enforce), request).await;
+        assert_eq!(
+            error_code(&response).as_deref(),
+            Some("QUERY_NOT_IN_SAFELIST")
+        );
+
+        // a free-form query from the manifest is accepted
+        let request = SupergraphRequest::fake_builder()
+            .query("query Op1 { me { id } }")
+            .build()
+            .unwrap();
+        let response = call(configuration(&file, Safelist::Enforce), request).await;
+        assert!(response.errors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_executes_unknown_operations_in_log_mode() {
+        let file = manifest_file();
+        let request = SupergraphRequest::fake_builder()
+            .query("query Other { me { name } }")
+            .build()
+            .unwrap();
+
+        let response = call(configuration(&file, Safelist::Log), request).await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data.unwrap(),
+            json!({ "query": "query Other { me { name } }" })
+        );
+    }
+}
@@ -75,3 +75,9 @@ pub(crate) const MULTIPART_DEFER_SPEC_PARAMETER: &str = "deferSpec";
 pub(crate) const MULTIPART_DEFER_SPEC_VALUE: &str = "20220824";
 pub(crate) const MULTIPART_DEFER_CONTENT_TYPE: &str =
     "multipart/mixed;boundary=\"graphql\";deferSpec=20220824";
+
+// set the supported multipart HTTP subscription specification version
+pub(crate) const MULTIPART_SUBSCRIPTION_SPEC_PARAMETER: &str = "subscriptionSpec";
+pub(crate) const MULTIPART_SUBSCRIPTION_SPEC_VALUE: &str = "1.0";
+pub(crate) const MULTIPART_SUBSCRIPTION_CONTENT_TYPE: &str =
+    "multipart/mixed;boundary=\"graphql\";subscriptionSpec=1.0";
@@ -8,6 +8,7 @@ use static_assertions::assert_impl_all;
 
 use crate::graphql;
 use crate::query_planner::QueryPlan;
+use crate::spec::operation_limits::OperationMeasures;
 use crate::Context;
 
 assert_impl_all!(Request: Send);
@@ -46,8 +47,19 @@ pub(crate) struct Response {
 /// Query, QueryPlan and Introspection data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum QueryPlannerContent {
-    Plan { plan: Arc<QueryPlan> },
-    Introspection { response: Box<graphql::Response> },
+    Plan {
+        plan: Arc<QueryPlan>,
+    },
+    Introspection {
+        response: Box<graphql::Response>,
+        /// Size of the operation, checked against the operation limits for each request
+        measures: Option<OperationMeasures>,
+    },
+    /// Response to an operation only querying `__typename` at its root, which is not subject to
+    /// the introspection access rules
+    Typename {
+        response: Box<graphql::Response>,
+    },
     IntrospectionDisabled,
 }
 
@@ -7,11 +7,13 @@ use axum::body::StreamBody;
 use axum::response::*;
 use bytes::Buf;
 use bytes::Bytes;
+use futures::future::join_all;
 use futures::future::ready;
 use futures::future::BoxFuture;
 use futures::stream;
 use futures::stream::once;
 use futures::stream::StreamExt;
+use futures::FutureExt;
 use http::header::CONTENT_TYPE;
 use http::header::VARY;
 use http::HeaderMap;
@@ -22,6 +24,7 @@ use http_body::Body as _;
 use hyper::Body;
 use mime::APPLICATION_JSON;
 use multimap::MultiMap;
+use serde::Serialize;
 use tower::BoxError;
 use tower::Layer;
 use tower::ServiceBuilder;
@@ -32,7 +35,9 @@ use super::layers::apq::APQLayer;
 use super::layers::content_negociation;
 use super::layers::content_negociation::ACCEPTS_JSON_CONTEXT_KEY;
 use super::layers::content_negociation::ACCEPTS_MULTIPART_CONTEXT_KEY;
+use super::layers::content_negociation::ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY;
 use super::layers::content_negociation::ACCEPTS_WILDCARD_CONTEXT_KEY;
+use super::layers::persisted_queries::PersistedQueryLayer;
 use super::layers::static_page::StaticPageLayer;
 use super::new_service::ServiceFactory;
 use super::router;
@@ -40,9 +45,13 @@ use super::supergraph;
 use super::HasPlugins;
 #[cfg(test)]
 use super::SupergraphCreator;
+use super::IS_SUBSCRIPTION_CONTEXT_KEY;
 use super::MULTIPART_DEFER_CONTENT_TYPE;
+use super::MULTIPART_SUBSCRIPTION_CONTENT_TYPE;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::Batching;
 use crate::graphql;
+use crate::http_server_factory::LocalAddr;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
 use crate::router_factory::RouterFactory;
@@ -52,9 +61,13 @@ use crate::services::RouterResponse;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::Configuration;
+use crate::Context;
 use crate::Endpoint;
 use crate::ListenAddr;
 
+/// Number of operations in a batched request, used by the telemetry plugin.
+pub(crate) const BATCH_SIZE_CONTEXT_KEY: &str = "apollo_router::router::batch_size";
+
 /// Containing [`Service`] in the request lifecyle.
 #[derive(Clone)]
 pub(crate) struct RouterService<SF>
@@ -62,21 +75,167 @@ where
     SF: ServiceFactory<supergraph::Request> + Clone + Send + Sync + 'static,
 {
     supergraph_creator: Arc<SF>,
+    persisted_query_layer: Option<PersistedQueryLayer>,
     apq_layer: Option<APQLayer>,
+    batching: Batching,
 }
 
 impl<SF> RouterService<SF>
 where
     SF: ServiceFactory<supergraph::Request> + Clone + Send + Sync + 'static,
 {
-    pub(crate) fn new(supergraph_creator: Arc<SF>, apq_layer: Option<APQLayer>) -> Self {
+    pub(crate) fn new(
+        supergraph_creator: Arc<SF>,
+        persisted_query_layer: Option<PersistedQueryLayer>,
+        apq_layer: Option<APQLayer>,
+        batching: Batching,
+    ) -> Self {
         RouterService {
             supergraph_creator,
+            persisted_query_layer,
             apq_layer,
+            batching,
         }
     }
 }
 
+impl<SF> RouterService<SF>
+where
+    SF: ServiceFactory<supergraph::Request> + Clone + Send + Sync + 'static,
+    <SF as ServiceFactory<supergraph::Request>>::Service:
+        Service<supergraph::Request, Response = supergraph::Response, Error = BoxError> + Send,
+    <<SF as ServiceFactory<supergraph::Request>>::Service as Service<supergraph::Request>>::Future:
+        Send,
+{
+    /// Applies persisted queries and APQ, checks that a query is present, then calls the
+    /// supergraph service.
+    async fn call_supergraph(
+        supergraph_creator: Arc<SF>,
+        persisted_queries: Option<PersistedQueryLayer>,
+        apq: Option<APQLayer>,
+        request: SupergraphRequest,
+    ) -> Result<SupergraphResponse, BoxError> {
+        let request_res = match persisted_queries {
+            None => Ok(request),
+            Some(persisted_queries) => persisted_queries.request(request),
+        };
+        let request_res = match (request_res, apq) {
+            (Ok(request), Some(apq)) => apq.request(request).await,
+            (request_res, _) => request_res,
+        };
+
+        match request_res.and_then(|request| {
+            let query = request.supergraph_request.body().query.as_ref();
+
+            if query.is_none() || query.unwrap().trim().is_empty() {
+                let errors = vec![crate::error::Error::builder()
+                    .message("Must provide query string.".to_string())
+                    .extension_code("MISSING_QUERY_STRING")
+                    .build()];
+                tracing::error!(
+                    monotonic_counter.apollo_router_http_requests_total = 1u64,
+                    status = %StatusCode::BAD_REQUEST.as_u16(),
+                    error = "Must provide query string",
+                    "Must provide query string"
+                );
+
+                Err(SupergraphResponse::builder()
+                    .errors(errors)
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .context(request.context)
+                    .build()
+                    .expect("response is valid"))
+            } else {
+                Ok(request)
+            }
+        }) {
+            Err(response) => Ok(response),
+            Ok(request) => {
+                let supergraph_service = supergraph_creator.create();
+                supergraph_service.oneshot(request).await
+            }
+        }
+    }
+
+    /// Runs every operation of a batch concurrently and returns their responses in order. An
+    /// operation failing with an error gets a GraphQL error response at its index.
+    ///
+    /// Each operation gets its own copy of the router context. Batched responses are
+    /// always serialized as a JSON array, so deferred responses and subscriptions are
+    /// rejected by the supergraph service as if the client did not accept multipart.
+    async fn call_batch(
+        supergraph_creator: Arc<SF>,
+        persisted_queries: Option<PersistedQueryLayer>,
+        apq: Option<APQLayer>,
+        parts: http::request::Parts,
+        requests: Vec<graphql::Request>,
+        context: Context,
+    ) -> Result<router::Response, BoxError> {
+        context.insert(BATCH_SIZE_CONTEXT_KEY, requests.len())?;
+
+        let responses = join_all(requests.into_iter().map(|graphql_request| {
+            let supergraph_creator = supergraph_creator.clone();
+            let persisted_queries = persisted_queries.clone();
+            let apq = apq.clone();
+            let supergraph_request = batch_element_request(&parts, graphql_request);
+            let context = context.fork();
+
+            let response = async move {
+                context.insert(ACCEPTS_JSON_CONTEXT_KEY, true)?;
+                context.insert(ACCEPTS_MULTIPART_CONTEXT_KEY, false)?;
+                context.insert(ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY, false)?;
+
+                let request = SupergraphRequest {
+                    supergraph_request,
+                    context,
+                };
+                let SupergraphResponse { response, .. } =
+                    Self::call_supergraph(supergraph_creator, persisted_queries, apq, request)
+                        .await?;
+
+                Ok::<_, BoxError>(response.into_body().next().await.unwrap_or_else(|| {
+                    graphql::Response::builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message("router service is not available to process request")
+                                .extension_code("SERVICE_UNAVAILABLE")
+                                .build(),
+                        )
+                        .build()
+                }))
+            };
+
+            // a failing operation must not fail the other operations of the batch
+            response.map(|response| {
+                response.unwrap_or_else(|error| {
+                    tracing::error!("batched operation failed: {}", error);
+                    graphql::Response::builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message(error.to_string())
+                                .extension_code("INTERNAL_SERVER_ERROR")
+                                .build(),
+                        )
+                        .build()
+                })
+            })
+        }))
+        .await;
+
+        tracing::trace_span!("serialize_response").in_scope(|| {
+            let body = serde_json::to_string(&responses)?;
+            let mut response = http::Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .body(Body::from(body))
+                .expect("cannot fail");
+            process_vary_header(response.headers_mut());
+
+            Ok(router::Response { response, context })
+        })
+    }
+}
+
 #[cfg(test)]
 pub(crate) async fn from_supergraph_mock_callback_and_configuration(
     supergraph_callback: impl FnMut(supergraph::Request) -> supergraph::ServiceResult
@@ -105,6 +264,7 @@ pub(crate) async fn from_supergraph_mock_callback_and_configuration(
         &configuration,
     )
     .await
+    .unwrap()
     .make()
 }
 
@@ -145,6 +305,7 @@ pub(crate) async fn empty() -> impl Service<
         &Configuration::default(),
     )
     .await
+    .unwrap()
     .make()
 }
 
@@ -173,92 +334,85 @@ where
         let (parts, body) = router_request.into_parts();
 
         let supergraph_creator = self.supergraph_creator.clone();
+        let persisted_queries = self.persisted_query_layer.clone();
         let apq = self.apq_layer.clone();
+        let batching = self.batching.clone();
 
         let fut = async move {
-            let graphql_request: Result<graphql::Request, (&str, String)> = if parts.method
-                == Method::GET
-            {
-                parts
-                    .uri
-                    .query()
-                    .map(|q| {
-                        graphql::Request::from_urlencoded_query(q.to_string()).map_err(|e| {
-                            (
-                                "failed to decode a valid GraphQL request from path",
-                                format!("failed to decode a valid GraphQL request from path {}", e),
-                            )
+            let graphql_request: Result<GraphQLRequests, (&str, String)> =
+                if parts.method == Method::GET {
+                    parts
+                        .uri
+                        .query()
+                        .map(|q| {
+                            graphql::Request::from_urlencoded_query(q.to_string())
+                                .map(GraphQLRequests::Single)
+                                .map_err(|e| {
+                                    (
+                                        "failed to decode a valid GraphQL request from path",
+                                        format!(
+                                            "failed to decode a valid GraphQL request from path {}",
+                                            e
+                                        ),
+                                    )
+                                })
                         })
-                    })
-                    .unwrap_or_else(|| {
-                        Err(("missing query string", "missing query string".to_string()))
-                    })
-            } else {
-                hyper::body::to_bytes(body)
-                    .await
-                    .map_err(|e| {
-                        (
-                            "failed to get the request body",
-                            format!("failed to get the request body: {}", e),
-                        )
-                    })
-                    .and_then(|bytes| {
-                        serde_json::from_reader(bytes.reader()).map_err(|err| {
+                        .unwrap_or_else(|| {
+                            Err(("missing query string", "missing query string".to_string()))
+                        })
+                } else {
+                    hyper::body::to_bytes(body)
+                        .await
+                        .map_err(|e| {
                             (
-                                "failed to deserialize the request body into JSON",
-                                format!(
-                                    "failed to deserialize the request body into JSON: {}",
-                                    err
-                                ),
+                                "failed to get the request body",
+                                format!("failed to get the request body: {}", e),
                             )
                         })
-                    })
-            };
+                        .and_then(|bytes| {
+                            let requests = if is_batch(&bytes) {
+                                serde_json::from_reader(bytes.reader()).map(GraphQLRequests::Batch)
+                            } else {
+                                serde_json::from_reader(bytes.reader()).map(GraphQLRequests::Single)
+                            };
+                            requests.map_err(|err| {
+                                (
+                                    "failed to deserialize the request body into JSON",
+                                    format!(
+                                        "failed to deserialize the request body into JSON: {}",
+                                        err
+                                    ),
+                                )
+                            })
+                        })
+                        .and_then(|requests| match requests {
+                            GraphQLRequests::Batch(batch) => check_batch(&batching, batch.len())
+                                .map(|_| GraphQLRequests::Batch(batch)),
+                            single => Ok(single),
+                        })
+                };
 
             match graphql_request {
-                Ok(graphql_request) => {
+                Ok(GraphQLRequests::Batch(requests)) => {
+                    Self::call_batch(
+                        supergraph_creator,
+                        persisted_queries,
+                        apq,
+                        parts,
+                        requests,
+                        context,
+                    )
+                    .await
+                }
+                Ok(GraphQLRequests::Single(graphql_request)) => {
                     let request = SupergraphRequest {
                         supergraph_request: http::Request::from_parts(parts, graphql_request),
                         context,
                     };
 
-                    let request_res = match apq {
-                        None => Ok(request),
-                        Some(apq) => apq.request(request).await,
-                    };
-
                     let SupergraphResponse { response, context } =
-                        match request_res.and_then(|request| {
-                            let query = request.supergraph_request.body().query.as_ref();
-
-                            if query.is_none() || query.unwrap().trim().is_empty() {
-                                let errors = vec![crate::error::Error::builder()
-                                    .message("Must provide query string.".to_string())
-                                    .extension_code("MISSING_QUERY_STRING")
-                                    .build()];
-                                tracing::error!(
-                                    monotonic_counter.apollo_router_http_requests_total = 1u64,
-                                    status = %StatusCode::BAD_REQUEST.as_u16(),
-                                    error = "Must provide query string",
-                                    "Must provide query string"
-                                );
-
-                                Err(SupergraphResponse::builder()
-                                    .errors(errors)
-                                    .status_code(StatusCode::BAD_REQUEST)
-                                    .context(request.context)
-                                    .build()
-                                    .expect("response is valid"))
-                            } else {
-                                Ok(request)
-                            }
-                        }) {
-                            Err(response) => response,
-                            Ok(request) => {
-                                let supergraph_service = supergraph_creator.create();
-                                supergraph_service.oneshot(request).await?
-                            }
-                        };
+                        Self::call_supergraph(supergraph_creator, persisted_queries, apq, request)
+                            .await?;
 
                     let accepts_wildcard: bool = context
                         .get(ACCEPTS_WILDCARD_CONTEXT_KEY)
@@ -272,6 +426,14 @@ where
                         .get(ACCEPTS_MULTIPART_CONTEXT_KEY)
                         .unwrap_or_default()
                         .unwrap_or_default();
+                    let accepts_multipart_subscription: bool = context
+                        .get(ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY)
+                        .unwrap_or_default()
+                        .unwrap_or_default();
+                    let is_subscription: bool = context
+                        .get(IS_SUBSCRIPTION_CONTEXT_KEY)
+                        .unwrap_or_default()
+                        .unwrap_or_default();
 
                     let (mut parts, mut body) = response.into_parts();
                     process_vary_header(&mut parts.headers);
@@ -307,10 +469,16 @@ where
                                         context,
                                     })
                                 })
-                            } else if accepts_multipart {
+                            } else if accepts_multipart
+                                || (is_subscription && accepts_multipart_subscription)
+                            {
                                 parts.headers.insert(
                                     CONTENT_TYPE,
-                                    HeaderValue::from_static(MULTIPART_DEFER_CONTENT_TYPE),
+                                    HeaderValue::from_static(if is_subscription {
+                                        MULTIPART_SUBSCRIPTION_CONTENT_TYPE
+                                    } else {
+                                        MULTIPART_DEFER_CONTENT_TYPE
+                                    }),
                                 );
 
                                 // each chunk contains a response and the next delimiter, to let client parsers
@@ -318,7 +486,7 @@ where
                                 let mut first_buf = Vec::from(
                                     &b"\r\n--graphql\r\ncontent-type: application/json\r\n\r\n"[..],
                                 );
-                                serde_json::to_writer(&mut first_buf, &response)?;
+                                write_multipart_part(&mut first_buf, &response, is_subscription)?;
                                 if response.has_next.unwrap_or(false) {
                                     first_buf.extend_from_slice(b"\r\n--graphql\r\n");
                                 } else {
@@ -330,7 +498,7 @@ where
                                         let mut buf = Vec::from(
                                             &b"content-type: application/json\r\n\r\n"[..],
                                         );
-                                        serde_json::to_writer(&mut buf, &res)?;
+                                        write_multipart_part(&mut buf, &res, is_subscription)?;
 
                                         // the last chunk has a different end delimiter
                                         if res.has_next.unwrap_or(false) {
@@ -423,6 +591,87 @@ where
     }
 }
 
+/// The operations contained in a router request.
+enum GraphQLRequests {
+    Single(graphql::Request),
+    Batch(Vec<graphql::Request>),
+}
+
+// A request body containing a JSON array is a batch of operations
+fn is_batch(body: &[u8]) -> bool {
+    body.iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .map(|byte| *byte == b'[')
+        .unwrap_or(false)
+}
+
+fn check_batch(batching: &Batching, size: usize) -> Result<(), (&'static str, String)> {
+    if !batching.enabled {
+        return Err((
+            "batched requests are not enabled",
+            "batched requests are not enabled".to_string(),
+        ));
+    }
+    if size == 0 {
+        return Err((
+            "empty batch",
+            "a batch must contain at least one operation".to_string(),
+        ));
+    }
+    match batching.max_size {
+        Some(max_size) if size > max_size => Err((
+            "batch size limit exceeded",
+            format!(
+                "the batch contains {} operations, the maximum allowed is {}",
+                size, max_size
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// The parts of the router request are not cloneable, so each operation of a batch
+// gets a copy of the method, URI, version and headers
+fn batch_element_request(
+    parts: &http::request::Parts,
+    body: graphql::Request,
+) -> http::Request<graphql::Request> {
+    let mut request = http::Request::new(body);
+    *request.method_mut() = parts.method.clone();
+    *request.uri_mut() = parts.uri.clone();
+    *request.version_mut() = parts.version;
+    *request.headers_mut() = parts.headers.clone();
+    if let Some(local_addr) = parts.extensions.get::<LocalAddr>() {
+        request.extensions_mut().insert(local_addr.clone());
+    }
+    request
+}
+
+#[derive(Serialize)]
+struct SubscriptionPayload<'a> {
+    payload: &'a graphql::Response,
+}
+
+// Subscription events are wrapped in a `payload` field as described in the
+// multipart subscription protocol, and the end of the stream is sent as an
+// empty object
+fn write_multipart_part(
+    buf: &mut Vec<u8>,
+    response: &graphql::Response,
+    is_subscription: bool,
+) -> Result<(), serde_json::Error> {
+    if !is_subscription {
+        return serde_json::to_writer(buf, response);
+    }
+
+    if response.has_next == Some(false) && response.data.is_none() && response.errors.is_empty() {
+        buf.extend_from_slice(b"{}");
+        Ok(())
+    } else {
+        serde_json::to_writer(buf, &SubscriptionPayload { payload: response })
+    }
+}
+
 // Process the headers to make sure that `VARY` is set correctly
 fn process_vary_header(headers: &mut HeaderMap<HeaderValue>) {
     if headers.get(VARY).is_none() {
@@ -439,7 +688,9 @@ where
 {
     supergraph_creator: Arc<SF>,
     static_page: StaticPageLayer,
+    persisted_query_layer: Option<PersistedQueryLayer>,
     apq_layer: Option<APQLayer>,
+    batching: Batching,
 }
 
 impl<SF> ServiceFactory<router::Request> for RouterCreator<SF>
@@ -488,8 +739,13 @@ where
     <<SF as ServiceFactory<supergraph::Request>>::Service as Service<supergraph::Request>>::Future:
         Send,
 {
-    pub(crate) async fn new(supergraph_creator: Arc<SF>, configuration: &Configuration) -> Self {
+    pub(crate) async fn new(
+        supergraph_creator: Arc<SF>,
+        configuration: &Configuration,
+    ) -> Result<Self, BoxError> {
         let static_page = StaticPageLayer::new(configuration);
+        let persisted_query_layer =
+            PersistedQueryLayer::new(&configuration.supergraph.persisted_queries).await?;
         let apq_layer = if configuration.supergraph.apq.enabled {
             Some(APQLayer::with_cache(
                 DeduplicatingCache::from_configuration(
@@ -502,11 +758,13 @@ where
             None
         };
 
-        Self {
+        Ok(Self {
             supergraph_creator,
             static_page,
+            persisted_query_layer,
             apq_layer,
-        }
+            batching: configuration.batching.clone(),
+        })
     }
 
     pub(crate) fn make(
@@ -519,7 +777,9 @@ where
     > + Send {
         let router_service = content_negociation::RouterLayer::default().layer(RouterService::new(
             self.supergraph_creator.clone(),
+            self.persisted_query_layer.clone(),
             self.apq_layer.clone(),
+            self.batching.clone(),
         ));
 
         ServiceBuilder::new()
@@ -535,9 +795,24 @@ where
 }
 
 impl RouterCreator<crate::services::supergraph_service::SupergraphCreator> {
+    pub(crate) fn supergraph_creator(
+        &self,
+    ) -> &crate::services::supergraph_service::SupergraphCreator {
+        &self.supergraph_creator
+    }
+
     pub(crate) async fn cache_keys(&self, count: usize) -> Vec<(String, Option<String>)> {
         self.supergraph_creator.cache_keys(count).await
     }
+
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn expire_query_plans(
+        &self,
+        next: &crate::services::supergraph_service::SupergraphCreator,
+        ttl: std::time::Duration,
+    ) {
+        self.supergraph_creator.expire_query_plans(next, ttl)
+    }
 }
 
 #[cfg(test)]
@@ -707,4 +982,177 @@ mod tests {
         assert_eq!(expected_error, actual_error);
         assert!(response.errors[0].extensions.contains_key("code"));
     }
+
+    fn batch_request(body: &'static str) -> router::Request {
+        router::Request {
+            router_request: http::Request::builder()
+                .method(Method::POST)
+                .uri(Uri::from_static("/"))
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .body(Body::from(body))
+                .unwrap(),
+            context: Context::new(),
+        }
+    }
+
+    fn batching_configuration(max_size: Option<usize>) -> Arc<Configuration> {
+        Arc::new(
+            Configuration::fake_builder()
+                .batching(
+                    Batching::builder()
+                        .enabled(true)
+                        .and_max_size(max_size)
+                        .build(),
+                )
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_processes_batched_requests() {
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                let query = req.supergraph_request.body().query.clone().unwrap();
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "query": query }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            batching_configuration(None),
+        )
+        .await;
+
+        let response = router_service
+            .oneshot(batch_request(
+                r#"[{"query":"{ a }"},{"query":"{ b }"},{"query":"{ c }"}]"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::OK);
+        assert_eq!(
+            response.response.headers().get(CONTENT_TYPE).unwrap(),
+            APPLICATION_JSON.essence_str()
+        );
+        assert_eq!(
+            response
+                .context
+                .get::<_, usize>(BATCH_SIZE_CONTEXT_KEY)
+                .unwrap(),
+            Some(3)
+        );
+        let body = hyper::body::to_bytes(response.response.into_body())
+            .await
+            .unwrap();
+        let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            responses,
+            vec![
+                graphql::Response::builder()
+                    .data(json!({ "query": "{ a }" }))
+                    .build(),
+                graphql::Response::builder()
+                    .data(json!({ "query": "{ b }" }))
+                    .build(),
+                graphql::Response::builder()
+                    .data(json!({ "query": "{ c }" }))
+                    .build(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_returns_an_error_response_for_a_failing_batched_operation() {
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                let query = req.supergraph_request.body().query.clone().unwrap();
+                if query == "{ b }" {
+                    return Err("subgraph unavailable".into());
+                }
+                Ok(SupergraphResponse::new_from_graphql_response(
+                    graphql::Response::builder()
+                        .data(json!({ "query": query }))
+                        .build(),
+                    req.context,
+                ))
+            },
+            batching_configuration(None),
+        )
+        .await;
+
+        let response = router_service
+            .oneshot(batch_request(
+                r#"[{"query":"{ a }"},{"query":"{ b }"},{"query":"{ c }"}]"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.response.into_body())
+            .await
+            .unwrap();
+        let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            responses,
+            vec![
+                graphql::Response::builder()
+                    .data(json!({ "query": "{ a }" }))
+                    .build(),
+                graphql::Response::builder()
+                    .error(
+                        graphql::Error::builder()
+                            .message("subgraph unavailable")
+                            .extension_code("INTERNAL_SERVER_ERROR")
+                            .build()
+                    )
+                    .build(),
+                graphql::Response::builder()
+                    .data(json!({ "query": "{ c }" }))
+                    .build(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_batched_requests_when_disabled() {
+        let router_service = from_supergraph_mock_callback(move |_req| unreachable!()).await;
+
+        let response = router_service
+            .oneshot(batch_request(r#"[{"query":"{ a }"}]"#))
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_rejects_batches_over_the_maximum_size() {
+        let router_service = from_supergraph_mock_callback_and_configuration(
+            move |_req| unreachable!(),
+            batching_configuration(Some(2)),
+        )
+        .await;
+
+        let response = router_service
+            .oneshot(batch_request(
+                r#"[{"query":"{ a }"},{"query":"{ b }"},{"query":"{ c }"}]"#,
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.response.into_body())
+            .await
+            .unwrap();
+        let error: graphql::Error = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            error.extensions.get("details"),
+            Some(&json!(
+                "the batch contains 3 operations, the maximum allowed is 2"
+            ))
+        );
+    }
 }
@@ -2,6 +2,7 @@
 
 use std::sync::Arc;
 
+use futures::channel::mpsc;
 use http::StatusCode;
 use serde_json_bytes::ByteString;
 use serde_json_bytes::Map as JsonMap;
@@ -31,6 +32,10 @@ pub struct Request {
     pub operation_kind: OperationKind,
 
     pub context: Context,
+
+    /// Channel used to hand the event stream back to the execution service
+    /// when `operation_kind` is a subscription.
+    pub subscription_stream: Option<mpsc::Sender<graphql::ResponseStream>>,
 }
 
 #[buildstructor::buildstructor]
@@ -44,12 +49,14 @@ impl Request {
         subgraph_request: http::Request<graphql::Request>,
         operation_kind: OperationKind,
         context: Context,
+        subscription_stream: Option<mpsc::Sender<graphql::ResponseStream>>,
     ) -> Request {
         Self {
             supergraph_request,
             subgraph_request,
             operation_kind,
             context,
+            subscription_stream,
         }
     }
 
@@ -64,12 +71,14 @@ impl Request {
         subgraph_request: Option<http::Request<graphql::Request>>,
         operation_kind: Option<OperationKind>,
         context: Option<Context>,
+        subscription_stream: Option<mpsc::Sender<graphql::ResponseStream>>,
     ) -> Request {
         Request::new(
             supergraph_request.unwrap_or_default(),
             subgraph_request.unwrap_or_default(),
             operation_kind.unwrap_or(OperationKind::Query),
             context.unwrap_or_default(),
+            subscription_stream,
         )
     }
 }
@@ -99,6 +108,7 @@ impl Clone for Request {
             subgraph_request,
             operation_kind: self.operation_kind,
             context: self.context.clone(),
+            subscription_stream: self.subscription_stream.clone(),
         }
     }
 }
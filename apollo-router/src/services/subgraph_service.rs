@@ -2,32 +2,55 @@
 
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::Relaxed;
 use std::sync::Arc;
 use std::task::Poll;
+use std::time::Duration;
 
 use ::serde::Deserialize;
 use async_compression::tokio::write::BrotliEncoder;
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::tokio::write::ZlibEncoder;
+use bytes::Bytes;
 use futures::future::BoxFuture;
+use futures::SinkExt;
+use futures::StreamExt;
 use global::get_text_map_propagator;
 use http::header::ACCEPT;
 use http::header::CONTENT_ENCODING;
 use http::header::CONTENT_TYPE;
+use http::header::HOST;
 use http::header::{self};
+use http::uri::InvalidUri;
 use http::HeaderMap;
 use http::HeaderValue;
+use http::Uri;
+use http_body::Body as _;
+use hyper::client::connect::Connected;
+use hyper::client::connect::Connection;
 use hyper::client::HttpConnector;
 use hyper::Client;
 use hyper_rustls::ConfigBuilderExt;
 use hyper_rustls::HttpsConnector;
+use hyper_rustls::MaybeHttpsStream;
 use mime::APPLICATION_JSON;
 use opentelemetry::global;
+use rustls::Certificate;
+use rustls::ClientConfig;
+use rustls::PrivateKey;
 use rustls::RootCertStore;
 use schemars::JsonSchema;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
+use tokio::io::ReadBuf;
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tower::util::BoxService;
 use tower::BoxError;
 use tower::Service;
@@ -40,13 +63,17 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use super::layers::content_negociation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
 use super::Plugins;
+use crate::configuration::ConfigurationError;
 use crate::error::FetchError;
 use crate::graphql;
+use crate::json_ext::Value;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
+use crate::plugins::traffic_shaping::Merge;
 use crate::services::layers::apq;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
+use crate::services::MULTIPART_SUBSCRIPTION_CONTENT_TYPE;
 use crate::Context;
 
 const PERSISTED_QUERY_NOT_FOUND_EXTENSION_CODE: &str = "PERSISTED_QUERY_NOT_FOUND";
@@ -58,6 +85,7 @@ const PERSISTED_QUERY_KEY: &str = "persistedQuery";
 const HASH_VERSION_KEY: &str = "version";
 const HASH_VERSION_VALUE: i32 = 1;
 const HASH_KEY: &str = "sha256Hash";
+const UNIX_SCHEME: &str = "unix";
 
 enum APQError {
     PersistedQueryNotSupported,
@@ -76,6 +104,66 @@ pub(crate) enum Compression {
     Br,
 }
 
+/// HTTP client options for the requests to a subgraph
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpClientConfig {
+    /// Maximum number of idle connections kept open to each host. Unlimited by default
+    pool_max_idle_per_host: Option<usize>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// How long an idle connection is kept open. The default value is 90 seconds
+    pool_idle_timeout: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Maximum duration to establish a connection. Unlimited by default
+    connect_timeout: Option<Duration>,
+    /// Only use HTTP/2. Plaintext subgraphs are sent HTTP/2 requests without upgrade (h2c),
+    /// and TLS connections only negotiate HTTP/2. Disabled by default
+    http2_only: Option<bool>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Interval between HTTP/2 keepalive pings. Disabled by default
+    http2_keep_alive_interval: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// How long to wait for the acknowledgement of a keepalive ping before closing the
+    /// connection. The default value is 20 seconds
+    http2_keep_alive_timeout: Option<Duration>,
+    /// Initial HTTP/2 window size of the streams, in bytes
+    http2_initial_stream_window_size: Option<u32>,
+    /// Initial HTTP/2 window size of the connections, in bytes
+    http2_initial_connection_window_size: Option<u32>,
+}
+
+impl Merge for HttpClientConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => HttpClientConfig {
+                pool_max_idle_per_host: self
+                    .pool_max_idle_per_host
+                    .or(fallback.pool_max_idle_per_host),
+                pool_idle_timeout: self.pool_idle_timeout.or(fallback.pool_idle_timeout),
+                connect_timeout: self.connect_timeout.or(fallback.connect_timeout),
+                http2_only: self.http2_only.or(fallback.http2_only),
+                http2_keep_alive_interval: self
+                    .http2_keep_alive_interval
+                    .or(fallback.http2_keep_alive_interval),
+                http2_keep_alive_timeout: self
+                    .http2_keep_alive_timeout
+                    .or(fallback.http2_keep_alive_timeout),
+                http2_initial_stream_window_size: self
+                    .http2_initial_stream_window_size
+                    .or(fallback.http2_initial_stream_window_size),
+                http2_initial_connection_window_size: self
+                    .http2_initial_connection_window_size
+                    .or(fallback.http2_initial_connection_window_size),
+            },
+        }
+    }
+}
+
 impl Display for Compression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -92,7 +180,7 @@ pub(crate) struct SubgraphService {
     // Note: We use hyper::Client here in preference to reqwest to avoid expensive URL translation
     // in the hot path. We use reqwest elsewhere because it's convenient and some of the
     // opentelemetry crate require reqwest clients to work correctly (at time of writing).
-    client: Decompression<hyper::Client<HttpsConnector<HttpConnector>>>,
+    client: Decompression<hyper::Client<MeteredConnector>>,
     service: Arc<String>,
 
     /// Whether apq is enabled in the router for subgraph calls
@@ -108,39 +196,299 @@ impl SubgraphService {
     pub(crate) fn new(
         service: impl Into<String>,
         apq_enabled: Option<bool>,
-        tls_cert_store: Option<RootCertStore>,
+        tls_config: Option<ClientConfig>,
+        http_client: Option<HttpClientConfig>,
     ) -> Self {
+        let service = Arc::new(service.into());
+        let http_client = http_client.unwrap_or_default();
+        let http2_only = http_client.http2_only.unwrap_or_default();
+
         let mut http_connector = HttpConnector::new();
         http_connector.set_nodelay(true);
         http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
+        http_connector.set_connect_timeout(http_client.connect_timeout);
         http_connector.enforce_http(false);
-        let tls_config = match tls_cert_store {
-            None => rustls::ClientConfig::builder()
+        let tls_config = tls_config.unwrap_or_else(|| {
+            ClientConfig::builder()
                 .with_safe_defaults()
                 .with_native_roots()
-                .with_no_client_auth(),
-            Some(store) => rustls::ClientConfig::builder()
-                .with_safe_defaults()
-                .with_root_certificates(store)
-                .with_no_client_auth(),
-        };
-        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+                .with_no_client_auth()
+        });
+        let connector_builder = hyper_rustls::HttpsConnectorBuilder::new()
             .with_tls_config(tls_config)
-            .https_or_http()
-            .enable_http1()
-            .enable_http2()
-            .wrap_connector(http_connector);
+            .https_or_http();
+        let https_connector = if http2_only {
+            connector_builder
+                .enable_http2()
+                .wrap_connector(http_connector)
+        } else {
+            connector_builder
+                .enable_http1()
+                .enable_http2()
+                .wrap_connector(http_connector)
+        };
+        let connector = MeteredConnector {
+            https: https_connector,
+            subgraph: service.clone(),
+        };
+
+        let mut client_builder = hyper::Client::builder();
+        client_builder
+            .http2_only(http2_only)
+            .http2_keep_alive_interval(http_client.http2_keep_alive_interval)
+            .http2_initial_stream_window_size(http_client.http2_initial_stream_window_size)
+            .http2_initial_connection_window_size(http_client.http2_initial_connection_window_size);
+        if let Some(max_idle) = http_client.pool_max_idle_per_host {
+            client_builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = http_client.pool_idle_timeout {
+            client_builder.pool_idle_timeout(idle_timeout);
+        }
+        if let Some(keep_alive_timeout) = http_client.http2_keep_alive_timeout {
+            client_builder.http2_keep_alive_timeout(keep_alive_timeout);
+        }
 
         Self {
             client: ServiceBuilder::new()
                 .layer(DecompressionLayer::new())
-                .service(hyper::Client::builder().build(connector)),
-            service: Arc::new(service.into()),
+                .service(client_builder.build(connector)),
+            service,
             apq: Arc::new(<AtomicBool>::new(apq_enabled.unwrap_or(true))),
         }
     }
 }
 
+/// Parses the URL of a subgraph.
+///
+/// Besides HTTP and HTTPS URLs, subgraphs listening on a Unix domain socket are reached with
+/// `unix://` URLs: the path of the socket, optionally followed by a colon and the HTTP path of
+/// the requests, like `unix:///var/run/products.sock:/graphql`. As a [`Uri`] needs an authority,
+/// the path of the socket is hex encoded in it. Unix domain sockets are only supported on Unix
+/// platforms.
+pub(crate) fn parse_subgraph_url(url: &str) -> Result<Uri, ConfigurationError> {
+    let invalid_url = |err: InvalidUri| ConfigurationError::InvalidConfiguration {
+        message: "invalid subgraph URL",
+        error: format!("{url}: {err}"),
+    };
+    let socket = match url.strip_prefix("unix://") {
+        Some(socket) => socket,
+        None => return Uri::from_str(url).map_err(invalid_url),
+    };
+    if cfg!(not(unix)) {
+        return Err(ConfigurationError::InvalidConfiguration {
+            message: "unsupported subgraph URL",
+            error: format!("{url}: Unix domain sockets are only supported on Unix platforms"),
+        });
+    }
+    let (socket_path, http_path) = socket.split_once(':').unwrap_or((socket, "/"));
+    let separator = if http_path.starts_with('/') { "" } else { "/" };
+    Uri::from_str(&format!(
+        "{UNIX_SCHEME}://{}{separator}{http_path}",
+        hex::encode(socket_path)
+    ))
+    .map_err(invalid_url)
+}
+
+/// Path of the Unix domain socket of a subgraph URL parsed by [`parse_subgraph_url`]
+fn unix_socket_path(uri: &Uri) -> Option<PathBuf> {
+    if uri.scheme_str() != Some(UNIX_SCHEME) {
+        return None;
+    }
+    let socket_path = hex::decode(uri.host()?).ok()?;
+    String::from_utf8(socket_path).ok().map(PathBuf::from)
+}
+
+/// Connector to the subgraphs, over TCP with or without TLS, or over a Unix domain socket.
+/// It records the connections opened to a subgraph in the metrics
+#[derive(Clone)]
+struct MeteredConnector {
+    https: HttpsConnector<HttpConnector>,
+    subgraph: Arc<String>,
+}
+
+impl Service<Uri> for MeteredConnector {
+    type Response = MeteredStream;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.https.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting: BoxFuture<'static, Result<SubgraphStream, BoxError>> =
+            match unix_socket_path(&uri) {
+                #[cfg(unix)]
+                Some(socket_path) => Box::pin(async move {
+                    Ok(SubgraphStream::Unix(
+                        UnixStream::connect(socket_path).await?,
+                    ))
+                }),
+                _ => {
+                    let connecting = self.https.call(uri);
+                    Box::pin(async move { Ok(SubgraphStream::Http(connecting.await?)) })
+                }
+            };
+        let subgraph = self.subgraph.clone();
+        Box::pin(async move {
+            let stream = match connecting.await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    // This is a metric and will not appear in the logs
+                    tracing::info!(
+                        monotonic_counter.apollo_router_subgraph_connection_errors = 1u64,
+                        subgraph = %subgraph,
+                    );
+                    return Err(e);
+                }
+            };
+            // This is a metric and will not appear in the logs
+            tracing::info!(
+                monotonic_counter.apollo_router_subgraph_connections_created = 1u64,
+                subgraph = %subgraph,
+            );
+            // This is a metric and will not appear in the logs
+            tracing::info!(
+                counter.apollo_router_subgraph_connections_open = 1i64,
+                subgraph = %subgraph,
+            );
+            Ok(MeteredStream { stream, subgraph })
+        })
+    }
+}
+
+enum SubgraphStream {
+    Http(MaybeHttpsStream<TcpStream>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// Connection to a subgraph, counted in the open connections until it is dropped
+struct MeteredStream {
+    stream: SubgraphStream,
+    subgraph: Arc<String>,
+}
+
+impl Drop for MeteredStream {
+    fn drop(&mut self) {
+        // This is a metric and will not appear in the logs
+        tracing::info!(
+            counter.apollo_router_subgraph_connections_open = -1i64,
+            subgraph = %self.subgraph,
+        );
+    }
+}
+
+impl Connection for MeteredStream {
+    fn connected(&self) -> Connected {
+        match &self.stream {
+            SubgraphStream::Http(stream) => stream.connected(),
+            #[cfg(unix)]
+            SubgraphStream::Unix(_) => Connected::new(),
+        }
+    }
+}
+
+impl AsyncRead for MeteredStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match &mut self.stream {
+            SubgraphStream::Http(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MeteredStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match &mut self.stream {
+            SubgraphStream::Http(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        match &mut self.stream {
+            SubgraphStream::Http(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match &self.stream {
+            SubgraphStream::Http(stream) => stream.is_write_vectored(),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => stream.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match &mut self.stream {
+            SubgraphStream::Http(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match &mut self.stream {
+            SubgraphStream::Http(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            SubgraphStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+/// A client certificate chain and its private key, used to authenticate to subgraphs.
+#[derive(Clone)]
+pub(crate) struct ClientCertificate {
+    pub(crate) certificate_chain: Vec<Certificate>,
+    pub(crate) key: PrivateKey,
+}
+
+/// Creates the TLS configuration used to connect to a subgraph.
+///
+/// Without a certificate store, server certificates are verified with the system's
+/// certificate authorities.
+pub(crate) fn generate_tls_client_config(
+    tls_cert_store: Option<RootCertStore>,
+    client_certificate: Option<ClientCertificate>,
+) -> Result<ClientConfig, rustls::Error> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let builder = match tls_cert_store {
+        None => builder.with_native_roots(),
+        Some(store) => builder.with_root_certificates(store),
+    };
+
+    match client_certificate {
+        None => Ok(builder.with_no_client_auth()),
+        Some(ClientCertificate {
+            certificate_chain,
+            key,
+        }) => builder.with_single_cert(certificate_chain, key),
+    }
+}
+
 impl tower::Service<SubgraphRequest> for SubgraphService {
     type Response = SubgraphResponse;
     type Error = BoxError;
@@ -238,11 +586,13 @@ async fn call_http(
     request: SubgraphRequest,
     body: graphql::Request,
     context: Context,
-    mut client: Decompression<Client<HttpsConnector<HttpConnector>>>,
+    mut client: Decompression<Client<MeteredConnector>>,
     service_name: String,
 ) -> Result<SubgraphResponse, BoxError> {
     let SubgraphRequest {
-        subgraph_request, ..
+        subgraph_request,
+        subscription_stream,
+        ..
     } = request;
 
     let (parts, _) = subgraph_request.into_parts();
@@ -265,11 +615,29 @@ async fn call_http(
     let app_graphql_json: HeaderValue =
         HeaderValue::from_static(GRAPHQL_JSON_RESPONSE_HEADER_VALUE);
     request.headers_mut().insert(CONTENT_TYPE, app_json.clone());
-    request.headers_mut().insert(ACCEPT, app_json);
+    if subscription_stream.is_some() {
+        request.headers_mut().insert(
+            ACCEPT,
+            HeaderValue::from_static(MULTIPART_SUBSCRIPTION_CONTENT_TYPE),
+        );
+        request.headers_mut().append(ACCEPT, app_json);
+    } else {
+        request.headers_mut().insert(ACCEPT, app_json);
+    }
     request.headers_mut().append(ACCEPT, app_graphql_json);
 
     let schema_uri = request.uri().clone();
-    let host = schema_uri.host().map(String::from).unwrap_or_default();
+    let socket_path = unix_socket_path(&schema_uri);
+    if socket_path.is_some() {
+        // the authority of the URI is the encoded path of the socket, not a host name
+        request
+            .headers_mut()
+            .insert(HOST, HeaderValue::from_static("localhost"));
+    }
+    let host = match &socket_path {
+        Some(socket_path) => socket_path.display().to_string(),
+        None => schema_uri.host().map(String::from).unwrap_or_default(),
+    };
     let port = schema_uri.port_u16().unwrap_or_else(|| {
         let scheme = schema_uri.scheme_str();
         if scheme == Some("https") {
@@ -297,7 +665,7 @@ async fn call_http(
         "net.peer.port" = &display(port),
         "http.route" = &display(path),
         "http.url" = &display(schema_uri),
-        "net.transport" = "ip_tcp",
+        "net.transport" = if socket_path.is_some() { "unix" } else { "ip_tcp" },
         "apollo.subgraph.name" = %service_name
     );
     get_text_map_propagator(|propagator| {
@@ -332,6 +700,30 @@ async fn call_http(
                         http.response.headers = ?parts.headers, apollo.subgraph.name = %service_name, "Response headers from subgraph {service_name:?}"
                     );
         }
+        if let Some(mut stream_sender) = subscription_stream {
+            let boundary = parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|content_type| content_type.to_str().ok())
+                .and_then(|content_type| multer::parse_boundary(content_type).ok());
+
+            if let Some(boundary) = boundary {
+                cloned_context.leave_active_request().await;
+
+                let events = subscription_events(service_name.clone(), body, boundary);
+                if let Err(err) = stream_sender.send(events).await {
+                    tracing::error!(fetch_error = format!("{err:?}").as_str());
+
+                    return Err(FetchError::SubrequestHttpError {
+                        service: service_name.clone(),
+                        reason: err.to_string(),
+                    }.into());
+                }
+
+                return Ok((parts, None));
+            }
+        }
+
         if let Some(content_type) = parts.headers.get(header::CONTENT_TYPE) {
             if let Ok(content_type_str) = content_type.to_str() {
                 // Using .contains because sometimes we could have charset included (example: "application/json; charset=utf-8")
@@ -378,9 +770,20 @@ async fn call_http(
 
             cloned_context.leave_active_request().await;
 
-        Ok((parts, body))
+        Ok((parts, Some(body)))
     }.instrument(subgraph_req_span).await?;
 
+    // the subscription events were handed over to the execution service
+    let body = match body {
+        Some(body) => body,
+        None => {
+            return Ok(SubgraphResponse::new_from_response(
+                http::Response::from_parts(parts, graphql::Response::default()),
+                context,
+            ))
+        }
+    };
+
     if display_body {
         tracing::info!(
             http.response.body = %String::from_utf8_lossy(&body), apollo.subgraph.name = %cloned_service_name, "Raw response body from subgraph {cloned_service_name:?} received"
@@ -402,6 +805,106 @@ async fn call_http(
     Ok(SubgraphResponse::new_from_response(resp, context))
 }
 
+/// Turns a multipart subscription response body into a stream of GraphQL responses.
+///
+/// Each part contains either an event in a `payload` field, an empty object used as
+/// heartbeat, or transport level `errors` that end the subscription.
+fn subscription_events<B>(
+    service_name: String,
+    body: B,
+    boundary: String,
+) -> graphql::ResponseStream
+where
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let mut body = Box::pin(body);
+    let chunks = futures::stream::poll_fn(move |cx| {
+        body.as_mut()
+            .poll_data(cx)
+            .map(|chunk| chunk.map(|res| res.map_err(Into::<BoxError>::into)))
+    });
+    let multipart = multer::Multipart::new(chunks, boundary);
+
+    futures::stream::unfold(Some(multipart), move |multipart| {
+        let service_name = service_name.clone();
+        async move {
+            let mut multipart = multipart?;
+            loop {
+                let bytes = match multipart.next_field().await {
+                    Ok(Some(field)) => field.bytes().await,
+                    Ok(None) => return None,
+                    Err(err) => Err(err),
+                };
+                let part = bytes
+                    .map_err(|err| FetchError::SubrequestMalformedResponse {
+                        service: service_name.clone(),
+                        reason: err.to_string(),
+                    })
+                    .and_then(|bytes| parse_subscription_part(&service_name, bytes));
+
+                match part {
+                    Ok(SubscriptionPart::Heartbeat) => continue,
+                    Ok(SubscriptionPart::Event(response)) => {
+                        return Some((response, Some(multipart)))
+                    }
+                    Ok(SubscriptionPart::Close(response)) => return Some((response, None)),
+                    Err(err) => {
+                        tracing::error!(fetch_error = format!("{err:?}").as_str());
+                        return Some((err.to_response(), None));
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+enum SubscriptionPart {
+    Heartbeat,
+    Event(graphql::Response),
+    Close(graphql::Response),
+}
+
+fn parse_subscription_part(
+    service_name: &str,
+    bytes: Bytes,
+) -> Result<SubscriptionPart, FetchError> {
+    let value =
+        Value::from_bytes(bytes).map_err(|error| FetchError::SubrequestMalformedResponse {
+            service: service_name.to_string(),
+            reason: error.to_string(),
+        })?;
+    let mut object = match value {
+        Value::Object(object) => object,
+        _ => {
+            return Err(FetchError::SubrequestMalformedResponse {
+                service: service_name.to_string(),
+                reason: "subscription parts must be JSON objects".to_string(),
+            })
+        }
+    };
+
+    match object.remove("payload") {
+        Some(payload) if !payload.is_null() => Ok(SubscriptionPart::Event(
+            graphql::Response::from_value(service_name, payload)?,
+        )),
+        _ => match object.remove("errors") {
+            Some(Value::Array(errors)) => Ok(SubscriptionPart::Close(
+                graphql::Response::builder()
+                    .errors(
+                        errors
+                            .into_iter()
+                            .map(|error| graphql::Error::from_value(service_name, error))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )
+                    .build(),
+            )),
+            _ => Ok(SubscriptionPart::Heartbeat),
+        },
+    }
+}
+
 fn get_apq_error(gql_response: &graphql::Response) -> APQError {
     for error in &gql_response.errors {
         // Check if error message is an APQ error
@@ -959,11 +1462,98 @@ mod tests {
         server.await.unwrap();
     }
 
+    // starts a local server emulating a subgraph only accepting HTTP/2 requests without TLS
+    async fn emulate_h2c_subgraph(socket_addr: SocketAddr) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            assert_eq!(request.version(), http::Version::HTTP_2);
+
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .status(StatusCode::OK)
+                .body(
+                    serde_json::to_string(&Response {
+                        data: Some(Value::String(ByteString::from("test"))),
+                        ..Response::default()
+                    })
+                    .expect("always valid")
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).http2_only(true).serve(make_svc);
+        server.await.unwrap();
+    }
+
+    // starts a local server emulating a subgraph listening on a Unix domain socket
+    #[cfg(unix)]
+    async fn emulate_unix_socket_subgraph(listener: tokio::net::UnixListener) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            assert_eq!(request.uri().path(), "/graphql");
+            assert_eq!(request.headers().get(HOST).unwrap(), "localhost");
+
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .status(StatusCode::OK)
+                .body(
+                    serde_json::to_string(&Response {
+                        data: Some(Value::String(ByteString::from("test"))),
+                        ..Response::default()
+                    })
+                    .expect("always valid")
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let incoming = hyper::server::accept::from_stream(
+            tokio_stream::wrappers::UnixListenerStream::new(listener),
+        );
+        let server = Server::builder(incoming).serve(make_svc);
+        server.await.unwrap();
+    }
+
+    // starts a local server emulating a subgraph sending subscription events over multipart HTTP
+    async fn emulate_subgraph_subscription(socket_addr: SocketAddr) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            assert!(request
+                .headers()
+                .get_all(ACCEPT)
+                .iter()
+                .any(|value| value == MULTIPART_SUBSCRIPTION_CONTENT_TYPE));
+
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, MULTIPART_SUBSCRIPTION_CONTENT_TYPE)
+                .status(StatusCode::OK)
+                .body(
+                    concat!(
+                        "\r\n--graphql\r\ncontent-type: application/json\r\n\r\n",
+                        r#"{"payload":{"data":{"reviewAdded":{"id":"1"}}}}"#,
+                        "\r\n--graphql\r\ncontent-type: application/json\r\n\r\n",
+                        "{}",
+                        "\r\n--graphql\r\ncontent-type: application/json\r\n\r\n",
+                        r#"{"payload":{"data":{"reviewAdded":{"id":"2"}}}}"#,
+                        "\r\n--graphql\r\ncontent-type: application/json\r\n\r\n",
+                        r#"{"payload":null,"errors":[{"message":"subscription closed"}]}"#,
+                        "\r\n--graphql--\r\n"
+                    )
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        server.await.unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_bad_status_code_should_not_fail() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2626").unwrap();
         tokio::task::spawn(emulate_subgraph_bad_request(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let response = subgraph_service
@@ -983,6 +1573,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -997,7 +1588,7 @@ mod tests {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2525").unwrap();
         tokio::task::spawn(emulate_subgraph_bad_response_format(socket_addr));
 
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let err = subgraph_service
@@ -1017,6 +1608,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap_err();
@@ -1030,7 +1622,7 @@ mod tests {
     async fn test_compressed_request_response_body() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2727").unwrap();
         tokio::task::spawn(emulate_subgraph_compressed_response(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(false), None);
+        let subgraph_service = SubgraphService::new("test", Some(false), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let resp = subgraph_service
@@ -1051,6 +1643,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1067,7 +1660,7 @@ mod tests {
     async fn test_unauthorized() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2828").unwrap();
         tokio::task::spawn(emulate_subgraph_unauthorized(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let err = subgraph_service
@@ -1087,6 +1680,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap_err();
@@ -1100,7 +1694,7 @@ mod tests {
     async fn test_persisted_query_not_supported_message() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2929").unwrap();
         tokio::task::spawn(emulate_persisted_query_not_supported_message(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         assert!(subgraph_service.clone().apq.as_ref().load(Relaxed));
 
@@ -1123,6 +1717,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1142,7 +1737,7 @@ mod tests {
         tokio::task::spawn(emulate_persisted_query_not_supported_extension_code(
             socket_addr,
         ));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         assert!(subgraph_service.clone().apq.as_ref().load(Relaxed));
 
@@ -1165,6 +1760,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1182,7 +1778,7 @@ mod tests {
     async fn test_persisted_query_not_found_message() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:3131").unwrap();
         tokio::task::spawn(emulate_persisted_query_not_found_message(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let resp = subgraph_service
@@ -1203,6 +1799,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1221,7 +1818,7 @@ mod tests {
         tokio::task::spawn(emulate_persisted_query_not_found_extension_code(
             socket_addr,
         ));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let resp = subgraph_service
@@ -1242,6 +1839,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1258,7 +1856,7 @@ mod tests {
     async fn test_apq_enabled_subgraph_configuration() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:3333").unwrap();
         tokio::task::spawn(emulate_expected_apq_enabled_configuration(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(true), None);
+        let subgraph_service = SubgraphService::new("test", Some(true), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let resp = subgraph_service
@@ -1279,6 +1877,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1295,7 +1894,7 @@ mod tests {
     async fn test_apq_disabled_subgraph_configuration() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:3434").unwrap();
         tokio::task::spawn(emulate_expected_apq_disabled_configuration(socket_addr));
-        let subgraph_service = SubgraphService::new("test", Some(false), None);
+        let subgraph_service = SubgraphService::new("test", Some(false), None, None);
 
         let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
         let resp = subgraph_service
@@ -1316,6 +1915,7 @@ mod tests {
                     .expect("expecting valid request"),
                 operation_kind: OperationKind::Query,
                 context: Context::new(),
+                subscription_stream: None,
             })
             .await
             .unwrap();
@@ -1327,4 +1927,160 @@ mod tests {
 
         assert_eq!(resp.response.body(), &expected_resp);
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_subscription_multipart() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3535").unwrap();
+        tokio::task::spawn(emulate_subgraph_subscription(socket_addr));
+        let subgraph_service = SubgraphService::new("test", Some(false), None, None);
+        let (stream_sender, mut stream_receiver) = futures::channel::mpsc::channel(1);
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let resp = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: Arc::new(
+                    http::Request::builder()
+                        .header(HOST, "host")
+                        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                        .body(Request::builder().query("subscription").build())
+                        .expect("expecting valid request"),
+                ),
+                subgraph_request: http::Request::builder()
+                    .header(HOST, "rhost")
+                    .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                    .uri(url)
+                    .body(Request::builder().query("subscription").build())
+                    .expect("expecting valid request"),
+                operation_kind: OperationKind::Subscription,
+                context: Context::new(),
+                subscription_stream: Some(stream_sender),
+            })
+            .await
+            .unwrap();
+        assert!(resp.response.body().errors.is_empty());
+
+        let events = stream_receiver
+            .next()
+            .await
+            .expect("the event stream should be sent")
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            Response::builder()
+                .data(serde_json_bytes::json!({"reviewAdded": {"id": "1"}}))
+                .build()
+        );
+        // the heartbeat is not forwarded
+        assert_eq!(
+            events[1],
+            Response::builder()
+                .data(serde_json_bytes::json!({"reviewAdded": {"id": "2"}}))
+                .build()
+        );
+        assert_eq!(events[2].errors[0].message, "subscription closed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_http2_prior_knowledge() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3636").unwrap();
+        tokio::task::spawn(emulate_h2c_subgraph(socket_addr));
+        let http_client =
+            serde_json::from_value(serde_json::json!({ "http2_only": true })).unwrap();
+        let subgraph_service = SubgraphService::new("test", Some(false), None, Some(http_client));
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let response = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: Arc::new(
+                    http::Request::builder()
+                        .header(HOST, "host")
+                        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                        .body(Request::builder().query("query").build())
+                        .expect("expecting valid request"),
+                ),
+                subgraph_request: http::Request::builder()
+                    .header(HOST, "rhost")
+                    .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                    .uri(url)
+                    .body(Request::builder().query("query").build())
+                    .expect("expecting valid request"),
+                operation_kind: OperationKind::Query,
+                context: Context::new(),
+                subscription_stream: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("test")))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_parses_unix_socket_subgraph_urls() {
+        let url = parse_subgraph_url("unix:///var/run/products.sock:/graphql").unwrap();
+        assert_eq!(url.path(), "/graphql");
+        assert_eq!(
+            unix_socket_path(&url),
+            Some(PathBuf::from("/var/run/products.sock"))
+        );
+
+        let url = parse_subgraph_url("unix:///var/run/products.sock").unwrap();
+        assert_eq!(url.path(), "/");
+        assert_eq!(
+            unix_socket_path(&url),
+            Some(PathBuf::from("/var/run/products.sock"))
+        );
+
+        let url = parse_subgraph_url("http://localhost:4001/graphql").unwrap();
+        assert_eq!(url, Uri::from_str("http://localhost:4001/graphql").unwrap());
+        assert_eq!(unix_socket_path(&url), None);
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn it_rejects_unix_socket_subgraph_urls() {
+        assert!(parse_subgraph_url("unix:///var/run/products.sock:/graphql").is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unix_socket_subgraph() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("subgraph.sock");
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        tokio::task::spawn(emulate_unix_socket_subgraph(listener));
+        let subgraph_service = SubgraphService::new("test", Some(false), None, None);
+
+        let url =
+            parse_subgraph_url(&format!("unix://{}:/graphql", socket_path.display())).unwrap();
+        let response = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: Arc::new(
+                    http::Request::builder()
+                        .header(HOST, "host")
+                        .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                        .body(Request::builder().query("query").build())
+                        .expect("expecting valid request"),
+                ),
+                subgraph_request: http::Request::builder()
+                    .header(HOST, "rhost")
+                    .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                    .uri(url)
+                    .body(Request::builder().query("query").build())
+                    .expect("expecting valid request"),
+                operation_kind: OperationKind::Query,
+                context: Context::new(),
+                subscription_stream: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("test")))
+        );
+    }
 }
@@ -18,15 +18,19 @@ use tracing_futures::Instrument;
 
 use super::layers::content_negociation;
 use super::layers::content_negociation::ACCEPTS_MULTIPART_CONTEXT_KEY;
+use super::layers::content_negociation::ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY;
 use super::new_service::ServiceFactory;
 use super::subgraph_service::MakeSubgraphService;
 use super::subgraph_service::SubgraphServiceFactory;
 use super::ExecutionServiceFactory;
 use super::QueryPlannerContent;
+use crate::configuration::IntrospectionRule;
+use crate::configuration::WarmUp;
 use crate::error::CacheResolverError;
 use crate::error::ServiceBuildError;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
+use crate::introspection;
 use crate::introspection::Introspection;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
@@ -49,6 +53,7 @@ use crate::Endpoint;
 use crate::ListenAddr;
 
 pub(crate) const QUERY_PLANNING_SPAN_NAME: &str = "query_planning";
+pub(crate) const IS_SUBSCRIPTION_CONTEXT_KEY: &str = "apollo_router::supergraph::is_subscription";
 
 /// An [`IndexMap`] of available plugins.
 pub(crate) type Plugins = IndexMap<String, Box<dyn DynPlugin>>;
@@ -59,6 +64,7 @@ pub(crate) struct SupergraphService {
     execution_service_factory: ExecutionServiceFactory,
     query_planner_service: CachingQueryPlanner<BridgeQueryPlanner>,
     schema: Arc<Schema>,
+    introspection_rules: Arc<Vec<IntrospectionRule>>,
 }
 
 #[buildstructor::buildstructor]
@@ -68,11 +74,13 @@ impl SupergraphService {
         query_planner_service: CachingQueryPlanner<BridgeQueryPlanner>,
         execution_service_factory: ExecutionServiceFactory,
         schema: Arc<Schema>,
+        introspection_rules: Arc<Vec<IntrospectionRule>>,
     ) -> Self {
         SupergraphService {
             query_planner_service,
             execution_service_factory,
             schema,
+            introspection_rules,
         }
     }
 }
@@ -96,10 +104,11 @@ impl Service<SupergraphRequest> for SupergraphService {
         let execution = self.execution_service_factory.create();
 
         let schema = self.schema.clone();
+        let introspection_rules = self.introspection_rules.clone();
 
         let context_cloned = req.context.clone();
-        let fut =
-            service_call(planning, execution, schema, req).or_else(|error: BoxError| async move {
+        let fut = service_call(planning, execution, schema, introspection_rules, req).or_else(
+            |error: BoxError| async move {
                 let errors = vec![crate::error::Error {
                     message: error.to_string(),
                     extensions: serde_json_bytes::json!({
@@ -117,16 +126,32 @@ impl Service<SupergraphRequest> for SupergraphService {
                     .context(context_cloned)
                     .build()
                     .expect("building a response like this should not fail"))
-            });
+            },
+        );
 
         Box::pin(fut)
     }
 }
 
+fn introspection_disabled(message: &str, context: Context) -> SupergraphResponse {
+    let mut response = SupergraphResponse::new_from_graphql_response(
+        graphql::Response::builder()
+            .errors(vec![crate::error::Error::builder()
+                .message(message.to_string())
+                .extension_code("INTROSPECTION_DISABLED")
+                .build()])
+            .build(),
+        context,
+    );
+    *response.response.status_mut() = StatusCode::BAD_REQUEST;
+    response
+}
+
 async fn service_call<ExecutionService>(
     planning: CachingQueryPlanner<BridgeQueryPlanner>,
     execution: ExecutionService,
     schema: Arc<Schema>,
+    introspection_rules: Arc<Vec<IntrospectionRule>>,
     req: SupergraphRequest,
 ) -> Result<SupergraphResponse, BoxError>
 where
@@ -165,31 +190,43 @@ where
     }
 
     match content {
-        Some(QueryPlannerContent::Introspection { response }) => Ok(
-            SupergraphResponse::new_from_graphql_response(*response, context),
-        ),
-        Some(QueryPlannerContent::IntrospectionDisabled) => {
-            let mut response = SupergraphResponse::new_from_graphql_response(
-                graphql::Response::builder()
-                    .errors(vec![crate::error::Error::builder()
-                        .message(String::from("introspection has been disabled"))
-                        .extension_code("INTROSPECTION_DISABLED")
-                        .build()])
-                    .build(),
+        // the introspection responses are cached, the access rules are checked for each request
+        Some(QueryPlannerContent::Introspection { .. })
+            if !introspection::is_allowed(
+                &introspection_rules,
+                &req.supergraph_request,
+                &context,
+            ) =>
+        {
+            Ok(introspection_disabled(
+                "introspection is not allowed for this request",
                 context,
-            );
-            *response.response.status_mut() = StatusCode::BAD_REQUEST;
-            Ok(response)
+            ))
         }
+        Some(
+            QueryPlannerContent::Introspection { response, .. }
+            | QueryPlannerContent::Typename { response },
+        ) => Ok(SupergraphResponse::new_from_graphql_response(
+            *response, context,
+        )),
+        Some(QueryPlannerContent::IntrospectionDisabled) => Ok(introspection_disabled(
+            "introspection has been disabled",
+            context,
+        )),
 
         Some(QueryPlannerContent::Plan { plan }) => {
             let operation_name = body.operation_name.clone();
             let is_deferred = plan.is_deferred(operation_name.as_deref(), &variables);
+            let is_subscription = plan.is_subscription();
 
             let accepts_multipart: bool = context
                 .get(ACCEPTS_MULTIPART_CONTEXT_KEY)
                 .unwrap_or_default()
                 .unwrap_or_default();
+            let accepts_multipart_subscription: bool = context
+                .get(ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY)
+                .unwrap_or_default()
+                .unwrap_or_default();
 
             if is_deferred && !accepts_multipart {
                 let mut response = SupergraphResponse::new_from_graphql_response(graphql::Response::builder()
@@ -200,11 +237,24 @@ where
                     .build(), context);
                 *response.response.status_mut() = StatusCode::NOT_ACCEPTABLE;
                 Ok(response)
+            } else if is_subscription && !accepts_multipart_subscription {
+                let mut response = SupergraphResponse::new_from_graphql_response(graphql::Response::builder()
+                    .errors(vec![crate::error::Error::builder()
+                        .message(String::from("the router received a subscription but the client does not accept multipart/mixed HTTP responses. To enable subscription support, add the HTTP header 'Accept: multipart/mixed; subscriptionSpec=1.0'"))
+                        .extension_code("SUBSCRIPTION_BAD_HEADER")
+                        .build()])
+                    .build(), context);
+                *response.response.status_mut() = StatusCode::NOT_ACCEPTABLE;
+                Ok(response)
             } else if let Some(err) = plan.query.validate_variables(body, &schema).err() {
                 let mut res = SupergraphResponse::new_from_graphql_response(err, context);
                 *res.response.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(res)
             } else {
+                if is_subscription {
+                    context.insert(IS_SUBSCRIPTION_CONTEXT_KEY, true)?;
+                }
+
                 let execution_response = execution
                     .oneshot(
                         ExecutionRequest::builder()
@@ -271,6 +321,7 @@ pub(crate) struct PluggableSupergraphServiceBuilder {
     plugins: Plugins,
     subgraph_services: Vec<(String, Arc<dyn MakeSubgraphService>)>,
     configuration: Option<Arc<Configuration>>,
+    previous_introspection: Option<Arc<Introspection>>,
 }
 
 impl PluggableSupergraphServiceBuilder {
@@ -280,6 +331,7 @@ impl PluggableSupergraphServiceBuilder {
             plugins: Default::default(),
             subgraph_services: Default::default(),
             configuration: None,
+            previous_introspection: None,
         }
     }
 
@@ -313,6 +365,15 @@ impl PluggableSupergraphServiceBuilder {
         self
     }
 
+    /// Reuses the introspection cache of the previous supergraph, if it has the same schema
+    pub(crate) fn with_previous_supergraph(
+        mut self,
+        previous: &SupergraphCreator,
+    ) -> PluggableSupergraphServiceBuilder {
+        self.previous_introspection = previous.introspection.clone();
+        self
+    }
+
     pub(crate) async fn build(self) -> Result<SupergraphCreator, crate::error::ServiceBuildError> {
         // Note: The plugins are always applied in reverse, so that the
         // fold is applied in the correct sequence. We could reverse
@@ -323,21 +384,34 @@ impl PluggableSupergraphServiceBuilder {
 
         let configuration = self.configuration.unwrap_or_default();
 
+        let schema_id = self.schema.schema_id.as_deref();
         let introspection = if configuration.supergraph.introspection {
-            Some(Arc::new(Introspection::new(&configuration).await))
+            match self
+                .previous_introspection
+                .filter(|introspection| introspection.is_reusable(&configuration, schema_id))
+            {
+                Some(introspection) => Some(introspection),
+                None => Some(Arc::new(
+                    Introspection::new(&configuration, schema_id).await,
+                )),
+            }
         } else {
             None
         };
 
         // QueryPlannerService takes an UnplannedRequest and outputs PlannedRequest
-        let bridge_query_planner =
-            BridgeQueryPlanner::new(self.schema.clone(), introspection, configuration.clone())
-                .await
-                .map_err(ServiceBuildError::QueryPlannerError)?;
+        let bridge_query_planner = BridgeQueryPlanner::new(
+            self.schema.clone(),
+            introspection.clone(),
+            configuration.clone(),
+        )
+        .await
+        .map_err(ServiceBuildError::QueryPlannerError)?;
         let query_planner_service = CachingQueryPlanner::new(
             bridge_query_planner,
             self.schema.schema_id.clone(),
             &configuration.supergraph.query_planning,
+            Arc::new(configuration.operation_limits.clone()),
         )
         .await;
 
@@ -353,6 +427,10 @@ impl PluggableSupergraphServiceBuilder {
             subgraph_service_factory,
             schema: self.schema,
             plugins,
+            introspection,
+            introspection_rules: Arc::new(
+                configuration.supergraph.introspection_options.allow.clone(),
+            ),
         })
     }
 }
@@ -386,6 +464,8 @@ pub(crate) struct SupergraphCreator {
     subgraph_service_factory: Arc<SubgraphServiceFactory>,
     schema: Arc<Schema>,
     plugins: Arc<Plugins>,
+    introspection: Option<Arc<Introspection>>,
+    introspection_rules: Arc<Vec<IntrospectionRule>>,
 }
 
 pub(crate) trait HasPlugins {
@@ -422,6 +502,7 @@ impl SupergraphCreator {
                 subgraph_service_factory: self.subgraph_service_factory.clone(),
             })
             .schema(self.schema.clone())
+            .introspection_rules(self.introspection_rules.clone())
             .build();
 
         let supergraph_service = match self
@@ -452,8 +533,17 @@ impl SupergraphCreator {
     pub(crate) async fn warm_up_query_planner(
         &mut self,
         cache_keys: Vec<(String, Option<String>)>,
+        config: &WarmUp,
     ) {
-        self.query_planner_service.warm_up(cache_keys).await
+        self.query_planner_service.warm_up(cache_keys, config).await
+    }
+
+    /// Sets the expiration of the query plans stored in Redis for this supergraph, if `next`
+    /// uses another schema
+    #[cfg(feature = "experimental_cache")]
+    pub(crate) fn expire_query_plans(&self, next: &SupergraphCreator, ttl: std::time::Duration) {
+        self.query_planner_service
+            .expire_for_next_schema(&next.query_planner_service, ttl)
     }
 
     /// Create a test service.
@@ -515,7 +605,10 @@ impl ServiceFactory<supergraph::Request> for MockSupergraphCreator {
 mod tests {
 
     use super::*;
+    use crate::http_server_factory::LocalAddr;
     use crate::plugin::test::MockSubgraph;
+    use crate::query_planner::OperationKind;
+    use crate::services::subgraph;
     use crate::services::supergraph;
     use crate::test_harness::MockedSubgraphs;
     use crate::TestHarness;
@@ -1537,6 +1630,286 @@ mod tests {
         insta::assert_json_snapshot!(stream.next_response().await.unwrap());
     }
 
+    fn subscription_schema() -> String {
+        SCHEMA
+            .replace("query: Query", "query: Query\n        subscription: Subscription")
+            .replace(
+                "type Query {",
+                "type Subscription {\n       userWasCreated: User @join__field(graph: USER)\n   }\n   type Query {",
+            )
+    }
+
+    #[tokio::test]
+    async fn rejects_subscriptions_when_disabled() {
+        let subgraphs = MockedSubgraphs(
+            [
+                ("user", MockSubgraph::default()),
+                ("orga", MockSubgraph::default()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "supergraph": { "subscription_support": false }
+            }))
+            .unwrap()
+            .schema(&subscription_schema())
+            .extra_plugin(subgraphs)
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("subscription { userWasCreated { name } }")
+            .build()
+            .unwrap();
+        let response = service
+            .oneshot(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::json!("SUBSCRIPTION_NOT_SUPPORTED"))
+        );
+    }
+
+    #[tokio::test]
+    async fn executes_subscriptions() {
+        let service = TestHarness::builder()
+            .schema(&subscription_schema())
+            .subgraph_hook(|name, default| match name {
+                "user" => tower::service_fn(|request: subgraph::Request| async move {
+                    assert_eq!(request.operation_kind, OperationKind::Subscription);
+                    assert_eq!(
+                        request.subgraph_request.body().query.as_deref(),
+                        Some("subscription { userWasCreated { name } }")
+                    );
+
+                    let events = futures::stream::iter(vec![
+                        graphql::Response::builder()
+                            .data(serde_json_bytes::json!({"userWasCreated": {"name": "Ada"}}))
+                            .build(),
+                        graphql::Response::builder()
+                            .data(serde_json_bytes::json!({"userWasCreated": {"name": "Grace"}}))
+                            .build(),
+                    ])
+                    .boxed();
+                    request
+                        .subscription_stream
+                        .expect("the subscription stream sender should be set")
+                        .try_send(events)
+                        .unwrap();
+
+                    Ok::<_, BoxError>(subgraph::Response::fake_builder().build())
+                })
+                .boxed(),
+                _ => default,
+            })
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let context = Context::new();
+        context
+            .insert(ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY, true)
+            .unwrap();
+        let request = supergraph::Request::fake_builder()
+            .query("subscription { userWasCreated { name } }")
+            .context(context)
+            .build()
+            .unwrap();
+        let mut stream = service.oneshot(request).await.unwrap();
+
+        let first = stream.next_response().await.unwrap();
+        assert!(first.errors.is_empty());
+        assert_eq!(
+            first.data,
+            Some(serde_json_bytes::json!({"userWasCreated": {"name": "Ada"}}))
+        );
+        let second = stream.next_response().await.unwrap();
+        assert_eq!(
+            second.data,
+            Some(serde_json_bytes::json!({"userWasCreated": {"name": "Grace"}}))
+        );
+        let last = stream.next_response().await.unwrap();
+        assert_eq!(last.has_next, Some(false));
+        assert!(last.data.is_none());
+        assert!(stream.next_response().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_subscriptions_resolved_by_several_subgraphs() {
+        let service = TestHarness::builder()
+            .schema(&subscription_schema())
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let context = Context::new();
+        context
+            .insert(ACCEPTS_MULTIPART_SUBSCRIPTION_CONTEXT_KEY, true)
+            .unwrap();
+        let request = supergraph::Request::fake_builder()
+            .query("subscription { userWasCreated { activeOrganization { name } } }")
+            .context(context)
+            .build()
+            .unwrap();
+        let response = service
+            .oneshot(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::json!("SUBSCRIPTION_NOT_SUPPORTED"))
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_introspection_access_rules() {
+        let service = TestHarness::builder()
+            .schema(SCHEMA)
+            .configuration_json(serde_json::json!({
+                "supergraph": {
+                    "introspection": true,
+                    "introspection_options": {
+                        "allow": [
+                            { "header": { "name": "x-introspection", "value": "allowed" } },
+                            { "listener": "0.0.0.0:4001" },
+                        ]
+                    }
+                }
+            }))
+            .unwrap()
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        let query = |header: &str, listen_addr: &str| {
+            let mut request = supergraph::Request::fake_builder()
+                .query("{ __schema { queryType { name } } }")
+                .header("x-introspection", header)
+                .build()
+                .unwrap();
+            request
+                .supergraph_request
+                .extensions_mut()
+                .insert(LocalAddr(ListenAddr::SocketAddr(
+                    listen_addr.parse().unwrap(),
+                )));
+            let service = service.clone();
+            async move {
+                service
+                    .oneshot(request)
+                    .await
+                    .unwrap()
+                    .next_response()
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // rejected: no rule matches
+        let response = query("denied", "0.0.0.0:4000").await;
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::json!("INTROSPECTION_DISABLED"))
+        );
+
+        // allowed by the header rule
+        let response = query("allowed", "0.0.0.0:4000").await;
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data,
+            Some(serde_json_bytes::json!({ "__schema": { "queryType": { "name": "Query" } } }))
+        );
+
+        // allowed by the listener rule, matched against the configured `0.0.0.0` address
+        let response = query("denied", "0.0.0.0:4001").await;
+        assert!(response.errors.is_empty());
+
+        // the address of the interface the connection was accepted on does not match
+        let response = query("denied", "127.0.0.1:4001").await;
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::json!("INTROSPECTION_DISABLED"))
+        );
+    }
+
+    #[tokio::test]
+    async fn checks_operation_limits_for_each_request() {
+        let query = |service: supergraph::BoxCloneService| async move {
+            let request = supergraph::Request::fake_builder()
+                .query("{ currentUser { activeOrganization { id } } }")
+                .build()
+                .unwrap();
+            service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap()
+        };
+
+        let service = TestHarness::builder()
+            .schema(SCHEMA)
+            .configuration_json(serde_json::json!({
+                "operation_limits": { "max_depth": 2 }
+            }))
+            .unwrap()
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        // the rejections are not cached, the second request is rejected before planning too
+        for _ in 0..2 {
+            let response = query(service.clone()).await;
+            assert_eq!(response.data, None);
+            assert_eq!(
+                response.errors[0].extensions.get("code"),
+                Some(&serde_json_bytes::json!("OPERATION_LIMIT_EXCEEDED"))
+            );
+        }
+
+        let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"{currentUser{activeOrganization{__typename id}}}"}},
+                serde_json::json!{{"data": {"currentUser": { "activeOrganization": null }}}}
+            ).build()),
+            ("orga", MockSubgraph::default())
+        ].into_iter().collect());
+        let service = TestHarness::builder()
+            .schema(SCHEMA)
+            .configuration_json(serde_json::json!({
+                "operation_limits": { "max_depth": 2, "warn_only": true }
+            }))
+            .unwrap()
+            .extra_plugin(subgraphs)
+            .build_supergraph()
+            .await
+            .unwrap();
+
+        // the second request uses the cached query plan, its limits are checked again
+        for _ in 0..2 {
+            let response = query(service.clone()).await;
+            assert!(response.errors.is_empty());
+            assert_eq!(
+                response.data,
+                Some(serde_json_bytes::json!({ "currentUser": { "activeOrganization": null } }))
+            );
+        }
+    }
+
     fn defer_context() -> Context {
         let context = Context::new();
         context.insert(ACCEPTS_MULTIPART_CONTEXT_KEY, true).unwrap();
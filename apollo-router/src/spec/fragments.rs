@@ -4,6 +4,7 @@ use apollo_parser::ast;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::spec::operation_limits::count_directives;
 use crate::spec::parse_include;
 use crate::spec::parse_skip;
 use crate::spec::FieldType;
@@ -112,6 +113,8 @@ impl Fragments {
                     })
                     .unwrap_or(Include::Yes);
 
+                let directives = count_directives(&fragment_definition);
+
                 Ok(Some((
                     name,
                     Fragment {
@@ -119,6 +122,7 @@ impl Fragments {
                         selection_set,
                         skip,
                         include,
+                        directives,
                     },
                 )))
             })
@@ -142,4 +146,7 @@ pub(crate) struct Fragment {
     pub(crate) selection_set: Vec<Selection>,
     pub(crate) skip: Skip,
     pub(crate) include: Include,
+    /// Number of directives in the fragment definition
+    #[serde(default)]
+    pub(crate) directives: u32,
 }
@@ -4,6 +4,7 @@
 
 mod field_type;
 mod fragments;
+pub(crate) mod operation_limits;
 pub(crate) mod query;
 mod schema;
 mod selection;
@@ -36,6 +37,14 @@ pub(crate) enum SpecError {
     ParsingError(String),
     /// subscription operation is not supported
     SubscriptionNotSupported,
+    /// subscription cannot be planned: {0}
+    UnplannableSubscription(String),
+    /// operation exceeds the {limit} limit: measured {measured}, maximum {max}
+    OperationLimitExceeded {
+        limit: String,
+        measured: u32,
+        max: u32,
+    },
 }
 
 impl SpecError {
@@ -54,7 +63,10 @@ impl ErrorExtension for SpecError {
             SpecError::InvalidType(_) => "INVALID_TYPE",
             SpecError::InvalidField(_, _) => "INVALID_FIELD",
             SpecError::ParsingError(_) => "PARSING_ERROR",
-            SpecError::SubscriptionNotSupported => "SUBSCRIPTION_NOT_SUPPORTED",
+            SpecError::SubscriptionNotSupported | SpecError::UnplannableSubscription(_) => {
+                "SUBSCRIPTION_NOT_SUPPORTED"
+            }
+            SpecError::OperationLimitExceeded { .. } => "OPERATION_LIMIT_EXCEEDED",
         }
         .to_string()
     }
@@ -69,6 +81,15 @@ impl ErrorExtension for SpecError {
                 obj.insert("type", ty.clone().into());
                 obj.insert("field", field.clone().into());
             }
+            SpecError::OperationLimitExceeded {
+                limit,
+                measured,
+                max,
+            } => {
+                obj.insert("limit", limit.clone().into());
+                obj.insert("measured", (*measured).into());
+                obj.insert("max", (*max).into());
+            }
             _ => (),
         }
 
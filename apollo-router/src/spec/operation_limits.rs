@@ -0,0 +1,300 @@
+//! Measures of the size of operations, checked against the configured limits.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use apollo_parser::ast;
+use apollo_parser::ast::AstNode;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::configuration::OperationLimits;
+use crate::spec::Fragments;
+use crate::spec::Selection;
+use crate::spec::SpecError;
+
+/// Size of an operation, with its fragments expanded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OperationMeasures {
+    pub(crate) depth: u32,
+    pub(crate) height: u32,
+    pub(crate) root_fields: u32,
+    pub(crate) aliases: u32,
+    pub(crate) directives: u32,
+}
+
+impl OperationMeasures {
+    /// Compares the measures to the limits, and returns the first limit that is exceeded
+    pub(crate) fn check(&self, limits: &OperationLimits) -> Result<(), SpecError> {
+        let checks = [
+            ("max_depth", self.depth, limits.max_depth),
+            ("max_height", self.height, limits.max_height),
+            ("max_root_fields", self.root_fields, limits.max_root_fields),
+            ("max_aliases", self.aliases, limits.max_aliases),
+            ("max_directives", self.directives, limits.max_directives),
+        ];
+
+        for (limit, measured, max) in checks {
+            if let Some(max) = max {
+                if measured > max {
+                    return Err(SpecError::OperationLimitExceeded {
+                        limit: limit.to_string(),
+                        measured,
+                        max,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the measures of the operation of a request: the operation is rejected if it
+    /// exceeds the limits, or only logged in warn only mode. The measures are cached with the
+    /// query plan, so this is done for each request.
+    pub(crate) fn enforce(
+        &self,
+        limits: &OperationLimits,
+        operation_name: Option<&str>,
+    ) -> Result<(), SpecError> {
+        let error = match self.check(limits) {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        if let SpecError::OperationLimitExceeded { limit, .. } = &error {
+            // This is a metric and will not appear in the logs
+            tracing::info!(
+                monotonic_counter.apollo_router_operation_limits_exceeded_count = 1u64,
+                limit = %limit,
+                warn_only = limits.warn_only,
+            );
+        }
+
+        if limits.warn_only {
+            tracing::warn!(
+                operation_name = operation_name.unwrap_or_default(),
+                "{}",
+                error
+            );
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+}
+
+/// Number of directives in a node of the document, including its children
+pub(crate) fn count_directives(node: &impl AstNode) -> u32 {
+    node.syntax()
+        .descendants()
+        .filter_map(ast::Directive::cast)
+        .count() as u32
+}
+
+/// Measures an operation from its root selection set and the number of directives of its
+/// definition.
+pub(crate) fn measure(
+    selection_set: &[Selection],
+    directives: u32,
+    fragments: &Fragments,
+) -> OperationMeasures {
+    let mut measurer = Measurer {
+        fragments,
+        cache: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+    let measures = measurer.selection_set(selection_set);
+
+    OperationMeasures {
+        depth: measures.depth,
+        height: measures.height,
+        root_fields: measures.fields,
+        aliases: measures.aliases,
+        directives: measures.directives.saturating_add(directives),
+    }
+}
+
+/// Measures of a selection set, the depth being relative to it
+#[derive(Clone, Copy, Default)]
+struct SelectionSetMeasures {
+    depth: u32,
+    height: u32,
+    /// Fields directly in the selection set, or in its fragments
+    fields: u32,
+    aliases: u32,
+    directives: u32,
+}
+
+impl SelectionSetMeasures {
+    /// Adds the measures of a fragment at the same level
+    fn add(&mut self, other: SelectionSetMeasures) {
+        self.depth = self.depth.max(other.depth);
+        self.height = self.height.saturating_add(other.height);
+        self.fields = self.fields.saturating_add(other.fields);
+        self.aliases = self.aliases.saturating_add(other.aliases);
+        self.directives = self.directives.saturating_add(other.directives);
+    }
+}
+
+struct Measurer<'a> {
+    fragments: &'a Fragments,
+    /// Fragments are measured once, whatever the number of times they are spread
+    cache: HashMap<&'a str, SelectionSetMeasures>,
+    /// Fragments being measured, to stop on cycles
+    visiting: HashSet<&'a str>,
+}
+
+impl<'a> Measurer<'a> {
+    fn selection_set(&mut self, selection_set: &'a [Selection]) -> SelectionSetMeasures {
+        let mut measures = SelectionSetMeasures::default();
+
+        for selection in selection_set {
+            match selection {
+                Selection::Field {
+                    alias,
+                    selection_set,
+                    ..
+                } => {
+                    let sub_measures = selection_set
+                        .as_deref()
+                        .map(|selection_set| self.selection_set(selection_set))
+                        .unwrap_or_default();
+
+                    measures.depth = measures.depth.max(sub_measures.depth.saturating_add(1));
+                    measures.height = measures
+                        .height
+                        .saturating_add(1)
+                        .saturating_add(sub_measures.height);
+                    measures.fields = measures.fields.saturating_add(1);
+                    measures.aliases = measures
+                        .aliases
+                        .saturating_add(alias.is_some() as u32)
+                        .saturating_add(sub_measures.aliases);
+                    measures.directives =
+                        measures.directives.saturating_add(sub_measures.directives);
+                }
+                Selection::InlineFragment { selection_set, .. } => {
+                    measures.add(self.selection_set(selection_set));
+                }
+                Selection::FragmentSpread { name, .. } => {
+                    measures.add(self.fragment(name));
+                }
+            }
+        }
+
+        measures
+    }
+
+    fn fragment(&mut self, name: &'a str) -> SelectionSetMeasures {
+        if let Some(measures) = self.cache.get(name) {
+            return *measures;
+        }
+        let fragment = match self.fragments.get(name) {
+            Some(fragment) => fragment,
+            None => return SelectionSetMeasures::default(),
+        };
+        if !self.visiting.insert(name) {
+            return SelectionSetMeasures::default();
+        }
+
+        let mut measures = self.selection_set(&fragment.selection_set);
+        measures.directives = measures.directives.saturating_add(fragment.directives);
+
+        self.visiting.remove(name);
+        self.cache.insert(name, measures);
+        measures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+
+    #[test]
+    fn it_measures_operations_with_fragments() {
+        let schema = Schema::parse(
+            include_str!("../../testing_schema.graphql"),
+            &Default::default(),
+        )
+        .unwrap();
+        let query = Query::parse(
+            r#"query Me {
+                me { ...UserFields }
+                other: me { id }
+            }
+            fragment UserFields on User {
+                id
+                reviews {
+                    author @include(if: true) { name }
+                    body
+                }
+            }"#,
+            &schema,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            query.measure(None),
+            Some(OperationMeasures {
+                depth: 4,
+                height: 8,
+                root_fields: 2,
+                aliases: 1,
+                directives: 1,
+            })
+        );
+        assert_eq!(query.measure(Some("Unknown")), None);
+    }
+
+    #[test]
+    fn it_reports_the_first_exceeded_limit() {
+        let measures = OperationMeasures {
+            depth: 4,
+            height: 8,
+            root_fields: 2,
+            aliases: 1,
+            directives: 1,
+        };
+
+        assert!(measures.check(&OperationLimits::default()).is_ok());
+        assert!(measures
+            .check(&OperationLimits::builder().max_depth(4).build())
+            .is_ok());
+        assert!(matches!(
+            measures.check(
+                &OperationLimits::builder()
+                    .max_height(5)
+                    .max_aliases(0)
+                    .build()
+            ),
+            Err(SpecError::OperationLimitExceeded { limit, measured: 8, max: 5 })
+                if limit == "max_height"
+        ));
+    }
+
+    #[test]
+    fn it_measures_the_depth_of_introspection_operations() {
+        let schema = Schema::parse(
+            include_str!("../../testing_schema.graphql"),
+            &Default::default(),
+        )
+        .unwrap();
+        let query = Query::parse(
+            r#"query Introspection {
+                __schema { types { ...TypeFields } }
+            }
+            fragment TypeFields on __Type {
+                fields { type { name } }
+            }"#,
+            &schema,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(query.introspection_depth(), 5);
+    }
+}
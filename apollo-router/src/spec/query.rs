@@ -24,6 +24,9 @@ use crate::json_ext::Path;
 use crate::json_ext::PathElement;
 use crate::json_ext::Value;
 use crate::query_planner::fetch::OperationKind;
+use crate::spec::operation_limits;
+use crate::spec::operation_limits::count_directives;
+use crate::spec::operation_limits::OperationMeasures;
 use crate::spec::FieldType;
 use crate::spec::Fragments;
 use crate::spec::InvalidValue;
@@ -301,6 +304,41 @@ impl Query {
         })
     }
 
+    /// Whether the operation is a subscription
+    pub(crate) fn is_subscription(&self, operation_name: Option<&str>) -> bool {
+        self.operation(operation_name)
+            .map(|operation| operation.kind == OperationKind::Subscription)
+            .unwrap_or_default()
+    }
+
+    /// Measures the size of an operation, with its fragments expanded
+    pub(crate) fn measure(&self, operation_name: Option<&str>) -> Option<OperationMeasures> {
+        self.operation(operation_name).map(|operation| {
+            operation_limits::measure(
+                &operation.selection_set,
+                operation.directives,
+                &self.fragments,
+            )
+        })
+    }
+
+    /// Depth of the deepest introspection operation, with its fragments expanded
+    pub(crate) fn introspection_depth(&self) -> u32 {
+        self.operations
+            .iter()
+            .filter(|operation| operation.is_introspection())
+            .map(|operation| {
+                operation_limits::measure(
+                    &operation.selection_set,
+                    operation.directives,
+                    &self.fragments,
+                )
+                .depth
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn format_value(
         &self,
@@ -1047,6 +1085,9 @@ pub(crate) struct Operation {
     kind: OperationKind,
     selection_set: Vec<Selection>,
     variables: HashMap<ByteString, Variable>,
+    /// Number of directives in the operation definition, not including its fragments
+    #[serde(default)]
+    directives: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1062,6 +1103,7 @@ impl Operation {
     // Spec: https://spec.graphql.org/draft/#sec-Language.Operations
     fn from_ast(operation: ast::OperationDefinition, schema: &Schema) -> Result<Self, SpecError> {
         let name = operation.name().map(|x| x.text().to_string());
+        let directives = count_directives(&operation);
 
         let kind = operation
             .operation_type()
@@ -1076,7 +1118,7 @@ impl Operation {
         let current_field_type = match kind {
             OperationKind::Query => FieldType::Named("Query".to_string()),
             OperationKind::Mutation => FieldType::Named("Mutation".to_string()),
-            OperationKind::Subscription => return Err(SpecError::SubscriptionNotSupported),
+            OperationKind::Subscription => FieldType::Named("Subscription".to_string()),
         };
 
         let selection_set = operation
@@ -1132,6 +1174,7 @@ impl Operation {
             name,
             variables,
             kind,
+            directives,
         })
     }
 
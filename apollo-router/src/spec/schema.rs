@@ -2,7 +2,6 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::str::FromStr;
 use std::sync::Arc;
 
 use apollo_compiler::hir;
@@ -21,6 +20,7 @@ use crate::error::SchemaError;
 use crate::json_ext::Object;
 use crate::json_ext::Value;
 use crate::query_planner::OperationKind;
+use crate::services::subgraph_service::parse_subgraph_url;
 use crate::spec::query::parse_hir_value;
 use crate::spec::query::parse_value;
 use crate::spec::FieldType;
@@ -205,7 +205,7 @@ impl Schema {
                     if url.is_empty() {
                         return Err(SchemaError::MissingSubgraphUrl(name.clone()));
                     }
-                    let url = Uri::from_str(url)
+                    let url = parse_subgraph_url(url)
                         .map_err(|err| SchemaError::UrlParse(name.clone(), err))?;
                     if subgraphs.insert(name.clone(), url).is_some() {
                         return Err(SchemaError::Api(format!(
@@ -457,12 +457,14 @@ impl Schema {
                                                     if subgraphs
                                                         .insert(
                                                             name.clone(),
-                                                            Uri::from_str(&url).map_err(|err| {
-                                                                SchemaError::UrlParse(
-                                                                    name.clone(),
-                                                                    err,
-                                                                )
-                                                            })?,
+                                                            parse_subgraph_url(&url).map_err(
+                                                                |err| {
+                                                                    SchemaError::UrlParse(
+                                                                        name.clone(),
+                                                                        err,
+                                                                    )
+                                                                },
+                                                            )?,
                                                         )
                                                         .is_some()
                                                     {
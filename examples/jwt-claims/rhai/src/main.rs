@@ -89,7 +89,11 @@ mod tests {
             "authentication": {
                 "experimental" : {
                     "jwt": {
-                        "jwks_url": &jwks_url
+                        "jwks": [
+                            {
+                                "url": &jwks_url
+                            }
+                        ]
                     }
                 }
             }